@@ -15,12 +15,16 @@ pub mod anima;
 pub mod scene;
 pub mod ui;
 pub mod util;
-
-//mod workspace;
+pub mod workspace;
 
 fn main() {
     crate::util::enable_tracing();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(("compile", args)) = args.split_first().map(|(cmd, rest)| (cmd.as_str(), rest)) {
+        std::process::exit(crate::workspace::compile::run(args));
+    }
+
     let mut app = App::new();
     app.insert_resource(ClearColor(Color::CRIMSON))
         .insert_resource(Msaa { samples: 1 })
@@ -58,6 +62,7 @@ fn main() {
     app.add_editor_tab::<self::scene::SceneTab>();
 
     app.add_plugin(self::anima::AnimaPlugin);
+    app.add_plugin(self::workspace::WorkspacePlugin);
 
     app.add_startup_system(setup);
 
@@ -74,6 +79,7 @@ fn setup(
     use crate::anima::{Animation2d, TimelinePanel};
     use crate::scene::{FileBrowser, Hierarchy, Inspector, SceneTab};
     use crate::ui::*;
+    use crate::workspace::{Blackboard, Diagnostics, MeshPreviewTab, Workspace};
 
     {
         let world = exampe_scene();
@@ -101,12 +107,14 @@ fn setup(
         }
     }
 
-    let node_tree = commands.placeholder(icon::NODETREE, "Node Tree");
+    let node_tree = commands.tab(icon::NODETREE, "Node Tree", Workspace::default());
+    let mesh_preview = commands.tab(icon::MAT_SPHERE, "Preview", MeshPreviewTab);
 
     let scene = SceneTab::spawn(&mut commands, &mut images);
 
     let hierarchy = commands.tab(icon::OUTLINER, "Hierarchy", Hierarchy::default());
     let inspector = commands.tab(icon::PROPERTIES, "Inspector", Inspector::default());
+    let blackboard = commands.tab(icon::PRESET, "Blackboard", Blackboard);
     let files = commands.tab(icon::FILEBROWSER, "File Browser", FileBrowser::default());
 
     let assets = commands.placeholder(icon::ASSET_MANAGER, "Asset Manager");
@@ -114,12 +122,18 @@ fn setup(
     let anim = Animation2d::spawn(&mut commands, &mut images);
 
     let timeline = commands.tab(icon::TIME, "Timeline", TimelinePanel::default());
+    let diagnostics = commands.tab(icon::ERROR, "Diagnostics", Diagnostics);
 
     let root = TreeNode::leaf_with(vec![anim, scene, node_tree]);
     let mut split_tree = SplitTree::new(root);
 
-    let [a, b] = split_tree.split_tabs(NodeIndex::root(), Split::Right, 0.7, vec![inspector]);
-    let [_, _] = split_tree.split_tabs(a, Split::Below, 0.8, vec![timeline]);
+    let [a, b] = split_tree.split_tabs(
+        NodeIndex::root(),
+        Split::Right,
+        0.7,
+        vec![mesh_preview, inspector, blackboard],
+    );
+    let [_, _] = split_tree.split_tabs(a, Split::Below, 0.8, vec![timeline, diagnostics]);
     let [_, _] = split_tree.split_tabs(b, Split::Below, 0.5, vec![hierarchy, files, assets]);
 
     commands.insert_resource(split_tree);
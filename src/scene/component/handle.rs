@@ -55,6 +55,8 @@ impl<T: bevy::asset::Asset> ComponentEditor for ProxyHandle<T> {
             (icon::MESH_DATA, "Mesh".into())
         } else if type_name == std::any::type_name::<bevy::prelude::StandardMaterial>() {
             (icon::MATERIAL_DATA, "Material".into())
+        } else if type_name == std::any::type_name::<crate::workspace::GraphMaterial>() {
+            (icon::NODE_MATERIAL, "Graph Material".into())
         } else {
             (' ', format!("Handle<{}>", type_name).into())
         }
@@ -17,6 +17,7 @@ pub struct EditorPlugin;
 
 impl bevy::app::Plugin for EditorPlugin {
     fn build(&self, app: &mut bevy::app::App) {
+        use crate::workspace::GraphMaterial;
         use bevy::prelude::*;
 
         app.add_plugin(crate::scene::ScenePlugin)
@@ -24,7 +25,8 @@ impl bevy::app::Plugin for EditorPlugin {
             .register_type::<ProxyTransform>()
             .register_type::<ProxyPointLight>()
             .register_type::<ProxyHandle<Mesh>>()
-            .register_type::<ProxyHandle<StandardMaterial>>();
+            .register_type::<ProxyHandle<StandardMaterial>>()
+            .register_type::<ProxyHandle<GraphMaterial>>();
     }
 }
 
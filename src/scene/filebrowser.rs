@@ -1,6 +1,8 @@
 use crate::ui::{icon, EditorTab, Style};
+use crate::workspace::OpenShaderGraph;
 use bevy::asset::AssetIo;
-use bevy::ecs::system::lifetimeless::SRes;
+use bevy::ecs::event::Events;
+use bevy::ecs::system::lifetimeless::{SRes, SResMut};
 use bevy::ecs::system::SystemParamItem;
 use bevy::prelude::*;
 use std::ffi::OsStr;
@@ -10,29 +12,38 @@ use std::path::Path;
 pub struct FileBrowser;
 
 impl EditorTab for FileBrowser {
-    type Param = (SRes<Style>, SRes<AssetServer>);
+    type Param = (
+        SRes<Style>,
+        SRes<AssetServer>,
+        SResMut<Events<OpenShaderGraph>>,
+    );
 
     fn ui<'w>(
         &mut self,
         ui: &mut egui::Ui,
         _entity: Entity,
-        (style, assets): &mut SystemParamItem<'w, '_, Self::Param>,
+        (style, assets, open_shader): &mut SystemParamItem<'w, '_, Self::Param>,
     ) {
         let rect = ui.available_rect_before_wrap();
         ui.painter().rect_filled(rect, 0.0, style.panel);
         let io = assets.asset_io();
-        read_dir(ui, io, ".".as_ref());
+        read_dir(ui, io, ".".as_ref(), open_shader);
     }
 }
 
-fn read_dir(ui: &mut egui::Ui, io: &dyn AssetIo, path: &Path) {
+fn read_dir(
+    ui: &mut egui::Ui,
+    io: &dyn AssetIo,
+    path: &Path,
+    open_shader: &mut Events<OpenShaderGraph>,
+) {
     ui.indent(&path, |ui| {
         if let Ok(dir) = io.read_directory(path) {
             for path in dir {
                 let path_string = path.file_name().unwrap().to_string_lossy().to_string();
                 if io.is_dir(&path) {
                     ui.label(format!("{} {}", icon::FILE_FOLDER, path_string));
-                    read_dir(ui, io, &path);
+                    read_dir(ui, io, &path, open_shader);
                 } else {
                     let ext = path.extension().map(OsStr::to_string_lossy);
                     let icon = if let Some(ext) = ext.as_deref() {
@@ -54,7 +65,14 @@ fn read_dir(ui: &mut egui::Ui, io: &dyn AssetIo, path: &Path) {
                         icon::FILE
                     };
 
-                    ui.label(format!("{} {}", icon, path_string));
+                    let label = ui.label(format!("{} {}", icon, path_string));
+
+                    if ext.as_deref() == Some("shader") {
+                        let label = label.interact(egui::Sense::click());
+                        if label.on_hover_text("Double click to open").double_clicked() {
+                            open_shader.send(OpenShaderGraph { path });
+                        }
+                    }
                 }
             }
         }
@@ -17,6 +17,7 @@ pub fn enable_tracing() {
 
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
         .event_format(format)
         .init();
 }
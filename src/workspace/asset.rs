@@ -0,0 +1,480 @@
+use super::graph::Graph;
+use super::{Direction, GraphMaterial, Node, Port, Property, Storage};
+use anyhow::Result;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashMap, HashSet};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Bumped every time the layout of [`ShaderGraph`] changes in a way older readers can't handle.
+pub const SHADER_GRAPH_VERSION: u32 = 1;
+
+/// Sent to open a `.shader` file (relative to the asset folder) in the node tree.
+pub struct OpenShaderGraph {
+    pub path: PathBuf,
+}
+
+#[derive(Error, Debug)]
+pub enum ShaderGraphError {
+    #[error("shader graph version {0} is newer than the supported version {SHADER_GRAPH_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("unknown node type `{0}`")]
+    UnknownNode(String),
+    #[error("link refers to the missing node #{0}")]
+    NodeNotFound(usize),
+    #[error("node #{node} has no {direction:?} port named `{port}`")]
+    PortNotFound {
+        node: usize,
+        direction: Direction,
+        port: String,
+    },
+    #[error("invalid state for node `{kind}`: {error}")]
+    InvalidState { kind: String, error: ron::Error },
+    #[error("input `{}` of node #{} is linked twice", .to.1, .to.0)]
+    DuplicateLink {
+        from: (usize, String),
+        to: (usize, String),
+    },
+    #[error("link from `{}` of node #{} to `{}` of node #{} makes a cycle", .from.1, .from.0, .to.1, .to.0)]
+    CyclicLink {
+        from: (usize, String),
+        to: (usize, String),
+    },
+}
+
+/// Serializable representation of a node graph, stored in `.shader` files.
+///
+/// Nodes are referenced by their index in `nodes` and ports by their label,
+/// so the file does not depend on the slotmap keys of a running editor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5bd4a1c6-2c8e-4a35-9a3e-6f1d0ad0c8a7"]
+pub struct ShaderGraph {
+    pub version: u32,
+    pub nodes: Vec<SerializedNode>,
+    pub links: Vec<SerializedLink>,
+    /// The blackboard, empty for copied nodes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>,
+}
+
+impl Default for ShaderGraph {
+    fn default() -> Self {
+        Self {
+            version: SHADER_GRAPH_VERSION,
+            nodes: Vec::new(),
+            links: Vec::new(),
+            properties: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SerializedNode {
+    /// Name of the node in the creation menu.
+    pub kind: String,
+    pub title: String,
+    pub position: (f32, f32),
+    /// Values of the [`InputDefault`](super::InputDefault) editors, by input port label.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaults: Vec<SerializedDefault>,
+    /// Node specific settings, see [`PreviewBuilder::save`](super::PreviewBuilder::save).
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_state"
+    )]
+    pub state: Option<ron::Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SerializedDefault {
+    pub port: String,
+    pub value: [f64; 4],
+    #[serde(default)]
+    pub checked: bool,
+}
+
+/// Connects the output `from` to the input `to`, both as `(node index, port label)`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SerializedLink {
+    pub from: (usize, String),
+    pub to: (usize, String),
+}
+
+impl ShaderGraph {
+    pub fn from_storage(storage: &Storage) -> Self {
+        Self {
+            properties: storage.properties.clone(),
+            ..Self::from_nodes(storage, storage.nodes.keys())
+        }
+    }
+
+    /// Saves only `keys` and the links between them, as used by the clipboard.
+    pub fn from_nodes(storage: &Storage, keys: impl IntoIterator<Item = Node>) -> Self {
+        let mut index = HashMap::default();
+        let mut nodes = Vec::new();
+
+        for key in keys {
+            let node = &storage.nodes[key];
+            if node.kind.is_empty() {
+                tracing::warn!("skip node {:?} without a type", key);
+                continue;
+            }
+
+            let position = if node.position.is_finite() {
+                (node.position.x, node.position.y)
+            } else {
+                (0.0, 0.0)
+            };
+
+            let defaults = node
+                .inputs
+                .iter()
+                .map(|&port| &storage.ports[port])
+                .filter_map(|port| {
+                    let default = port.input_default.as_ref()?;
+                    Some(SerializedDefault {
+                        port: port.label.clone(),
+                        value: [default.x, default.y, default.z, default.w],
+                        checked: default.checked,
+                    })
+                })
+                .collect();
+
+            index.insert(key, nodes.len());
+            nodes.push(SerializedNode {
+                kind: node.kind.to_string(),
+                title: node.title.clone(),
+                position,
+                defaults,
+                state: node.builder.save(),
+            });
+        }
+
+        let links = storage
+            .links
+            .values()
+            .filter_map(|link| {
+                let min = &storage.ports[link.min.port];
+                let max = &storage.ports[link.max.port];
+                Some(SerializedLink {
+                    from: (*index.get(&link.min.node)?, min.label.clone()),
+                    to: (*index.get(&link.max.node)?, max.label.clone()),
+                })
+            })
+            .collect();
+
+        Self {
+            version: SHADER_GRAPH_VERSION,
+            nodes,
+            links,
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn to_storage(&self) -> Result<Storage, ShaderGraphError> {
+        let mut storage = Storage::default();
+        self.insert_into(&mut storage)?;
+        Ok(storage)
+    }
+
+    /// Spawns the nodes and links of the graph next to the ones already in `storage`.
+    ///
+    /// Returns the new nodes in file order. Nothing is added when an error is returned.
+    pub fn insert_into(&self, storage: &mut Storage) -> Result<Vec<Node>, ShaderGraphError> {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let properties = storage.properties.len();
+        let result = self.insert_impl(storage, &mut nodes);
+        if result.is_err() {
+            for &node in &nodes {
+                storage.despawn(node);
+            }
+            storage.properties.truncate(properties);
+        }
+        result.map(|()| nodes)
+    }
+
+    fn insert_impl(
+        &self,
+        storage: &mut Storage,
+        nodes: &mut Vec<Node>,
+    ) -> Result<(), ShaderGraphError> {
+        if self.version > SHADER_GRAPH_VERSION {
+            return Err(ShaderGraphError::UnsupportedVersion(self.version));
+        }
+
+        // Properties already in the blackboard win over the ones of the file.
+        for property in &self.properties {
+            if !storage.properties.iter().any(|p| p.name == property.name) {
+                storage.properties.push(property.clone());
+            }
+        }
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let key = storage
+                .spawn_kind(&node.kind)
+                .ok_or_else(|| ShaderGraphError::UnknownNode(node.kind.clone()))?;
+            nodes.push(key);
+
+            let data = &mut storage.nodes[key];
+            data.title = node.title.clone();
+            data.position = egui::pos2(node.position.0, node.position.1);
+
+            if let Some(state) = node.state.clone() {
+                data.builder
+                    .load(state)
+                    .map_err(|error| ShaderGraphError::InvalidState {
+                        kind: node.kind.clone(),
+                        error,
+                    })?;
+            }
+
+            // Ports of some nodes depend on the state that was just loaded.
+            storage.sync_ports(key);
+
+            for default in &node.defaults {
+                let port = find_port(storage, key, index, Direction::Input, &default.port)?;
+                if let Some(input_default) = storage.ports[port].input_default.as_mut() {
+                    let [x, y, z, w] = default.value;
+                    input_default.x = x;
+                    input_default.y = y;
+                    input_default.z = z;
+                    input_default.w = w;
+                    input_default.checked = default.checked;
+                }
+            }
+        }
+
+        // Hand-edited files may link an input twice or loop back on themselves.
+        let mut graph = Graph::default();
+        let mut linked = HashSet::default();
+        for link in &self.links {
+            let (from, ref from_label) = link.from;
+            let (to, ref to_label) = link.to;
+
+            let min = *nodes
+                .get(from)
+                .ok_or(ShaderGraphError::NodeNotFound(from))?;
+            let max = *nodes.get(to).ok_or(ShaderGraphError::NodeNotFound(to))?;
+
+            let min = find_port(storage, min, from, Direction::Output, from_label)?;
+            let max = find_port(storage, max, to, Direction::Input, to_label)?;
+
+            if !linked.insert(max) {
+                return Err(ShaderGraphError::DuplicateLink {
+                    from: link.from.clone(),
+                    to: link.to.clone(),
+                });
+            }
+            graph.add_edge(from, to);
+            if graph.reachable(to, from) {
+                return Err(ShaderGraphError::CyclicLink {
+                    from: link.from.clone(),
+                    to: link.to.clone(),
+                });
+            }
+
+            storage.link(min, max);
+        }
+
+        Ok(())
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        crate::scene::asset::serialize_ron(self)
+    }
+
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, ron::error::SpannedError> {
+        ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::all())
+            .from_bytes(bytes)
+    }
+}
+
+fn find_port(
+    storage: &Storage,
+    node: Node,
+    index: usize,
+    direction: Direction,
+    label: &str,
+) -> Result<Port, ShaderGraphError> {
+    let data = &storage.nodes[node];
+    let ports = match direction {
+        Direction::Input => &data.inputs,
+        Direction::Output => &data.outputs,
+    };
+
+    ports
+        .iter()
+        .copied()
+        .find(|&port| storage.ports[port].label == label)
+        .ok_or_else(|| ShaderGraphError::PortNotFound {
+            node: index,
+            direction,
+            port: label.to_string(),
+        })
+}
+
+/// Accepts states with and without `Some`, which the `implicit_some` extension
+/// leaves out and nested graphs never get back once they went through a [`ron::Value`].
+fn deserialize_state<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ron::Value>, D::Error> {
+    Ok(match ron::Value::deserialize(deserializer)? {
+        ron::Value::Option(value) => value.map(|value| *value),
+        value => Some(value),
+    })
+}
+
+/// Converts node settings into the untyped form stored in [`SerializedNode::state`].
+pub fn to_state<T: Serialize>(value: &T) -> Option<ron::Value> {
+    let text = ron::to_string(value).ok()?;
+    ron::from_str(&text).ok()
+}
+
+pub fn from_state<T: DeserializeOwned>(state: ron::Value) -> Result<T, ron::Error> {
+    state.into_rust()
+}
+
+#[derive(Default)]
+pub struct ShaderGraphLoader;
+
+impl AssetLoader for ShaderGraphLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let graph = ShaderGraph::from_ron(bytes)?;
+            let material = GraphMaterial::new(graph.clone());
+            load_context.set_labeled_asset("Material", LoadedAsset::new(material));
+            load_context.set_default_asset(LoadedAsset::new(graph));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["shader"]
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut storage = Storage::default();
+    let color = storage.spawn_kind("Color").unwrap();
+    let flipbook = storage.spawn_kind("Flipbook").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+
+    let state = ron::from_str(r#"{ "flip_x": false, "flip_y": true }"#).unwrap();
+    storage.nodes[flipbook].builder.load(state).unwrap();
+    storage.nodes[flipbook].position = egui::pos2(10.0, 20.0);
+
+    let b = storage.nodes[add].inputs[1];
+    storage.ports[b].input_default.as_mut().unwrap().x = 0.5;
+
+    let out = storage.nodes[color].outputs[0];
+    let a = storage.nodes[add].inputs[0];
+    storage.link(out, a);
+
+    let saved = ShaderGraph::from_storage(&storage);
+    let text = saved.to_ron().unwrap();
+    let loaded = ShaderGraph::from_ron(text.as_bytes()).unwrap();
+    let loaded = loaded.to_storage().unwrap();
+
+    assert_eq!(loaded.nodes.len(), 3);
+    assert_eq!(loaded.links.len(), 1);
+
+    let find = |kind| {
+        loaded
+            .nodes
+            .values()
+            .find(|node| node.kind == kind)
+            .unwrap()
+    };
+
+    let flipbook = find("Flipbook");
+    assert_eq!(flipbook.position, egui::pos2(10.0, 20.0));
+    assert_eq!(flipbook.builder.save(), saved.nodes[1].state);
+
+    let add = find("Add");
+    let b = &loaded.ports[add.inputs[1]];
+    assert_eq!(b.input_default.as_ref().unwrap().x, 0.5);
+    assert_eq!(loaded.ports[add.inputs[0]].links.len(), 1);
+
+    assert!(ShaderGraph::from_ron(b"(version: 2, nodes: [], links: [])")
+        .unwrap()
+        .to_storage()
+        .is_err());
+}
+
+#[test]
+fn copy_selection() {
+    let mut storage = Storage::default();
+    let color = storage.spawn_kind("Color").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+    let negate = storage.spawn_kind("Negate").unwrap();
+
+    let out = storage.nodes[color].outputs[0];
+    let a = storage.nodes[add].inputs[0];
+    storage.link(out, a);
+
+    let out = storage.nodes[add].outputs[0];
+    let input = storage.nodes[negate].inputs[0];
+    storage.link(out, input);
+
+    // The link into `color` is left behind with the unselected node.
+    let copied = ShaderGraph::from_nodes(&storage, [add, negate]);
+    assert_eq!(copied.nodes.len(), 2);
+    assert_eq!(copied.links.len(), 1);
+
+    let text = copied.to_ron().unwrap();
+    let pasted = ShaderGraph::from_ron(text.as_bytes()).unwrap();
+    let nodes = pasted.insert_into(&mut storage).unwrap();
+
+    assert_eq!(storage.nodes.len(), 5);
+    assert_eq!(storage.links.len(), 3);
+    assert!(!nodes.contains(&add) && !nodes.contains(&negate));
+
+    let input = storage.nodes[nodes[1]].inputs[0];
+    let link = storage.ports[input].links.iter().next().unwrap();
+    assert_eq!(storage.links[*link].min.node, nodes[0]);
+
+    let mut broken = pasted;
+    broken.nodes[1].kind = String::from("Nothing");
+    assert!(broken.insert_into(&mut storage).is_err());
+    assert_eq!(storage.nodes.len(), 5);
+}
+
+#[test]
+fn reject_invalid_links() {
+    let mut storage = Storage::default();
+    let add = storage.spawn_kind("Add").unwrap();
+    let negate = storage.spawn_kind("Negate").unwrap();
+    let out = storage.nodes[add].outputs[0];
+    let input = storage.nodes[negate].inputs[0];
+    storage.link(out, input);
+    let saved = ShaderGraph::from_storage(&storage);
+    let link = |from: usize, to: usize, port: &str| SerializedLink {
+        from: (from, String::from("out")),
+        to: (to, String::from(port)),
+    };
+
+    // A second link into the input of Negate.
+    let mut twice = saved.clone();
+    twice.links.push(link(0, 1, "a"));
+    let error = twice.to_storage().err().unwrap();
+    assert!(matches!(error, ShaderGraphError::DuplicateLink { to, .. } if to == (1, "a".into())));
+
+    // Negate back into Add.
+    let mut cyclic = saved.clone();
+    cyclic.links.push(link(1, 0, "a"));
+    let error = cyclic.to_storage().err().unwrap();
+    assert!(
+        matches!(error, ShaderGraphError::CyclicLink { from, .. } if from == (1, "out".into()))
+    );
+
+    assert_eq!(saved.to_storage().unwrap().links.len(), 1);
+}
@@ -0,0 +1,358 @@
+//! Graph properties, bound to shaders as a uniform buffer.
+//!
+//! Every property of the graph is a member of the `Properties` struct, in
+//! blackboard order and laid out with the WGSL rules for uniform buffers:
+//! `f32` is aligned to 4 bytes, `vec2<f32>` to 8 and `vec3<f32>`/`vec4<f32>`
+//! to 16, and the buffer is padded to a multiple of 16 bytes. The struct is
+//! bound right after the resources of the render target of the master, at
+//! `@group(0)`, or `@group(1)` for graph materials. Exports describe it in a
+//! `.layout.ron` file next to the shader.
+
+use super::{Change, Data, Workspace};
+use crate::ui::{EditorTab, Style};
+use bevy::ecs::system::lifetimeless::{SQuery, SRes, Write};
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use egui::style::Margin;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PropertyValue {
+    Float(f32),
+    Vector2([f32; 2]),
+    Vector3([f32; 3]),
+    Vector4([f32; 4]),
+    /// Linear RGBA.
+    Color([f32; 4]),
+}
+
+impl PropertyValue {
+    pub const DEFAULTS: [Self; 5] = [
+        Self::Float(0.0),
+        Self::Vector2([0.0; 2]),
+        Self::Vector3([0.0; 3]),
+        Self::Vector4([0.0, 0.0, 0.0, 1.0]),
+        Self::Color([1.0; 4]),
+    ];
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Float(_) => "Float",
+            Self::Vector2(_) => "Vector2",
+            Self::Vector3(_) => "Vector3",
+            Self::Vector4(_) => "Vector4",
+            Self::Color(_) => "Color",
+        }
+    }
+
+    pub fn data(&self) -> Data {
+        match self {
+            Self::Float(_) => Data::Float,
+            Self::Vector2(_) => Data::Vector2,
+            Self::Vector3(_) => Data::Vector3,
+            Self::Vector4(_) | Self::Color(_) => Data::Vector4,
+        }
+    }
+
+    pub fn components(&self) -> &[f32] {
+        match self {
+            Self::Float(x) => std::slice::from_ref(x),
+            Self::Vector2(v) => v,
+            Self::Vector3(v) => v,
+            Self::Vector4(v) | Self::Color(v) => v,
+        }
+    }
+
+    pub fn wgsl_type(&self) -> &'static str {
+        match self.components().len() {
+            1 => "f32",
+            2 => "vec2<f32>",
+            3 => "vec3<f32>",
+            _ => "vec4<f32>",
+        }
+    }
+
+    /// Size and alignment in a uniform buffer.
+    pub fn size_align(&self) -> (u32, u32) {
+        match self.components().len() {
+            1 => (4, 4),
+            2 => (8, 8),
+            3 => (12, 16),
+            _ => (16, 16),
+        }
+    }
+
+    /// Keeps the components both types have.
+    pub fn convert(&self, to: Self) -> Self {
+        let mut value = to;
+        let src = self.components();
+        let dst = match &mut value {
+            Self::Float(x) => std::slice::from_mut(x),
+            Self::Vector2(v) => v,
+            Self::Vector3(v) => v,
+            Self::Vector4(v) | Self::Color(v) => v,
+        };
+        let len = src.len().min(dst.len());
+        dst[..len].copy_from_slice(&src[..len]);
+        value
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+        fn drag(ui: &mut egui::Ui, values: &mut [f32]) -> egui::Response {
+            let mut response = ui.allocate_response(egui::Vec2::ZERO, egui::Sense::hover());
+            for (value, label) in values.iter_mut().zip(["x ", "y ", "z ", "w "]) {
+                let widget = egui::DragValue::new(value).speed(0.01).prefix(label);
+                response |= ui.add(widget);
+            }
+            response
+        }
+
+        match self {
+            Self::Float(x) => drag(ui, std::slice::from_mut(x)),
+            Self::Vector2(v) => drag(ui, v),
+            Self::Vector3(v) => drag(ui, v),
+            Self::Vector4(v) => drag(ui, v),
+            Self::Color(rgba) => {
+                let [r, g, b, a] = *rgba;
+                let mut color = egui::Rgba::from_rgba_unmultiplied(r, g, b, a);
+                let alpha = egui::color_picker::Alpha::OnlyBlend;
+                let response = egui::color_picker::color_edit_button_rgba(ui, &mut color, alpha);
+                if response.changed() {
+                    *rgba = color.to_rgba_unmultiplied();
+                }
+                response
+            }
+        }
+    }
+}
+
+/// A named value of the graph, read by `Property` nodes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub value: PropertyValue,
+}
+
+/// Offset of every property in the uniform buffer, and the size of the buffer.
+pub fn layout(properties: &[Property]) -> (Vec<u32>, u32) {
+    let mut offsets = Vec::with_capacity(properties.len());
+    let mut end = 0u32;
+    for property in properties {
+        let (size, align) = property.value.size_align();
+        let offset = end.div_ceil(align) * align;
+        offsets.push(offset);
+        end = offset + size;
+    }
+    (offsets, end.div_ceil(16) * 16)
+}
+
+/// Contents of the uniform buffer.
+pub fn uniform_bytes(properties: &[Property]) -> Vec<u8> {
+    let (offsets, size) = layout(properties);
+    let mut bytes = vec![0; size as usize];
+    for (property, offset) in properties.iter().zip(offsets) {
+        for (index, value) in property.value.components().iter().enumerate() {
+            let start = offset as usize + index * 4;
+            bytes[start..start + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Edits the properties of the node trees.
+#[derive(Default, Component)]
+pub struct Blackboard;
+
+impl EditorTab for Blackboard {
+    type Param = (SRes<Style>, SQuery<Write<Workspace>>);
+
+    fn ui<'w>(
+        &mut self,
+        ui: &mut egui::Ui,
+        _entity: Entity,
+        (style, workspaces): &mut SystemParamItem<'w, '_, Self::Param>,
+    ) {
+        let rect = ui.available_rect_before_wrap();
+        ui.painter().rect_filled(rect, 0.0, style.panel);
+
+        ui.scope(|ui| {
+            style.set_theme_visuals(ui);
+            style.for_scrollbar(ui);
+
+            let scroll = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+            scroll.show(ui, |ui| {
+                style.scrollarea(ui);
+
+                egui::Frame::none()
+                    .inner_margin(Margin::same(4.0))
+                    .show(ui, |ui| {
+                        for mut workspace in workspaces.iter_mut() {
+                            properties_ui(ui, &mut workspace);
+                        }
+                    });
+            });
+        });
+    }
+}
+
+/// Edits a copy of the properties, recording the changes in the undo history.
+fn properties_ui(ui: &mut egui::Ui, workspace: &mut Workspace) {
+    let mut properties = workspace.storage.properties.clone();
+    let mut remove = None;
+
+    let mut renamed = None;
+    let names: Vec<_> = properties.iter().map(|p| p.name.clone()).collect();
+
+    for (index, property) in properties.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("❌").on_hover_text("Remove").clicked() {
+                    remove = Some(index);
+                }
+
+                egui::ComboBox::from_id_source("type")
+                    .selected_text(property.value.type_name())
+                    .width(80.0)
+                    .show_ui(ui, |ui| {
+                        for value in PropertyValue::DEFAULTS {
+                            let selected = property.value.type_name() == value.type_name();
+                            if ui.selectable_label(selected, value.type_name()).clicked() {
+                                property.value = property.value.convert(value);
+                            }
+                        }
+                    });
+
+                // Names stay unique, property nodes find their value by name.
+                let mut name = property.name.clone();
+                let edit = egui::TextEdit::singleline(&mut name).desired_width(100.0);
+                let changed = ui.add(edit).changed();
+                if changed && !name.is_empty() && !names.contains(&name) {
+                    renamed = Some((property.name.clone(), name.clone()));
+                    property.name = name;
+                }
+                property.value.ui(ui);
+            });
+        });
+    }
+
+    if let Some((old, new)) = renamed {
+        workspace.edit(Change::RenameProperty { old, new });
+    }
+
+    if let Some(index) = remove {
+        properties.remove(index);
+    }
+
+    if ui.button("➕ Add property").clicked() {
+        let mut name = String::from("property");
+        for index in 1.. {
+            if !properties.iter().any(|p| p.name == name) {
+                break;
+            }
+            name = format!("property_{}", index);
+        }
+        properties.push(Property {
+            name,
+            value: PropertyValue::Float(0.0),
+        });
+    }
+
+    let before = &workspace.storage.properties;
+    if properties == *before {
+        return;
+    }
+
+    // New values only have to be written to the buffer of the previews.
+    let same_layout = properties.len() == before.len()
+        && properties
+            .iter()
+            .zip(before)
+            .all(|(a, b)| a.name == b.name && a.value.type_name() == b.value.type_name());
+    let dirty = workspace.dirty;
+    let before = before.clone();
+    workspace.edit(Change::SetProperties {
+        before,
+        after: properties,
+    });
+    if same_layout {
+        workspace.dirty = dirty;
+        workspace.properties_changed = true;
+    }
+}
+
+#[test]
+fn bind_properties() {
+    use super::nodes::input::PropertyNode;
+    use super::Storage;
+
+    let property = |name: &str, value| Property {
+        name: name.to_string(),
+        value,
+    };
+
+    let mut storage = Storage {
+        properties: vec![
+            property("a", PropertyValue::Float(1.0)),
+            property("b", PropertyValue::Vector3([2.0, 3.0, 4.0])),
+            property("c", PropertyValue::Vector2([5.0, 6.0])),
+            property("d", PropertyValue::Color([7.0, 8.0, 9.0, 1.0])),
+        ],
+        ..Default::default()
+    };
+
+    let (offsets, size) = layout(&storage.properties);
+    assert_eq!(offsets, [0, 16, 32, 48]);
+    assert_eq!(size, 64);
+
+    let bytes = uniform_bytes(&storage.properties);
+    assert_eq!(bytes.len(), 64);
+    assert_eq!(bytes[16..20], 2.0f32.to_le_bytes());
+    assert_eq!(bytes[56..60], 9.0f32.to_le_bytes());
+
+    let master = storage.spawn_kind("Master").unwrap();
+    let d = storage.properties[3].clone();
+    let node = PropertyNode::spawn_property(&mut storage, &d);
+    let output = storage.nodes[node].outputs[0];
+    assert_eq!(storage.ports[output].label, "d");
+    assert_eq!(storage.ports[output].data, Data::Vector4);
+    storage.link(output, storage.nodes[master].inputs[1]);
+
+    let module = storage.nodes[master]
+        .builder
+        .module(master, &storage)
+        .unwrap();
+    let source = module.build().unwrap();
+    assert!(source.contains("var<uniform> properties: Properties;"));
+
+    // The generated struct matches the documented layout.
+    let (_, var) = module.resources[0];
+    let ty = module.module.global_variables[var].ty;
+    match module.module.types[ty].inner {
+        naga::TypeInner::Struct {
+            ref members, span, ..
+        } => {
+            let struct_offsets: Vec<_> = members.iter().map(|m| m.offset).collect();
+            assert_eq!(struct_offsets, offsets);
+            assert_eq!(span, size);
+        }
+        _ => panic!("properties aren't a struct"),
+    }
+    assert!(module
+        .binding_layout()
+        .to_ron()
+        .unwrap()
+        .contains("offset: 48"));
+
+    // Nodes follow renames and type changes.
+    storage.rename_property("d", "tint");
+    assert_eq!(storage.properties[3].name, "tint");
+    storage.properties[3].value = PropertyValue::Float(0.5);
+    storage.sync_properties();
+    let output = storage.nodes[node].outputs[0];
+    assert_eq!(storage.ports[output].label, "tint");
+    assert_eq!(storage.ports[output].data, Data::Float);
+
+    storage.properties.pop();
+    let mut module = super::builder::ModuleBuilder::new(&storage, Default::default());
+    assert!(module.function().property("tint").is_err());
+}
@@ -1,18 +1,35 @@
+use crate::workspace::blackboard::Property;
+use crate::workspace::material::expr_mesh_model;
 use crate::workspace::{Node, Port, Storage};
 use ahash::AHashMap;
 use naga::front::Typifier;
-use naga::valid::{Capabilities, Validator};
+use naga::valid::{
+    Capabilities, EntryPointError, FunctionError, ModuleInfo, ValidationError, Validator,
+};
 use naga::{
-    Binding, BuiltIn, Constant, EntryPoint, Expression, Function, FunctionArgument, FunctionResult,
-    GlobalVariable, Handle, Interpolation, LocalVariable, Module, Sampling, Span, Statement,
-    StructMember, Type, TypeInner,
+    AddressSpace, ArraySize, Binding, BuiltIn, Constant, EntryPoint, Expression, Function,
+    FunctionArgument, FunctionResult, GlobalVariable, Handle, ImageClass, ImageDimension,
+    LocalVariable, Module, ResourceBinding, ShaderStage, Span, Statement, StorageAccess,
+    StorageFormat, StructMember, Type, TypeInner, VectorSize,
 };
 
-pub use self::expr::{Emit, EmitError, EmitResult};
+pub use self::export::{BindingLayout, ExportTarget, ExportedShader};
+pub use self::expr::{Emit, EmitError, EmitResult, ErrorLocation};
+pub use self::optimize::{Pass, PassReport};
+pub use self::resource::{
+    filter_resources, Filter, Resource, SamplerState, TextureDimension, WrapMode, CAMERA_MEMBERS,
+    TIME_MEMBERS, TRANSFORM_MEMBERS,
+};
 pub use self::types::*;
+pub use self::varying::{Attribute, InvocationId, Varying};
 
+pub mod export;
 pub mod expr;
+pub mod merge;
+pub mod optimize;
+pub mod resource;
 pub mod types;
+pub mod varying;
 
 pub trait OutputBuilder: downcast_rs::Downcast {
     fn expr(&self, function: &mut FnBuilder, output: Port) -> EmitResult;
@@ -23,11 +40,38 @@ downcast_rs::impl_downcast!(OutputBuilder);
 pub struct ModuleBuilder<'storage> {
     pub storage: &'storage Storage,
     pub module: Module,
+    /// Nodes behind the names given by [`FnBuilder::named_expr`].
+    names: AHashMap<String, Node>,
+    /// Globals bound to `@group(group)`, the binding being the index.
+    pub resources: Vec<(Resource, Handle<GlobalVariable>)>,
+    /// Bind group of the resources, 0 unless bevy binds its own groups first.
+    pub group: u32,
+    /// The model matrix is read from bevy's mesh uniform instead of [`Resource::Transform`].
+    pub mesh_model: bool,
+    /// Members of the struct bound by [`Resource::Properties`].
+    pub properties: Vec<Property>,
+    /// Entry point being built, `None` for the functions of sub-graphs.
+    pub stage: Option<ShaderStage>,
+    /// Read by `fs_main`, the location being the index.
+    pub varyings: Vec<Varying>,
+    /// Read by `vs_main`.
+    pub attributes: Vec<Attribute>,
 }
 
 impl<'storage> ModuleBuilder<'storage> {
-    pub const fn new(storage: &'storage Storage, module: Module) -> Self {
-        Self { storage, module }
+    pub fn new(storage: &'storage Storage, module: Module) -> Self {
+        Self {
+            storage,
+            module,
+            names: AHashMap::default(),
+            resources: Vec::new(),
+            group: 0,
+            mesh_model: false,
+            properties: storage.properties.clone(),
+            stage: None,
+            varyings: Vec::new(),
+            attributes: Vec::new(),
+        }
     }
 
     pub fn from_wgsl(
@@ -37,25 +81,71 @@ impl<'storage> ModuleBuilder<'storage> {
         Ok(Self::new(storage, naga::front::wgsl::parse_str(source)?))
     }
 
-    /*
-    pub fn merge_str(&mut self, source: &str) -> Result<(), naga::front::wgsl::ParseError> {
+    /// Makes the functions of a WGSL source callable, with names prefixed by `prefix`.
+    pub fn merge_str(
+        &mut self,
+        source: &str,
+        prefix: &str,
+    ) -> Result<(), naga::front::wgsl::ParseError> {
         let src = naga::front::wgsl::parse_str(source)?;
-        self::merge::merge_module(&mut self.module, &src);
+        self::merge::merge_module(&mut self.module, src, prefix);
         Ok(())
     }
-    */
 
-    pub fn build(&self) -> EmitResult<String> {
+    pub fn validate(&self) -> EmitResult<ModuleInfo> {
         let mut validator = Validator::new(Default::default(), Capabilities::PRIMITIVE_INDEX);
-        let info = validator.validate(&self.module);
+        validator.validate(&self.module).map_err(|err| {
+            let node = self.locate(&err.clone().into_inner());
+            let err = EmitError::from(err);
+            match node {
+                Some(node) => err.at(node),
+                None => err,
+            }
+        })
+    }
 
-        if let Err(err) = info.as_ref() {
-            //println!("validate: {}\n{:#?}\n{:#?}", err, err, self.module);
-            println!("validate: {}\n{:#?}", err, err);
-        }
+    /// Finds the node that emitted the expression a validation error is about.
+    ///
+    /// Nodes emit their inputs and temporaries before their result, so an unnamed
+    /// expression is attributed to the first named expression that follows it.
+    fn locate(&self, error: &ValidationError) -> Option<Node> {
+        let (function, error) = match error {
+            ValidationError::Function { handle, error, .. } => {
+                (&self.module.functions[*handle], error)
+            }
+            ValidationError::EntryPoint {
+                name,
+                error: EntryPointError::Function(error),
+                ..
+            } => {
+                let entry = self.module.entry_points.iter().find(|e| &e.name == name)?;
+                (&entry.function, error)
+            }
+            _ => return None,
+        };
+
+        let expr = match *error {
+            FunctionError::Expression { handle, .. }
+            | FunctionError::InvalidReturnType(Some(handle))
+            | FunctionError::InvalidIfType(handle)
+            | FunctionError::InvalidStoreValue(handle)
+            | FunctionError::InvalidStoreTypes { value: handle, .. } => handle,
+            _ => return None,
+        };
+
+        function
+            .named_expressions
+            .iter()
+            .filter(|(handle, _)| handle.index() >= expr.index())
+            .min_by_key(|(handle, _)| handle.index())
+            .and_then(|(_, name)| self.names.get(name).copied())
+    }
+
+    pub fn build(&self) -> EmitResult<String> {
+        let info = self.validate()?;
 
         let flags = naga::back::wgsl::WriterFlags::EXPLICIT_TYPES;
-        Ok(naga::back::wgsl::write_string(&self.module, &info?, flags)?)
+        Ok(naga::back::wgsl::write_string(&self.module, &info, flags)?)
     }
 
     pub fn insert_type(&mut self, ty: Type) -> Handle<Type> {
@@ -70,10 +160,171 @@ impl<'storage> ModuleBuilder<'storage> {
         self.module.global_variables.append(var, Span::default())
     }
 
+    /// Global variable bound to `resource`, shared by every node using the same one.
+    pub fn bind(&mut self, resource: Resource) -> Handle<GlobalVariable> {
+        if let Some((_, var)) = self.resources.iter().find(|(r, _)| *r == resource) {
+            return *var;
+        }
+
+        let binding = self.resources.len() as u32;
+        let (name, space, ty) = match &resource {
+            Resource::Texture { dimension, .. } => {
+                let ty = self.insert_type(dimension.naga_type());
+                (format!("texture_{}", binding), AddressSpace::Handle, ty)
+            }
+            Resource::SceneColor | Resource::SceneDepth | Resource::MainTexture => {
+                let ty = self.insert_type(TextureDimension::D2.naga_type());
+                let name = match resource {
+                    Resource::SceneColor => "scene_color",
+                    Resource::SceneDepth => "scene_depth",
+                    _ => "main_texture",
+                };
+                (String::from(name), AddressSpace::Handle, ty)
+            }
+            Resource::Sampler(_) => {
+                let ty = self.insert_type(Type {
+                    name: None,
+                    inner: TypeInner::Sampler { comparison: false },
+                });
+                (format!("sampler_{}", binding), AddressSpace::Handle, ty)
+            }
+            Resource::Properties => {
+                let ty = self.properties_type();
+                (String::from("properties"), AddressSpace::Uniform, ty)
+            }
+            Resource::Time => {
+                let f32 = BaseTypes::new(self).f32;
+                let mut builder = StructBuilder::new(&mut self.module, "Time");
+                for name in TIME_MEMBERS {
+                    builder = builder.uniform(name, f32);
+                }
+                (String::from("time"), AddressSpace::Uniform, builder.build())
+            }
+            Resource::Camera => {
+                let ty = BaseTypes::new(self);
+                let mut builder = StructBuilder::new(&mut self.module, "Camera")
+                    .uniform(CAMERA_MEMBERS[0], ty.f32x4x4)
+                    .uniform(CAMERA_MEMBERS[1], ty.f32x3);
+                builder.span = builder.span.div_ceil(16) * 16;
+                (
+                    String::from("camera"),
+                    AddressSpace::Uniform,
+                    builder.build(),
+                )
+            }
+            Resource::Transform => {
+                let f32x4x4 = BaseTypes::new(self).f32x4x4;
+                let mut builder = StructBuilder::new(&mut self.module, "Transform");
+                for name in TRANSFORM_MEMBERS {
+                    builder = builder.uniform(name, f32x4x4);
+                }
+                (
+                    String::from("transform"),
+                    AddressSpace::Uniform,
+                    builder.build(),
+                )
+            }
+            Resource::Tint => {
+                let f32x4 = BaseTypes::new(self).f32x4;
+                let builder = StructBuilder::new(&mut self.module, "Tint").uniform("color", f32x4);
+                (String::from("tint"), AddressSpace::Uniform, builder.build())
+            }
+            Resource::Viewport => {
+                let f32x2 = BaseTypes::new(self).f32x2;
+                let mut builder =
+                    StructBuilder::new(&mut self.module, "Viewport").uniform("size", f32x2);
+                builder.span = builder.span.div_ceil(16) * 16;
+                (
+                    String::from("viewport"),
+                    AddressSpace::Uniform,
+                    builder.build(),
+                )
+            }
+            Resource::StorageTexture => {
+                let ty = self.insert_type(Type {
+                    name: None,
+                    inner: TypeInner::Image {
+                        dim: ImageDimension::D2,
+                        arrayed: false,
+                        class: ImageClass::Storage {
+                            format: StorageFormat::Rgba8Unorm,
+                            access: StorageAccess::STORE,
+                        },
+                    },
+                });
+                (String::from("output_texture"), AddressSpace::Handle, ty)
+            }
+            Resource::StorageBuffer { writable } => {
+                let f32x4 = BaseTypes::new(self).f32x4;
+                let ty = self.insert_type(Type {
+                    name: None,
+                    inner: TypeInner::Array {
+                        base: f32x4,
+                        size: ArraySize::Dynamic,
+                        stride: 16,
+                    },
+                });
+                let (name, access) = if *writable {
+                    ("output_buffer", StorageAccess::LOAD | StorageAccess::STORE)
+                } else {
+                    ("input_buffer", StorageAccess::LOAD)
+                };
+                (String::from(name), AddressSpace::Storage { access }, ty)
+            }
+        };
+        let var = self.global_variable(GlobalVariable {
+            name: Some(name),
+            space,
+            binding: Some(ResourceBinding {
+                group: self.group,
+                binding,
+            }),
+            ty,
+            init: None,
+        });
+        self.resources.push((resource, var));
+        var
+    }
+
+    /// The `Properties` struct, laid out as described in [`crate::workspace::blackboard`].
+    fn properties_type(&mut self) -> Handle<Type> {
+        let ty = BaseTypes::new(self);
+        let types: Vec<_> = self
+            .properties
+            .iter()
+            .map(|property| match property.value.components().len() {
+                1 => ty.f32,
+                2 => ty.f32x2,
+                3 => ty.f32x3,
+                _ => ty.f32x4,
+            })
+            .collect();
+
+        let mut builder = StructBuilder::new(&mut self.module, "Properties");
+        for (property, ty) in self.properties.iter().zip(types) {
+            // Names are unique in the blackboard, but not in files edited by hand.
+            let name = match builder.has(&property.name) {
+                true => format!("{}_{}", property.name, builder.members.len()),
+                false => property.name.clone(),
+            };
+            builder = builder.uniform(name, ty);
+        }
+        builder.span = (builder.span + 15) & !15;
+        builder.build()
+    }
+
     pub fn append_function(&mut self, function: Function) -> Handle<Function> {
         self.module.functions.append(function, Span::default())
     }
 
+    /// Appends a function built from another storage, blaming `node` for its errors.
+    pub fn append_foreign_function(&mut self, node: Node, function: Function) -> Handle<Function> {
+        for name in function.named_expressions.values() {
+            self.names.insert(name.clone(), node);
+        }
+        self.append_function(function)
+    }
+
     pub fn push_entry_point(&mut self, entry: EntryPoint) {
         self.module.entry_points.push(entry)
     }
@@ -89,6 +340,7 @@ impl<'storage> ModuleBuilder<'storage> {
             module: self,
             function: Function::default(),
             typifier: Typifier::default(),
+            parameters: AHashMap::default(),
             cache: AHashMap::default(),
         }
     }
@@ -98,6 +350,8 @@ pub struct FnBuilder<'a, 'storage> {
     pub module: &'a mut ModuleBuilder<'storage>,
     pub function: Function,
     pub typifier: Typifier,
+    /// Sub-graph inputs bound to the arguments of the function being built.
+    pub parameters: AHashMap<Node, u32>,
     cache: AHashMap<Port, Handle<Expression>>,
 }
 
@@ -185,6 +439,18 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
         self.for_input_resolve(input, VectorKind::V4)
     }
 
+    /// Reads a matrix input as a `dst` matrix, see [`Self::resolve_matrix`].
+    pub fn for_input_matrix(&mut self, input: Port, dst: MatrixKind) -> EmitResult {
+        let expr = self.for_input(input)?;
+        let src = MatrixKind::parse(self.extract_type(expr)?).ok_or(EmitError::FailType)?;
+        self.resolve_matrix(expr, src, dst)
+    }
+
+    pub fn is_linked(&self, input: Port) -> bool {
+        let mut links = self.module.storage.links.values();
+        links.any(|link| link.output_for(input).is_some())
+    }
+
     pub fn for_input(&mut self, input: Port) -> EmitResult {
         assert!(self.module.storage.ports[input].direction.is_input());
 
@@ -198,11 +464,20 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
         let output = match output {
             Some(output) => output,
             None => {
-                return self.module.storage.ports[input]
+                let storage = self.module.storage;
+                let port = &storage.ports[input];
+                // Matrices have no editable default, unlinked they are the identity.
+                if port.input_default.is_none() {
+                    if let Some(kind) = MatrixKind::from_data(port.data) {
+                        return kind.identity(self);
+                    }
+                }
+                return port
                     .input_default
                     .as_ref()
                     .ok_or(EmitError::MaybeDefault)
-                    .and_then(|def| def.emit(self));
+                    .and_then(|def| def.emit(self))
+                    .map_err(|err| err.at(input));
             }
         };
 
@@ -212,7 +487,17 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
             let node = self.module.storage.ports[output].node;
             let expr = self.module.storage.nodes[node]
                 .builder
-                .output_expr(node, self, output)?;
+                .output_expr(node, self, output)
+                .map_err(|err| err.at(node))?;
+
+            // Lets validation errors find their way back to the node.
+            let is_pointer = matches!(
+                self.extract_type(expr),
+                Ok(TypeInner::Pointer { .. } | TypeInner::ValuePointer { .. })
+            );
+            if !is_pointer && !self.function.named_expressions.contains_key(&expr) {
+                self.named_expr(node, expr);
+            }
 
             self.cache.insert(output, expr);
             expr
@@ -227,6 +512,50 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
             .find_map(|(handle, f)| (f.name.as_deref() == Some(name)).then(|| handle))
     }
 
+    /// Reads the global bound to `resource`.
+    pub fn resource(&mut self, resource: Resource) -> Handle<Expression> {
+        let var = self.module.bind(resource);
+        self.expression(Expression::GlobalVariable(var))
+    }
+
+    /// Loads a property of the blackboard from the uniform buffer.
+    pub fn property(&mut self, name: &str) -> EmitResult {
+        let index = self
+            .module
+            .properties
+            .iter()
+            .position(|property| property.name == name)
+            .ok_or_else(|| EmitError::UnknownProperty(name.to_string()))?;
+
+        let properties = self.resource(Resource::Properties);
+        let pointer = self.access_index(properties, index as u32);
+        Ok(self.emit(Expression::Load { pointer }))
+    }
+
+    /// Loads a member of the clock, indexing [`TIME_MEMBERS`].
+    pub fn time(&mut self, member: u32) -> EmitResult {
+        let time = self.resource(Resource::Time);
+        let pointer = self.access_index(time, member);
+        Ok(self.emit(Expression::Load { pointer }))
+    }
+
+    /// Loads a member of the preview camera, indexing [`CAMERA_MEMBERS`].
+    pub fn camera(&mut self, member: u32) -> EmitResult {
+        let camera = self.resource(Resource::Camera);
+        let pointer = self.access_index(camera, member);
+        Ok(self.emit(Expression::Load { pointer }))
+    }
+
+    /// Loads one of the transformation matrices, indexing [`TRANSFORM_MEMBERS`].
+    pub fn transform(&mut self, member: u32) -> EmitResult {
+        if member == 0 && self.module.mesh_model {
+            return Ok(expr_mesh_model(self));
+        }
+        let transform = self.resource(Resource::Transform);
+        let pointer = self.access_index(transform, member);
+        Ok(self.emit(Expression::Load { pointer }))
+    }
+
     pub fn access_index(&mut self, base: Handle<Expression>, index: u32) -> Handle<Expression> {
         self.emit(Expression::AccessIndex { base, index })
     }
@@ -252,6 +581,7 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
             .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
         let key = <Node as slotmap::Key>::data(&node);
         let name = format!("_e{}_{}{:?}", expr.index(), prefix, key);
+        self.module.names.insert(name.clone(), node);
         self.function.named_expressions.insert(expr, name);
     }
 }
@@ -281,13 +611,8 @@ impl<'a> StructBuilder<'a> {
         self.append(name, ty, Some(Binding::BuiltIn(builtin)))
     }
 
-    pub fn interpolator(self, name: impl Into<String>, ty: Handle<Type>, loc: u32) -> Self {
-        let binding = Binding::Location {
-            location: loc,
-            interpolation: Some(Interpolation::Perspective),
-            sampling: Some(Sampling::Center),
-        };
-        self.append(name, ty, binding)
+    pub fn interpolator(self, varying: Varying, ty: Handle<Type>, loc: u32) -> Self {
+        self.append(varying.name(), ty, varying.binding(loc))
     }
 
     pub fn append(
@@ -314,6 +639,33 @@ impl<'a> StructBuilder<'a> {
         self
     }
 
+    /// Appends a member with the alignment WGSL requires in uniform buffers.
+    pub fn uniform(mut self, name: impl Into<String>, ty: Handle<Type>) -> Self {
+        let name = name.into();
+        assert!(!self.has(&name));
+
+        let ty_inner = &self.module.types[ty].inner;
+        let size = ty_inner.size(&self.module.constants);
+        let align = match *ty_inner {
+            TypeInner::Scalar { .. } => 4,
+            TypeInner::Vector {
+                size: VectorSize::Bi,
+                ..
+            } => 8,
+            _ => 16,
+        };
+
+        let offset = self.span.div_ceil(align) * align;
+        self.members.push(StructMember {
+            name: Some(name),
+            ty,
+            binding: None,
+            offset,
+        });
+        self.span = offset + size;
+        self
+    }
+
     pub fn build(self) -> Handle<Type> {
         let Self {
             module,
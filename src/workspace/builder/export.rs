@@ -0,0 +1,323 @@
+use super::{EmitError, EmitResult, ModuleBuilder, Resource, SamplerState, TextureDimension};
+use crate::workspace::blackboard::{self, PropertyValue};
+use naga::back::{glsl, hlsl, msl, spv, wgsl};
+use naga::ShaderStage;
+use serde::Serialize;
+
+/// Shading languages a validated module can be written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportTarget {
+    Wgsl,
+    /// GLSL 330 core.
+    Glsl,
+    /// GLSL ES 300, as accepted by WebGL2.
+    GlslEs,
+    SpirV,
+    Hlsl,
+    Msl,
+}
+
+impl ExportTarget {
+    pub const ALL: [Self; 6] = [
+        Self::Wgsl,
+        Self::Glsl,
+        Self::GlslEs,
+        Self::SpirV,
+        Self::Hlsl,
+        Self::Msl,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Wgsl => "WGSL",
+            Self::Glsl => "GLSL 330",
+            Self::GlslEs => "GLSL ES 300",
+            Self::SpirV => "SPIR-V",
+            Self::Hlsl => "HLSL",
+            Self::Msl => "MSL",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wgsl => "wgsl",
+            Self::Glsl => "glsl",
+            Self::GlslEs => "glsl-es",
+            Self::SpirV => "spirv",
+            Self::Hlsl => "hlsl",
+            Self::Msl => "msl",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wgsl => "wgsl",
+            Self::Glsl | Self::GlslEs => "glsl",
+            Self::SpirV => "spv",
+            Self::Hlsl => "hlsl",
+            Self::Msl => "metal",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|target| target.name() == name)
+    }
+
+    pub fn is_binary(self) -> bool {
+        matches!(self, Self::SpirV)
+    }
+}
+
+pub struct ExportedShader {
+    /// Set for targets that need a separate shader per entry point.
+    pub stage: Option<ShaderStage>,
+    pub data: Vec<u8>,
+}
+
+impl ExportedShader {
+    /// `stem.vert.glsl` for per stage shaders, `stem.wgsl` otherwise.
+    pub fn file_name(&self, stem: &str, target: ExportTarget) -> String {
+        match self.stage {
+            Some(stage) => format!("{}.{}.{}", stem, stage_suffix(stage), target.extension()),
+            None => format!("{}.{}", stem, target.extension()),
+        }
+    }
+}
+
+/// Resources an exported shader binds at `@group(0)`, written next to it as
+/// `<stem>.layout.ron` for the code that creates its bind group.
+#[derive(Debug, Serialize)]
+pub struct BindingLayout {
+    pub bindings: Vec<LayoutBinding>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum LayoutBinding {
+    /// Uniform buffer with the properties of the blackboard, bound right after
+    /// the resources of the render target of the master.
+    Properties {
+        binding: u32,
+        /// Size of the buffer in bytes.
+        size: u32,
+        members: Vec<LayoutMember>,
+    },
+    /// Uniform buffer with the clock, `time`, `sin_time`, `cos_time` and
+    /// `delta_time` as consecutive `f32`s in seconds.
+    Time {
+        binding: u32,
+    },
+    /// Uniform buffer with the `view_proj` matrix and `light` direction of
+    /// the mesh preview, only bound by previews.
+    Camera {
+        binding: u32,
+    },
+    /// Uniform buffer with the `model`, `view` and `projection` matrices of
+    /// the drawn mesh, as consecutive `mat4x4<f32>`s.
+    Transform {
+        binding: u32,
+    },
+    /// Color of the rendered scene, for post-processing.
+    SceneColor {
+        binding: u32,
+    },
+    /// Linear depth of the rendered scene in an `R32Float` texture.
+    SceneDepth {
+        binding: u32,
+    },
+    /// Texture of the sprite or widget being drawn.
+    MainTexture {
+        binding: u32,
+    },
+    /// Uniform buffer with the `vec4<f32>` color multiplying a sprite.
+    Tint {
+        binding: u32,
+    },
+    /// Uniform buffer with the `vec2<f32>` size of the render target in
+    /// pixels, padded to 16 bytes.
+    Viewport {
+        binding: u32,
+    },
+    /// Write-only `rgba8unorm` texture written by a compute master.
+    StorageTexture {
+        binding: u32,
+    },
+    /// Storage buffer of `vec4<f32>`, one element per texel of the storage
+    /// texture, row by row.
+    StorageBuffer {
+        binding: u32,
+        writable: bool,
+    },
+    /// Image of the asset folder, with float samples.
+    Texture {
+        binding: u32,
+        path: String,
+        dimension: TextureDimension,
+    },
+    Sampler {
+        binding: u32,
+        state: SamplerState,
+    },
+}
+
+/// A property of the uniform buffer, written as little endian `f32`s at `offset`.
+#[derive(Debug, Serialize)]
+pub struct LayoutMember {
+    pub name: String,
+    /// WGSL type of the member.
+    pub ty: &'static str,
+    pub offset: u32,
+    pub size: u32,
+    pub default: PropertyValue,
+}
+
+impl BindingLayout {
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        crate::scene::asset::serialize_ron(self)
+    }
+}
+
+pub fn stage_suffix(stage: ShaderStage) -> &'static str {
+    match stage {
+        ShaderStage::Vertex => "vert",
+        ShaderStage::Fragment => "frag",
+        ShaderStage::Compute => "comp",
+    }
+}
+
+impl<'storage> ModuleBuilder<'storage> {
+    pub fn binding_layout(&self) -> BindingLayout {
+        let bindings = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(binding, (resource, _))| {
+                let binding = binding as u32;
+                match resource {
+                    Resource::Properties => {
+                        let (offsets, size) = blackboard::layout(&self.properties);
+                        let members = self
+                            .properties
+                            .iter()
+                            .zip(offsets)
+                            .map(|(property, offset)| LayoutMember {
+                                name: property.name.clone(),
+                                ty: property.value.wgsl_type(),
+                                offset,
+                                size: property.value.size_align().0,
+                                default: property.value,
+                            })
+                            .collect();
+                        LayoutBinding::Properties {
+                            binding,
+                            size,
+                            members,
+                        }
+                    }
+                    Resource::Time => LayoutBinding::Time { binding },
+                    Resource::Camera => LayoutBinding::Camera { binding },
+                    Resource::Transform => LayoutBinding::Transform { binding },
+                    Resource::SceneColor => LayoutBinding::SceneColor { binding },
+                    Resource::SceneDepth => LayoutBinding::SceneDepth { binding },
+                    Resource::MainTexture => LayoutBinding::MainTexture { binding },
+                    Resource::Tint => LayoutBinding::Tint { binding },
+                    Resource::Viewport => LayoutBinding::Viewport { binding },
+                    Resource::StorageTexture => LayoutBinding::StorageTexture { binding },
+                    &Resource::StorageBuffer { writable } => {
+                        LayoutBinding::StorageBuffer { binding, writable }
+                    }
+                    Resource::Texture { path, dimension } => LayoutBinding::Texture {
+                        binding,
+                        path: path.clone(),
+                        dimension: *dimension,
+                    },
+                    Resource::Sampler(state) => LayoutBinding::Sampler {
+                        binding,
+                        state: *state,
+                    },
+                }
+            })
+            .collect();
+
+        BindingLayout { bindings }
+    }
+
+    pub fn export(&self, target: ExportTarget) -> EmitResult<Vec<ExportedShader>> {
+        let info = self.validate()?;
+        let module = &self.module;
+
+        let single = |data: Vec<u8>| vec![ExportedShader { stage: None, data }];
+
+        Ok(match target {
+            ExportTarget::Wgsl => {
+                let flags = wgsl::WriterFlags::EXPLICIT_TYPES;
+                single(wgsl::write_string(module, &info, flags)?.into_bytes())
+            }
+            ExportTarget::Glsl | ExportTarget::GlslEs => {
+                let version = if target == ExportTarget::GlslEs {
+                    glsl::Version::Embedded {
+                        version: 300,
+                        is_webgl: true,
+                    }
+                } else {
+                    glsl::Version::Desktop(330)
+                };
+
+                let options = glsl::Options {
+                    version,
+                    ..Default::default()
+                };
+
+                let mut shaders = Vec::new();
+                for entry in &module.entry_points {
+                    let pipeline_options = glsl::PipelineOptions {
+                        shader_stage: entry.stage,
+                        entry_point: entry.name.clone(),
+                        multiview: None,
+                    };
+
+                    let mut source = String::new();
+                    let policies = naga::proc::BoundsCheckPolicies::default();
+                    glsl::Writer::new(
+                        &mut source,
+                        module,
+                        &info,
+                        &options,
+                        &pipeline_options,
+                        policies,
+                    )
+                    .and_then(|mut writer| writer.write())
+                    .map_err(EmitError::Glsl)?;
+
+                    shaders.push(ExportedShader {
+                        stage: Some(entry.stage),
+                        data: source.into_bytes(),
+                    });
+                }
+                shaders
+            }
+            ExportTarget::SpirV => {
+                let words = spv::write_vec(module, &info, &spv::Options::default(), None)
+                    .map_err(EmitError::SpirV)?;
+                single(words.into_iter().flat_map(u32::to_le_bytes).collect())
+            }
+            ExportTarget::Hlsl => {
+                let mut source = String::new();
+                hlsl::Writer::new(&mut source, &hlsl::Options::default())
+                    .write(module, &info)
+                    .map_err(EmitError::Hlsl)?;
+                single(source.into_bytes())
+            }
+            ExportTarget::Msl => {
+                let options = msl::Options::default();
+                let pipeline_options = msl::PipelineOptions::default();
+                let (source, _) = msl::write_string(module, &info, &options, &pipeline_options)
+                    .map_err(EmitError::Msl)?;
+                single(source.into_bytes())
+            }
+        })
+    }
+}
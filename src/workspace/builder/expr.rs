@@ -1,21 +1,127 @@
 use super::FnBuilder;
+use crate::workspace::{Node, Port};
 use naga::{
     BinaryOperator, Constant, ConstantInner, Expression, Handle, MathFunction, ScalarKind,
     ScalarValue, Type, TypeInner, UnaryOperator, VectorSize,
 };
+use thiserror::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum EmitError {
+    #[error(transparent)]
+    At(Located),
+    #[error("port not found")]
     PortNotFound,
+    #[error("input is not linked and has no default value")]
     MaybeDefault,
+    #[error("unexpected type")]
     FailType,
-    Resolve(naga::proc::ResolveError),
-    Validation(naga::WithSpan<naga::valid::ValidationError>),
-    Wgsl(naga::back::wgsl::Error),
+    #[error("failed to load texture `{0}`")]
+    TextureLoad(String),
+    #[error("texture `{0}` can't be split into {1} layers")]
+    TextureLayers(String, u32),
+    #[error("invalid custom function: {0}")]
+    CustomFunction(String),
+    #[error("`{0}` is only available to entry points, not inside sub-graphs")]
+    NotInEntryPoint(&'static str),
+    #[error("`{0}` is only available to fragment shaders")]
+    FragmentOnly(&'static str),
+    #[error("`{0}` is only available to compute shaders")]
+    ComputeOnly(&'static str),
+    #[error("no property named `{0}` in the blackboard")]
+    UnknownProperty(String),
+    #[error("failed to resolve type")]
+    Resolve(#[source] naga::proc::ResolveError),
+    #[error("generated module is invalid")]
+    Validation(#[source] naga::WithSpan<naga::valid::ValidationError>),
+    #[error("failed to write WGSL")]
+    Wgsl(#[source] naga::back::wgsl::Error),
+    #[error("failed to write GLSL")]
+    Glsl(#[source] naga::back::glsl::Error),
+    #[error("failed to write SPIR-V")]
+    SpirV(#[source] naga::back::spv::Error),
+    #[error("failed to write HLSL")]
+    Hlsl(#[source] naga::back::hlsl::Error),
+    #[error("failed to write MSL")]
+    Msl(#[source] naga::back::msl::Error),
 }
 
 pub type EmitResult<T = Handle<Expression>> = Result<T, EmitError>;
 
+/// Part of the graph an [`EmitError`] comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorLocation {
+    Node(Node),
+    Port(Port),
+}
+
+impl From<Node> for ErrorLocation {
+    fn from(node: Node) -> Self {
+        Self::Node(node)
+    }
+}
+
+impl From<Port> for ErrorLocation {
+    fn from(port: Port) -> Self {
+        Self::Port(port)
+    }
+}
+
+/// An [`EmitError`] tagged with its [`ErrorLocation`], displayed as the inner error.
+#[derive(Debug)]
+pub struct Located {
+    pub location: ErrorLocation,
+    pub error: Box<EmitError>,
+}
+
+impl std::fmt::Display for Located {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for Located {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl EmitError {
+    /// Attaches `location`, keeping the one already set by a more precise caller.
+    pub fn at(self, location: impl Into<ErrorLocation>) -> Self {
+        match self {
+            Self::At(_) => self,
+            error => Self::At(Located {
+                location: location.into(),
+                error: Box::new(error),
+            }),
+        }
+    }
+
+    pub fn location(&self) -> Option<ErrorLocation> {
+        match self {
+            Self::At(located) => Some(located.location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn inner(&self) -> &Self {
+        match self {
+            Self::At(located) => located.error.inner(),
+            error => error,
+        }
+    }
+
+    /// Drops the location, for errors whose nodes live in another storage.
+    pub fn into_inner(self) -> Self {
+        match self {
+            Self::At(located) => located.error.into_inner(),
+            error => error,
+        }
+    }
+}
+
 impl From<naga::proc::ResolveError> for EmitError {
     fn from(err: naga::proc::ResolveError) -> Self {
         Self::Resolve(err)
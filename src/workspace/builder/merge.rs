@@ -0,0 +1,276 @@
+use naga::{
+    Arena, ArraySize, Block, Constant, ConstantInner, Expression, Function, GlobalVariable, Handle,
+    Module, Span, Statement, StructMember, Type, TypeInner, UniqueArena,
+};
+
+/// Copies the types, constants, globals and functions of `src` into `dst`,
+/// prefixing the names of the functions and globals with `prefix`.
+///
+/// Entry points are left out, `src` only provides functions to call. Globals
+/// keep their binding, which must not be used by the resources of `dst`.
+pub fn merge_module(dst: &mut Module, src: Module, prefix: &str) {
+    let Module {
+        types,
+        constants,
+        global_variables,
+        functions,
+        ..
+    } = src;
+
+    let mut merger = Merger {
+        types: vec![None; types.len()],
+        constants: vec![None; constants.len()],
+        src_types: &types,
+        src_constants: &constants,
+        dst,
+    };
+
+    let globals: Vec<_> = global_variables
+        .iter()
+        .map(|(_, var)| {
+            let name = var.name.as_ref().map(|name| format!("{}{}", prefix, name));
+            let var = GlobalVariable {
+                name,
+                ..var.clone()
+            };
+            Some(merger.global(&var))
+        })
+        .collect();
+
+    // Functions can only call the ones defined before them.
+    let mut calls = Vec::with_capacity(functions.len());
+    for mut function in functions.into_inner() {
+        merger.function(&mut function, &globals, &calls);
+        function.name = function.name.map(|name| format!("{}{}", prefix, name));
+        calls.push(Some(merger.dst.functions.append(function, Span::default())));
+    }
+}
+
+/// Rebuilds `module` with the functions and globals flagged in `functions`
+/// and `globals`, and only the types and constants they use.
+///
+/// Returns where each global went. The kept functions must only call kept
+/// ones and only read kept globals.
+pub fn retain(
+    module: &mut Module,
+    functions: &[bool],
+    globals: &[bool],
+) -> Vec<Option<Handle<GlobalVariable>>> {
+    let Module {
+        types,
+        constants,
+        global_variables,
+        functions: src_functions,
+        entry_points,
+    } = std::mem::take(module);
+
+    let mut merger = Merger {
+        types: vec![None; types.len()],
+        constants: vec![None; constants.len()],
+        src_types: &types,
+        src_constants: &constants,
+        dst: module,
+    };
+
+    let globals: Vec<_> = global_variables
+        .iter()
+        .zip(globals)
+        .map(|((_, var), &keep)| keep.then(|| merger.global(var)))
+        .collect();
+
+    let mut calls = Vec::with_capacity(src_functions.len());
+    for (mut function, &keep) in src_functions.into_inner().into_iter().zip(functions) {
+        calls.push(keep.then(|| {
+            merger.function(&mut function, &globals, &calls);
+            merger.dst.functions.append(function, Span::default())
+        }));
+    }
+
+    for mut entry in entry_points {
+        merger.function(&mut entry.function, &globals, &calls);
+        merger.dst.entry_points.push(entry);
+    }
+
+    globals
+}
+
+struct Merger<'a> {
+    dst: &'a mut Module,
+    src_types: &'a UniqueArena<Type>,
+    src_constants: &'a Arena<Constant>,
+    types: Vec<Option<Handle<Type>>>,
+    constants: Vec<Option<Handle<Constant>>>,
+}
+
+impl Merger<'_> {
+    fn ty(&mut self, handle: Handle<Type>) -> Handle<Type> {
+        if let Some(ty) = self.types[handle.index()] {
+            return ty;
+        }
+
+        let ty = &self.src_types[handle];
+        // `TypeInner` isn't `Clone`, every variant is rebuilt.
+        let inner = match ty.inner {
+            TypeInner::Scalar { kind, width } => TypeInner::Scalar { kind, width },
+            TypeInner::Vector { size, kind, width } => TypeInner::Vector { size, kind, width },
+            TypeInner::Matrix {
+                columns,
+                rows,
+                width,
+            } => TypeInner::Matrix {
+                columns,
+                rows,
+                width,
+            },
+            TypeInner::Atomic { kind, width } => TypeInner::Atomic { kind, width },
+            TypeInner::Pointer { base, space } => TypeInner::Pointer {
+                base: self.ty(base),
+                space,
+            },
+            TypeInner::ValuePointer {
+                size,
+                kind,
+                width,
+                space,
+            } => TypeInner::ValuePointer {
+                size,
+                kind,
+                width,
+                space,
+            },
+            TypeInner::Array { base, size, stride } => TypeInner::Array {
+                base: self.ty(base),
+                size: self.array_size(size),
+                stride,
+            },
+            TypeInner::Struct { ref members, span } => TypeInner::Struct {
+                members: members
+                    .iter()
+                    .map(|member| StructMember {
+                        ty: self.ty(member.ty),
+                        ..member.clone()
+                    })
+                    .collect(),
+                span,
+            },
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+            TypeInner::Sampler { comparison } => TypeInner::Sampler { comparison },
+            TypeInner::BindingArray { base, size } => TypeInner::BindingArray {
+                base: self.ty(base),
+                size: self.array_size(size),
+            },
+        };
+
+        let name = ty.name.clone();
+        let merged = self.dst.types.insert(Type { name, inner }, Span::default());
+        self.types[handle.index()] = Some(merged);
+        merged
+    }
+
+    fn array_size(&mut self, size: ArraySize) -> ArraySize {
+        match size {
+            ArraySize::Constant(constant) => ArraySize::Constant(self.constant(constant)),
+            ArraySize::Dynamic => ArraySize::Dynamic,
+        }
+    }
+
+    fn constant(&mut self, handle: Handle<Constant>) -> Handle<Constant> {
+        if let Some(constant) = self.constants[handle.index()] {
+            return constant;
+        }
+
+        let constant = &self.src_constants[handle];
+        let inner = match constant.inner.clone() {
+            ConstantInner::Composite { ty, components } => ConstantInner::Composite {
+                ty: self.ty(ty),
+                components: components.into_iter().map(|c| self.constant(c)).collect(),
+            },
+            inner => inner,
+        };
+
+        let constant = Constant {
+            name: constant.name.clone(),
+            specialization: constant.specialization,
+            inner,
+        };
+        let merged = self.dst.constants.append(constant, Span::default());
+        self.constants[handle.index()] = Some(merged);
+        merged
+    }
+
+    fn global(&mut self, var: &GlobalVariable) -> Handle<GlobalVariable> {
+        let var = GlobalVariable {
+            ty: self.ty(var.ty),
+            init: var.init.map(|init| self.constant(init)),
+            ..var.clone()
+        };
+        self.dst.global_variables.append(var, Span::default())
+    }
+
+    fn function(
+        &mut self,
+        function: &mut Function,
+        globals: &[Option<Handle<GlobalVariable>>],
+        calls: &[Option<Handle<Function>>],
+    ) {
+        for argument in &mut function.arguments {
+            argument.ty = self.ty(argument.ty);
+        }
+        if let Some(result) = function.result.as_mut() {
+            result.ty = self.ty(result.ty);
+        }
+        for (_, var) in function.local_variables.iter_mut() {
+            var.ty = self.ty(var.ty);
+            var.init = var.init.map(|init| self.constant(init));
+        }
+
+        for (_, expr) in function.expressions.iter_mut() {
+            match expr {
+                Expression::Constant(constant) => *constant = self.constant(*constant),
+                Expression::Compose { ty, .. } => *ty = self.ty(*ty),
+                Expression::GlobalVariable(var) => *var = globals[var.index()].unwrap(),
+                Expression::ImageSample {
+                    offset: Some(offset),
+                    ..
+                } => *offset = self.constant(*offset),
+                Expression::CallResult(function) => *function = calls[function.index()].unwrap(),
+                _ => {}
+            }
+        }
+
+        remap_calls(&mut function.body, calls);
+    }
+}
+
+fn remap_calls(block: &mut Block, calls: &[Option<Handle<Function>>]) {
+    for (stmt, _) in block.span_iter_mut() {
+        match stmt {
+            Statement::Call { function, .. } => *function = calls[function.index()].unwrap(),
+            Statement::Block(block) => remap_calls(block, calls),
+            Statement::If { accept, reject, .. } => {
+                remap_calls(accept, calls);
+                remap_calls(reject, calls);
+            }
+            Statement::Switch { cases, .. } => {
+                for case in cases {
+                    remap_calls(&mut case.body, calls);
+                }
+            }
+            Statement::Loop {
+                body, continuing, ..
+            } => {
+                remap_calls(body, calls);
+                remap_calls(continuing, calls);
+            }
+            _ => {}
+        }
+    }
+}
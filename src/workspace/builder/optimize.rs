@@ -0,0 +1,1171 @@
+use super::{EmitResult, ModuleBuilder};
+use ahash::AHashMap;
+use naga::{
+    AddressSpace, Arena, AtomicFunction, BinaryOperator, Block, Constant, ConstantInner,
+    Expression, Function, GlobalVariable, Handle, ImageQuery, LocalVariable, Module, SampleLevel,
+    ScalarKind, ScalarValue, Span, Statement, Type, TypeInner, UnaryOperator, UniqueArena,
+    BOOL_WIDTH,
+};
+use std::fmt;
+
+/// Rewrites of a module applied by [`ModuleBuilder::optimize`] before it is
+/// written out. The generated code is valid without them, only bigger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Evaluates the operations on constants and merges the identical constants.
+    ConstantFolding,
+    /// Reuses the result of an identical expression computed before.
+    CommonSubexpressions,
+    /// Drops the stores to local variables that are never read afterwards.
+    DeadStores,
+    /// Drops the functions, globals, constants and types nothing uses, most
+    /// of them coming from `builtin.wgsl`.
+    UnusedItems,
+}
+
+impl Pass {
+    /// Every pass, in the order that lets each one clean up after the previous.
+    pub const ALL: [Self; 4] = [
+        Self::ConstantFolding,
+        Self::CommonSubexpressions,
+        Self::DeadStores,
+        Self::UnusedItems,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ConstantFolding => "constant folding",
+            Self::CommonSubexpressions => "common subexpressions",
+            Self::DeadStores => "dead stores",
+            Self::UnusedItems => "unused items",
+        }
+    }
+}
+
+/// How big a module is, [`bytes`](Self::bytes) being the length of its WGSL.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModuleSize {
+    pub functions: usize,
+    pub constants: usize,
+    pub globals: usize,
+    /// In every function and entry point.
+    pub expressions: usize,
+    pub bytes: usize,
+}
+
+/// Size of a module before and after a pass.
+#[derive(Clone, Copy, Debug)]
+pub struct PassReport {
+    pub pass: Pass,
+    pub before: ModuleSize,
+    pub after: ModuleSize,
+}
+
+impl fmt::Display for PassReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (before, after) = (self.before, self.after);
+        write!(
+            f,
+            "{:<22} {:>6} -> {:>6} bytes, {:>3} -> {:>3} functions, {:>4} -> {:>4} expressions",
+            self.pass.label(),
+            before.bytes,
+            after.bytes,
+            before.functions,
+            after.functions,
+            before.expressions,
+            after.expressions,
+        )
+    }
+}
+
+impl<'storage> ModuleBuilder<'storage> {
+    pub fn size(&self) -> EmitResult<ModuleSize> {
+        let module = &self.module;
+        let expressions = module
+            .functions
+            .iter()
+            .map(|(_, function)| function)
+            .chain(module.entry_points.iter().map(|entry| &entry.function))
+            .map(|function| function.expressions.len())
+            .sum();
+
+        Ok(ModuleSize {
+            functions: module.functions.len(),
+            constants: module.constants.len(),
+            globals: module.global_variables.len(),
+            expressions,
+            bytes: self.build()?.len(),
+        })
+    }
+
+    /// Runs `passes` in order, validating the module after each of them.
+    pub fn optimize(&mut self, passes: &[Pass]) -> EmitResult<Vec<PassReport>> {
+        let mut reports = Vec::with_capacity(passes.len());
+        let mut before = self.size()?;
+        for &pass in passes {
+            match pass {
+                Pass::ConstantFolding => fold_constants(&mut self.module),
+                Pass::CommonSubexpressions => {
+                    let Module {
+                        global_variables,
+                        functions,
+                        entry_points,
+                        ..
+                    } = &mut self.module;
+                    for function in functions_mut(functions, entry_points) {
+                        common_subexpressions(function, global_variables);
+                    }
+                }
+                Pass::DeadStores => {
+                    let Module {
+                        functions,
+                        entry_points,
+                        ..
+                    } = &mut self.module;
+                    for function in functions_mut(functions, entry_points) {
+                        dead_stores(function);
+                    }
+                }
+                Pass::UnusedItems => self.remove_unused(),
+            }
+
+            let after = self.size()?;
+            reports.push(PassReport {
+                pass,
+                before,
+                after,
+            });
+            before = after;
+        }
+        Ok(reports)
+    }
+
+    /// Keeps the functions called from the entry points and the globals they
+    /// read, along with every bound global so bindings don't move.
+    fn remove_unused(&mut self) {
+        let module = &self.module;
+
+        let mut functions = vec![false; module.functions.len()];
+        let mut calls = Vec::new();
+        for entry in &module.entry_points {
+            collect_calls(&entry.function.body, &mut calls);
+        }
+        while let Some(call) = calls.pop() {
+            if !std::mem::replace(&mut functions[call.index()], true) {
+                collect_calls(&module.functions[call].body, &mut calls);
+            }
+        }
+
+        let mut globals: Vec<_> = module
+            .global_variables
+            .iter()
+            .map(|(_, var)| var.binding.is_some())
+            .collect();
+        let kept = module
+            .functions
+            .iter()
+            .filter(|(handle, _)| functions[handle.index()])
+            .map(|(_, function)| function)
+            .chain(module.entry_points.iter().map(|entry| &entry.function));
+        for function in kept {
+            for (_, expr) in function.expressions.iter() {
+                if let Expression::GlobalVariable(var) = *expr {
+                    globals[var.index()] = true;
+                }
+            }
+        }
+
+        let globals = super::merge::retain(&mut self.module, &functions, &globals);
+        for (_, var) in &mut self.resources {
+            *var = globals[var.index()].expect("bound globals are kept");
+        }
+    }
+}
+
+fn functions_mut<'a>(
+    functions: &'a mut Arena<Function>,
+    entry_points: &'a mut [naga::EntryPoint],
+) -> impl Iterator<Item = &'a mut Function> {
+    functions
+        .iter_mut()
+        .map(|(_, function)| function)
+        .chain(entry_points.iter_mut().map(|entry| &mut entry.function))
+}
+
+fn collect_calls(block: &Block, calls: &mut Vec<Handle<Function>>) {
+    for stmt in block.iter() {
+        if let Statement::Call { function, .. } = *stmt {
+            calls.push(function);
+        }
+        for block in blocks(stmt) {
+            collect_calls(block, calls);
+        }
+    }
+}
+
+/// Expressions that are in scope for the whole function, and can't be emitted.
+fn is_pre_emit(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::Constant(_)
+            | Expression::FunctionArgument(_)
+            | Expression::GlobalVariable(_)
+            | Expression::LocalVariable(_)
+    )
+}
+
+/// Results of statements, added to the arena by the statement itself.
+fn is_result(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::CallResult(_) | Expression::AtomicResult { .. }
+    )
+}
+
+fn operands_mut(expr: &mut Expression) -> Vec<&mut Handle<Expression>> {
+    let mut operands = Vec::new();
+    match expr {
+        Expression::Access { base, index } => operands.extend([base, index]),
+        Expression::AccessIndex { base, .. } => operands.push(base),
+        Expression::Splat { value, .. } => operands.push(value),
+        Expression::Swizzle { vector, .. } => operands.push(vector),
+        Expression::Compose { components, .. } => operands.extend(components),
+        Expression::Load { pointer } => operands.push(pointer),
+        Expression::ImageSample {
+            image,
+            sampler,
+            coordinate,
+            array_index,
+            level,
+            depth_ref,
+            ..
+        } => {
+            operands.extend([image, sampler, coordinate]);
+            operands.extend(array_index);
+            match level {
+                SampleLevel::Auto | SampleLevel::Zero => {}
+                SampleLevel::Exact(level) | SampleLevel::Bias(level) => operands.push(level),
+                SampleLevel::Gradient { x, y } => operands.extend([x, y]),
+            }
+            operands.extend(depth_ref);
+        }
+        Expression::ImageLoad {
+            image,
+            coordinate,
+            array_index,
+            sample,
+            level,
+        } => {
+            operands.extend([image, coordinate]);
+            operands.extend(array_index);
+            operands.extend(sample);
+            operands.extend(level);
+        }
+        Expression::ImageQuery { image, query } => {
+            operands.push(image);
+            if let ImageQuery::Size { level: Some(level) } = query {
+                operands.push(level);
+            }
+        }
+        Expression::Unary { expr, .. } => operands.push(expr),
+        Expression::Binary { left, right, .. } => operands.extend([left, right]),
+        Expression::Select {
+            condition,
+            accept,
+            reject,
+        } => operands.extend([condition, accept, reject]),
+        Expression::Derivative { expr, .. } => operands.push(expr),
+        Expression::Relational { argument, .. } => operands.push(argument),
+        Expression::Math {
+            arg,
+            arg1,
+            arg2,
+            arg3,
+            ..
+        } => {
+            operands.push(arg);
+            operands.extend(arg1);
+            operands.extend(arg2);
+            operands.extend(arg3);
+        }
+        Expression::As { expr, .. } => operands.push(expr),
+        Expression::ArrayLength(array) => operands.push(array),
+        Expression::Constant(_)
+        | Expression::FunctionArgument(_)
+        | Expression::GlobalVariable(_)
+        | Expression::LocalVariable(_)
+        | Expression::CallResult(_)
+        | Expression::AtomicResult { .. } => {}
+    }
+    operands
+}
+
+/// Expressions used by a statement, leaving out emitted ranges and nested blocks.
+fn statement_operands_mut(stmt: &mut Statement) -> Vec<&mut Handle<Expression>> {
+    let mut operands = Vec::new();
+    match stmt {
+        Statement::If { condition, .. } => operands.push(condition),
+        Statement::Switch { selector, .. } => operands.push(selector),
+        Statement::Loop { break_if, .. } => operands.extend(break_if),
+        Statement::Return { value } => operands.extend(value),
+        Statement::Store { pointer, value } => operands.extend([pointer, value]),
+        Statement::ImageStore {
+            image,
+            coordinate,
+            array_index,
+            value,
+        } => {
+            operands.extend([image, coordinate]);
+            operands.extend(array_index);
+            operands.push(value);
+        }
+        Statement::Atomic {
+            pointer,
+            fun,
+            value,
+            result,
+        } => {
+            operands.extend([pointer, value, result]);
+            if let AtomicFunction::Exchange {
+                compare: Some(compare),
+            } = fun
+            {
+                operands.push(compare);
+            }
+        }
+        Statement::Call {
+            arguments, result, ..
+        } => {
+            operands.extend(arguments);
+            operands.extend(result);
+        }
+        Statement::Emit(_)
+        | Statement::Block(_)
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Kill
+        | Statement::Barrier(_) => {}
+    }
+    operands
+}
+
+fn blocks(stmt: &Statement) -> Vec<&Block> {
+    match stmt {
+        Statement::Block(block) => vec![block],
+        Statement::If { accept, reject, .. } => vec![accept, reject],
+        Statement::Switch { cases, .. } => cases.iter().map(|case| &case.body).collect(),
+        Statement::Loop {
+            body, continuing, ..
+        } => vec![body, continuing],
+        _ => Vec::new(),
+    }
+}
+
+fn blocks_mut(stmt: &mut Statement) -> Vec<&mut Block> {
+    match stmt {
+        Statement::Block(block) => vec![block],
+        Statement::If { accept, reject, .. } => vec![accept, reject],
+        Statement::Switch { cases, .. } => cases.iter_mut().map(|case| &mut case.body).collect(),
+        Statement::Loop {
+            body, continuing, ..
+        } => vec![body, continuing],
+        _ => Vec::new(),
+    }
+}
+
+/// Local variable a pointer is into, through any access.
+fn local_root(
+    expressions: &Arena<Expression>,
+    mut pointer: Handle<Expression>,
+) -> Option<Handle<LocalVariable>> {
+    loop {
+        match expressions[pointer] {
+            Expression::Access { base, .. } | Expression::AccessIndex { base, .. } => {
+                pointer = base
+            }
+            Expression::LocalVariable(local) => return Some(local),
+            _ => return None,
+        }
+    }
+}
+
+/// Rebuilds the expressions of `function` in the order they are emitted, with
+/// each use of an expression in `replace` going to its replacement, and leaves
+/// out the expressions and local variables nothing uses anymore.
+///
+/// A replacement either comes before the expression it replaces or has no
+/// operands.
+fn compact(function: &mut Function, replace: &[Option<Handle<Expression>>]) {
+    let resolve =
+        |expr: Handle<Expression>| replace.get(expr.index()).copied().flatten().unwrap_or(expr);
+
+    let mut live = vec![false; function.expressions.len()];
+    for_each_operand(&mut function.body, &mut |expr| {
+        live[resolve(*expr).index()] = true
+    });
+    for (handle, expr) in function.expressions.iter_mut().rev() {
+        if live[handle.index()] {
+            for operand in operands_mut(expr) {
+                live[resolve(*operand).index()] = true;
+            }
+        }
+    }
+
+    let mut emitted = vec![false; function.expressions.len()];
+    mark_emitted(&function.body, &mut emitted);
+
+    let mut compactor = Compactor {
+        map: vec![None; function.expressions.len()],
+        emitted,
+        old: std::mem::take(&mut function.expressions),
+        new: Arena::new(),
+        live,
+        replace,
+        locals: vec![None; function.local_variables.len()],
+    };
+
+    let mut used = vec![false; function.local_variables.len()];
+    for (handle, expr) in compactor.old.iter() {
+        if let Expression::LocalVariable(local) = *expr {
+            used[local.index()] |= compactor.is_kept(handle);
+        }
+    }
+    let mut local_variables = Arena::new();
+    for (local, var) in function.local_variables.iter() {
+        if used[local.index()] {
+            let local = &mut compactor.locals[local.index()];
+            *local = Some(local_variables.append(var.clone(), Span::default()));
+        }
+    }
+
+    // Arguments, globals, locals and constants go first, they are in scope everywhere.
+    let handles: Vec<_> = compactor.old.iter().map(|(handle, _)| handle).collect();
+    for &handle in &handles {
+        if compactor.is_kept(handle) && is_pre_emit(&compactor.old[handle]) {
+            compactor.append(handle);
+        }
+    }
+    function.body = compactor.block(&function.body);
+
+    function.named_expressions = std::mem::take(&mut function.named_expressions)
+        .into_iter()
+        .filter_map(|(handle, name)| Some((compactor.map[handle.index()]?, name)))
+        .collect();
+    function.expressions = compactor.new;
+    function.local_variables = local_variables;
+}
+
+struct Compactor<'a> {
+    old: Arena<Expression>,
+    new: Arena<Expression>,
+    /// Where each old expression went, `None` when left out.
+    map: Vec<Option<Handle<Expression>>>,
+    /// Whether each old expression is in an emitted range. The ones that are
+    /// neither emitted nor in scope everywhere are written where they are used.
+    emitted: Vec<bool>,
+    live: Vec<bool>,
+    replace: &'a [Option<Handle<Expression>>],
+    locals: Vec<Option<Handle<LocalVariable>>>,
+}
+
+impl Compactor<'_> {
+    fn is_kept(&self, expr: Handle<Expression>) -> bool {
+        let replaced = matches!(self.replace.get(expr.index()), Some(Some(_)));
+        self.live[expr.index()] && !replaced
+    }
+
+    fn get(&mut self, expr: Handle<Expression>) -> Handle<Expression> {
+        let expr = match self.replace.get(expr.index()) {
+            Some(&Some(replacement)) => replacement,
+            _ => expr,
+        };
+        if self.map[expr.index()].is_none() && !self.emitted[expr.index()] {
+            self.append(expr);
+        }
+        self.map[expr.index()].expect("operands come before the expressions using them")
+    }
+
+    fn append(&mut self, handle: Handle<Expression>) {
+        let mut expr = self.old[handle].clone();
+        for operand in operands_mut(&mut expr) {
+            *operand = self.get(*operand);
+        }
+        if let Expression::LocalVariable(ref mut local) = expr {
+            *local = self.locals[local.index()].expect("locals of live pointers are kept");
+        }
+        self.map[handle.index()] = Some(self.new.append(expr, Span::default()));
+    }
+
+    fn block(&mut self, old: &Block) -> Block {
+        let mut block = Block::with_capacity(old.len());
+        for (stmt, span) in old.span_iter() {
+            if let Statement::Emit(ref range) = *stmt {
+                let start = self.new.len();
+                for handle in range.clone() {
+                    if self.is_kept(handle) {
+                        self.append(handle);
+                    }
+                }
+                if self.new.len() > start {
+                    block.push(Statement::Emit(self.new.range_from(start)), *span);
+                }
+                continue;
+            }
+
+            let mut stmt = stmt.clone();
+            match stmt {
+                Statement::Call {
+                    result: Some(result),
+                    ..
+                }
+                | Statement::Atomic { result, .. } => self.append(result),
+                _ => {}
+            }
+            for block in blocks_mut(&mut stmt) {
+                *block = self.block(block);
+            }
+            for operand in statement_operands_mut(&mut stmt) {
+                *operand = self.get(*operand);
+            }
+            block.push(stmt, *span);
+        }
+        block
+    }
+}
+
+fn mark_emitted(block: &Block, emitted: &mut [bool]) {
+    for stmt in block.iter() {
+        if let Statement::Emit(ref range) = *stmt {
+            for handle in range.clone() {
+                emitted[handle.index()] = true;
+            }
+        }
+        for block in blocks(stmt) {
+            mark_emitted(block, emitted);
+        }
+    }
+}
+
+fn for_each_operand(block: &mut Block, f: &mut impl FnMut(&mut Handle<Expression>)) {
+    for stmt in block.iter_mut() {
+        for operand in statement_operands_mut(stmt) {
+            f(operand);
+        }
+        for block in blocks_mut(stmt) {
+            for_each_operand(block, f);
+        }
+    }
+}
+
+fn fold_constants(module: &mut Module) {
+    let Module {
+        types,
+        constants,
+        functions,
+        entry_points,
+        ..
+    } = module;
+    for function in functions_mut(functions, entry_points) {
+        let mut folder = Folder { types, constants };
+        let mut replace = vec![None; function.expressions.len()];
+        let handles: Vec<_> = function
+            .expressions
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in handles {
+            let mut expr = function.expressions[handle].clone();
+            if is_pre_emit(&expr) {
+                continue;
+            }
+            for operand in operands_mut(&mut expr) {
+                if let Some(replacement) = replace[operand.index()] {
+                    *operand = replacement;
+                }
+            }
+            if let Some(constant) = folder.fold(&function.expressions, &expr) {
+                let folded = Expression::Constant(constant);
+                replace[handle.index()] =
+                    Some(function.expressions.append(folded, Span::default()));
+            }
+        }
+        compact(function, &replace);
+    }
+    merge_constants(module);
+}
+
+struct Folder<'a> {
+    types: &'a mut UniqueArena<Type>,
+    constants: &'a mut Arena<Constant>,
+}
+
+impl Folder<'_> {
+    fn append(&mut self, inner: ConstantInner) -> Handle<Constant> {
+        let constant = Constant {
+            name: None,
+            specialization: None,
+            inner,
+        };
+        self.constants.append(constant, Span::default())
+    }
+
+    fn scalar(&self, constant: Handle<Constant>) -> Option<(u8, ScalarValue)> {
+        match self.constants[constant].inner {
+            ConstantInner::Scalar { width, value } => Some((width, value)),
+            ConstantInner::Composite { .. } => None,
+        }
+    }
+
+    fn vector_type(
+        &mut self,
+        size: naga::VectorSize,
+        scalar: Handle<Constant>,
+    ) -> Option<Handle<Type>> {
+        let (width, value) = self.scalar(scalar)?;
+        let inner = TypeInner::Vector {
+            size,
+            kind: scalar_kind(value),
+            width,
+        };
+        Some(
+            self.types
+                .insert(Type { name: None, inner }, Span::default()),
+        )
+    }
+
+    /// Constant `expr` evaluates to when its operands are constants.
+    fn fold(
+        &mut self,
+        expressions: &Arena<Expression>,
+        expr: &Expression,
+    ) -> Option<Handle<Constant>> {
+        let constant = |expr: Handle<Expression>| match expressions[expr] {
+            Expression::Constant(constant) => Some(constant),
+            _ => None,
+        };
+
+        match *expr {
+            Expression::AccessIndex { base, index } => {
+                match self.constants[constant(base)?].inner {
+                    ConstantInner::Composite { ref components, .. } => {
+                        components.get(index as usize).copied()
+                    }
+                    ConstantInner::Scalar { .. } => None,
+                }
+            }
+            Expression::Swizzle {
+                size,
+                vector,
+                pattern,
+            } => {
+                let components = match self.constants[constant(vector)?].inner {
+                    ConstantInner::Composite { ref components, .. } => components.clone(),
+                    ConstantInner::Scalar { .. } => return None,
+                };
+                let components: Vec<_> = pattern[..size as usize]
+                    .iter()
+                    .map(|&component| components[component as usize])
+                    .collect();
+                let ty = self.vector_type(size, components[0])?;
+                Some(self.append(ConstantInner::Composite { ty, components }))
+            }
+            Expression::Compose { ty, ref components } => {
+                // Vectors can be composed from smaller vectors, constants can't.
+                let count = match self.types[ty].inner {
+                    TypeInner::Vector { size, .. } => size as usize,
+                    TypeInner::Matrix { columns, .. } => columns as usize,
+                    TypeInner::Struct { ref members, .. } => members.len(),
+                    TypeInner::Array { .. } => components.len(),
+                    _ => return None,
+                };
+                let components = components
+                    .iter()
+                    .map(|&component| constant(component))
+                    .collect::<Option<Vec<_>>>()?;
+                if components.len() != count {
+                    return None;
+                }
+                if let TypeInner::Vector { .. } = self.types[ty].inner {
+                    for &component in &components {
+                        self.scalar(component)?;
+                    }
+                }
+                Some(self.append(ConstantInner::Composite { ty, components }))
+            }
+            Expression::Unary { op, expr } => {
+                let (width, value) = self.scalar(constant(expr)?)?;
+                let value = fold_unary(op, value)?;
+                Some(self.append(ConstantInner::Scalar { width, value }))
+            }
+            Expression::Binary { op, left, right } => {
+                let (left, right) = (constant(left)?, constant(right)?);
+                self.fold_binary(op, left, right)
+            }
+            Expression::As {
+                expr,
+                kind,
+                convert: Some(width),
+            } => {
+                let (_, value) = self.scalar(constant(expr)?)?;
+                let value = fold_cast(value, kind)?;
+                let width = if kind == ScalarKind::Bool {
+                    BOOL_WIDTH
+                } else {
+                    width
+                };
+                Some(self.append(ConstantInner::Scalar { width, value }))
+            }
+            _ => None,
+        }
+    }
+
+    fn fold_binary(
+        &mut self,
+        op: BinaryOperator,
+        left: Handle<Constant>,
+        right: Handle<Constant>,
+    ) -> Option<Handle<Constant>> {
+        match (&self.constants[left].inner, &self.constants[right].inner) {
+            (
+                &ConstantInner::Scalar { width, value: left },
+                &ConstantInner::Scalar { value: right, .. },
+            ) => {
+                let value = fold_binary(op, left, right)?;
+                let width = if let ScalarValue::Bool(_) = value {
+                    BOOL_WIDTH
+                } else {
+                    width
+                };
+                Some(self.append(ConstantInner::Scalar { width, value }))
+            }
+            // Vectors of the same type, component by component.
+            (
+                ConstantInner::Composite {
+                    ty,
+                    components: left,
+                },
+                ConstantInner::Composite {
+                    ty: right_ty,
+                    components: right,
+                },
+            ) if ty == right_ty && is_arithmetic(op) => {
+                if !matches!(self.types[*ty].inner, TypeInner::Vector { .. }) {
+                    return None;
+                }
+                let (ty, pairs): (_, Vec<_>) = (
+                    *ty,
+                    left.iter().copied().zip(right.iter().copied()).collect(),
+                );
+                let components = pairs
+                    .into_iter()
+                    .map(|(left, right)| self.fold_binary(op, left, right))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(self.append(ConstantInner::Composite { ty, components }))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn scalar_kind(value: ScalarValue) -> ScalarKind {
+    match value {
+        ScalarValue::Sint(_) => ScalarKind::Sint,
+        ScalarValue::Uint(_) => ScalarKind::Uint,
+        ScalarValue::Float(_) => ScalarKind::Float,
+        ScalarValue::Bool(_) => ScalarKind::Bool,
+    }
+}
+
+fn is_arithmetic(op: BinaryOperator) -> bool {
+    use BinaryOperator as Op;
+    matches!(
+        op,
+        Op::Add | Op::Subtract | Op::Multiply | Op::Divide | Op::Modulo
+    )
+}
+
+/// Floats are evaluated as `f32` and integers as 32 bits, like on the GPU.
+fn fold_unary(op: UnaryOperator, value: ScalarValue) -> Option<ScalarValue> {
+    Some(match (op, value) {
+        (UnaryOperator::Negate, ScalarValue::Float(value)) => {
+            ScalarValue::Float(-(value as f32) as f64)
+        }
+        (UnaryOperator::Negate, ScalarValue::Sint(value)) => {
+            ScalarValue::Sint((value as i32).wrapping_neg() as i64)
+        }
+        (UnaryOperator::Not, ScalarValue::Sint(value)) => ScalarValue::Sint(!(value as i32) as i64),
+        (UnaryOperator::Not, ScalarValue::Uint(value)) => ScalarValue::Uint(!(value as u32) as u64),
+        (UnaryOperator::Not, ScalarValue::Bool(value)) => ScalarValue::Bool(!value),
+        _ => return None,
+    })
+}
+
+fn fold_binary(op: BinaryOperator, left: ScalarValue, right: ScalarValue) -> Option<ScalarValue> {
+    use BinaryOperator as Op;
+    use ScalarValue::*;
+
+    let value = match (left, right) {
+        (Float(left), Float(right)) => {
+            let (left, right) = (left as f32, right as f32);
+            let value = match op {
+                Op::Add => left + right,
+                Op::Subtract => left - right,
+                Op::Multiply => left * right,
+                Op::Divide => left / right,
+                Op::Modulo => left % right,
+                _ => return compare(op, left.partial_cmp(&right)?),
+            };
+            // WGSL has no literal for infinities and NaNs.
+            if !value.is_finite() {
+                return None;
+            }
+            Float(value as f64)
+        }
+        (Sint(left), Sint(right)) => {
+            let (left, right) = (left as i32, right as i32);
+            Sint(match op {
+                Op::Add => left.wrapping_add(right),
+                Op::Subtract => left.wrapping_sub(right),
+                Op::Multiply => left.wrapping_mul(right),
+                Op::Divide => left.checked_div(right)?,
+                Op::Modulo => left.checked_rem(right)?,
+                Op::And => left & right,
+                Op::InclusiveOr => left | right,
+                Op::ExclusiveOr => left ^ right,
+                _ => return compare(op, left.cmp(&right)),
+            } as i64)
+        }
+        (Uint(left), Uint(right)) => {
+            let (left, right) = (left as u32, right as u32);
+            Uint(match op {
+                Op::Add => left.wrapping_add(right),
+                Op::Subtract => left.wrapping_sub(right),
+                Op::Multiply => left.wrapping_mul(right),
+                Op::Divide => left.checked_div(right)?,
+                Op::Modulo => left.checked_rem(right)?,
+                Op::And => left & right,
+                Op::InclusiveOr => left | right,
+                Op::ExclusiveOr => left ^ right,
+                _ => return compare(op, left.cmp(&right)),
+            } as u64)
+        }
+        (Bool(left), Bool(right)) => Bool(match op {
+            Op::LogicalAnd | Op::And => left && right,
+            Op::LogicalOr | Op::InclusiveOr => left || right,
+            Op::Equal => left == right,
+            Op::NotEqual => left != right,
+            _ => return None,
+        }),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn compare(op: BinaryOperator, ordering: std::cmp::Ordering) -> Option<ScalarValue> {
+    use std::cmp::Ordering;
+    use BinaryOperator as Op;
+
+    Some(ScalarValue::Bool(match op {
+        Op::Equal => ordering == Ordering::Equal,
+        Op::NotEqual => ordering != Ordering::Equal,
+        Op::Less => ordering == Ordering::Less,
+        Op::LessEqual => ordering != Ordering::Greater,
+        Op::Greater => ordering == Ordering::Greater,
+        Op::GreaterEqual => ordering != Ordering::Less,
+        _ => return None,
+    }))
+}
+
+fn fold_cast(value: ScalarValue, kind: ScalarKind) -> Option<ScalarValue> {
+    use ScalarValue::*;
+
+    Some(match (value, kind) {
+        (Float(value), ScalarKind::Float) => Float(value),
+        (Float(value), ScalarKind::Sint) => {
+            let value = value.trunc();
+            if !(i32::MIN as f64..=i32::MAX as f64).contains(&value) {
+                return None;
+            }
+            Sint(value as i64)
+        }
+        (Float(value), ScalarKind::Uint) => {
+            let value = value.trunc();
+            if !(0.0..=u32::MAX as f64).contains(&value) {
+                return None;
+            }
+            Uint(value as u64)
+        }
+        (Sint(value), ScalarKind::Float) => Float(value as i32 as f32 as f64),
+        (Uint(value), ScalarKind::Float) => Float(value as u32 as f32 as f64),
+        (Sint(value), ScalarKind::Sint) => Sint(value),
+        (Uint(value), ScalarKind::Uint) => Uint(value),
+        (Sint(value), ScalarKind::Uint) => Uint(value as i32 as u32 as u64),
+        (Uint(value), ScalarKind::Sint) => Sint(value as u32 as i32 as i64),
+        (Bool(value), ScalarKind::Float) => Float(value as u8 as f64),
+        (Bool(value), ScalarKind::Sint) => Sint(value as i64),
+        (Bool(value), ScalarKind::Uint) => Uint(value as u64),
+        (Bool(value), ScalarKind::Bool) => Bool(value),
+        (Float(value), ScalarKind::Bool) => Bool(value != 0.0),
+        (Sint(value), ScalarKind::Bool) => Bool(value != 0),
+        (Uint(value), ScalarKind::Bool) => Bool(value != 0),
+    })
+}
+
+/// Points every use of an unnamed constant to the first one with the same
+/// value. The duplicates are left for [`Pass::UnusedItems`] to drop.
+fn merge_constants(module: &mut Module) {
+    let mut seen = AHashMap::default();
+    let mut canonical = Vec::with_capacity(module.constants.len());
+    for (handle, constant) in module.constants.iter_mut() {
+        if let ConstantInner::Composite {
+            ref mut components, ..
+        } = constant.inner
+        {
+            for component in components {
+                *component = canonical
+                    .get(component.index())
+                    .copied()
+                    .unwrap_or(*component);
+            }
+        }
+        let merged = if constant.name.is_none() && constant.specialization.is_none() {
+            *seen
+                .entry(format!("{:?}", constant.inner))
+                .or_insert(handle)
+        } else {
+            handle
+        };
+        canonical.push(merged);
+    }
+
+    let remap = |constant: &mut Handle<Constant>| *constant = canonical[constant.index()];
+    for (_, var) in module.global_variables.iter_mut() {
+        var.init.iter_mut().for_each(remap);
+    }
+    let Module {
+        functions,
+        entry_points,
+        ..
+    } = module;
+    for function in functions_mut(functions, entry_points) {
+        for (_, var) in function.local_variables.iter_mut() {
+            var.init.iter_mut().for_each(remap);
+        }
+        for (_, expr) in function.expressions.iter_mut() {
+            match expr {
+                Expression::Constant(constant) => remap(constant),
+                Expression::ImageSample {
+                    offset: Some(offset),
+                    ..
+                } => remap(offset),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Replaces each expression by an identical one emitted before it in the same
+/// block or an enclosing one. Loads are only merged from uniforms, which
+/// nothing can store to.
+fn common_subexpressions(function: &mut Function, globals: &Arena<GlobalVariable>) {
+    let mut eliminator = Eliminator {
+        expressions: &function.expressions,
+        globals,
+        replace: vec![None; function.expressions.len()],
+        scopes: vec![AHashMap::default()],
+    };
+    for (handle, expr) in function.expressions.iter() {
+        if is_pre_emit(expr) {
+            eliminator.visit(handle);
+        }
+    }
+    eliminator.block(&function.body);
+
+    let replace = eliminator.replace;
+    for (handle, _) in function.expressions.iter() {
+        if replace[handle.index()].is_some() {
+            function.named_expressions.remove(&handle);
+        }
+    }
+    compact(function, &replace);
+}
+
+struct Eliminator<'a> {
+    expressions: &'a Arena<Expression>,
+    globals: &'a Arena<GlobalVariable>,
+    replace: Vec<Option<Handle<Expression>>>,
+    /// Expressions in scope by the debug output of their operation.
+    scopes: Vec<AHashMap<String, Handle<Expression>>>,
+}
+
+impl Eliminator<'_> {
+    fn visit(&mut self, handle: Handle<Expression>) {
+        let mut expr = self.expressions[handle].clone();
+        if is_result(&expr) {
+            return;
+        }
+        if let Expression::Load { pointer } = expr {
+            if !self.is_uniform(pointer) {
+                return;
+            }
+        }
+        for operand in operands_mut(&mut expr) {
+            *operand = self.replace[operand.index()].unwrap_or(*operand);
+        }
+
+        let key = format!("{:?}", expr);
+        match self.scopes.iter().rev().find_map(|scope| scope.get(&key)) {
+            Some(&first) => self.replace[handle.index()] = Some(first),
+            None => {
+                self.scopes.last_mut().unwrap().insert(key, handle);
+            }
+        }
+    }
+
+    fn is_uniform(&self, mut pointer: Handle<Expression>) -> bool {
+        loop {
+            match self.expressions[pointer] {
+                Expression::Access { base, .. } | Expression::AccessIndex { base, .. } => {
+                    pointer = base
+                }
+                Expression::GlobalVariable(var) => {
+                    return self.globals[var].space == AddressSpace::Uniform
+                }
+                _ => return false,
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(AHashMap::default());
+        for stmt in block.iter() {
+            if let Statement::Emit(ref range) = *stmt {
+                for handle in range.clone() {
+                    self.visit(handle);
+                }
+            }
+            for block in blocks(stmt) {
+                self.block(block);
+            }
+        }
+        self.scopes.pop();
+    }
+}
+
+/// Drops the stores to local variables that are never loaded, and the ones
+/// overwritten before being loaded.
+fn dead_stores(function: &mut Function) {
+    let expressions = &function.expressions;
+    let mut read = vec![false; function.local_variables.len()];
+    for (_, expr) in expressions.iter() {
+        if let Expression::Load { pointer } = *expr {
+            if let Some(local) = local_root(expressions, pointer) {
+                read[local.index()] = true;
+            }
+        }
+    }
+    mark_passed(&function.body, expressions, &mut read);
+    remove_stores(&mut function.body, expressions, &read);
+    compact(function, &[]);
+}
+
+/// Marks the locals given to functions and atomics as read, they may be
+/// through the pointer.
+fn mark_passed(block: &Block, expressions: &Arena<Expression>, read: &mut [bool]) {
+    for stmt in block.iter() {
+        let pointers = match *stmt {
+            Statement::Call { ref arguments, .. } => arguments.clone(),
+            Statement::Atomic { pointer, .. } => vec![pointer],
+            _ => Vec::new(),
+        };
+        for pointer in pointers {
+            if let Some(local) = local_root(expressions, pointer) {
+                read[local.index()] = true;
+            }
+        }
+        for block in blocks(stmt) {
+            mark_passed(block, expressions, read);
+        }
+    }
+}
+
+fn remove_stores(block: &mut Block, expressions: &Arena<Expression>, read: &[bool]) {
+    let mut dead = Vec::new();
+    // Last store to the whole of a local, if it wasn't loaded since.
+    let mut pending = AHashMap::default();
+    for (index, stmt) in block.iter().enumerate() {
+        match *stmt {
+            Statement::Emit(ref range) => {
+                for handle in range.clone() {
+                    if let Expression::Load { pointer } = expressions[handle] {
+                        if let Some(local) = local_root(expressions, pointer) {
+                            pending.remove(&local);
+                        }
+                    }
+                }
+            }
+            Statement::Store { pointer, .. } => match local_root(expressions, pointer) {
+                Some(local) if !read[local.index()] => dead.push(index),
+                Some(local) if matches!(expressions[pointer], Expression::LocalVariable(_)) => {
+                    dead.extend(pending.insert(local, index));
+                }
+                Some(local) => {
+                    pending.remove(&local);
+                }
+                None => {}
+            },
+            // Locals don't outlive the function.
+            Statement::Return { .. } | Statement::Kill => {
+                dead.extend(pending.drain().map(|(_, index)| index));
+            }
+            // The value may be read by the next iteration, another branch or a callee.
+            _ => pending.clear(),
+        }
+    }
+
+    dead.sort_unstable();
+    for index in dead.into_iter().rev() {
+        block.cull(index..=index);
+    }
+    for stmt in block.iter_mut() {
+        for block in blocks_mut(stmt) {
+            remove_stores(block, expressions, read);
+        }
+    }
+}
+
+#[test]
+fn optimize_module() {
+    let storage = crate::workspace::Storage::default();
+    let source = "
+        fn unused(x: f32) -> f32 {
+            return x * 2.0;
+        }
+
+        fn shade(a: f32) -> f32 {
+            var scratch: f32;
+            var kept: f32;
+            scratch = a;
+            kept = a;
+            kept = a * 3.0;
+            return (a + 1.0) * (a + 1.0) + (2.0 * 3.0) + kept;
+        }
+
+        @fragment
+        fn fs_main() -> @location(0) vec4<f32> {
+            return vec4<f32>(shade(0.5));
+        }
+    ";
+    let mut module = ModuleBuilder::from_wgsl(&storage, source).unwrap();
+    let reports = module.optimize(&Pass::ALL).unwrap();
+    assert_eq!(reports.len(), Pass::ALL.len());
+    assert!(reports.last().unwrap().after.bytes < reports[0].before.bytes);
+
+    let wgsl = module.build().unwrap();
+    assert!(!wgsl.contains("fn unused"));
+    assert!(!wgsl.contains("scratch"));
+    assert!(!wgsl.contains("_ ="));
+    assert!(wgsl.contains("6.0"));
+    assert_eq!(wgsl.matches("(a + 1.0)").count(), 1, "{}", wgsl);
+    assert_eq!(wgsl.matches("kept =").count(), 1, "{}", wgsl);
+}
@@ -0,0 +1,307 @@
+use naga::{ImageClass, ImageDimension, ScalarKind, Type, TypeInner};
+use serde::{Deserialize, Serialize};
+
+/// Shape of a texture and of the view it is bound with.
+///
+/// Saved by name, `D2Array(4)` for an array of 4 layers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum TextureDimension {
+    D2,
+    /// Layers stacked vertically in the image file.
+    D2Array(u32),
+    Cube,
+}
+
+impl TextureDimension {
+    pub const CLASS: ImageClass = ImageClass::Sampled {
+        kind: ScalarKind::Float,
+        multi: false,
+    };
+
+    pub fn layers(self) -> u32 {
+        match self {
+            Self::D2 => 1,
+            Self::D2Array(layers) => layers,
+            Self::Cube => 6,
+        }
+    }
+
+    pub fn naga_type(self) -> Type {
+        let (dim, arrayed) = match self {
+            Self::D2 => (ImageDimension::D2, false),
+            Self::D2Array(_) => (ImageDimension::D2, true),
+            Self::Cube => (ImageDimension::Cube, false),
+        };
+        Type {
+            name: None,
+            inner: TypeInner::Image {
+                dim,
+                arrayed,
+                class: Self::CLASS,
+            },
+        }
+    }
+
+    pub fn view(self) -> wgpu::TextureViewDimension {
+        match self {
+            Self::D2 => wgpu::TextureViewDimension::D2,
+            Self::D2Array(_) => wgpu::TextureViewDimension::D2Array,
+            Self::Cube => wgpu::TextureViewDimension::Cube,
+        }
+    }
+}
+
+impl From<TextureDimension> for String {
+    fn from(dimension: TextureDimension) -> Self {
+        match dimension {
+            TextureDimension::D2 => String::from("D2"),
+            TextureDimension::D2Array(layers) => format!("D2Array({})", layers),
+            TextureDimension::Cube => String::from("Cube"),
+        }
+    }
+}
+
+impl TryFrom<String> for TextureDimension {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        let layers = name
+            .strip_prefix("D2Array(")
+            .and_then(|layers| layers.strip_suffix(')'))
+            .map(|layers| layers.parse());
+        match (name.as_str(), layers) {
+            ("D2", _) => Ok(Self::D2),
+            ("Cube", _) => Ok(Self::Cube),
+            (_, Some(Ok(layers))) if layers > 0 => Ok(Self::D2Array(layers)),
+            _ => Err(format!("unknown TextureDimension `{}`", name)),
+        }
+    }
+}
+
+/// Saved by name, see `FILTERS` in the texture nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Filter {
+    #[default]
+    Linear,
+    Point,
+}
+
+/// Saved by name, see `WRAP_MODES` in the texture nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SamplerState {
+    pub filter: Filter,
+    pub wrap: WrapMode,
+}
+
+impl SamplerState {
+    pub fn descriptor(self) -> wgpu::SamplerDescriptor<'static> {
+        let filter = match self.filter {
+            Filter::Linear => wgpu::FilterMode::Linear,
+            Filter::Point => wgpu::FilterMode::Nearest,
+        };
+        let address = match self.wrap {
+            WrapMode::Repeat => wgpu::AddressMode::Repeat,
+            WrapMode::Clamp => wgpu::AddressMode::ClampToEdge,
+            WrapMode::Mirror => wgpu::AddressMode::MirrorRepeat,
+        };
+        wgpu::SamplerDescriptor {
+            address_mode_u: address,
+            address_mode_v: address,
+            address_mode_w: address,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            ..Default::default()
+        }
+    }
+}
+
+/// What a global variable of a module is bound to, at the group of the module
+/// and the binding given by its index in [`super::ModuleBuilder::resources`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// Image of the asset folder.
+    Texture {
+        path: String,
+        dimension: TextureDimension,
+    },
+    Sampler(SamplerState),
+    /// Uniform buffer holding the properties of the blackboard.
+    Properties,
+    /// Uniform buffer holding the clock, see [`TIME_MEMBERS`].
+    Time,
+    /// Uniform buffer holding the orbit camera of the mesh preview, see [`CAMERA_MEMBERS`].
+    Camera,
+    /// Uniform buffer holding the transformation matrices, see [`TRANSFORM_MEMBERS`].
+    Transform,
+    /// Color of the rendered scene, read by post-processing.
+    SceneColor,
+    /// Linear depth of the rendered scene in `r`, unfilterable and read with `textureLoad`.
+    SceneDepth,
+    /// Texture of the sprite or widget being drawn.
+    MainTexture,
+    /// Uniform buffer holding the `vec4<f32>` color multiplying a sprite.
+    Tint,
+    /// Uniform buffer holding the `vec2<f32>` size of the render target in pixels.
+    Viewport,
+    /// Write-only `rgba8unorm` texture of a compute master, shown by its preview.
+    StorageTexture,
+    /// Storage buffer of `vec4<f32>`, the input of compute kernels unless writable.
+    StorageBuffer {
+        writable: bool,
+    },
+}
+
+/// Members of the `Time` struct, each an `f32`.
+pub const TIME_MEMBERS: [&str; 4] = ["time", "sin_time", "cos_time", "delta_time"];
+
+/// Members of the `Camera` struct, a `mat4x4<f32>` and the `vec3<f32>`
+/// direction towards the light.
+pub const CAMERA_MEMBERS: [&str; 2] = ["view_proj", "light"];
+
+/// Members of the `Transform` struct, each a `mat4x4<f32>`.
+pub const TRANSFORM_MEMBERS: [&str; 3] = ["model", "view", "projection"];
+
+/// Whether textures of `format` can be read through a linear sampler.
+pub fn is_filterable(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format.describe().sample_type,
+        wgpu::TextureSampleType::Float { filterable: true }
+    )
+}
+
+/// Checks the formats of the asset textures of `resources`, given by `format`
+/// for the images loaded so far, and returns whether they can all be filtered.
+///
+/// When they can't, samplers fall back to [`Filter::Point`], since sampling an
+/// unfilterable texture through a filtering sampler is invalid.
+pub fn filter_resources(
+    resources: &mut [Resource],
+    format: impl Fn(&str) -> Option<wgpu::TextureFormat>,
+) -> bool {
+    let filterable = resources.iter().all(|resource| match resource {
+        Resource::Texture { path, .. } => match format(path) {
+            Some(format) => is_filterable(format),
+            // Bound again once loaded.
+            None => true,
+        },
+        _ => true,
+    });
+    if !filterable {
+        for resource in resources {
+            if let Resource::Sampler(state) = resource {
+                state.filter = Filter::Point;
+            }
+        }
+    }
+    filterable
+}
+
+impl Resource {
+    /// Layout of the resource at `binding`, asset textures being bound as
+    /// `filterable` or not, see [`filter_resources`].
+    pub fn layout_entry(&self, binding: u32, filterable: bool) -> wgpu::BindGroupLayoutEntry {
+        let ty = match self {
+            Self::Texture { dimension, .. } => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                view_dimension: dimension.view(),
+                multisampled: false,
+            },
+            Self::SceneColor | Self::MainTexture => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            Self::SceneDepth => wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            Self::Sampler(state) => wgpu::BindingType::Sampler(match state.filter {
+                Filter::Linear => wgpu::SamplerBindingType::Filtering,
+                Filter::Point => wgpu::SamplerBindingType::NonFiltering,
+            }),
+            Self::Properties
+            | Self::Time
+            | Self::Camera
+            | Self::Transform
+            | Self::Tint
+            | Self::Viewport => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            Self::StorageTexture => wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            Self::StorageBuffer { writable } => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: !writable,
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        };
+        // Vertex shaders can't write to storage.
+        let visibility = match self {
+            Self::StorageTexture | Self::StorageBuffer { writable: true } => {
+                wgpu::ShaderStages::COMPUTE
+            }
+            _ => wgpu::ShaderStages::VERTEX_FRAGMENT,
+        };
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        }
+    }
+}
+
+#[test]
+fn unfilterable_textures() {
+    let texture = |path: &str| Resource::Texture {
+        path: path.to_string(),
+        dimension: TextureDimension::D2,
+    };
+    let mut resources = vec![
+        texture("albedo.png"),
+        texture("height.exr"),
+        Resource::Sampler(SamplerState::default()),
+    ];
+    let format = |path: &str| match path {
+        "albedo.png" => Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+        _ => None,
+    };
+    assert!(filter_resources(&mut resources.clone(), format));
+
+    // Float images can only be read through point samplers.
+    let format = |path: &str| match path {
+        "albedo.png" => Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+        _ => Some(wgpu::TextureFormat::Rgba32Float),
+    };
+    assert!(!filter_resources(&mut resources, format));
+    assert_eq!(
+        resources[2],
+        Resource::Sampler(SamplerState {
+            filter: Filter::Point,
+            wrap: WrapMode::Repeat,
+        })
+    );
+    let entry = resources[2].layout_entry(2, false);
+    let sampler = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering);
+    assert_eq!(entry.ty, sampler);
+}
@@ -1,5 +1,6 @@
-use super::expr::{Emit, EmitResult};
+use super::expr::{Emit, EmitResult, Float, Sint, Uint};
 use super::FnBuilder;
+use crate::workspace::Data;
 use naga::{
     front::Typifier,
     proc::{ResolveContext, ResolveError},
@@ -27,6 +28,10 @@ pub struct BaseTypes {
     pub f32x2: Handle<Type>,
     pub f32x3: Handle<Type>,
     pub f32x4: Handle<Type>,
+
+    pub f32x2x2: Handle<Type>,
+    pub f32x3x3: Handle<Type>,
+    pub f32x4x4: Handle<Type>,
 }
 
 impl BaseTypes {
@@ -41,6 +46,15 @@ impl BaseTypes {
             Type { name: None, inner }
         }
 
+        const fn matrix(size: VectorSize) -> Type {
+            let inner = TypeInner::Matrix {
+                columns: size,
+                rows: size,
+                width: 4,
+            };
+            Type { name: None, inner }
+        }
+
         Self {
             bool: module.insert_type(scalar(ScalarKind::Bool, naga::BOOL_WIDTH)),
             bool2: module.insert_type(vector(ScalarKind::Bool, VectorSize::Bi, naga::BOOL_WIDTH)),
@@ -61,6 +75,10 @@ impl BaseTypes {
             f32x2: module.insert_type(vector(ScalarKind::Float, VectorSize::Bi, 4)),
             f32x3: module.insert_type(vector(ScalarKind::Float, VectorSize::Tri, 4)),
             f32x4: module.insert_type(vector(ScalarKind::Float, VectorSize::Quad, 4)),
+
+            f32x2x2: module.insert_type(matrix(VectorSize::Bi)),
+            f32x3x3: module.insert_type(matrix(VectorSize::Tri)),
+            f32x4x4: module.insert_type(matrix(VectorSize::Quad)),
         }
     }
 }
@@ -98,15 +116,9 @@ pub fn is_vector(ty: &TypeInner) -> Option<(ScalarKind, VectorSize)> {
     }
 }
 
-#[derive(Clone, Copy)]
-pub enum MatrixKind {
-    M2,
-    M3,
-    M4,
-}
 */
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum VectorKind {
     V1,
     V2,
@@ -164,13 +176,20 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
         src: VectorKind,
         dst: VectorKind,
     ) -> EmitResult {
-        use super::expr::Float;
         use VectorKind::*;
 
         let function = self;
 
-        let zero = Float(0.0).emit(function)?;
-        let one = Float(1.0).emit(function)?;
+        // Padding takes the kind of the components, integers staying exact.
+        let kind = match *function.extract_type(expr)? {
+            TypeInner::Scalar { kind, .. } | TypeInner::Vector { kind, .. } => kind,
+            _ => return Err(super::EmitError::FailType),
+        };
+        let (zero, one) = match kind {
+            ScalarKind::Sint => (Sint(0).emit(function)?, Sint(1).emit(function)?),
+            ScalarKind::Uint => (Uint(0).emit(function)?, Uint(1).emit(function)?),
+            _ => (Float(0.0).emit(function)?, Float(1.0).emit(function)?),
+        };
 
         macro_rules! swizzle {
             ($x:ident, $y:ident, $z:ident, $w:ident) => {
@@ -232,3 +251,147 @@ impl<'a, 'storage> FnBuilder<'a, 'storage> {
         })
     }
 }
+
+/// Size of a square matrix of floats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatrixKind {
+    M2,
+    M3,
+    M4,
+}
+
+impl MatrixKind {
+    pub fn parse(ty: &TypeInner) -> Option<Self> {
+        match ty {
+            TypeInner::Matrix { columns, rows, .. } if columns == rows => Some(match columns {
+                VectorSize::Bi => Self::M2,
+                VectorSize::Tri => Self::M3,
+                VectorSize::Quad => Self::M4,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn from_data(data: Data) -> Option<Self> {
+        match data {
+            Data::Matrix2 => Some(Self::M2),
+            Data::Matrix3 => Some(Self::M3),
+            Data::Matrix4 | Data::MatrixAny => Some(Self::M4),
+            _ => None,
+        }
+    }
+
+    /// Matrix with `kind` columns.
+    pub fn from_vector(kind: VectorKind) -> Option<Self> {
+        match kind {
+            VectorKind::V1 => None,
+            VectorKind::V2 => Some(Self::M2),
+            VectorKind::V3 => Some(Self::M3),
+            VectorKind::V4 => Some(Self::M4),
+        }
+    }
+
+    /// Kind of the rows and columns.
+    pub fn vector(self) -> VectorKind {
+        match self {
+            Self::M2 => VectorKind::V2,
+            Self::M3 => VectorKind::V3,
+            Self::M4 => VectorKind::V4,
+        }
+    }
+
+    pub fn size(self) -> u32 {
+        match self {
+            Self::M2 => 2,
+            Self::M3 => 3,
+            Self::M4 => 4,
+        }
+    }
+
+    pub fn data(self) -> Data {
+        match self {
+            Self::M2 => Data::Matrix2,
+            Self::M3 => Data::Matrix3,
+            Self::M4 => Data::Matrix4,
+        }
+    }
+
+    pub fn ty(self, function: &mut FnBuilder) -> Handle<Type> {
+        let ty = BaseTypes::new(function.module);
+        match self {
+            Self::M2 => ty.f32x2x2,
+            Self::M3 => ty.f32x3x3,
+            Self::M4 => ty.f32x4x4,
+        }
+    }
+
+    /// Type of the columns.
+    pub fn column_ty(self, function: &mut FnBuilder) -> Handle<Type> {
+        let ty = BaseTypes::new(function.module);
+        match self {
+            Self::M2 => ty.f32x2,
+            Self::M3 => ty.f32x3,
+            Self::M4 => ty.f32x4,
+        }
+    }
+
+    /// Composes a matrix from its columns.
+    pub fn compose(self, function: &mut FnBuilder, columns: Vec<Handle<Expression>>) -> EmitResult {
+        assert_eq!(columns.len(), self.size() as usize);
+        let ty = self.ty(function);
+        Ok(function.emit(Expression::Compose {
+            ty,
+            components: columns,
+        }))
+    }
+
+    /// Column `index` of the identity, the `index` component being 1.
+    fn identity_column(self, function: &mut FnBuilder, index: u32) -> EmitResult {
+        let components: Vec<_> = (0..self.size())
+            .map(|row| Float(if row == index { 1.0 } else { 0.0 }).emit(function))
+            .collect::<EmitResult<_>>()?;
+        let ty = self.column_ty(function);
+        Ok(function.emit(Expression::Compose { ty, components }))
+    }
+
+    pub fn identity(self, function: &mut FnBuilder) -> EmitResult {
+        let columns = (0..self.size())
+            .map(|index| self.identity_column(function, index))
+            .collect::<EmitResult<_>>()?;
+        self.compose(function, columns)
+    }
+}
+
+impl<'a, 'storage> FnBuilder<'a, 'storage> {
+    /// Resizes a matrix, keeping its upper left corner and completing it with
+    /// the identity, so that a `mat4x4` transformation gives its `mat3x3` rotation
+    /// and scale.
+    pub fn resolve_matrix(
+        &mut self,
+        expr: Handle<Expression>,
+        src: MatrixKind,
+        dst: MatrixKind,
+    ) -> EmitResult {
+        if src == dst {
+            return Ok(expr);
+        }
+
+        let zero = Float(0.0).emit(self)?;
+        let mut columns = Vec::new();
+        for index in 0..dst.size() {
+            let column = if index < src.size() {
+                let column = self.access_index(expr, index);
+                let mut components: Vec<_> = (0..dst.size().min(src.size()))
+                    .map(|row| self.access_index(column, row))
+                    .collect();
+                components.resize(dst.size() as usize, zero);
+                let ty = dst.column_ty(self);
+                self.emit(Expression::Compose { ty, components })
+            } else {
+                dst.identity_column(self, index)?
+            };
+            columns.push(column);
+        }
+        dst.compose(self, columns)
+    }
+}
@@ -0,0 +1,254 @@
+use super::expr::{AccessIndex, Emit, FunctionArgument};
+use super::{BaseTypes, EmitError, EmitResult, FnBuilder, ModuleBuilder, VectorKind};
+use naga::{
+    Binding, BuiltIn, Expression, Handle, Interpolation, MathFunction, Sampling, ShaderStage, Type,
+};
+
+/// Vertex attributes of the preview meshes, each in its own vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    Position,
+    Normal,
+    Uv0,
+    /// Tangent with the sign of the bitangent in `w`.
+    Tangent,
+    Uv1,
+    /// Linear RGBA color of sprites and widgets.
+    Color,
+}
+
+impl Attribute {
+    /// Same locations as the attributes of bevy meshes.
+    pub fn location(self) -> u32 {
+        match self {
+            Self::Position => 0,
+            Self::Normal => 1,
+            Self::Uv0 => 2,
+            Self::Tangent => 3,
+            Self::Uv1 => 4,
+            Self::Color => 5,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Position => "vertex_position",
+            Self::Normal => "vertex_normal",
+            Self::Uv0 => "vertex_uv0",
+            Self::Tangent => "vertex_tangent",
+            Self::Uv1 => "vertex_uv1",
+            Self::Color => "vertex_color",
+        }
+    }
+
+    pub fn format(self) -> wgpu::VertexFormat {
+        match self {
+            Self::Position | Self::Normal => wgpu::VertexFormat::Float32x3,
+            Self::Uv0 | Self::Uv1 => wgpu::VertexFormat::Float32x2,
+            Self::Tangent | Self::Color => wgpu::VertexFormat::Float32x4,
+        }
+    }
+
+    fn ty(self, module: &mut ModuleBuilder) -> Handle<Type> {
+        let ty = BaseTypes::new(module);
+        match self {
+            Self::Position | Self::Normal => ty.f32x3,
+            Self::Uv0 | Self::Uv1 => ty.f32x2,
+            Self::Tangent | Self::Color => ty.f32x4,
+        }
+    }
+}
+
+/// Values interpolated from `vs_main` to `fs_main`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Varying {
+    Position,
+    Normal,
+    Tangent,
+    Bitangent,
+    Uv0,
+    Uv1,
+    Color,
+    /// Index of the vertex, not interpolated.
+    VertexId,
+    /// Index of the instance, not interpolated.
+    InstanceId,
+}
+
+impl Varying {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Position => "position",
+            Self::Normal => "normal",
+            Self::Tangent => "tangent",
+            Self::Bitangent => "bitangent",
+            Self::Uv0 => "uv0",
+            Self::Uv1 => "uv1",
+            Self::Color => "color",
+            Self::VertexId => "vertex_id",
+            Self::InstanceId => "instance_id",
+        }
+    }
+
+    pub fn ty(self, module: &mut ModuleBuilder) -> Handle<Type> {
+        let ty = BaseTypes::new(module);
+        match self {
+            Self::Uv0 | Self::Uv1 => ty.f32x2,
+            Self::Color => ty.f32x4,
+            Self::VertexId | Self::InstanceId => ty.u32,
+            _ => ty.f32x3,
+        }
+    }
+
+    /// Binding of the varying on both sides, integers being flat as WGSL requires.
+    pub fn binding(self, location: u32) -> Binding {
+        match self {
+            Self::VertexId | Self::InstanceId => Binding::Location {
+                location,
+                interpolation: Some(Interpolation::Flat),
+                sampling: None,
+            },
+            _ => Binding::Location {
+                location,
+                interpolation: Some(Interpolation::Perspective),
+                sampling: Some(Sampling::Center),
+            },
+        }
+    }
+
+    /// Computes the varying from the vertex attributes in `vs_main`.
+    pub fn vertex(self, function: &mut FnBuilder) -> EmitResult {
+        Ok(match self {
+            Self::Position => function.attribute(Attribute::Position)?,
+            Self::Normal => function.attribute(Attribute::Normal)?,
+            Self::Uv0 => function.attribute(Attribute::Uv0)?,
+            Self::Uv1 => function.attribute(Attribute::Uv1)?,
+            Self::Color => function.attribute(Attribute::Color)?,
+            Self::VertexId | Self::InstanceId => {
+                // Members of the `VertexInput` argument of `vs_main`.
+                let input = FunctionArgument(0).emit(function)?;
+                let index = if self == Self::VertexId { 0 } else { 1 };
+                AccessIndex(input, index).emit(function)?
+            }
+            Self::Tangent => {
+                let tangent = function.attribute(Attribute::Tangent)?;
+                function.resolve_vector(tangent, VectorKind::V4, VectorKind::V3)?
+            }
+            Self::Bitangent => {
+                let normal = function.attribute(Attribute::Normal)?;
+                let tangent = function.attribute(Attribute::Tangent)?;
+                let sign = function.access_index(tangent, 3);
+                let tangent = function.resolve_vector(tangent, VectorKind::V4, VectorKind::V3)?;
+                let cross = function.emit(Expression::Math {
+                    fun: MathFunction::Cross,
+                    arg: normal,
+                    arg1: Some(tangent),
+                    arg2: None,
+                    arg3: None,
+                });
+                function.emit(Expression::Binary {
+                    op: naga::BinaryOperator::Multiply,
+                    left: cross,
+                    right: sign,
+                })
+            }
+        })
+    }
+}
+
+/// Built-in inputs of `cs_main`, each a `vec3<u32>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InvocationId {
+    Global,
+    Local,
+    Workgroup,
+}
+
+impl InvocationId {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global_invocation_id",
+            Self::Local => "local_invocation_id",
+            Self::Workgroup => "workgroup_id",
+        }
+    }
+
+    fn builtin(self) -> BuiltIn {
+        match self {
+            Self::Global => BuiltIn::GlobalInvocationId,
+            Self::Local => BuiltIn::LocalInvocationId,
+            Self::Workgroup => BuiltIn::WorkGroupId,
+        }
+    }
+}
+
+impl<'a, 'storage> FnBuilder<'a, 'storage> {
+    /// Reads a varying, from the vertex attributes in `vs_main` or as an
+    /// interpolated argument of `fs_main`.
+    pub fn varying(&mut self, varying: Varying) -> EmitResult {
+        match self.module.stage {
+            Some(ShaderStage::Vertex) => varying.vertex(self),
+            Some(ShaderStage::Fragment) => {
+                let location = match self.module.varyings.iter().position(|&v| v == varying) {
+                    Some(location) => location,
+                    None => {
+                        self.module.varyings.push(varying);
+                        self.module.varyings.len() - 1
+                    }
+                };
+                let ty = varying.ty(self.module);
+                let binding = varying.binding(location as u32);
+                Ok(self.entry_argument(varying.name(), ty, binding))
+            }
+            _ => Err(EmitError::NotInEntryPoint(varying.name())),
+        }
+    }
+
+    /// Reads a vertex attribute in `vs_main`.
+    pub fn attribute(&mut self, attribute: Attribute) -> EmitResult {
+        if self.module.stage != Some(ShaderStage::Vertex) {
+            return Err(EmitError::NotInEntryPoint(attribute.name()));
+        }
+        if !self.module.attributes.contains(&attribute) {
+            self.module.attributes.push(attribute);
+        }
+        let ty = attribute.ty(self.module);
+        let binding = Binding::Location {
+            location: attribute.location(),
+            interpolation: None,
+            sampling: None,
+        };
+        Ok(self.entry_argument(attribute.name(), ty, binding))
+    }
+
+    /// Reads the id of the invocation running `cs_main`.
+    pub fn invocation(&mut self, invocation: InvocationId) -> EmitResult {
+        if self.module.stage != Some(ShaderStage::Compute) {
+            return Err(EmitError::ComputeOnly(invocation.name()));
+        }
+        let ty = BaseTypes::new(self.module).u32x3;
+        let binding = Binding::BuiltIn(invocation.builtin());
+        Ok(self.entry_argument(invocation.name(), ty, binding))
+    }
+
+    /// Argument of the entry point named `name`, added on first use.
+    fn entry_argument(
+        &mut self,
+        name: &str,
+        ty: Handle<Type>,
+        binding: Binding,
+    ) -> Handle<Expression> {
+        let arguments = &self.function.arguments;
+        let index = match arguments
+            .iter()
+            .position(|arg| arg.name.as_deref() == Some(name))
+        {
+            Some(index) => index,
+            None => {
+                self.argument(name, ty, binding);
+                self.function.arguments.len() - 1
+            }
+        };
+        self.expression(Expression::FunctionArgument(index as u32))
+    }
+}
@@ -63,6 +63,277 @@ fn builtin_remap_4(input: vec4<f32>, in_range: vec2<f32>, out_range: vec2<f32>)
     return out_range.x + (input - in_range.x) * (out_range.y - out_range.x) / (in_range.y - in_range.x);
 }
 
+// WGSL has `determinant` and `transpose`, but no `inverse`.
+fn builtin_matrix_inverse_2(m: mat2x2<f32>) -> mat2x2<f32> {
+    let adjugate = mat2x2<f32>(
+        vec2<f32>(m[1][1], -m[0][1]),
+        vec2<f32>(-m[1][0], m[0][0]),
+    );
+    return adjugate * (1.0 / determinant(m));
+}
+fn builtin_matrix_inverse_3(m: mat3x3<f32>) -> mat3x3<f32> {
+    let r0 = cross(m[1], m[2]);
+    let r1 = cross(m[2], m[0]);
+    let r2 = cross(m[0], m[1]);
+    return transpose(mat3x3<f32>(r0, r1, r2)) * (1.0 / dot(m[0], r0));
+}
+fn builtin_matrix_inverse_4(m: mat4x4<f32>) -> mat4x4<f32> {
+    let a00 = m[0][0]; let a01 = m[0][1]; let a02 = m[0][2]; let a03 = m[0][3];
+    let a10 = m[1][0]; let a11 = m[1][1]; let a12 = m[1][2]; let a13 = m[1][3];
+    let a20 = m[2][0]; let a21 = m[2][1]; let a22 = m[2][2]; let a23 = m[2][3];
+    let a30 = m[3][0]; let a31 = m[3][1]; let a32 = m[3][2]; let a33 = m[3][3];
+
+    let b00 = a00 * a11 - a01 * a10;
+    let b01 = a00 * a12 - a02 * a10;
+    let b02 = a00 * a13 - a03 * a10;
+    let b03 = a01 * a12 - a02 * a11;
+    let b04 = a01 * a13 - a03 * a11;
+    let b05 = a02 * a13 - a03 * a12;
+    let b06 = a20 * a31 - a21 * a30;
+    let b07 = a20 * a32 - a22 * a30;
+    let b08 = a20 * a33 - a23 * a30;
+    let b09 = a21 * a32 - a22 * a31;
+    let b10 = a21 * a33 - a23 * a31;
+    let b11 = a22 * a33 - a23 * a32;
+    let det = b00 * b11 - b01 * b10 + b02 * b09 + b03 * b08 - b04 * b07 + b05 * b06;
+
+    let adjugate = mat4x4<f32>(
+        vec4<f32>(a11 * b11 - a12 * b10 + a13 * b09, a02 * b10 - a01 * b11 - a03 * b09, a31 * b05 - a32 * b04 + a33 * b03, a22 * b04 - a21 * b05 - a23 * b03),
+        vec4<f32>(a12 * b08 - a10 * b11 - a13 * b07, a00 * b11 - a02 * b08 + a03 * b07, a32 * b02 - a30 * b05 - a33 * b01, a20 * b05 - a22 * b02 + a23 * b01),
+        vec4<f32>(a10 * b10 - a11 * b08 + a13 * b06, a01 * b08 - a00 * b10 - a03 * b06, a30 * b04 - a31 * b02 + a33 * b00, a21 * b02 - a20 * b04 - a23 * b00),
+        vec4<f32>(a11 * b07 - a10 * b09 - a12 * b06, a00 * b09 - a01 * b07 + a02 * b06, a31 * b01 - a30 * b03 - a32 * b00, a20 * b03 - a21 * b01 + a22 * b00),
+    );
+    return adjugate * (1.0 / det);
+}
+
+fn builtin_channel_mixer(input: vec3<f32>, red: vec3<f32>, green: vec3<f32>, blue: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(dot(input, red), dot(input, green), dot(input, blue));
+}
+
+fn builtin_contrast(input: vec3<f32>, contrast: f32) -> vec3<f32> {
+    let midpoint = pow(0.5, 2.2);
+    return (input - midpoint) * contrast + midpoint;
+}
+
+fn _rgb_to_hsv(rgb: vec3<f32>) -> vec3<f32> {
+    let k = vec4<f32>(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+    let p = mix(vec4<f32>(rgb.bg, k.wz), vec4<f32>(rgb.gb, k.xy), step(rgb.b, rgb.g));
+    let q = mix(vec4<f32>(p.xyw, rgb.r), vec4<f32>(rgb.r, p.yzx), step(p.x, rgb.r));
+    let d = q.x - min(q.w, q.y);
+    let e = 1e-10;
+    return vec3<f32>(abs(q.z + (q.w - q.y) / (6.0 * d + e)), d / (q.x + e), q.x);
+}
+
+fn _hsv_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
+    let k = vec4<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+    let p = abs(fract(hsv.xxx + k.xyz) * 6.0 - k.www);
+    return hsv.z * mix(k.xxx, clamp(p - k.xxx, vec3<f32>(0.0), vec3<f32>(1.0)), hsv.y);
+}
+
+// Rotates the hue by `offset` turns.
+fn builtin_hue(input: vec3<f32>, offset: f32) -> vec3<f32> {
+    let hsv = _rgb_to_hsv(input);
+    return _hsv_to_rgb(vec3<f32>(fract(hsv.x + offset), hsv.yz));
+}
+
+fn builtin_replace_color(input: vec3<f32>, color: vec3<f32>, replacement: vec3<f32>, range: f32, fuzziness: f32) -> vec3<f32> {
+    let d = distance(color, input);
+    return mix(replacement, input, clamp((d - range) / max(fuzziness, 1e-5), 0.0, 1.0));
+}
+
+fn builtin_saturation(input: vec3<f32>, saturation: f32) -> vec3<f32> {
+    let luma = dot(input, vec3<f32>(0.2126729, 0.7151522, 0.0721750));
+    return luma + saturation * (input - luma);
+}
+
+// Von Kries adaptation in the LMS space, from the D65 white point to the one
+// shifted by `temperature` (blue to yellow) and `tint` (green to magenta).
+fn builtin_white_balance(input: vec3<f32>, temperature: f32, tint: f32) -> vec3<f32> {
+    let t1 = temperature * 10.0 / 6.0;
+    let t2 = tint * 10.0 / 6.0;
+
+    let x = 0.31271 - t1 * select(0.05, 0.1, t1 < 0.0);
+    let y = 2.87 * x - 3.0 * x * x - 0.27509507 + t2 * 0.05;
+
+    let white_x = x / y;
+    let white_z = (1.0 - x - y) / y;
+    let w1 = vec3<f32>(0.949237, 1.03542, 1.08728);
+    let w2 = vec3<f32>(
+        0.7328 * white_x + 0.4296 - 0.1624 * white_z,
+        -0.7036 * white_x + 1.6975 + 0.0061 * white_z,
+        0.0030 * white_x + 0.0136 + 0.9834 * white_z,
+    );
+
+    // Rows of the matrices, multiplied on the left.
+    let linear_to_lms = mat3x3<f32>(
+        vec3<f32>(3.90405e-1, 5.49941e-1, 8.92632e-3),
+        vec3<f32>(7.08416e-2, 9.63172e-1, 1.35775e-3),
+        vec3<f32>(2.31082e-2, 1.28021e-1, 9.36245e-1),
+    );
+    let lms_to_linear = mat3x3<f32>(
+        vec3<f32>(2.85847e+0, -1.62879e+0, -2.48910e-2),
+        vec3<f32>(-2.10182e-1, 1.15820e+0, 3.24281e-4),
+        vec3<f32>(-4.18120e-2, -1.18169e-1, 1.06867e+0),
+    );
+    let lms = input * linear_to_lms * (w1 / w2);
+    return lms * lms_to_linear;
+}
+
+fn builtin_color_mask(input: vec3<f32>, mask: vec3<f32>, range: f32, fuzziness: f32) -> f32 {
+    let d = distance(mask, input);
+    return clamp(1.0 - (d - range) / max(fuzziness, 1e-5), 0.0, 1.0);
+}
+
+// Ordered dithering, from a 4x4 Bayer matrix indexed by the pixel.
+fn builtin_dither_threshold(pixel: vec2<f32>) -> f32 {
+    var thresholds = array<f32, 16>(
+        1.0 / 17.0, 9.0 / 17.0, 3.0 / 17.0, 11.0 / 17.0,
+        13.0 / 17.0, 5.0 / 17.0, 15.0 / 17.0, 7.0 / 17.0,
+        4.0 / 17.0, 12.0 / 17.0, 2.0 / 17.0, 10.0 / 17.0,
+        16.0 / 17.0, 8.0 / 17.0, 14.0 / 17.0, 6.0 / 17.0,
+    );
+    let p = vec2<u32>(abs(pixel)) % vec2<u32>(4u);
+    return thresholds[p.x * 4u + p.y];
+}
+
+// Blend modes, `blend` being drawn over `base`.
+fn builtin_blend_burn(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return 1.0 - (1.0 - blend) / base;
+}
+fn builtin_blend_darken(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return min(blend, base);
+}
+fn builtin_blend_difference(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return abs(blend - base);
+}
+fn builtin_blend_dodge(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return base / (1.0 - blend);
+}
+fn builtin_blend_divide(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return base / (blend + 1e-9);
+}
+fn builtin_blend_exclusion(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return blend + base - 2.0 * blend * base;
+}
+fn builtin_blend_hard_light(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    let dark = 2.0 * base * blend;
+    let light = 1.0 - 2.0 * (1.0 - base) * (1.0 - blend);
+    return select(light, dark, blend <= vec4<f32>(0.5));
+}
+fn builtin_blend_hard_mix(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return step(1.0 - base, blend);
+}
+fn builtin_blend_lighten(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return max(blend, base);
+}
+fn builtin_blend_linear_burn(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return base + blend - 1.0;
+}
+fn builtin_blend_linear_dodge(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return base + blend;
+}
+fn builtin_blend_linear_light(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    let dark = max(base + 2.0 * blend - 1.0, vec4<f32>(0.0));
+    let light = min(base + 2.0 * (blend - 0.5), vec4<f32>(1.0));
+    return select(light, dark, blend < vec4<f32>(0.5));
+}
+fn builtin_blend_linear_light_add_sub(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return blend + 2.0 * base - 1.0;
+}
+fn builtin_blend_multiply(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return base * blend;
+}
+fn builtin_blend_negation(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return 1.0 - abs(1.0 - blend - base);
+}
+fn builtin_blend_overlay(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    let dark = 2.0 * base * blend;
+    let light = 1.0 - 2.0 * (1.0 - base) * (1.0 - blend);
+    return select(light, dark, base <= vec4<f32>(0.5));
+}
+fn builtin_blend_pin_light(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    let dark = min(2.0 * base, blend);
+    let light = max(2.0 * (base - 0.5), blend);
+    return select(dark, light, blend >= vec4<f32>(0.5));
+}
+fn builtin_blend_screen(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return 1.0 - (1.0 - blend) * (1.0 - base);
+}
+fn builtin_blend_soft_light(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    let dark = 2.0 * base * blend + base * base * (1.0 - 2.0 * blend);
+    let light = sqrt(base) * (2.0 * blend - 1.0) + 2.0 * base * (1.0 - blend);
+    return select(dark, light, blend >= vec4<f32>(0.5));
+}
+fn builtin_blend_subtract(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return base - blend;
+}
+fn builtin_blend_vivid_light(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    let dark = 1.0 - (1.0 - blend) / (2.0 * base);
+    let light = blend / (2.0 * (1.0 - base));
+    return select(dark, light, base >= vec4<f32>(0.5));
+}
+fn builtin_blend_overwrite(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
+    return blend;
+}
+
+// Shapes, as signed distances in UV units from the center of the UV square,
+// negative inside. `width` and `height` are fractions of the square.
+fn builtin_sdf_mask(sdf: f32, width: f32) -> f32 {
+    return clamp(0.5 - sdf / max(width, 1e-5), 0.0, 1.0);
+}
+fn _shape_size(width: f32, height: f32) -> vec2<f32> {
+    return max(abs(vec2<f32>(width, height)) * 0.5, vec2<f32>(1e-5));
+}
+fn builtin_ellipse_sdf(uv: vec2<f32>, width: f32, height: f32) -> f32 {
+    // Not exact away from the edge, which is all the mask needs.
+    let size = _shape_size(width, height);
+    let p = uv - 0.5;
+    let k0 = length(p / size);
+    let k1 = length(p / (size * size));
+    if (k1 == 0.0) {
+        return -min(size.x, size.y);
+    }
+    return k0 * (k0 - 1.0) / k1;
+}
+fn _box_sdf(p: vec2<f32>, size: vec2<f32>, radius: f32) -> f32 {
+    let q = abs(p) - size + radius;
+    return length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - radius;
+}
+fn builtin_rectangle_sdf(uv: vec2<f32>, width: f32, height: f32) -> f32 {
+    return _box_sdf(uv - 0.5, _shape_size(width, height), 0.0);
+}
+fn builtin_rounded_rectangle_sdf(uv: vec2<f32>, width: f32, height: f32, radius: f32) -> f32 {
+    let size = _shape_size(width, height);
+    return _box_sdf(uv - 0.5, size, clamp(radius, 0.0, min(size.x, size.y)));
+}
+// Regular polygon of unit circumradius with a vertex up, its edges rounded
+// by `roundness` times the inradius.
+fn _polygon_sdf(p: vec2<f32>, sides: f32, roundness: f32) -> f32 {
+    let sector = 3.14159265 / max(floor(sides), 3.0);
+    let corner = vec2<f32>(cos(sector), sin(sector));
+    let turn = atan2(p.x, -p.y);
+    let angle = turn - floor(turn / (2.0 * sector)) * 2.0 * sector - sector;
+    let radius = clamp(roundness, 0.0, 1.0);
+    let q = length(p) * vec2<f32>(cos(angle), abs(sin(angle))) - (1.0 - radius) * corner;
+    let edge = vec2<f32>(q.x, q.y + clamp(-q.y, 0.0, (1.0 - radius) * corner.y));
+    return length(edge) * sign(edge.x) - radius * corner.x;
+}
+fn builtin_polygon_sdf(uv: vec2<f32>, width: f32, height: f32, sides: f32) -> f32 {
+    let size = _shape_size(width, height);
+    return _polygon_sdf((uv - 0.5) / size, sides, 0.0) * min(size.x, size.y);
+}
+fn builtin_rounded_polygon_sdf(uv: vec2<f32>, width: f32, height: f32, sides: f32, roundness: f32) -> f32 {
+    let size = _shape_size(width, height);
+    return _polygon_sdf((uv - 0.5) / size, sides, roundness) * min(size.x, size.y);
+}
+// Distance to the nearest cell edge, negative in the cells of even parity.
+fn builtin_checkerboard_sdf(uv: vec2<f32>, frequency: vec2<f32>) -> f32 {
+    let p = uv * frequency;
+    let edge = (0.5 - abs(fract(p) - 0.5)) / max(abs(frequency), vec2<f32>(1e-5));
+    let parity = fract((floor(p.x) + floor(p.y)) * 0.5) * 2.0;
+    return (parity * 2.0 - 1.0) * min(edge.x, edge.y);
+}
+
 fn _gradient_noise_dir(p: vec2<f32>) -> vec2<f32> {
     let p = p % vec2<f32>(289.0);
     let x = (34.0 * p.x + 1.0) * p.x % 289.0 + p.y;
@@ -71,48 +342,204 @@ fn _gradient_noise_dir(p: vec2<f32>) -> vec2<f32> {
     return normalize(vec2<f32>(x - floor(x + 0.5), abs(x) - 0.5));
 }
 
-fn _gradient_noise(p: vec2<f32>) -> f32 {
+// Lattice cell `cell` repeating every `period` cells, or not at all if it is zero.
+fn _tile(cell: vec2<f32>, period: f32) -> vec2<f32> {
+    let period = round(period);
+    if (period < 1.0) {
+        return cell;
+    }
+    return cell - floor(cell / period) * period;
+}
+
+fn _gradient_noise(p: vec2<f32>, period: f32) -> f32 {
     let ip = floor(p);
     let fp = fract(p);
-    let d00 = dot(_gradient_noise_dir(ip), fp);
-    let d01 = dot(_gradient_noise_dir(ip + vec2<f32>(0.0, 1.0)), fp - vec2<f32>(0.0, 1.0));
-    let d10 = dot(_gradient_noise_dir(ip + vec2<f32>(1.0, 0.0)), fp - vec2<f32>(1.0, 0.0));
-    let d11 = dot(_gradient_noise_dir(ip + vec2<f32>(1.0, 1.0)), fp - vec2<f32>(1.0, 1.0));
+    let d00 = dot(_gradient_noise_dir(_tile(ip, period)), fp);
+    let d01 = dot(_gradient_noise_dir(_tile(ip + vec2<f32>(0.0, 1.0), period)), fp - vec2<f32>(0.0, 1.0));
+    let d10 = dot(_gradient_noise_dir(_tile(ip + vec2<f32>(1.0, 0.0), period)), fp - vec2<f32>(1.0, 0.0));
+    let d11 = dot(_gradient_noise_dir(_tile(ip + vec2<f32>(1.0, 1.0), period)), fp - vec2<f32>(1.0, 1.0));
     let fp = fp * fp * fp * (fp * (fp * 6.0 - 15.0) + 10.0);
     return mix(mix(d00, d01, fp.y), mix(d10, d11, fp.y), fp.x);
 }
 
 fn builtin_gradient_noise(uv: vec2<f32>, scale: f32) -> f32 {
-    return _gradient_noise(uv * scale) + 0.5;
+    return _gradient_noise(uv * scale, 0.0) + 0.5;
 }
 
 fn _random_01(uv: vec2<f32>) -> f32 {
     return fract(sin(dot(uv, vec2<f32>(12.9898, 78.233)))*43758.5453);
 }
 
-fn _value_noise(uv: vec2<f32>) -> f32 {
+fn _value_noise(uv: vec2<f32>, period: f32) -> f32 {
     let i = floor(uv);
     let f = fract(uv);
     let f = f * f * (3.0 - 2.0 * f);
 
     //let uv = abs(fract(uv) - 0.5);
 
-    let r0 = _random_01(i + vec2<f32>(0.0, 0.0));
-    let r1 = _random_01(i + vec2<f32>(1.0, 0.0));
-    let r2 = _random_01(i + vec2<f32>(0.0, 1.0));
-    let r3 = _random_01(i + vec2<f32>(1.0, 1.0));
+    let r0 = _random_01(_tile(i + vec2<f32>(0.0, 0.0), period));
+    let r1 = _random_01(_tile(i + vec2<f32>(1.0, 0.0), period));
+    let r2 = _random_01(_tile(i + vec2<f32>(0.0, 1.0), period));
+    let r3 = _random_01(_tile(i + vec2<f32>(1.0, 1.0), period));
 
     return mix(mix(r0, r1, f.x), mix(r2, r3, f.x), f.y);
 }
 
 fn builtin_simple_noise(uv: vec2<f32>, scale: f32) -> f32 {
     let t = 0.0;
-    let t = t + _value_noise(vec2<f32>(uv.x * scale    , uv.y * scale    )) * 0.125;
-    let t = t + _value_noise(vec2<f32>(uv.x * scale/2.0, uv.y * scale/2.0)) * 0.25;
-    let t = t + _value_noise(vec2<f32>(uv.x * scale/4.0, uv.y * scale/4.0)) * 0.5;
+    let t = t + _value_noise(vec2<f32>(uv.x * scale    , uv.y * scale    ), 0.0) * 0.125;
+    let t = t + _value_noise(vec2<f32>(uv.x * scale/2.0, uv.y * scale/2.0), 0.0) * 0.25;
+    let t = t + _value_noise(vec2<f32>(uv.x * scale/4.0, uv.y * scale/4.0), 0.0) * 0.5;
     return t;
 }
 
+fn builtin_value_noise(uv: vec2<f32>, scale: f32) -> f32 {
+    return _value_noise(uv * scale, 0.0);
+}
+
+// Tileable noises repeat every `period` lattice cells, so they are seamless
+// over the UV square when it matches `scale`.
+fn builtin_tileable_gradient_noise(uv: vec2<f32>, scale: f32, period: f32) -> f32 {
+    return _gradient_noise(uv * scale, period) + 0.5;
+}
+
+fn builtin_tileable_value_noise(uv: vec2<f32>, scale: f32, period: f32) -> f32 {
+    return _value_noise(uv * scale, period);
+}
+
+// Octaves of gradient noise, each `lacunarity` times finer and `gain` times
+// fainter than the last, normalized to the range of a single octave.
+fn _fractal_noise(p: vec2<f32>, octaves: f32, lacunarity: f32, gain: f32) -> f32 {
+    let count = clamp(i32(octaves), 1, 16);
+    var q = p;
+    var amplitude = 1.0;
+    var sum = 0.0;
+    var total = 0.0;
+    for (var i = 0; i < count; i = i + 1) {
+        sum = sum + _gradient_noise(q, 0.0) * amplitude;
+        total = total + amplitude;
+        q = q * lacunarity;
+        amplitude = amplitude * gain;
+    }
+    return sum / max(total, 1e-5);
+}
+
+fn builtin_fractal_noise(uv: vec2<f32>, scale: f32, octaves: f32, lacunarity: f32, gain: f32) -> f32 {
+    return _fractal_noise(uv * scale, octaves, lacunarity, gain) + 0.5;
+}
+
+// Offsets `uv` by two fractal noises, to feed the coordinates of another noise.
+fn builtin_domain_warp(uv: vec2<f32>, scale: f32, strength: f32) -> vec2<f32> {
+    let p = uv * scale;
+    let x = _fractal_noise(p, 4.0, 2.0, 0.5);
+    let y = _fractal_noise(p + vec2<f32>(5.2, 1.3), 4.0, 2.0, 0.5);
+    return uv + vec2<f32>(x, y) * strength;
+}
+
+// Distances to the nearest and second nearest cell points, to the border
+// between the nearest cells, and a random value of the nearest cell.
+struct VoronoiCell {
+    f1: f32,
+    f2: f32,
+    edge: f32,
+    cell: f32,
+};
+
+fn _voronoi_point(cell: vec2<f32>, angle_offset: f32) -> vec2<f32> {
+    let r = vec2<f32>(_random_01(cell), _random_01(cell + vec2<f32>(37.0, 17.0)));
+    return 0.5 + 0.5 * sin(angle_offset + 6.2831853 * r);
+}
+
+fn _voronoi(p: vec2<f32>, angle_offset: f32, period: f32) -> VoronoiCell {
+    let ip = floor(p);
+    let fp = fract(p);
+
+    var f1 = 8.0;
+    var f2 = 8.0;
+    var nearest = vec2<f32>(0.0);
+    var nearest_cell = vec2<f32>(0.0);
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y));
+            let cell = _tile(ip + offset, period);
+            let r = offset + _voronoi_point(cell, angle_offset) - fp;
+            let d = length(r);
+            if (d < f1) {
+                f2 = f1;
+                f1 = d;
+                nearest = r;
+                nearest_cell = cell;
+            } else if (d < f2) {
+                f2 = d;
+            }
+        }
+    }
+
+    var edge = 8.0;
+    for (var y = -2; y <= 2; y = y + 1) {
+        for (var x = -2; x <= 2; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y));
+            let r = offset + _voronoi_point(_tile(ip + offset, period), angle_offset) - fp;
+            let between = r - nearest;
+            if (dot(between, between) > 1e-5) {
+                edge = min(edge, dot(0.5 * (nearest + r), normalize(between)));
+            }
+        }
+    }
+    return VoronoiCell(f1, f2, edge, _random_01(nearest_cell + vec2<f32>(71.0, 13.0)));
+}
+
+fn builtin_voronoi(uv: vec2<f32>, angle_offset: f32, cell_density: f32) -> VoronoiCell {
+    return _voronoi(uv * cell_density, angle_offset, 0.0);
+}
+
+fn builtin_tileable_voronoi(uv: vec2<f32>, angle_offset: f32, cell_density: f32, period: f32) -> VoronoiCell {
+    return _voronoi(uv * cell_density, angle_offset, period);
+}
+
+// Lambert lighting of the mesh preview, with some ambient light.
+fn builtin_preview_light(color: vec4<f32>, normal: vec3<f32>, light: vec3<f32>) -> vec4<f32> {
+    let diffuse = max(dot(normalize(normal), light), 0.0);
+    return vec4<f32>(color.rgb * (0.25 + 0.75 * diffuse), color.a);
+}
+
+// Shading of the Lit master in previews, with a fixed light and viewer.
+// Graph materials replace it with the PBR lighting of bevy, see `lit.wgsl`.
+fn builtin_lit(
+    base_color: vec3<f32>,
+    normal_ts: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    emission: vec3<f32>,
+    occlusion: f32,
+    alpha: f32,
+    alpha_clip: f32,
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    tangent: vec3<f32>,
+    bitangent: vec3<f32>,
+    frag_coord: vec4<f32>,
+    is_front: bool,
+) -> vec4<f32> {
+    if (alpha < alpha_clip) {
+        discard;
+    }
+
+    var n = normalize(normal_ts.x * tangent + normal_ts.y * bitangent + normal_ts.z * normal);
+    if (!is_front) {
+        n = -n;
+    }
+
+    let l = normalize(vec3<f32>(0.5, 1.0, 0.8));
+    let h = normalize(l + vec3<f32>(0.0, 0.0, 1.0));
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let shininess = exp2(10.0 * (1.0 - roughness) + 1.0);
+
+    let diffuse = base_color * (1.0 - metallic) * (max(dot(n, l), 0.0) + 0.25);
+    let specular = f0 * pow(max(dot(n, h), 0.0), shininess);
+    return vec4<f32>((diffuse + specular) * occlusion + emission, alpha);
+}
+
 
 //  fn color_conv_rgb_rgb(input: vec3<f32>) -> vec3<f32> {
 //      return input;
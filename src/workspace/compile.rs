@@ -0,0 +1,285 @@
+//! `shaderlab compile`: turns a saved graph into shader source without a window or GPU.
+
+use super::builder::export::stage_suffix;
+use super::builder::{BindingLayout, Pass, PassReport};
+use super::nodes::custom::CustomFunction;
+use super::{ExportTarget, ExportedShader, Node, ShaderGraph, Storage};
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "usage: shaderlab compile <graph.shader> [--node <title|index>] \
+    [--target <wgsl|glsl|glsl-es|spirv|hlsl|msl>] [--output <file>] [--optimize]";
+
+struct Options {
+    input: PathBuf,
+    node: Option<String>,
+    target: ExportTarget,
+    output: Option<PathBuf>,
+    /// Runs every [`Pass`] before writing, reporting the sizes on stderr.
+    optimize: bool,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut input = None;
+        let mut node = None;
+        let mut target = ExportTarget::Wgsl;
+        let mut output = None;
+        let mut optimize = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-n" | "--node" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("missing value for {}", arg))?;
+                    node = Some(value.clone());
+                }
+                "-t" | "--target" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("missing value for {}", arg))?;
+                    target = ExportTarget::parse(value)
+                        .ok_or_else(|| anyhow!("unknown target {}", value))?;
+                }
+                "-o" | "--output" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("missing value for {}", arg))?;
+                    output = Some(PathBuf::from(value));
+                }
+                "-O" | "--optimize" => optimize = true,
+                _ if arg.starts_with('-') => bail!("unknown option {}", arg),
+                _ if input.is_none() => input = Some(PathBuf::from(arg)),
+                _ => bail!("unexpected argument {}", arg),
+            }
+        }
+
+        let input = input.ok_or_else(|| anyhow!("missing graph file"))?;
+        Ok(Self {
+            input,
+            node,
+            target,
+            output,
+            optimize,
+        })
+    }
+}
+
+/// Runs the `compile` subcommand and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n{}", err, USAGE);
+            return 2;
+        }
+    };
+
+    match compile(&options) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            for cause in err.chain().skip(1) {
+                eprintln!("  caused by: {}", cause);
+            }
+            1
+        }
+    }
+}
+
+fn compile(options: &Options) -> Result<()> {
+    let bytes = std::fs::read(&options.input)
+        .with_context(|| format!("failed to read {}", options.input.display()))?;
+
+    let graph = ShaderGraph::from_ron(&bytes)
+        .with_context(|| format!("failed to parse {}", options.input.display()))?;
+
+    let mut storage = graph.to_storage()?;
+    let dir = options.input.parent().unwrap_or_else(|| Path::new(""));
+    read_custom_functions(&mut storage, dir);
+    let node = find_master(&storage, options.node.as_deref())?;
+
+    let passes: &[Pass] = if options.optimize { &Pass::ALL } else { &[] };
+    let (shaders, reports) = export_node(&storage, node, options.target, passes)?;
+    for report in &reports {
+        eprintln!("{}", report);
+    }
+
+    match options.output.as_ref() {
+        Some(path) => {
+            // `out.wgsl` is documented by `out.layout.ron`.
+            let layout = binding_layout(&storage, node)?;
+            if !layout.is_empty() {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let path = path.with_file_name(format!("{}.layout.ron", stem));
+                std::fs::write(&path, layout.to_ron()?)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+
+            for shader in &shaders {
+                let path = match shader.stage {
+                    // `out.glsl` becomes `out.vert.glsl` and `out.frag.glsl`.
+                    Some(_) => {
+                        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                        path.with_file_name(shader.file_name(&stem, options.target))
+                    }
+                    None => path.clone(),
+                };
+                std::fs::write(&path, &shader.data)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+        }
+        None if options.target.is_binary() => {
+            let mut stdout = std::io::stdout();
+            for shader in &shaders {
+                stdout.write_all(&shader.data)?;
+            }
+        }
+        None => {
+            for shader in &shaders {
+                if let Some(stage) = shader.stage {
+                    println!("// {}", stage_suffix(stage));
+                }
+                print!("{}", String::from_utf8_lossy(&shader.data));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the files of the custom functions again, relative to `dir`, the
+/// folder of the graph. Functions whose file is missing keep the code saved
+/// in the graph.
+fn read_custom_functions(storage: &mut Storage, dir: &Path) {
+    let keys: Vec<Node> = storage.nodes.keys().collect();
+    for node in keys {
+        let builder = &mut storage.nodes[node].builder;
+        if let Some(custom) = builder.downcast_mut::<CustomFunction>() {
+            if !custom.has_file() {
+                continue;
+            }
+            if let Err(err) = custom.read_file(dir) {
+                eprintln!("warning: {}, using the code saved in the graph", err);
+            }
+            storage.sync_ports(node);
+        }
+    }
+}
+
+/// Builds and validates the module of `node`, returning WGSL.
+pub fn compile_node(storage: &Storage, node: Node) -> Result<String> {
+    let data = &storage.nodes[node];
+    let module = data
+        .builder
+        .module(node, storage)
+        .with_context(|| format!("failed to generate `{}`", data.title))?;
+
+    module
+        .build()
+        .with_context(|| format!("failed to compile `{}`", data.title))
+}
+
+/// Builds and validates the module of `node`, runs `passes` over it and writes
+/// it in the language of `target`.
+pub fn export_node(
+    storage: &Storage,
+    node: Node,
+    target: ExportTarget,
+    passes: &[Pass],
+) -> Result<(Vec<ExportedShader>, Vec<PassReport>)> {
+    let data = &storage.nodes[node];
+    let mut module = data
+        .builder
+        .module(node, storage)
+        .with_context(|| format!("failed to generate `{}`", data.title))?;
+
+    let reports = module
+        .optimize(passes)
+        .with_context(|| format!("failed to optimize `{}`", data.title))?;
+    let shaders = module
+        .export(target)
+        .with_context(|| format!("failed to export `{}` as {}", data.title, target.label()))?;
+    Ok((shaders, reports))
+}
+
+/// Describes the resources the shader of `node` binds.
+pub fn binding_layout(storage: &Storage, node: Node) -> Result<BindingLayout> {
+    let data = &storage.nodes[node];
+    let module = data
+        .builder
+        .module(node, storage)
+        .with_context(|| format!("failed to generate `{}`", data.title))?;
+    Ok(module.binding_layout())
+}
+
+/// Picks the master node by title or by its index in the file,
+/// or the only master node when `name` is not given.
+fn find_master(storage: &Storage, name: Option<&str>) -> Result<Node> {
+    // `to_storage` spawns nodes in file order into an empty storage.
+    let nodes: Vec<_> = storage.nodes.iter().collect();
+
+    if let Some(name) = name {
+        let by_index = name
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| nodes.get(index));
+        let by_title = || nodes.iter().find(|(_, node)| node.title == name);
+
+        let &(key, node) = by_index
+            .or_else(by_title)
+            .ok_or_else(|| anyhow!("no node named `{}`", name))?;
+
+        if !node.builder.is_master() {
+            bail!("`{}` is not a master node", node.title);
+        }
+
+        return Ok(key);
+    }
+
+    let mut masters = nodes.iter().filter(|(_, node)| node.builder.is_master());
+    match (masters.next(), masters.next()) {
+        (Some(&(key, _)), None) => Ok(key),
+        (None, _) => bail!("graph has no master node"),
+        (Some(_), Some(_)) => {
+            let titles: Vec<_> = nodes
+                .iter()
+                .filter(|(_, node)| node.builder.is_master())
+                .map(|(_, node)| node.title.as_str())
+                .collect();
+            bail!(
+                "graph has several master nodes ({}), pick one with --node",
+                titles.join(", ")
+            )
+        }
+    }
+}
+
+#[test]
+fn compile_master() {
+    let mut storage = Storage::default();
+    storage.spawn_kind("Color").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+
+    assert_eq!(find_master(&storage, None).unwrap(), master);
+    assert_eq!(find_master(&storage, Some("1")).unwrap(), master);
+    assert!(find_master(&storage, Some("Color")).is_err());
+
+    let source = compile_node(&storage, master).unwrap();
+    assert!(source.contains("fn vs_main"));
+    assert!(source.contains("fn fs_main"));
+
+    for target in ExportTarget::ALL {
+        let (shaders, _) = export_node(&storage, master, target, &Pass::ALL).unwrap();
+        assert!(!shaders.is_empty());
+        assert!(shaders.iter().all(|shader| !shader.data.is_empty()));
+    }
+
+    let (glsl, reports) = export_node(&storage, master, ExportTarget::GlslEs, &[]).unwrap();
+    assert!(reports.is_empty());
+    assert_eq!(glsl.len(), 2);
+    assert!(String::from_utf8_lossy(&glsl[0].data).starts_with("#version 300 es"));
+}
@@ -0,0 +1,138 @@
+use super::builder::{EmitError, ErrorLocation};
+use super::{Node, Port, Storage, Workspace};
+use crate::ui::{icon, EditorTab, Style};
+use bevy::ecs::system::lifetimeless::{SQuery, SRes, Write};
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use egui::style::Margin;
+use egui::*;
+
+/// A problem found while generating the shader of a node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// The node that caused the error, which may be upstream of the one being built.
+    pub node: Node,
+    pub port: Option<Port>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Blames the location of `error`, or `built` when it has none.
+    pub fn new(storage: &Storage, built: Node, error: &EmitError) -> Self {
+        let (node, port) = match error.location() {
+            Some(ErrorLocation::Node(node)) => (node, None),
+            Some(ErrorLocation::Port(port)) => (storage.ports[port].node, Some(port)),
+            None => (built, None),
+        };
+
+        let mut message = match port {
+            Some(port) => format!("{}: {}", storage.ports[port].label, error),
+            None => error.to_string(),
+        };
+
+        let mut source = std::error::Error::source(error);
+        while let Some(cause) = source {
+            message.push_str(": ");
+            message.push_str(&cause.to_string());
+            source = cause.source();
+        }
+
+        Self {
+            node,
+            port,
+            message,
+        }
+    }
+}
+
+/// Lists the problems of every node tree.
+#[derive(Default, Component)]
+pub struct Diagnostics;
+
+impl EditorTab for Diagnostics {
+    type Param = (SRes<Style>, SQuery<Write<Workspace>>);
+
+    fn ui<'w>(
+        &mut self,
+        ui: &mut egui::Ui,
+        _entity: Entity,
+        (style, workspaces): &mut SystemParamItem<'w, '_, Self::Param>,
+    ) {
+        let rect = ui.available_rect_before_wrap();
+        ui.painter().rect_filled(rect, 0.0, style.panel);
+
+        ui.scope(|ui| {
+            style.set_theme_visuals(ui);
+            style.for_scrollbar(ui);
+
+            let scroll = ScrollArea::vertical().auto_shrink([false; 2]);
+            scroll.show(ui, |ui| {
+                style.scrollarea(ui);
+
+                Frame::none()
+                    .inner_margin(Margin::same(4.0))
+                    .show(ui, |ui| {
+                        let mut empty = true;
+                        for mut workspace in workspaces.iter_mut() {
+                            let mut focus = None;
+                            for diagnostic in &workspace.diagnostics {
+                                empty = false;
+
+                                let title = workspace
+                                    .storage
+                                    .nodes
+                                    .get(diagnostic.node)
+                                    .map_or("?", |node| node.title.as_str());
+
+                                let text = format!("{} {}", icon::ERROR, title);
+                                let response = ui
+                                    .horizontal_wrapped(|ui| {
+                                        let label = ui.add(Label::new(text).sense(Sense::click()));
+                                        ui.label(&diagnostic.message);
+                                        label
+                                    })
+                                    .inner
+                                    .on_hover_text("Click to show the node");
+
+                                if response.clicked() {
+                                    focus = Some(diagnostic.node);
+                                }
+                            }
+
+                            if let Some(node) = focus {
+                                workspace.focus(node);
+                            }
+                        }
+
+                        if empty {
+                            ui.vertical_centered_justified(|ui| ui.label("No problems"));
+                        }
+                    });
+            });
+        });
+    }
+}
+
+#[test]
+fn blame_upstream_port() {
+    let mut storage = Storage::default();
+    let add = storage.spawn_kind("Add").unwrap();
+    let negate = storage.spawn_kind("Negate").unwrap();
+
+    let b = storage.nodes[add].inputs[1];
+    storage.ports[b].input_default = None;
+
+    let out = storage.nodes[add].outputs[0];
+    let input = storage.nodes[negate].inputs[0];
+    storage.link(out, input);
+
+    let builder = &storage.nodes[negate].builder;
+    let err = builder.module(negate, &storage).err().unwrap();
+    assert_eq!(err.location(), Some(ErrorLocation::Port(b)));
+    assert!(matches!(err.inner(), EmitError::MaybeDefault));
+
+    let diagnostic = Diagnostic::new(&storage, negate, &err);
+    assert_eq!(diagnostic.node, add);
+    assert_eq!(diagnostic.port, Some(b));
+    assert!(diagnostic.message.starts_with("b: "));
+}
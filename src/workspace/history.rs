@@ -0,0 +1,677 @@
+//! Undoable edits of a [`Storage`].
+//!
+//! Slotmap keys of a despawned node can't be brought back, so edits refer to
+//! nodes by [`NodeId`] and to ports by their position on the node.
+
+use super::asset::{ShaderGraph, ShaderGraphError};
+use super::blackboard::Property;
+use super::{Direction, InputDefault, Node, Port, Storage};
+use thiserror::Error;
+use undo::Merged;
+
+pub type History = undo::Record<Edit, fn(undo::Signal)>;
+
+/// Identifies a node for its whole life, including despawn and undo.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortRef {
+    pub node: NodeId,
+    pub direction: Direction,
+    pub index: usize,
+}
+
+/// Why a change couldn't be applied, the storage being left as it was.
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("node {0:?} is missing")]
+    NodeNotFound(NodeId),
+    #[error("node {:?} has no {:?} port #{}", .0.node, .0.direction, .0.index)]
+    PortNotFound(PortRef),
+    #[error("no link from {from:?} to {to:?}")]
+    LinkNotFound { from: PortRef, to: PortRef },
+    #[error("input #{} of node {:?} has no default value", .0.index, .0.node)]
+    NoDefault(PortRef),
+    #[error("node {0:?} can't be saved")]
+    Unsaved(NodeId),
+    #[error("failed to restore node {node:?}: {error}")]
+    Restore {
+        node: NodeId,
+        error: ShaderGraphError,
+    },
+}
+
+/// Values of an [`InputDefault`] editor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DefaultValue {
+    pub value: [f64; 4],
+    pub checked: bool,
+}
+
+impl DefaultValue {
+    pub fn new(default: &InputDefault) -> Self {
+        Self {
+            value: [default.x, default.y, default.z, default.w],
+            checked: default.checked,
+        }
+    }
+
+    fn set(self, default: &mut InputDefault) {
+        let [x, y, z, w] = self.value;
+        default.x = x;
+        default.y = y;
+        default.z = z;
+        default.w = w;
+        default.checked = self.checked;
+    }
+}
+
+impl Storage {
+    pub fn find(&self, id: NodeId) -> Option<Node> {
+        self.nodes
+            .iter()
+            .find_map(|(key, node)| (node.id == id).then_some(key))
+    }
+
+    pub fn port_ref(&self, port: Port) -> PortRef {
+        let data = &self.ports[port];
+        let node = &self.nodes[data.node];
+        let ports = match data.direction {
+            Direction::Input => &node.inputs,
+            Direction::Output => &node.outputs,
+        };
+
+        PortRef {
+            node: node.id,
+            direction: data.direction,
+            index: ports.iter().position(|&p| p == port).unwrap(),
+        }
+    }
+
+    pub fn resolve(&self, port: PortRef) -> Option<Port> {
+        let node = &self.nodes[self.find(port.node)?];
+        let ports = match port.direction {
+            Direction::Input => &node.inputs,
+            Direction::Output => &node.outputs,
+        };
+        ports.get(port.index).copied()
+    }
+
+    /// Moves a node together with its ports, so links follow before the next redraw.
+    pub fn translate(&mut self, node: Node, delta: egui::Vec2) {
+        let node = match self.nodes.get_mut(node) {
+            Some(node) => node,
+            None => return,
+        };
+
+        node.position += delta;
+        node.rect = node.rect.translate(delta);
+
+        for &port in node.inputs.iter().chain(node.outputs.iter()) {
+            let port = &mut self.ports[port];
+            port.position += delta;
+            port.rect = port.rect.translate(delta);
+        }
+    }
+}
+
+/// A node taken out of the storage, with everything needed to put it back.
+///
+/// Builders hold the keys of their ports, which can't be handed out again, so
+/// the node is kept like a copied one and spawned anew by kind when put back.
+pub struct RemovedNode {
+    id: NodeId,
+    graph: ShaderGraph,
+    links: Vec<(PortRef, PortRef)>,
+}
+
+impl RemovedNode {
+    /// Saves `node` and its links, leaving it in the storage.
+    pub fn save(storage: &Storage, node: Node) -> Option<Self> {
+        let data = storage.nodes.get(node)?;
+        let graph = ShaderGraph::from_nodes(storage, [node]);
+        if graph.nodes.is_empty() {
+            return None;
+        }
+
+        let mut links = Vec::new();
+        for &port in data.inputs.iter().chain(&data.outputs) {
+            for &link in &storage.ports[port].links {
+                if let Some(link) = storage.links.get(link) {
+                    let min = storage.port_ref(link.min.port);
+                    let max = storage.port_ref(link.max.port);
+                    if !links.contains(&(min, max)) {
+                        links.push((min, max));
+                    }
+                }
+            }
+        }
+
+        Some(Self {
+            id: data.id,
+            graph,
+            links,
+        })
+    }
+
+    /// Replaces the saved settings of the node, ports are rebuilt from them when put back.
+    pub fn with_state(mut self, state: Option<ron::Value>) -> Self {
+        self.graph.nodes[0].state = state;
+        self
+    }
+
+    fn take(storage: &mut Storage, node: Node) -> Option<Self> {
+        let removed = Self::save(storage, node)?;
+        storage.despawn(node);
+        Some(removed)
+    }
+
+    /// Spawns the node again, `self` being kept to put it back another time.
+    fn put(&self, storage: &mut Storage) -> Result<Node, HistoryError> {
+        let nodes = self
+            .graph
+            .insert_into(storage)
+            .map_err(|error| HistoryError::Restore {
+                node: self.id,
+                error,
+            })?;
+        let node = nodes[0];
+        storage.nodes[node].id = self.id;
+
+        for &(min, max) in &self.links {
+            if let (Some(min), Some(max)) = (storage.resolve(min), storage.resolve(max)) {
+                storage.link(min, max);
+            }
+        }
+
+        Ok(node)
+    }
+}
+
+pub enum Change {
+    /// Records a node the editor has already spawned.
+    Spawn {
+        node: NodeId,
+        removed: Option<RemovedNode>,
+    },
+    Despawn {
+        node: NodeId,
+        removed: Option<RemovedNode>,
+    },
+    Link {
+        from: PortRef,
+        to: PortRef,
+    },
+    Unlink {
+        from: PortRef,
+        to: PortRef,
+    },
+    Move {
+        nodes: Vec<NodeId>,
+        delta: egui::Vec2,
+    },
+    SetDefault {
+        port: PortRef,
+        before: DefaultValue,
+        after: DefaultValue,
+    },
+    /// Records ports the editor has already rebuilt from new settings, `before`
+    /// holding the node with its former settings, ports and links.
+    SyncPorts {
+        node: NodeId,
+        before: Option<RemovedNode>,
+        after: Option<RemovedNode>,
+    },
+    /// Renames a property of the blackboard and the nodes reading it.
+    RenameProperty {
+        old: String,
+        new: String,
+    },
+    /// Replaces the properties of the blackboard. Ports of the property nodes
+    /// whose type changed are rebuilt by the editor, as [`Change::SyncPorts`].
+    SetProperties {
+        before: Vec<Property>,
+        after: Vec<Property>,
+    },
+}
+
+impl Change {
+    fn find(storage: &Storage, node: NodeId) -> Result<Node, HistoryError> {
+        storage.find(node).ok_or(HistoryError::NodeNotFound(node))
+    }
+
+    fn resolve(storage: &Storage, port: PortRef) -> Result<Port, HistoryError> {
+        storage
+            .resolve(port)
+            .ok_or(HistoryError::PortNotFound(port))
+    }
+
+    fn take(
+        storage: &mut Storage,
+        node: NodeId,
+        removed: &mut Option<RemovedNode>,
+    ) -> Result<(), HistoryError> {
+        let key = Self::find(storage, node)?;
+        let taken = RemovedNode::take(storage, key).ok_or(HistoryError::Unsaved(node))?;
+        *removed = Some(taken);
+        Ok(())
+    }
+
+    fn put(storage: &mut Storage, removed: &mut Option<RemovedNode>) -> Result<(), HistoryError> {
+        if let Some(node) = removed {
+            node.put(storage)?;
+            *removed = None;
+        }
+        Ok(())
+    }
+
+    /// Swaps the node for the saved one in `put`, saving it in `take`.
+    fn replace(
+        storage: &mut Storage,
+        node: NodeId,
+        take: &mut Option<RemovedNode>,
+        put: &mut Option<RemovedNode>,
+    ) -> Result<(), HistoryError> {
+        if put.is_some() {
+            Self::take(storage, node, take)?;
+            if let Err(err) = Self::put(storage, put) {
+                Self::put(storage, take)?;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn link(storage: &mut Storage, from: PortRef, to: PortRef) -> Result<(), HistoryError> {
+        let min = Self::resolve(storage, from)?;
+        let max = Self::resolve(storage, to)?;
+        storage.link(min, max);
+        Ok(())
+    }
+
+    fn unlink(storage: &mut Storage, from: PortRef, to: PortRef) -> Result<(), HistoryError> {
+        let min = Self::resolve(storage, from)?;
+        let max = Self::resolve(storage, to)?;
+        let link = storage
+            .links
+            .iter()
+            .find_map(|(key, link)| (link.min.port == min && link.max.port == max).then_some(key))
+            .ok_or(HistoryError::LinkNotFound { from, to })?;
+        storage.unlink(link);
+        Ok(())
+    }
+
+    fn translate(storage: &mut Storage, nodes: &[NodeId], delta: egui::Vec2) {
+        for &id in nodes {
+            if let Some(node) = storage.find(id) {
+                storage.translate(node, delta);
+            }
+        }
+    }
+
+    fn set_default(
+        storage: &mut Storage,
+        port: PortRef,
+        value: DefaultValue,
+    ) -> Result<(), HistoryError> {
+        let key = Self::resolve(storage, port)?;
+        let default = storage.ports[key].input_default.as_mut();
+        value.set(default.ok_or(HistoryError::NoDefault(port))?);
+        Ok(())
+    }
+
+    fn set_properties(storage: &mut Storage, properties: &[Property]) {
+        storage.properties = properties.to_vec();
+        storage.update_property_types();
+    }
+
+    fn apply(&mut self, storage: &mut Storage) -> Result<(), HistoryError> {
+        match self {
+            Self::Spawn { removed, .. } => Self::put(storage, removed)?,
+            Self::Despawn { node, removed } => Self::take(storage, *node, removed)?,
+            Self::Link { from, to } => Self::link(storage, *from, *to)?,
+            Self::Unlink { from, to } => Self::unlink(storage, *from, *to)?,
+            Self::Move { nodes, delta } => Self::translate(storage, nodes, *delta),
+            Self::SetDefault { port, after, .. } => Self::set_default(storage, *port, *after)?,
+            Self::SyncPorts {
+                node,
+                before,
+                after,
+            } => Self::replace(storage, *node, before, after)?,
+            Self::RenameProperty { old, new } => storage.rename_property(old, new),
+            Self::SetProperties { after, .. } => Self::set_properties(storage, after),
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, storage: &mut Storage) -> Result<(), HistoryError> {
+        match self {
+            Self::Spawn { node, removed } => Self::take(storage, *node, removed)?,
+            Self::Despawn { removed, .. } => Self::put(storage, removed)?,
+            Self::Link { from, to } => Self::unlink(storage, *from, *to)?,
+            Self::Unlink { from, to } => Self::link(storage, *from, *to)?,
+            Self::Move { nodes, delta } => Self::translate(storage, nodes, -*delta),
+            Self::SetDefault { port, before, .. } => Self::set_default(storage, *port, *before)?,
+            Self::SyncPorts {
+                node,
+                before,
+                after,
+            } => Self::replace(storage, *node, after, before)?,
+            Self::RenameProperty { old, new } => storage.rename_property(new, old),
+            Self::SetProperties { before, .. } => Self::set_properties(storage, before),
+        }
+        Ok(())
+    }
+}
+
+/// One entry of the history: every change made during a single gesture,
+/// such as a whole drag or unplugging a link and plugging it somewhere else.
+pub struct Edit {
+    gesture: u64,
+    changes: Vec<Change>,
+}
+
+impl Edit {
+    pub fn new(gesture: u64, change: Change) -> Self {
+        Self {
+            gesture,
+            changes: vec![change],
+        }
+    }
+}
+
+impl undo::Action for Edit {
+    type Target = Storage;
+    type Output = ();
+    type Error = HistoryError;
+
+    /// Applies every change or none, undoing the applied ones when one fails.
+    fn apply(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
+        for index in 0..self.changes.len() {
+            if let Err(err) = self.changes[index].apply(target) {
+                for change in self.changes[..index].iter_mut().rev() {
+                    if let Err(err) = change.undo(target) {
+                        tracing::error!("failed to roll back graph edit: {}", err);
+                    }
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes every change or none, applying the undone ones again when one fails.
+    fn undo(&mut self, target: &mut Self::Target) -> undo::Result<Self> {
+        for index in (0..self.changes.len()).rev() {
+            if let Err(err) = self.changes[index].undo(target) {
+                for change in &mut self.changes[index + 1..] {
+                    if let Err(err) = change.apply(target) {
+                        tracing::error!("failed to roll back graph edit: {}", err);
+                    }
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &mut Self) -> Merged {
+        if self.gesture != other.gesture {
+            return Merged::No;
+        }
+
+        for change in other.changes.drain(..) {
+            match (self.changes.last_mut(), change) {
+                (
+                    Some(Change::Move { nodes, delta }),
+                    Change::Move {
+                        nodes: other_nodes,
+                        delta: other_delta,
+                    },
+                ) if *nodes == other_nodes => *delta += other_delta,
+                (
+                    Some(Change::SetDefault { port, after, .. }),
+                    Change::SetDefault {
+                        port: other_port,
+                        after: other_after,
+                        ..
+                    },
+                ) if *port == other_port => *after = other_after,
+                (
+                    Some(Change::RenameProperty { new, .. }),
+                    Change::RenameProperty {
+                        old: other_old,
+                        new: other_new,
+                    },
+                ) if *new == other_old => *new = other_new,
+                (
+                    Some(Change::SetProperties { after, .. }),
+                    Change::SetProperties {
+                        after: other_after, ..
+                    },
+                ) => *after = other_after,
+                (_, change) => self.changes.push(change),
+            }
+        }
+
+        Merged::Yes
+    }
+}
+
+#[test]
+fn undo_despawn() {
+    let mut storage = Storage::default();
+    let color = storage.spawn_kind("Color").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+
+    let out = storage.nodes[color].outputs[0];
+    let a = storage.nodes[add].inputs[0];
+    storage.link(out, a);
+
+    let b = storage.nodes[add].inputs[1];
+    let b = storage.port_ref(b);
+    let add = storage.nodes[add].id;
+
+    let mut history: History = undo::record::Builder::new().build();
+    let mut gesture = 0;
+    let mut edit = |storage: &mut Storage, change| {
+        gesture += 1;
+        history.apply(storage, Edit::new(gesture, change)).unwrap();
+    };
+
+    let before = DefaultValue::new(
+        storage.ports[storage.resolve(b).unwrap()]
+            .input_default
+            .as_ref()
+            .unwrap(),
+    );
+    let after = DefaultValue {
+        value: [0.5, 0.0, 0.0, 1.0],
+        ..before
+    };
+    edit(
+        &mut storage,
+        Change::SetDefault {
+            port: b,
+            before,
+            after,
+        },
+    );
+    edit(
+        &mut storage,
+        Change::Despawn {
+            node: add,
+            removed: None,
+        },
+    );
+
+    assert_eq!(storage.nodes.len(), 1);
+    assert!(storage.links.is_empty());
+
+    history.undo(&mut storage).unwrap().unwrap();
+    assert_eq!(storage.nodes.len(), 2);
+    assert_eq!(storage.links.len(), 1);
+
+    // The builder of the restored node reads its new ports.
+    let build = |storage: &Storage| {
+        let node = storage.find(add).unwrap();
+        let module = storage.nodes[node].builder.module(node, storage).unwrap();
+        module.build().unwrap();
+    };
+    build(&storage);
+
+    let port = storage.resolve(b).unwrap();
+    assert_eq!(storage.ports[port].input_default.as_ref().unwrap().x, 0.5);
+
+    history.undo(&mut storage).unwrap().unwrap();
+    assert_eq!(storage.ports[port].input_default.as_ref().unwrap().x, 0.0);
+
+    history.redo(&mut storage).unwrap().unwrap();
+    history.redo(&mut storage).unwrap().unwrap();
+    assert_eq!(storage.nodes.len(), 1);
+
+    // Spawns are redone the same way.
+    let spawn = storage.spawn_kind("Negate").unwrap();
+    let spawn = storage.nodes[spawn].id;
+    let change = Change::Spawn {
+        node: spawn,
+        removed: None,
+    };
+    history.apply(&mut storage, Edit::new(3, change)).unwrap();
+    history.undo(&mut storage).unwrap().unwrap();
+    assert!(storage.find(spawn).is_none());
+    history.redo(&mut storage).unwrap().unwrap();
+    let node = storage.find(spawn).unwrap();
+    let module = storage.nodes[node].builder.module(node, &storage).unwrap();
+    module.build().unwrap();
+}
+
+#[test]
+fn undo_port_changes() {
+    use super::asset::to_state;
+    use super::nodes::subgraph::PortState;
+
+    let mut storage = Storage::default();
+    let input = storage.spawn_kind("Sub Graph Input").unwrap();
+    let negate = storage.spawn_kind("Negate").unwrap();
+    let out = storage.nodes[input].outputs[0];
+    let a = storage.nodes[negate].inputs[0];
+    storage.link(out, a);
+    let id = storage.nodes[input].id;
+
+    // The editor keeps the settings from when the ports were in sync.
+    let state = storage.nodes[input].builder.save();
+    let changed = to_state(&PortState {
+        name: String::from("height"),
+        data: String::from("Float"),
+    });
+    storage.nodes[input].builder.load(changed.unwrap()).unwrap();
+    let before = RemovedNode::save(&storage, input).map(|node| node.with_state(state));
+    assert!(storage.sync_ports(input));
+    assert!(storage.links.is_empty());
+
+    let mut history: History = undo::record::Builder::new().build();
+    let after = None;
+    let change = Change::SyncPorts {
+        node: id,
+        before,
+        after,
+    };
+    history.apply(&mut storage, Edit::new(0, change)).unwrap();
+
+    let label = |storage: &Storage| {
+        let node = storage.find(id).unwrap();
+        let port = storage.nodes[node].outputs[0];
+        storage.ports[port].label.clone()
+    };
+    history.undo(&mut storage).unwrap().unwrap();
+    assert_eq!(label(&storage), "in");
+    assert_eq!(storage.links.len(), 1);
+    let module = storage.nodes[negate].builder.module(negate, &storage);
+    module.unwrap().build().unwrap();
+
+    history.redo(&mut storage).unwrap().unwrap();
+    assert_eq!(label(&storage), "height");
+    assert!(storage.links.is_empty());
+}
+
+#[test]
+fn roll_back_failed_edit() {
+    let mut storage = Storage::default();
+    let color = storage.spawn_kind("Color").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+    let out = storage.port_ref(storage.nodes[color].outputs[0]);
+    let a = storage.port_ref(storage.nodes[add].inputs[0]);
+    let position = storage.nodes[color].position;
+    let id = storage.nodes[color].id;
+
+    // The link is made, then undone when moving on to the missing port.
+    let missing = PortRef { index: 9, ..a };
+    let mut edit = Edit::new(
+        0,
+        Change::Move {
+            nodes: vec![id],
+            delta: egui::vec2(10.0, 0.0),
+        },
+    );
+    edit.changes.push(Change::Link { from: out, to: a });
+    edit.changes.push(Change::Link {
+        from: out,
+        to: missing,
+    });
+
+    let mut history: History = undo::record::Builder::new().build();
+    let err = history.apply(&mut storage, edit).unwrap_err();
+    assert!(matches!(err, HistoryError::PortNotFound(port) if port == missing));
+    assert_eq!(storage.nodes[color].position, position);
+    assert!(storage.links.is_empty());
+    assert!(!history.can_undo());
+}
+
+#[test]
+fn undo_property_changes() {
+    use super::blackboard::PropertyValue;
+    use super::nodes::input::PropertyNode;
+    use super::Data;
+
+    let tint = Property {
+        name: String::from("tint"),
+        value: PropertyValue::Color([1.0; 4]),
+    };
+    let mut storage = Storage {
+        properties: vec![tint.clone()],
+        ..Default::default()
+    };
+    let node = PropertyNode::spawn_property(&mut storage, &tint);
+
+    // Typing a name is a single step.
+    let mut history: History = undo::record::Builder::new().build();
+    for (old, new) in [("tint", "t"), ("t", "tone")] {
+        let (old, new) = (old.to_string(), new.to_string());
+        let change = Change::RenameProperty { old, new };
+        history.apply(&mut storage, Edit::new(0, change)).unwrap();
+    }
+    let mut after = storage.properties.clone();
+    after[0].value = PropertyValue::Float(0.5);
+    let before = storage.properties.clone();
+    let change = Change::SetProperties { before, after };
+    history.apply(&mut storage, Edit::new(1, change)).unwrap();
+
+    let property = |storage: &Storage| {
+        let property = storage.nodes[node].builder.downcast_ref::<PropertyNode>();
+        let property = property.unwrap();
+        (property.name.clone(), property.data)
+    };
+    assert_eq!(property(&storage), (String::from("tone"), Data::Float));
+
+    history.undo(&mut storage).unwrap().unwrap();
+    assert_eq!(property(&storage), (String::from("tone"), Data::Vector4));
+    history.undo(&mut storage).unwrap().unwrap();
+    assert_eq!(property(&storage), (String::from("tint"), Data::Vector4));
+    assert_eq!(storage.properties, [tint]);
+
+    history.redo(&mut storage).unwrap().unwrap();
+    history.redo(&mut storage).unwrap().unwrap();
+    assert_eq!(storage.properties[0].value, PropertyValue::Float(0.5));
+}
@@ -0,0 +1,163 @@
+//! Concrete types of the dynamic ports, propagated along the links.
+
+use super::{Data, Diagnostic, Port, Storage};
+use ahash::AHashMap;
+use slotmap::SecondaryMap;
+
+pub struct PortTypes {
+    pub types: SecondaryMap<Port, Data>,
+    /// Inputs linked to a type their node can't take.
+    pub conflicts: Vec<Port>,
+}
+
+impl Storage {
+    /// Resolves the dynamic ports of each node to one type: the one linked to
+    /// its dynamic inputs, or the one of their defaults when none is linked.
+    /// Ports stay dynamic when what they depend on is.
+    pub fn infer_types(&self) -> PortTypes {
+        self.infer_types_with(None)
+    }
+
+    /// Same as [`Self::infer_types`], as if `link` from an output to an input existed.
+    pub fn infer_types_with(&self, link: Option<(Port, Port)>) -> PortTypes {
+        let mut sources: AHashMap<Port, Port> = self
+            .links
+            .values()
+            .map(|link| (link.max.port, link.min.port))
+            .collect();
+        sources.extend(link.map(|(output, input)| (input, output)));
+
+        let mut types: SecondaryMap<Port, Data> = self
+            .ports
+            .iter()
+            .map(|(port, data)| (port, data.data))
+            .collect();
+        let mut conflicts = Vec::new();
+
+        // Each pass resolves at least the next node along the longest chain of links.
+        for _ in 0..=self.nodes.len() {
+            conflicts.clear();
+            let mut changed = false;
+
+            for node in self.nodes.values() {
+                let dynamic = |port: &&Port| self.ports[**port].data.is_dynamic();
+
+                let mut linked: Option<Data> = None;
+                let mut defaults: Option<Data> = None;
+                for &input in node.inputs.iter().filter(dynamic) {
+                    match sources.get(&input) {
+                        Some(&source) if types[source].is_dynamic() => {}
+                        Some(&source) => match linked {
+                            Some(data) => match data.unify(types[source]) {
+                                Some(data) => linked = Some(data),
+                                None => conflicts.push(input),
+                            },
+                            None => linked = Some(types[source]),
+                        },
+                        None => {
+                            let port = &self.ports[input];
+                            if let Some(data) = port.input_default.as_ref().and_then(|d| d.data()) {
+                                defaults = defaults.map_or(Some(data), |d| d.unify(data));
+                            }
+                        }
+                    }
+                }
+
+                // Unlinked vectors take the width of a linked matrix, as in products.
+                let data = match (linked, defaults) {
+                    (
+                        Some(matrix @ (Data::Matrix2 | Data::Matrix3 | Data::Matrix4)),
+                        Some(Data::Vector2 | Data::Vector3 | Data::Vector4),
+                    ) => Data::VectorAny.specialize(matrix),
+                    (Some(linked), _) => Some(linked),
+                    (None, defaults) => defaults,
+                };
+                if let Some(data) = data {
+                    for &port in node.inputs.iter().chain(&node.outputs).filter(dynamic) {
+                        match self.ports[port].data.specialize(data) {
+                            Some(data) if types[port] != data => {
+                                types[port] = data;
+                                changed = true;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        for (&input, &source) in &sources {
+            let data = self.ports[input].data;
+            if !data.is_dynamic() && !data.can_connect(types[source]) {
+                conflicts.push(input);
+            }
+        }
+
+        PortTypes { types, conflicts }
+    }
+
+    /// Shows the inferred types on the ports, and the conflicting links as diagnostics.
+    pub fn update_port_types(&mut self) -> Vec<Diagnostic> {
+        let PortTypes { types, conflicts } = self.infer_types();
+        for (port, data) in self.ports.iter_mut() {
+            data.inferred = Some(types[port]).filter(|_| data.data.is_dynamic());
+        }
+
+        conflicts
+            .into_iter()
+            .map(|port| {
+                let data = &self.ports[port];
+                let source = self.links.values().find_map(|link| link.output_for(port));
+                let linked = source.map_or(data.data, |source| types[source]);
+                Diagnostic {
+                    node: data.node,
+                    port: Some(port),
+                    message: format!(
+                        "`{}` is linked to a {} but resolves to a {}",
+                        data.label,
+                        linked.label().unwrap_or("value"),
+                        types[port].label().unwrap_or("value"),
+                    ),
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn infer_dynamic_ports() {
+    let mut storage = Storage::default();
+    let vector2 = storage.spawn_kind("Vector2").unwrap();
+    let vector3 = storage.spawn_kind("Vector3").unwrap();
+    let float = storage.spawn_kind("Float").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+    let normalize = storage.spawn_kind("Normalize").unwrap();
+
+    let output = |node| storage.nodes[node].outputs[0];
+    let (vector2, vector3, float) = (output(vector2), output(vector3), output(float));
+    let (sum, normalized) = (output(add), output(normalize));
+    let [a, b] = [0, 1].map(|index| storage.nodes[add].inputs[index]);
+    let normalize = storage.nodes[normalize].inputs[0];
+
+    // Unlinked, the defaults are floats.
+    assert_eq!(storage.infer_types().types[sum], Data::Float);
+
+    storage.link(vector3, a);
+    storage.link(sum, normalize);
+    let types = storage.infer_types();
+    assert_eq!(types.types[b], Data::Vector3);
+    assert_eq!(types.types[normalized], Data::Vector3);
+    assert!(types.conflicts.is_empty());
+
+    let mut graph = super::graph::Graph::default();
+    assert!(storage.should_link_snap(&mut graph, float, b));
+    assert!(!storage.should_link_snap(&mut graph, vector2, b));
+
+    storage.link(vector2, b);
+    assert_eq!(storage.update_port_types().len(), 1);
+    assert_eq!(storage.ports[sum].inferred, Some(Data::Vector3));
+}
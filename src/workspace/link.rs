@@ -146,13 +146,15 @@ impl Data {
         use Data::*;
         match self {
             Boolean => PORT_BOOL,
-            Float | FloatOrVector | VectorAny => PORT_VECTOR_1,
+            Float | FloatOrVector | VectorAny | Number => PORT_VECTOR_1,
             Vector2 => PORT_VECTOR_2,
             Vector3 => PORT_VECTOR_3,
             Vector4 => PORT_VECTOR_4,
             FloatOrVectorOrMatrix | VectorOrMatrix => PORT_MATRIX,
             Matrix2 | Matrix3 | Matrix4 | MatrixAny => PORT_MATRIX,
-            Image(_) => PORT_IMAGE,
+            Int | Int2 | Int3 | Int4 | IntAny | IntegerAny => PORT_INT,
+            UInt | UInt2 | UInt3 | UInt4 | UIntAny => PORT_UINT,
+            Image(_) | ImageArray(_) | ImageCube(_) => PORT_IMAGE,
             VirtualTexture | Gradient | Sampler => PORT_STRUCT,
         }
     }
@@ -164,6 +166,8 @@ pub const PORT_BOOL: Color32 = Color32::from_rgb(0x94, 0x81, 0xe6);
 pub const PORT_STRUCT: Color32 = Color32::from_rgb(0xc8, 0xc8, 0xc8);
 pub const PORT_IMAGE: Color32 = Color32::from_rgb(0xff, 0x8b, 0x8b);
 pub const PORT_MATRIX: Color32 = Color32::from_rgb(0x8f, 0xc1, 0xdf);
+pub const PORT_INT: Color32 = Color32::from_rgb(0x6a, 0xd8, 0xa4);
+pub const PORT_UINT: Color32 = Color32::from_rgb(0x5f, 0xa8, 0xe8);
 
 pub const PORT_VECTOR_1: Color32 = Color32::from_rgb(0x84, 0xe4, 0xe7);
 pub const PORT_VECTOR_2: Color32 = Color32::from_rgb(0x9a, 0xef, 0x92);
@@ -0,0 +1,59 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::pbr_types
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::shadows
+#import bevy_pbr::pbr_functions
+
+// Shading of the Lit master in graph materials, in place of the preview one
+// of `builtin.wgsl`. Vertex data is still in object space.
+fn builtin_lit(
+    base_color: vec3<f32>,
+    normal_ts: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    emission: vec3<f32>,
+    occlusion: f32,
+    alpha: f32,
+    alpha_clip: f32,
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    tangent: vec3<f32>,
+    bitangent: vec3<f32>,
+    frag_coord: vec4<f32>,
+    is_front: bool,
+) -> vec4<f32> {
+    if (alpha < alpha_clip) {
+        discard;
+    }
+
+    let world_position = mesh.model * vec4<f32>(position, 1.0);
+    let world_normal = normalize((mesh.inverse_transpose_model * vec4<f32>(normal, 0.0)).xyz);
+    let world_tangent = (mesh.model * vec4<f32>(tangent, 0.0)).xyz;
+    let world_bitangent = (mesh.model * vec4<f32>(bitangent, 0.0)).xyz;
+
+    var N = normalize(normal_ts.x * world_tangent + normal_ts.y * world_bitangent + normal_ts.z * world_normal);
+    if (!is_front) {
+        N = -N;
+    }
+
+    var pbr_input = pbr_input_new();
+    pbr_input.material.base_color = vec4<f32>(base_color, alpha);
+    pbr_input.material.emissive = vec4<f32>(emission, 1.0);
+    pbr_input.material.metallic = metallic;
+    pbr_input.material.perceptual_roughness = roughness;
+    // Alpha is clipped above, and ignored by opaque pipelines.
+    pbr_input.material.flags = STANDARD_MATERIAL_FLAGS_ALPHA_MODE_BLEND;
+    pbr_input.occlusion = occlusion;
+
+    pbr_input.frag_coord = frag_coord;
+    pbr_input.world_position = world_position;
+    pbr_input.world_normal = world_normal;
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = N;
+    pbr_input.V = calculate_view(world_position, pbr_input.is_orthographic);
+
+    return tone_mapping(pbr(pbr_input));
+}
@@ -0,0 +1,653 @@
+//! Shader graphs drawn on scene entities as a bevy [`Material`].
+//!
+//! Every `.shader` file also loads as a [`GraphMaterial`] labeled `Material`,
+//! referenced by entities as `graph.shader#Material`. The material is compiled
+//! from the first master node of the graph, with bevy's view and mesh uniforms
+//! at `@group(0)` and `@group(2)` and the resources of the graph at `@group(1)`.
+//! Saving the graph reloads the file, which compiles the material again.
+//!
+//! The clock, camera and viewport uniforms are shared by every material, in
+//! [`GraphBuffers`] written by the render world each frame, so that animated
+//! materials keep their bind group and pipeline.
+
+use super::blackboard::uniform_bytes;
+use super::builder::{
+    filter_resources, Attribute, FnBuilder, Resource, StructBuilder, TextureDimension,
+};
+use super::nodes::master::Surface;
+use super::preview::{
+    prepare_texture, tint_bytes, transform_bytes, viewport_bytes, PreviewMode, PreviewTime,
+    TextureLayers,
+};
+use super::{InputDefault, ShaderGraph, Storage};
+use ahash::AHashMap;
+use anyhow::anyhow;
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::{CameraProjection, Projection};
+use bevy::render::extract_resource::ExtractResource;
+use bevy::render::mesh::{MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{
+    AsBindGroup, AsBindGroupError, BindGroupLayout, Buffer, OwnedBindingResource,
+    PreparedBindGroup, RenderPipelineDescriptor, Shader, SpecializedMeshPipelineError,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::texture::FallbackImage;
+use naga::{
+    AddressSpace, BinaryOperator, Expression, GlobalVariable, Handle as NagaHandle,
+    ResourceBinding, Type, TypeInner, VectorSize,
+};
+use std::sync::{Arc, Mutex};
+
+/// A shader graph compiled for bevy's mesh pipeline.
+#[derive(Clone, TypeUuid)]
+#[uuid = "0b7e3f5e-8d4c-4c1b-9a57-3f2b1d6e9c42"]
+pub struct GraphMaterial {
+    pub graph: ShaderGraph,
+    /// Set once compiled by [`compile_graph_materials`].
+    pub shader: Option<Handle<Shader>>,
+    /// Bound at `@group(1)`, the binding being the index.
+    pub resources: Vec<Resource>,
+    pub attributes: Vec<Attribute>,
+    pub surface: Option<Surface>,
+    properties: Vec<u8>,
+    /// Set along with the shader by [`compile_graph_materials`].
+    buffers: Option<GraphBuffers>,
+    /// Image bound for each texture path, its copy split into layers once loaded.
+    textures: Vec<(Handle<Image>, String, TextureDimension)>,
+    /// Textures are loaded and split into their layers.
+    ready: bool,
+    /// Why the graph can't be drawn, the material being drawn by
+    /// [`error_storage`] instead until the graph is reloaded.
+    error: Option<String>,
+}
+
+impl GraphMaterial {
+    pub fn new(graph: ShaderGraph) -> Self {
+        Self {
+            graph,
+            shader: None,
+            resources: Vec::new(),
+            attributes: Vec::new(),
+            surface: None,
+            properties: Vec::new(),
+            buffers: None,
+            textures: Vec::new(),
+            ready: false,
+            error: None,
+        }
+    }
+
+    /// Generates the entry points of the first master node drawing meshes.
+    fn compile(
+        &mut self,
+        shaders: &mut Assets<Shader>,
+        assets: &AssetServer,
+    ) -> anyhow::Result<()> {
+        let storage = self.graph.to_storage()?;
+        self.compile_storage(&storage, shaders, assets)
+    }
+
+    /// Keeps `error` and compiles [`error_storage`] instead, bevy retrying to
+    /// prepare a material until it can be drawn.
+    fn fail(&mut self, error: String, shaders: &mut Assets<Shader>, assets: &AssetServer) {
+        self.error = Some(error);
+        self.compile_storage(&error_storage(), shaders, assets)
+            .expect("the error material compiles");
+        self.ready = true;
+    }
+
+    fn compile_storage(
+        &mut self,
+        storage: &Storage,
+        shaders: &mut Assets<Shader>,
+        assets: &AssetServer,
+    ) -> anyhow::Result<()> {
+        let (master, data) = storage
+            .nodes
+            .iter()
+            .find(|(_, node)| node.builder.is_master() && node.builder.workgroup_size().is_none())
+            .ok_or_else(|| anyhow!("graph has no master node"))?;
+        let module = data
+            .builder
+            .preview_module(master, storage, PreviewMode::Material)?;
+        let mut source = module.build()?;
+        if data.builder.is_lit() {
+            source = lit_source(&source);
+        }
+
+        self.resources = module.resources.iter().map(|(r, _)| r.clone()).collect();
+        self.attributes = module.attributes.clone();
+        self.surface = data.builder.surface();
+        self.properties = uniform_bytes(&storage.properties);
+        self.textures = self
+            .resources
+            .iter()
+            .filter_map(|resource| match resource {
+                Resource::Texture { path, dimension } => {
+                    Some((assets.load(path.as_str()), path.clone(), *dimension))
+                }
+                _ => None,
+            })
+            .collect();
+        self.ready = self.textures.is_empty();
+        self.shader = Some(shaders.add(Shader::from_wgsl(source)));
+        Ok(())
+    }
+}
+
+/// Resources bound by a bind group layout, and whether its textures are filterable.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct LayoutKey {
+    resources: Vec<Resource>,
+    filterable: bool,
+}
+
+/// Uniform buffers shared by every graph material, written from [`GraphUniforms`]
+/// by [`write_graph_uniforms`].
+#[derive(Clone)]
+pub struct GraphBuffers {
+    time: Buffer,
+    transform: Buffer,
+    viewport: Buffer,
+    /// Created once for each set of resources, as pipelines are specialized
+    /// for the layout they are given.
+    layouts: Arc<Mutex<AHashMap<LayoutKey, BindGroupLayout>>>,
+}
+
+impl FromWorld for GraphBuffers {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let uniforms = GraphUniforms::default();
+        let buffer = |contents: &[u8]| {
+            device.create_buffer_with_data(&wgpu::util::BufferInitDescriptor {
+                label: Some("graph material"),
+                contents,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+        Self {
+            time: buffer(&uniforms.time),
+            transform: buffer(&uniforms.transform),
+            viewport: buffer(&uniforms.viewport),
+            layouts: Arc::default(),
+        }
+    }
+}
+
+impl GraphBuffers {
+    fn layout(&self, device: &RenderDevice, key: &LayoutKey) -> BindGroupLayout {
+        let mut layouts = self.layouts.lock().unwrap();
+        let layout = layouts.entry(key.clone()).or_insert_with(|| {
+            let entries: Vec<_> = key
+                .resources
+                .iter()
+                .enumerate()
+                .map(|(binding, resource)| resource.layout_entry(binding as u32, key.filterable))
+                .collect();
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("graph material"),
+                entries: &entries,
+            })
+        });
+        layout.clone()
+    }
+}
+
+/// Contents of the [`GraphBuffers`], updated by [`update_graph_uniforms`].
+#[derive(Clone, ExtractResource)]
+pub struct GraphUniforms {
+    time: Vec<u8>,
+    transform: Vec<u8>,
+    viewport: Vec<u8>,
+}
+
+impl Default for GraphUniforms {
+    fn default() -> Self {
+        Self {
+            time: PreviewTime::default().uniform_bytes(),
+            transform: transform_bytes(Mat4::IDENTITY, Mat4::IDENTITY, Mat4::IDENTITY),
+            viewport: viewport_bytes(1, 1),
+        }
+    }
+}
+
+/// Graph drawing a material that failed in magenta.
+fn error_storage() -> Storage {
+    let mut storage = Storage::default();
+    let master = storage.spawn_kind("Master").unwrap();
+    let color = storage.nodes[master].inputs[1];
+    storage.ports[color].input_default = Some(InputDefault::vector4(1.0, 0.0, 1.0, 1.0));
+    storage
+}
+
+/// Lights the Lit master with bevy's PBR functions instead of the preview light.
+fn lit_source(source: &str) -> String {
+    let source = source.replace("fn builtin_lit(", "fn builtin_lit_preview(");
+    format!("{}\n{}", include_str!("lit.wgsl"), source)
+}
+
+/// Transforms `position` like bevy's mesh shader.
+pub fn expr_mesh_to_clip(
+    function: &mut FnBuilder,
+    position: NagaHandle<Expression>,
+) -> NagaHandle<Expression> {
+    let view_proj = load_bevy_matrix(function, 0, "GraphView", "graph_view", "view_proj");
+    let model = expr_mesh_model(function);
+
+    let world = function.emit(Expression::Binary {
+        op: BinaryOperator::Multiply,
+        left: model,
+        right: position,
+    });
+    function.emit(Expression::Binary {
+        op: BinaryOperator::Multiply,
+        left: view_proj,
+        right: world,
+    })
+}
+
+/// Model matrix of the drawn entity, in the `Mesh` uniform bevy binds at `@group(2)`.
+pub fn expr_mesh_model(function: &mut FnBuilder) -> NagaHandle<Expression> {
+    load_bevy_matrix(function, 2, "GraphMesh", "graph_mesh", "model")
+}
+
+/// Loads `member`, the first matrix of the uniform bevy binds at `@binding(0)`
+/// of `group`, the rest of the struct being left out.
+///
+/// It is declared as `var` of type `name` instead of bevy's own names,
+/// `lit.wgsl` importing those.
+fn load_bevy_matrix(
+    function: &mut FnBuilder,
+    group: u32,
+    name: &str,
+    var: &str,
+    member: &str,
+) -> NagaHandle<Expression> {
+    let declared = function
+        .module
+        .module
+        .global_variables
+        .iter()
+        .find(|(_, global)| global.name.as_deref() == Some(var))
+        .map(|(global, _)| global);
+    let global = match declared {
+        Some(global) => global,
+        None => {
+            let mat4 = function.insert_type(Type {
+                name: None,
+                inner: TypeInner::Matrix {
+                    columns: VectorSize::Quad,
+                    rows: VectorSize::Quad,
+                    width: 4,
+                },
+            });
+            let ty = StructBuilder::new(&mut function.module.module, name)
+                .uniform(member, mat4)
+                .build();
+            function.module.global_variable(GlobalVariable {
+                name: Some(var.to_string()),
+                space: AddressSpace::Uniform,
+                binding: Some(ResourceBinding { group, binding: 0 }),
+                ty,
+                init: None,
+            })
+        }
+    };
+    let global = function.expression(Expression::GlobalVariable(global));
+    let pointer = function.access_index(global, 0);
+    function.emit(Expression::Load { pointer })
+}
+
+/// Specializes the pipeline of a [`GraphMaterial`], every graph having its
+/// own shader and bind group layout.
+///
+/// Layouts are compared by the resources they were created for, materials
+/// binding the same ones sharing their layout through [`GraphBuffers`].
+#[derive(Clone)]
+pub struct GraphMaterialKey {
+    shader: Handle<Shader>,
+    attributes: Vec<Attribute>,
+    surface: Option<Surface>,
+    resources: LayoutKey,
+    layout: BindGroupLayout,
+}
+
+impl PartialEq for GraphMaterialKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.shader == other.shader
+            && self.attributes == other.attributes
+            && self.surface == other.surface
+            && self.resources == other.resources
+    }
+}
+
+impl Eq for GraphMaterialKey {}
+
+impl std::hash::Hash for GraphMaterialKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.shader.hash(state);
+        self.attributes.hash(state);
+        self.surface.hash(state);
+        self.resources.hash(state);
+    }
+}
+
+impl AsBindGroup for GraphMaterial {
+    type Data = GraphMaterialKey;
+
+    fn as_bind_group(
+        &self,
+        _layout: &BindGroupLayout,
+        render_device: &RenderDevice,
+        images: &RenderAssets<Image>,
+        fallback_image: &FallbackImage,
+    ) -> Result<PreparedBindGroup<Self>, AsBindGroupError> {
+        let (shader, buffers) = match (&self.shader, &self.buffers, self.ready) {
+            (Some(shader), Some(buffers), true) => (shader.clone(), buffers),
+            _ => return Err(AsBindGroupError::RetryNextUpdate),
+        };
+
+        let mut resources = self.resources.clone();
+        let filterable = filter_resources(&mut resources, |path| {
+            let (handle, _, _) = self.textures.iter().find(|(_, p, _)| p == path)?;
+            Some(images.get(handle)?.texture_format)
+        });
+        let key = LayoutKey {
+            resources,
+            filterable,
+        };
+        let layout = buffers.layout(render_device, &key);
+
+        let tint = tint_bytes(Vec4::ONE);
+        let mut bindings = Vec::with_capacity(key.resources.len());
+        for resource in &key.resources {
+            let contents = match resource {
+                Resource::Texture { path, dimension } => {
+                    let handle = self.textures.iter().find(|(_, p, _)| p == path).unwrap();
+                    let image = images
+                        .get(&handle.0)
+                        .ok_or(AsBindGroupError::RetryNextUpdate)?;
+                    let view = image.texture.create_view(&wgpu::TextureViewDescriptor {
+                        dimension: Some(dimension.view()),
+                        ..default()
+                    });
+                    bindings.push(OwnedBindingResource::TextureView(view));
+                    continue;
+                }
+                Resource::Sampler(state) => {
+                    let sampler = render_device.create_sampler(&state.descriptor());
+                    bindings.push(OwnedBindingResource::Sampler(sampler));
+                    continue;
+                }
+                // Stand-ins until scene entities can provide their own.
+                Resource::SceneColor | Resource::SceneDepth | Resource::MainTexture => {
+                    let view = fallback_image.texture_view.clone();
+                    bindings.push(OwnedBindingResource::TextureView(view));
+                    continue;
+                }
+                Resource::StorageBuffer { writable: false } => {
+                    let buffer = render_device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("graph material"),
+                        size: 16,
+                        usage: wgpu::BufferUsages::STORAGE,
+                        mapped_at_creation: false,
+                    });
+                    bindings.push(OwnedBindingResource::Buffer(buffer));
+                    continue;
+                }
+                Resource::StorageTexture | Resource::StorageBuffer { writable: true } => {
+                    unreachable!("only bound by compute masters")
+                }
+                Resource::Time | Resource::Transform | Resource::Viewport => {
+                    let buffer = match resource {
+                        Resource::Time => &buffers.time,
+                        Resource::Transform => &buffers.transform,
+                        _ => &buffers.viewport,
+                    };
+                    bindings.push(OwnedBindingResource::Buffer(buffer.clone()));
+                    continue;
+                }
+                Resource::Properties => &self.properties,
+                Resource::Tint => &tint,
+                // Only bound by the mesh preview of the workspace.
+                Resource::Camera => return Err(AsBindGroupError::RetryNextUpdate),
+            };
+            let buffer = render_device.create_buffer_with_data(&wgpu::util::BufferInitDescriptor {
+                label: Some("graph material"),
+                contents,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            bindings.push(OwnedBindingResource::Buffer(buffer));
+        }
+
+        let entries: Vec<_> = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: resource.get_binding(),
+            })
+            .collect();
+        let bind_group = render_device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("graph material"),
+            layout: &layout,
+            entries: &entries,
+        });
+
+        Ok(PreparedBindGroup {
+            bindings,
+            bind_group,
+            data: GraphMaterialKey {
+                shader,
+                attributes: self.attributes.clone(),
+                surface: self.surface,
+                resources: key,
+                layout,
+            },
+        })
+    }
+
+    /// Replaced by the layout of each graph in [`Material::specialize`].
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("graph material"),
+            entries: &[],
+        })
+    }
+}
+
+impl Material for GraphMaterial {
+    fn alpha_mode(&self) -> AlphaMode {
+        match self.surface {
+            Some(surface) if surface.is_transparent() => AlphaMode::Blend,
+            _ => AlphaMode::Opaque,
+        }
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let key = key.bind_group_data;
+
+        // Meshes have a single set of UVs, read by both `uv0` and `uv1`.
+        let attributes: Vec<_> = key
+            .attributes
+            .iter()
+            .map(|&attribute| {
+                let mesh_attribute: MeshVertexAttribute = match attribute {
+                    Attribute::Position => Mesh::ATTRIBUTE_POSITION,
+                    Attribute::Normal => Mesh::ATTRIBUTE_NORMAL,
+                    Attribute::Uv0 | Attribute::Uv1 => Mesh::ATTRIBUTE_UV_0,
+                    Attribute::Tangent => Mesh::ATTRIBUTE_TANGENT,
+                    Attribute::Color => Mesh::ATTRIBUTE_COLOR,
+                };
+                mesh_attribute.at_shader_location(attribute.location())
+            })
+            .collect();
+
+        descriptor.vertex.shader = key.shader.clone();
+        descriptor.vertex.entry_point = "vs_main".into();
+        descriptor.vertex.buffers = vec![layout.get_layout(&attributes)?];
+
+        let fragment = descriptor.fragment.as_mut().unwrap();
+        fragment.shader = key.shader;
+        fragment.entry_point = "fs_main".into();
+
+        if let Some(surface) = key.surface {
+            if let Some(blend) = surface.blend_state() {
+                fragment.targets[0].as_mut().unwrap().blend = Some(blend);
+            }
+            descriptor.primitive.cull_mode = surface.cull_mode();
+            if let Some(depth_stencil) = &mut descriptor.depth_stencil {
+                depth_stencil.depth_write_enabled = surface.depth_write;
+            }
+        }
+
+        descriptor.layout.as_mut().unwrap()[1] = key.layout;
+        Ok(())
+    }
+}
+
+/// Compiles the materials loaded along with their `.shader` file.
+pub fn compile_graph_materials(
+    mut events: EventReader<AssetEvent<GraphMaterial>>,
+    mut materials: ResMut<Assets<GraphMaterial>>,
+    mut shaders: ResMut<Assets<Shader>>,
+    buffers: Res<GraphBuffers>,
+    assets: Res<AssetServer>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+
+        // Compiling modifies the material too, which must not compile it again.
+        if !matches!(materials.get(handle), Some(material) if material.shader.is_none()) {
+            continue;
+        }
+
+        let material = materials.get_mut(handle).unwrap();
+        material.buffers = Some(buffers.clone());
+        if let Err(err) = material.compile(&mut shaders, &assets) {
+            let path = assets.get_handle_path(handle);
+            let path = path.as_ref().map(|path| path.path());
+            tracing::error!("failed to compile graph material {:?}: {:#}", path, err);
+            material.fail(format!("{:#}", err), &mut shaders, &assets);
+        }
+    }
+}
+
+/// Splits the layers of the textures of graph materials once they are loaded.
+pub fn prepare_graph_textures(
+    mut materials: ResMut<Assets<GraphMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut layers: ResMut<TextureLayers>,
+    mut shaders: ResMut<Assets<Shader>>,
+    assets: Res<AssetServer>,
+) {
+    let pending: Vec<_> = materials
+        .iter()
+        .filter(|(_, material)| material.shader.is_some() && !material.ready)
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in pending {
+        let material = materials.get_mut(&Handle::weak(id)).unwrap();
+        let mut ready = true;
+        let mut error = None;
+        for (handle, path, dimension) in &mut material.textures {
+            let path = path.clone();
+            match prepare_texture(&mut images, &mut layers, &assets, handle, path, *dimension) {
+                Ok(bound) => *handle = bound,
+                Err(err) => error = Some(err.to_string()),
+            }
+            ready &= images.get(handle).is_some();
+        }
+        match error {
+            Some(err) => {
+                tracing::error!("graph material: {}", err);
+                material.fail(err, &mut shaders, &assets);
+            }
+            None => material.ready = ready,
+        }
+    }
+}
+
+/// Updates the clock, the view and projection of the first active 3D camera,
+/// and the size of its viewport.
+pub fn update_graph_uniforms(
+    time: Res<Time>,
+    cameras: Query<(&Camera, &GlobalTransform, &Projection), With<Camera3d>>,
+    mut uniforms: ResMut<GraphUniforms>,
+) {
+    let clock = PreviewTime {
+        playing: true,
+        elapsed: time.seconds_since_startup() as f32,
+        delta: time.delta_seconds(),
+    };
+    uniforms.time = clock.uniform_bytes();
+
+    if let Some((camera, transform, projection)) =
+        cameras.iter().find(|(camera, ..)| camera.is_active)
+    {
+        let view = transform.compute_matrix().inverse();
+        let projection = projection.get_projection_matrix();
+        let size = camera.physical_viewport_size().unwrap_or(UVec2::ONE);
+        // Materials read the model matrix from bevy's mesh uniform instead.
+        uniforms.transform = transform_bytes(Mat4::IDENTITY, view, projection);
+        uniforms.viewport = viewport_bytes(size.x, size.y);
+    }
+}
+
+/// Writes the uniforms extracted from the main world to the buffers bound by
+/// every graph material.
+pub fn write_graph_uniforms(
+    uniforms: Res<GraphUniforms>,
+    buffers: Res<GraphBuffers>,
+    queue: Res<RenderQueue>,
+) {
+    queue.write_buffer(&buffers.time, 0, &uniforms.time);
+    queue.write_buffer(&buffers.transform, 0, &uniforms.transform);
+    queue.write_buffer(&buffers.viewport, 0, &uniforms.viewport);
+}
+
+#[test]
+fn bind_material_groups() {
+    use super::Storage;
+
+    let mut storage = Storage::default();
+    let time = storage.spawn_kind("Time").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    let sine = storage.nodes[time].outputs[1];
+    storage.link(sine, storage.nodes[master].inputs[1]);
+
+    // Bevy binds its own groups around the resources of the graph.
+    let module = storage.nodes[master]
+        .builder
+        .preview_module(master, &storage, PreviewMode::Material)
+        .unwrap();
+    assert_eq!(module.attributes, [Attribute::Position]);
+    let source = module.build().unwrap();
+    assert!(source.contains("@group(0) @binding(0) \nvar<uniform> graph_view: GraphView;"));
+    assert!(source.contains("@group(1) @binding(0) \nvar<uniform> time: Time;"));
+    assert!(source.contains("@group(2) @binding(0) \nvar<uniform> graph_mesh: GraphMesh;"));
+}
+
+#[test]
+fn error_material() {
+    let storage = error_storage();
+    let (master, data) = storage.nodes.iter().next().unwrap();
+    let module = data
+        .builder
+        .preview_module(master, &storage, PreviewMode::Material)
+        .unwrap();
+    let source = module.build().unwrap();
+    assert!(source.contains("vec4<f32>(1.0, 0.0, 1.0, 1.0)"));
+}
@@ -0,0 +1,613 @@
+//! Large preview of a master node, drawn on a mesh seen from an orbit camera.
+
+use super::blackboard::uniform_bytes;
+use super::builder::{Attribute, Resource};
+use super::preview::{
+    image_format, prepare_texture, storage_bytes, transform_bytes, viewport_bytes, PreviewBinding,
+    PreviewJob, PreviewMode, PreviewScene, TextureLayers,
+};
+use super::{Node, Preview, PreviewBuilder, PreviewQueue, Storage, Workspace};
+use crate::ui::shell::EguiContext;
+use crate::ui::{EditorTab, Style};
+use bevy::asset::LoadState;
+use bevy::ecs::system::lifetimeless::{SQuery, SRes, Write};
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::renderer::RenderDevice;
+use bevy::window::WindowId;
+
+/// Mesh the master node is drawn on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreviewMesh {
+    Sphere,
+    Cube,
+    Quad,
+    /// Mesh of the asset folder, the first primitive of glTF files by default.
+    Asset(String),
+}
+
+impl PreviewMesh {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Sphere => "Sphere",
+            Self::Cube => "Cube",
+            Self::Quad => "Quad",
+            Self::Asset(_) => "Asset",
+        }
+    }
+
+    fn builtin(&self) -> Option<Mesh> {
+        match self {
+            Self::Sphere => Some(Mesh::from(shape::UVSphere {
+                radius: 1.0,
+                sectors: 48,
+                stacks: 24,
+            })),
+            Self::Cube => Some(Mesh::from(shape::Cube { size: 1.0 })),
+            Self::Quad => Some(Mesh::from(shape::Quad::new(Vec2::splat(2.0)))),
+            Self::Asset(_) => None,
+        }
+    }
+}
+
+/// Vertex attributes of a triangle list, with the sphere bounding it.
+pub struct MeshData {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    tangents: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl MeshData {
+    /// Missing normals, UVs and tangents are filled with constants.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        if mesh.primitive_topology() != wgpu::PrimitiveTopology::TriangleList {
+            return None;
+        }
+
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float32x3(positions) => positions.clone(),
+            _ => return None,
+        };
+        let len = positions.len();
+        let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(normals)) => normals.clone(),
+            _ => vec![[0.0, 0.0, 1.0]; len],
+        };
+        let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(uvs)) => uvs.clone(),
+            _ => vec![[0.0; 2]; len],
+        };
+        let tangents = match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+            Some(VertexAttributeValues::Float32x4(tangents)) => tangents.clone(),
+            _ => vec![[1.0, 0.0, 0.0, 1.0]; len],
+        };
+        let indices = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as u32).collect(),
+            Some(Indices::U32(indices)) => indices.clone(),
+            None => (0..len as u32).collect(),
+        };
+
+        let min = positions
+            .iter()
+            .fold(Vec3::splat(f32::MAX), |a, &b| a.min(b.into()));
+        let max = positions
+            .iter()
+            .fold(Vec3::splat(f32::MIN), |a, &b| a.max(b.into()));
+        let center = (min + max) * 0.5;
+        let radius = (max - center).length().max(0.001);
+
+        Some(Self {
+            positions,
+            normals,
+            uvs,
+            tangents,
+            indices,
+            center,
+            radius,
+        })
+    }
+
+    /// Vertex buffer of `attribute`, the second UV set being the first one.
+    pub fn attribute(&self, attribute: Attribute) -> Vec<u8> {
+        let values: Vec<f32> = match attribute {
+            Attribute::Position => self.positions.iter().flatten().copied().collect(),
+            Attribute::Normal => self.normals.iter().flatten().copied().collect(),
+            Attribute::Uv0 | Attribute::Uv1 => self.uvs.iter().flatten().copied().collect(),
+            Attribute::Tangent => self.tangents.iter().flatten().copied().collect(),
+            Attribute::Color => vec![1.0; self.positions.len() * 4],
+        };
+        bytemuck::cast_slice(&values).to_vec()
+    }
+
+    pub fn index_bytes(&self) -> Vec<u8> {
+        bytemuck::cast_slice(&self.indices).to_vec()
+    }
+}
+
+/// Turns around the mesh, lit from slightly above the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitCamera {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Distance to the mesh, relative to its size.
+    pub zoom: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.6,
+            pitch: -0.4,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl OrbitCamera {
+    pub fn orbit(&mut self, delta: egui::Vec2) {
+        self.yaw -= delta.x * 0.01;
+        self.pitch = (self.pitch - delta.y * 0.01).clamp(-1.5, 1.5);
+    }
+
+    pub fn zoom_by(&mut self, scroll: f32) {
+        self.zoom = (self.zoom * (1.0 - scroll * 0.002)).clamp(0.2, 5.0);
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.0)
+    }
+
+    /// View and projection matrices framing the sphere at `center`.
+    pub fn matrices(&self, aspect: f32, center: Vec3, radius: f32) -> (Mat4, Mat4) {
+        let distance = radius * 2.5 * self.zoom;
+        let eye = center + self.rotation() * Vec3::Z * distance;
+
+        let view = Mat4::look_at_rh(eye, center, Vec3::Y);
+        let near = (distance - radius * 2.0).max(distance * 0.01);
+        let proj = Mat4::perspective_rh(0.8, aspect, near, distance + radius * 2.0);
+        (view, proj)
+    }
+
+    /// Contents of the `Camera` uniform buffer, framing the sphere at `center`.
+    pub fn uniform_bytes(&self, aspect: f32, center: Vec3, radius: f32) -> Vec<u8> {
+        let (view, proj) = self.matrices(aspect, center, radius);
+        let light = self.rotation() * Vec3::new(0.4, 0.6, 1.0).normalize();
+
+        let mut values = (proj * view).to_cols_array().to_vec();
+        values.extend_from_slice(&[light.x, light.y, light.z, 0.0]);
+        bytemuck::cast_slice(&values).to_vec()
+    }
+
+    /// Contents of the `Transform` uniform buffer, the mesh staying in place.
+    pub fn transform_bytes(&self, aspect: f32, center: Vec3, radius: f32) -> Vec<u8> {
+        let (view, proj) = self.matrices(aspect, center, radius);
+        transform_bytes(Mat4::IDENTITY, view, proj)
+    }
+}
+
+/// State of the [`MeshPreviewTab`] of a workspace.
+pub struct MeshPreview {
+    pub mesh: PreviewMesh,
+    /// Path edited for [`PreviewMesh::Asset`].
+    pub asset_path: String,
+    pub camera: OrbitCamera,
+    /// Master node previewed, the first one of the graph when unset.
+    pub target: Option<Node>,
+    /// The shader or the mesh changed, the pipeline is built again.
+    pub rebuild: bool,
+    /// Only the buffers changed.
+    pub redraw: bool,
+
+    size: egui::Vec2,
+    preview: Option<Preview>,
+    handle: Option<Handle<Mesh>>,
+    data: Option<MeshData>,
+    error: Option<String>,
+}
+
+impl Default for MeshPreview {
+    fn default() -> Self {
+        Self {
+            mesh: PreviewMesh::Sphere,
+            asset_path: String::from("models/BoxTextured.glb"),
+            camera: OrbitCamera::default(),
+            target: None,
+            rebuild: true,
+            redraw: false,
+
+            size: egui::Vec2::ZERO,
+            preview: None,
+            handle: None,
+            data: None,
+            error: None,
+        }
+    }
+}
+
+impl MeshPreview {
+    pub fn set_mesh(&mut self, mesh: PreviewMesh) {
+        if self.mesh != mesh {
+            self.mesh = mesh;
+            self.handle = None;
+            self.data = None;
+        }
+    }
+
+    /// Loads the mesh, `Err` once it failed to.
+    fn load_mesh(&mut self, meshes: &Assets<Mesh>, assets: &AssetServer) -> Result<(), String> {
+        if self.data.is_some() {
+            return Ok(());
+        }
+
+        let mesh = match &self.mesh {
+            PreviewMesh::Asset(path) => {
+                let path = match path.contains('#') {
+                    true => path.clone(),
+                    false => format!("{}#Mesh0/Primitive0", path),
+                };
+                let handle = self.handle.get_or_insert_with(|| assets.load(&path));
+                match meshes.get(handle) {
+                    Some(mesh) => mesh.clone(),
+                    None if assets.get_load_state(&*handle) == LoadState::Failed => {
+                        return Err(format!("failed to load mesh `{}`", path));
+                    }
+                    None => return Ok(()),
+                }
+            }
+            mesh => mesh.builtin().unwrap(),
+        };
+
+        let data = MeshData::from_mesh(&mesh).ok_or("the mesh isn't a list of triangles")?;
+        self.data = Some(data);
+        self.rebuild = true;
+        Ok(())
+    }
+
+    /// Contents of the `Camera` and `Transform` uniform buffers.
+    fn camera_bytes(&self) -> (Vec<u8>, Vec<u8>) {
+        let aspect = self.size.x / self.size.y;
+        let (center, radius) = match &self.data {
+            Some(data) => (data.center, data.radius),
+            None => (Vec3::ZERO, 1.0),
+        };
+        (
+            self.camera.uniform_bytes(aspect, center, radius),
+            self.camera.transform_bytes(aspect, center, radius),
+        )
+    }
+}
+
+/// Masters drawing meshes, unlike compute masters.
+fn is_drawn(builder: &dyn PreviewBuilder) -> bool {
+    builder.is_master() && builder.workgroup_size().is_none()
+}
+
+/// The master node previewed by `preview`, which may have been removed.
+fn target(storage: &Storage, preview: &MeshPreview) -> Option<Node> {
+    let is_master = |node: Node| {
+        storage
+            .nodes
+            .get(node)
+            .is_some_and(|node| is_drawn(node.builder.as_ref()))
+    };
+    let first = || storage.nodes.keys().find(|&node| is_master(node));
+    preview
+        .target
+        .filter(|&node| is_master(node))
+        .or_else(first)
+}
+
+pub fn update_mesh_previews(
+    mut workspaces: Query<&mut Workspace>,
+    mut context: ResMut<EguiContext>,
+    mut images: ResMut<Assets<Image>>,
+    mut layers: ResMut<TextureLayers>,
+    mut queue: ResMut<PreviewQueue>,
+    meshes: Res<Assets<Mesh>>,
+    device: Res<RenderDevice>,
+    assets: Res<AssetServer>,
+    scene: Res<PreviewScene>,
+) {
+    let scale_factor = {
+        let [ctx] = context.ctx_mut([WindowId::primary()]);
+        ctx.pixels_per_point()
+    };
+
+    for mut workspace in workspaces.iter_mut() {
+        let workspace = &mut *workspace;
+        let preview = &mut workspace.mesh_preview;
+        let storage = &workspace.storage;
+
+        // Not drawn until the tab is shown.
+        if preview.size.x < 1.0 || preview.size.y < 1.0 {
+            continue;
+        }
+
+        if let Err(err) = preview.load_mesh(&meshes, &assets) {
+            preview.error = Some(err);
+            continue;
+        }
+        if preview.data.is_none() {
+            continue;
+        }
+
+        let target = match target(storage, preview) {
+            Some(target) => target,
+            None => {
+                preview.error = Some(String::from("the graph has no master node"));
+                continue;
+            }
+        };
+        if preview.target != Some(target) {
+            preview.target = Some(target);
+            preview.rebuild = true;
+        }
+
+        let (width, height) = (preview.size.x as u32, preview.size.y as u32);
+        let mut image = match preview.preview.take() {
+            Some(image)
+                if image.scale == scale_factor
+                    && image.size.x as u32 == width
+                    && image.size.y as u32 == height =>
+            {
+                image
+            }
+            image => {
+                if let Some(image) = image {
+                    image.free(&mut images, &mut context);
+                }
+                preview.rebuild = true;
+                Preview::new(
+                    &mut images,
+                    &mut context,
+                    wgpu::TextureFormat::Rgba8Unorm,
+                    width,
+                    height,
+                    scale_factor,
+                )
+            }
+        };
+
+        let properties = uniform_bytes(&storage.properties);
+        let clock = workspace.time.uniform_bytes();
+        let (camera, transform) = preview.camera_bytes();
+
+        if std::mem::take(&mut preview.rebuild) {
+            preview.redraw = false;
+            preview.error = None;
+
+            let builder = storage.nodes[target].builder.as_ref();
+            let format = |path: &str| image_format(&images, path);
+            let pipeline = builder.pipeline(target, storage, &device, PreviewMode::Mesh, &format);
+            let pipeline = match pipeline {
+                Ok(pipeline) => pipeline,
+                Err(err) => {
+                    preview.error = Some(err.to_string());
+                    image.job = None;
+                    preview.preview = Some(image);
+                    continue;
+                }
+            };
+
+            let data = preview.data.as_ref().unwrap();
+            let vertex_buffers = pipeline
+                .attributes
+                .iter()
+                .map(|&attribute| data.attribute(attribute))
+                .collect();
+            let indices = data.index_bytes();
+            let vertices = 0..data.indices.len() as u32;
+
+            let mut bindings = Vec::new();
+            for resource in pipeline.resources {
+                bindings.push(match resource {
+                    Resource::Texture { path, dimension } => {
+                        let handle = assets.load(path.as_str());
+                        let bound = prepare_texture(
+                            &mut images,
+                            &mut layers,
+                            &assets,
+                            &handle,
+                            path,
+                            dimension,
+                        );
+                        let bound = bound.unwrap_or_else(|err| {
+                            preview.error = Some(err.to_string());
+                            handle.clone()
+                        });
+                        if !workspace.textures.contains(&handle) {
+                            workspace.textures.push(handle);
+                        }
+                        PreviewBinding::Texture(bound.clone_weak(), dimension.view())
+                    }
+                    Resource::Sampler(state) => PreviewBinding::Sampler(state.descriptor()),
+                    Resource::Properties => {
+                        PreviewBinding::Buffer(Resource::Properties, properties.clone())
+                    }
+                    Resource::Time => PreviewBinding::Buffer(Resource::Time, clock.clone()),
+                    Resource::Camera => PreviewBinding::Buffer(Resource::Camera, camera.clone()),
+                    Resource::Transform => {
+                        PreviewBinding::Buffer(Resource::Transform, transform.clone())
+                    }
+                    Resource::StorageTexture => unreachable!("only bound by compute masters"),
+                    Resource::StorageBuffer { .. } => {
+                        let size = image.size * image.scale;
+                        PreviewBinding::Storage(storage_bytes(size.x as u32, size.y as u32))
+                    }
+                    Resource::Viewport => {
+                        let size = image.size * image.scale;
+                        let bytes = viewport_bytes(size.x as u32, size.y as u32);
+                        PreviewBinding::Buffer(Resource::Viewport, bytes)
+                    }
+                    resource => scene.binding(&resource).unwrap(),
+                });
+            }
+
+            image.source = pipeline.source;
+            image.job = Some(PreviewJob {
+                image: image.image.clone_weak(),
+                pipeline: Some((pipeline.pipeline, pipeline.layout)),
+                bindings,
+                vertex_buffers,
+                indices: Some(indices),
+                vertices,
+                depth: true,
+            });
+        } else if let Some(job) = image.job.as_mut() {
+            let animated = workspace.time.playing && job.is_animated();
+            if !std::mem::take(&mut preview.redraw) && !animated {
+                preview.preview = Some(image);
+                continue;
+            }
+            job.write_buffers(&properties, &clock);
+            job.write_buffer(&Resource::Camera, &camera);
+            job.write_buffer(&Resource::Transform, &transform);
+        }
+
+        if let Some(job) = &image.job {
+            queue.jobs.push(job.clone());
+        }
+        preview.preview = Some(image);
+    }
+}
+
+/// Shows the mesh preview of the node trees.
+#[derive(Default, Component)]
+pub struct MeshPreviewTab;
+
+impl EditorTab for MeshPreviewTab {
+    type Param = (SRes<Style>, SQuery<Write<Workspace>>);
+
+    fn ui<'w>(
+        &mut self,
+        ui: &mut egui::Ui,
+        _entity: Entity,
+        (style, workspaces): &mut SystemParamItem<'w, '_, Self::Param>,
+    ) {
+        let rect = ui.available_rect_before_wrap();
+        ui.painter().rect_filled(rect, 0.0, style.panel);
+
+        ui.scope(|ui| {
+            style.set_theme_visuals(ui);
+            for mut workspace in workspaces.iter_mut() {
+                let workspace = &mut *workspace;
+                mesh_preview_ui(ui, &workspace.storage, &mut workspace.mesh_preview);
+            }
+        });
+    }
+}
+
+fn mesh_preview_ui(ui: &mut egui::Ui, storage: &Storage, preview: &mut MeshPreview) {
+    ui.horizontal(|ui| {
+        let masters: Vec<_> = storage
+            .nodes
+            .iter()
+            .filter(|(_, node)| is_drawn(node.builder.as_ref()))
+            .map(|(key, node)| (key, node.title.as_str()))
+            .collect();
+        let selected = masters
+            .iter()
+            .find(|(node, _)| Some(*node) == preview.target)
+            .map_or("None", |(_, title)| title);
+        egui::ComboBox::from_id_source("master")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (node, title) in masters {
+                    let checked = Some(node) == preview.target;
+                    if ui.selectable_label(checked, title).clicked() {
+                        preview.target = Some(node);
+                        preview.rebuild = true;
+                    }
+                }
+            });
+
+        let asset = PreviewMesh::Asset(preview.asset_path.clone());
+        egui::ComboBox::from_id_source("mesh")
+            .selected_text(preview.mesh.label())
+            .show_ui(ui, |ui| {
+                for mesh in [
+                    PreviewMesh::Sphere,
+                    PreviewMesh::Cube,
+                    PreviewMesh::Quad,
+                    asset,
+                ] {
+                    let checked = mesh.label() == preview.mesh.label();
+                    if ui.selectable_label(checked, mesh.label()).clicked() {
+                        preview.set_mesh(mesh);
+                    }
+                }
+            });
+
+        if let PreviewMesh::Asset(_) = preview.mesh {
+            let edit = egui::TextEdit::singleline(&mut preview.asset_path);
+            if ui.add(edit).lost_focus() {
+                preview.set_mesh(PreviewMesh::Asset(preview.asset_path.clone()));
+            }
+        }
+    });
+
+    let size = ui.available_size().floor();
+    if size != preview.size {
+        preview.size = size;
+    }
+
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::drag());
+    if let Some(image) = &preview.preview {
+        egui::Image::new(image.texture_id, size).paint_at(ui, rect);
+    }
+    if let Some(error) = &preview.error {
+        let text = egui::RichText::new(error).color(egui::Color32::RED);
+        ui.put(rect.shrink(8.0), egui::Label::new(text).wrap(true));
+    }
+
+    if response.dragged() {
+        preview.camera.orbit(response.drag_delta());
+        preview.redraw = true;
+    }
+    let scroll = ui.input().scroll_delta.y;
+    if response.hovered() && scroll != 0.0 {
+        preview.camera.zoom_by(scroll);
+        preview.redraw = true;
+    }
+}
+
+#[test]
+fn frame_mesh() {
+    let data = MeshData::from_mesh(&PreviewMesh::Cube.builtin().unwrap()).unwrap();
+    assert_eq!(data.center, Vec3::ZERO);
+    assert!((data.radius - 0.75f32.sqrt()).abs() < 1e-5);
+    assert_eq!(data.attribute(Attribute::Position).len(), 24 * 12);
+    assert_eq!(data.attribute(Attribute::Uv1).len(), 24 * 8);
+    assert_eq!(data.index_bytes().len(), 36 * 4);
+
+    // The center of the mesh lands in the middle of the preview.
+    let bytes = OrbitCamera::default().uniform_bytes(1.0, data.center, data.radius);
+    assert_eq!(bytes.len(), 80);
+    let values: &[f32] = bytemuck::cast_slice(&bytes);
+    let view_proj = Mat4::from_cols_slice(&values[..16]);
+    let clip = view_proj * data.center.extend(1.0);
+    assert!(clip.x.abs() < 1e-5 && clip.y.abs() < 1e-5);
+    assert!((0.0..1.0).contains(&(clip.z / clip.w)));
+
+    // Master nodes get the camera and lighting, everything else stays flat.
+    let mut storage = Storage::default();
+    let master = storage.spawn_kind("Master").unwrap();
+    let builder = storage.nodes[master].builder.as_ref();
+    let module = builder
+        .preview_module(master, &storage, PreviewMode::Mesh)
+        .unwrap();
+    let source = module.build().unwrap();
+    assert!(source.contains("var<uniform> camera: Camera;"));
+    assert!(source.contains("builtin_preview_light("));
+    assert_eq!(module.attributes, [Attribute::Position, Attribute::Normal]);
+    assert!(builder
+        .module(master, &storage)
+        .unwrap()
+        .resources
+        .is_empty());
+}
@@ -1,24 +1,101 @@
 use self::graph::Graph;
-use ahash::AHashSet;
+use self::nodes::custom::CustomFunction;
+use self::nodes::input::PropertyNode;
+use self::nodes::subgraph::SubGraph;
+use crate::ui::shell::clipboard::EguiClipboard;
+use crate::ui::{AddEditorTab, EditorTab, Style};
+use ahash::{AHashMap, AHashSet};
+use bevy::asset::{AssetIo, FileAssetIo, LoadState};
+use bevy::ecs::system::lifetimeless::{SRes, SResMut};
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::*;
+use bevy::render::extract_resource::ExtractResourcePlugin;
+use bevy::render::{render_graph::RenderGraph, RenderApp, RenderStage};
 use slotmap::SlotMap;
+use std::path::{Path, PathBuf};
 
+pub mod asset;
+mod blackboard;
 mod builder;
+pub mod compile;
+mod diagnostics;
 mod graph;
+mod history;
+mod infer;
 mod link;
+mod material;
+mod mesh_preview;
 mod node;
 mod nodes;
 mod port;
 pub mod preview;
+mod subgraph;
 
 pub use self::{
+    asset::{OpenShaderGraph, ShaderGraph, ShaderGraphError, ShaderGraphLoader},
+    blackboard::{Blackboard, Property, PropertyValue},
+    builder::{ErrorLocation, ExportTarget, ExportedShader, Pass},
+    diagnostics::{Diagnostic, Diagnostics},
+    history::{Change, DefaultValue, Edit, History, HistoryError, NodeId, PortRef, RemovedNode},
     link::{Link, LinkBezier, LinkData, Slot},
+    material::GraphMaterial,
+    mesh_preview::{MeshPreview, MeshPreviewTab, OrbitCamera, PreviewMesh},
     node::{Node, NodeBuilder, NodeData, NodeInteraction},
     port::Direction::{Input, Output},
     port::Stage::{Fragment, Vertex},
-    port::{Data, Direction, InputDefault, InputDefaultType, Port, PortData, Stage},
-    preview::{Preview, PreviewBuilder},
+    port::{Data, Direction, InputDefault, InputDefaultType, Port, PortData, PortSpec, Stage},
+    preview::{
+        Preview, PreviewBuilder, PreviewNode, PreviewQueue, PreviewScene, PreviewTime, PREVIEW_PASS,
+    },
+    subgraph::{SubGraphAsset, SubGraphLibrary},
 };
 
+#[derive(Default)]
+pub struct WorkspacePlugin;
+
+impl Plugin for WorkspacePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ShaderGraph>()
+            .init_asset_loader::<ShaderGraphLoader>()
+            .add_event::<OpenShaderGraph>()
+            .add_system(open_shader_graphs)
+            .init_resource::<SubGraphLibrary>()
+            .add_startup_system(self::subgraph::load_sub_graphs)
+            .add_system(self::subgraph::update_sub_graphs)
+            .init_resource::<PreviewQueue>()
+            .init_resource::<PreviewScene>()
+            .init_resource::<self::preview::TextureLayers>()
+            .add_plugin(ExtractResourcePlugin::<PreviewQueue>::default())
+            .add_system(self::preview::watch_textures)
+            .add_plugin(MaterialPlugin::<GraphMaterial>::default())
+            .init_resource::<self::material::GraphBuffers>()
+            .init_resource::<self::material::GraphUniforms>()
+            .add_plugin(ExtractResourcePlugin::<self::material::GraphUniforms>::default())
+            .add_system(self::material::compile_graph_materials)
+            .add_system(self::material::prepare_graph_textures)
+            .add_system(self::material::update_graph_uniforms)
+            .add_system_to_stage(CoreStage::PostUpdate, self::preview::update_previews)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                self::mesh_preview::update_mesh_previews.after(self::preview::update_previews),
+            )
+            .add_editor_tab::<Workspace>()
+            .add_editor_tab::<Diagnostics>()
+            .add_editor_tab::<Blackboard>()
+            .add_editor_tab::<MeshPreviewTab>();
+
+        let buffers = app.world.resource::<self::material::GraphBuffers>().clone();
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .insert_resource(buffers)
+            .add_system_to_stage(RenderStage::Prepare, self::material::write_graph_uniforms);
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+
+        graph.add_node(PREVIEW_PASS, PreviewNode);
+        graph.add_node_edge(PREVIEW_PASS, "ui_root").unwrap();
+    }
+}
+
 #[derive(Clone, Copy, Default, PartialEq)]
 pub struct ButtonState {
     pub pressed: bool,
@@ -61,6 +138,11 @@ pub struct Storage {
     pub nodes: SlotMap<Node, NodeData>,
     pub ports: SlotMap<Port, PortData>,
     pub links: SlotMap<Link, LinkData>,
+    /// Values of the graph bound as a uniform buffer, see [`blackboard`].
+    pub properties: Vec<Property>,
+    /// Previews of despawned nodes, freed by [`preview::update_previews`].
+    pub released_previews: Vec<Preview>,
+    last_id: u64,
 }
 
 impl Storage {
@@ -83,17 +165,39 @@ impl Storage {
             inputs: Vec::new(),
             outputs: Vec::new(),
         };
+        self.last_id += 1;
+        let id = NodeId(self.last_id);
         self.nodes.insert_with_key(|node| {
             let mut node = NodeData::new(title, width, builder(&mut node_builder, node));
+            node.id = id;
             node.inputs = node_builder.inputs;
             node.outputs = node_builder.outputs;
             node
         })
     }
 
+    /// Spawns a node by its name in the creation menu.
+    pub fn spawn_kind(&mut self, kind: &str) -> Option<Node> {
+        // Sub-graphs and properties are listed by name instead of their common kind.
+        let named: &[(&str, BuilderFn)] = &[
+            (SubGraph::KIND, SubGraph::spawn),
+            (PropertyNode::KIND, PropertyNode::spawn),
+        ];
+        let (kind, builder) = creator_menu()
+            .iter()
+            .flat_map(|group| group.items)
+            .chain(named)
+            .find(|&&(target, _)| target == kind)?;
+
+        let node = builder(self);
+        self.nodes[node].kind = kind;
+        Some(node)
+    }
+
     pub fn despawn(&mut self, node: Node) {
         if let Some(removed) = self.nodes.remove(node) {
             tracing::info!("despawn {:?}", node);
+            self.released_previews.extend(removed.preview);
 
             let inputs = removed.inputs.into_iter();
             let outputs = removed.outputs.into_iter();
@@ -107,6 +211,137 @@ impl Storage {
         }
     }
 
+    /// Renames the property `old` of the blackboard and the nodes reading it.
+    pub fn rename_property(&mut self, old: &str, new: &str) {
+        for property in &mut self.properties {
+            if property.name == old {
+                property.name = new.to_string();
+            }
+        }
+        for node in self.nodes.values_mut() {
+            if let Some(property) = node.builder.downcast_mut::<PropertyNode>() {
+                if property.name == old {
+                    property.name = new.to_string();
+                }
+            }
+        }
+    }
+
+    /// Gives the property nodes the types of the properties they read, leaving
+    /// their ports as they are. Returns the property nodes.
+    ///
+    /// Nodes reading a removed property keep their type and fail to build.
+    pub fn update_property_types(&mut self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        for (node, data) in &mut self.nodes {
+            if let Some(property) = data.builder.downcast_mut::<PropertyNode>() {
+                if let Some(value) = self.properties.iter().find(|p| p.name == property.name) {
+                    property.data = value.value.data();
+                }
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    /// Updates the types and ports of the property nodes after the blackboard changed.
+    pub fn sync_properties(&mut self) {
+        for node in self.update_property_types() {
+            self.sync_ports(node);
+        }
+    }
+
+    fn ports_match(&self, ports: &[Port], specs: &[PortSpec]) -> bool {
+        ports.len() == specs.len()
+            && ports.iter().zip(specs).all(|(&port, spec)| {
+                self.ports[port].label == spec.label && self.ports[port].data == spec.data
+            })
+    }
+
+    /// Whether [`Storage::sync_ports`] would change the ports of `node`.
+    pub fn ports_out_of_sync(&self, node: Node) -> bool {
+        match self.nodes.get(node).and_then(|n| n.builder.signature()) {
+            Some((inputs, outputs)) => {
+                let node = &self.nodes[node];
+                !self.ports_match(&node.inputs, &inputs)
+                    || !self.ports_match(&node.outputs, &outputs)
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuilds the ports of `node` from [`PreviewBuilder::signature`].
+    ///
+    /// Ports are kept, with their links and default values, when a port of the
+    /// same type has the same label or the same position. Returns whether anything changed.
+    pub fn sync_ports(&mut self, node: Node) -> bool {
+        let (inputs, outputs) = match self.nodes.get(node).and_then(|n| n.builder.signature()) {
+            Some(signature) => signature,
+            None => return false,
+        };
+
+        let mut changed = false;
+        for (direction, specs) in [(Input, inputs), (Output, outputs)] {
+            let current = match direction {
+                Input => self.nodes[node].inputs.clone(),
+                Output => self.nodes[node].outputs.clone(),
+            };
+
+            if self.ports_match(&current, &specs) {
+                continue;
+            }
+            changed = true;
+
+            let mut unused = current.clone();
+            let mut ports = Vec::with_capacity(specs.len());
+            for (index, spec) in specs.into_iter().enumerate() {
+                let by_label = unused.iter().position(|&port| {
+                    self.ports[port].label == spec.label && self.ports[port].data == spec.data
+                });
+                let by_index = || {
+                    let port = *current.get(index)?;
+                    let position = unused.iter().position(|&p| p == port)?;
+                    (self.ports[port].data == spec.data).then_some(position)
+                };
+
+                let port = match by_label.or_else(by_index) {
+                    Some(position) => {
+                        let port = unused.swap_remove(position);
+                        self.ports[port].label = spec.label;
+                        port
+                    }
+                    None => {
+                        let mut builder = NodeBuilder {
+                            storage: self,
+                            inputs: Vec::new(),
+                            outputs: Vec::new(),
+                        };
+                        match direction {
+                            Input => builder.input(node, spec.label, Fragment, spec.data, None),
+                            Output => builder.output(node, spec.label, Fragment, spec.data, None),
+                        }
+                    }
+                };
+                ports.push(port);
+            }
+
+            for port in unused {
+                if let Some(port) = self.ports.remove(port) {
+                    for link in port.links {
+                        self.unlink(link);
+                    }
+                }
+            }
+
+            match direction {
+                Input => self.nodes[node].inputs = ports,
+                Output => self.nodes[node].outputs = ports,
+            }
+        }
+
+        changed
+    }
+
     fn link_impl(
         ports: &mut SlotMap<Port, PortData>,
         links: &mut SlotMap<Link, LinkData>,
@@ -180,8 +415,8 @@ impl Storage {
             (Output, Input) if any_link_with_port(hovered) => return false,
             (Input, Output) if any_link_with_port(current) => return false,
 
-            (Output, Input) => (start, end),
-            (Input, Output) => (end, start),
+            (Output, Input) => (current, hovered),
+            (Input, Output) => (hovered, current),
         };
 
         graph.clear();
@@ -192,8 +427,15 @@ impl Storage {
             graph.add_edge(output, input);
         }
 
-        graph.add_edge(output.node, input.node);
-        !graph.reachable(input.node, output.node)
+        let (output_node, input_node) = (self.ports[output].node, self.ports[input].node);
+        graph.add_edge(output_node, input_node);
+        if graph.reachable(input_node, output_node) {
+            return false;
+        }
+
+        // Graphs saved before inference may already hold conflicting links.
+        let conflicts = self.infer_types().conflicts.len();
+        self.infer_types_with(Some((output, input))).conflicts.len() <= conflicts
     }
 }
 
@@ -206,20 +448,48 @@ enum Interaction {
     NodeCreation { position: egui::Pos2, first: bool },
 }
 
+#[derive(Component)]
 pub struct Workspace {
     pub storage: Storage,
     pub dirty: bool,
+    /// Property values changed without changing the layout of their buffer.
+    pub properties_changed: bool,
+    /// Clock of the animated previews.
+    pub time: PreviewTime,
+    pub mesh_preview: MeshPreview,
+
+    /// Where the graph is saved, relative to the asset folder.
+    pub path: Option<PathBuf>,
+    /// Path typed in the prompt of a graph saved for the first time, open while `Some`.
+    save_as: Option<String>,
+    loading: Option<Handle<ShaderGraph>>,
+    export: Option<(Node, ExportTarget)>,
+
+    /// Problems found by the last preview update.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Images bound by the previews, kept loaded while in use.
+    pub textures: Vec<Handle<Image>>,
+
+    history: History,
+    /// Settings of the nodes with a [`PreviewBuilder::signature`] when their
+    /// ports were last in sync, to undo port changes.
+    port_states: AHashMap<NodeId, Option<ron::Value>>,
+    /// Bumped on every click, changes made in between end up in a single undo step.
+    gesture: u64,
 
     input: InputState,
 
     graph: Graph<Node>,
     search: String,
+    /// Entries of the [`SubGraphLibrary`] for the creation menu.
+    sub_graphs: std::sync::Arc<Vec<SubGraphAsset>>,
 
     interaction: Interaction,
     interaction_cache: Vec<NodeInteraction>,
 
     pub pan_offset: egui::Vec2,
     pan_button: egui::PointerButton,
+    viewport: egui::Rect,
 
     selection: AHashSet<Node>,
     selection_button: egui::PointerButton,
@@ -238,6 +508,21 @@ impl Workspace {
         Self {
             storage: Storage::default(),
             dirty: true,
+            properties_changed: false,
+            time: PreviewTime::default(),
+            mesh_preview: MeshPreview::default(),
+
+            path: None,
+            save_as: None,
+            loading: None,
+            export: None,
+
+            diagnostics: Vec::new(),
+            textures: Vec::new(),
+
+            history: undo::record::Builder::new().build(),
+            port_states: AHashMap::default(),
+            gesture: 0,
 
             interaction: Interaction::None,
             interaction_cache: Vec::new(),
@@ -246,9 +531,11 @@ impl Workspace {
 
             graph: Graph::default(),
             search: String::new(),
+            sub_graphs: Default::default(),
 
             pan_offset: egui::Vec2::ZERO,
             pan_button: egui::PointerButton::Secondary,
+            viewport: egui::Rect::NOTHING,
 
             selection: AHashSet::default(),
             selection_button: egui::PointerButton::Primary,
@@ -257,10 +544,325 @@ impl Workspace {
         }
     }
 
-    pub fn draw(&mut self, ctx: &egui::Context) {
-        self.dirty = self.input.update(&ctx.input());
+    pub fn set_storage(&mut self, storage: Storage) {
+        let old = std::mem::replace(&mut self.storage, storage);
+        let previews = old.nodes.into_iter().filter_map(|(_, node)| node.preview);
+        let released = &mut self.storage.released_previews;
+        released.extend(previews.chain(old.released_previews));
+        self.selection.clear();
+        self.diagnostics.clear();
+        self.history.clear();
+        self.port_states.clear();
+        self.interaction = Interaction::None;
+        self.dirty = true;
+    }
+
+    /// Applies `change` and records it in the undo history.
+    pub fn edit(&mut self, change: Change) {
+        let edit = Edit::new(self.gesture, change);
+        if let Err(err) = self.history.apply(&mut self.storage, edit) {
+            tracing::warn!("failed to apply graph edit: {}", err);
+        }
+        self.dirty = true;
+    }
 
-        let mouse_is_over_area = ctx.available_rect().contains(self.input.pointer);
+    pub fn undo(&mut self) {
+        if let Some(Err(err)) = self.history.undo(&mut self.storage) {
+            tracing::warn!("failed to undo graph edit: {}", err);
+        }
+        self.after_history_step();
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(Err(err)) = self.history.redo(&mut self.storage) {
+            tracing::warn!("failed to redo graph edit: {}", err);
+        }
+        self.after_history_step();
+    }
+
+    /// Serializes the selected nodes and the links between them.
+    pub fn copy_selection(&self) -> Option<String> {
+        let nodes: Vec<_> = self
+            .selection
+            .iter()
+            .copied()
+            .filter(|&node| self.storage.nodes.contains_key(node))
+            .collect();
+
+        if nodes.is_empty() {
+            return None;
+        }
+
+        match ShaderGraph::from_nodes(&self.storage, nodes).to_ron() {
+            Ok(text) => Some(text),
+            Err(err) => {
+                tracing::error!("failed to copy nodes: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn delete_selection(&mut self) {
+        self.gesture += 1;
+
+        let nodes: Vec<_> = self
+            .selection
+            .drain()
+            .filter_map(|node| self.storage.nodes.get(node))
+            .map(|node| node.id)
+            .collect();
+
+        for node in nodes {
+            self.edit(Change::Despawn {
+                node,
+                removed: None,
+            });
+        }
+    }
+
+    /// Spawns copied nodes with the top left one at `position`, in screen space,
+    /// and selects them.
+    pub fn paste(&mut self, text: &str, position: egui::Pos2) -> anyhow::Result<()> {
+        let graph = ShaderGraph::from_ron(text.as_bytes())?;
+        let nodes = graph.insert_into(&mut self.storage)?;
+
+        let min = nodes
+            .iter()
+            .map(|&node| self.storage.nodes[node].position)
+            .fold(egui::pos2(f32::INFINITY, f32::INFINITY), egui::Pos2::min);
+        let delta = position - self.pan_offset - min;
+
+        self.gesture += 1;
+        self.selection.clear();
+        for node in nodes {
+            self.storage.translate(node, delta);
+            self.selection.insert(node);
+
+            let node = self.storage.nodes[node].id;
+            self.edit(Change::Spawn {
+                node,
+                removed: None,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn duplicate_selection(&mut self) -> anyhow::Result<()> {
+        let text = match self.copy_selection() {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+
+        let min = self
+            .selection
+            .iter()
+            .filter_map(|&node| self.storage.nodes.get(node))
+            .map(|node| node.position)
+            .fold(egui::pos2(f32::INFINITY, f32::INFINITY), egui::Pos2::min);
+
+        self.paste(&text, min + self.pan_offset + egui::vec2(20.0, 20.0))
+    }
+
+    /// Rebuilds the ports of `node` after its settings changed, as an undoable
+    /// edit going back to the settings it had when its ports were last in sync.
+    fn sync_ports(&mut self, node: Node) {
+        let data = &self.storage.nodes[node];
+        if !self.storage.ports_out_of_sync(node) {
+            if data.builder.signature().is_some() {
+                let state = || data.builder.save();
+                self.port_states.entry(data.id).or_insert_with(state);
+            }
+            return;
+        }
+
+        let id = data.id;
+        let before = match self.port_states.remove(&id) {
+            Some(state) => RemovedNode::save(&self.storage, node).map(|n| n.with_state(state)),
+            None => None,
+        };
+        self.storage.sync_ports(node);
+        self.port_states
+            .insert(id, self.storage.nodes[node].builder.save());
+        if before.is_some() {
+            self.edit(Change::SyncPorts {
+                node: id,
+                before,
+                after: None,
+            });
+        }
+        self.dirty = true;
+    }
+
+    /// Reads the files of the custom functions whose `Load` was clicked,
+    /// relative to the graph file.
+    fn load_custom_functions(&mut self, io: &dyn AssetIo) {
+        let root = match io.downcast_ref::<FileAssetIo>() {
+            Some(io) => io.root_path(),
+            None => return,
+        };
+        let graph = root.join(self.path.as_deref().unwrap_or(Path::new("untitled.shader")));
+        let dir = graph.parent().unwrap_or(root);
+        for node in self.storage.nodes.values_mut() {
+            if let Some(custom) = node.builder.downcast_mut::<CustomFunction>() {
+                if custom.take_load() {
+                    custom.load_file(dir);
+                    self.dirty = true;
+                }
+            }
+        }
+    }
+
+    fn after_history_step(&mut self) {
+        // Settings may have been brought back, they are saved again on the next sync.
+        self.port_states.clear();
+        // Don't merge the next edit into the one that was just undone or redone.
+        self.gesture += 1;
+        self.interaction = Interaction::None;
+        self.dirty = true;
+    }
+
+    /// Selects `node` and pans it to the middle of the view.
+    pub fn focus(&mut self, node: Node) {
+        let rect = match self.storage.nodes.get(node) {
+            Some(node) => node.rect,
+            None => return,
+        };
+
+        self.selection.clear();
+        self.selection.insert(node);
+
+        if self.viewport.is_positive() && rect.is_positive() {
+            self.pan_offset += self.viewport.center() - rect.center();
+        }
+    }
+
+    pub fn save(&mut self, io: &dyn AssetIo) -> anyhow::Result<()> {
+        let io = io
+            .downcast_ref::<FileAssetIo>()
+            .ok_or_else(|| anyhow::anyhow!("shader graphs can only be saved to files"))?;
+
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("the shader graph has no path yet"))?;
+
+        let text = ShaderGraph::from_storage(&self.storage).to_ron()?;
+        std::fs::write(io.root_path().join(path), text)?;
+
+        tracing::info!("saved {}", path.display());
+        Ok(())
+    }
+
+    /// Prompts for the path of a graph saved for the first time, returning
+    /// `true` once it is set.
+    fn save_as_ui(&mut self, ctx: &egui::Context, io: &dyn AssetIo) -> bool {
+        let (mut text, io) = match (self.save_as.take(), io.downcast_ref::<FileAssetIo>()) {
+            (Some(text), Some(io)) => (text, io),
+            _ => return false,
+        };
+
+        let mut open = true;
+        let mut confirmed = None;
+        egui::Window::new("Save Shader Graph")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let edit = egui::TextEdit::singleline(&mut text).hint_text("graph.shader");
+                let response = ui.add(edit);
+                let path = Path::new(&text).with_extension("shader");
+                let exists = io.root_path().join(&path).exists();
+                if exists {
+                    let warning = format!("{} already exists", path.display());
+                    ui.colored_label(egui::Color32::YELLOW, warning);
+                }
+
+                let label = if exists { "Overwrite" } else { "Save" };
+                let button = ui.add_enabled(!text.is_empty(), egui::Button::new(label));
+                let enter = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                if button.clicked() || (enter && !exists && !text.is_empty()) {
+                    confirmed = Some(path);
+                }
+            });
+
+        match confirmed {
+            Some(path) => {
+                self.path = Some(path);
+                true
+            }
+            None => {
+                if open {
+                    self.save_as = Some(text);
+                }
+                false
+            }
+        }
+    }
+
+    /// Writes the shader of a master node next to the graph file, after the
+    /// optimization `passes`, as `<graph>_<node>.<ext>` or one file per stage
+    /// for GLSL, with the resources it binds in `<graph>_<node>.layout.ron`.
+    pub fn export(
+        &self,
+        io: &dyn AssetIo,
+        node: Node,
+        target: ExportTarget,
+        passes: &[Pass],
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let io = io
+            .downcast_ref::<FileAssetIo>()
+            .ok_or_else(|| anyhow::anyhow!("shaders can only be exported to files"))?;
+
+        let data = &self.storage.nodes[node];
+        let mut module = data.builder.module(node, &self.storage)?;
+        for report in module.optimize(passes)? {
+            tracing::info!("{}", report);
+        }
+        let shaders = module.export(target)?;
+        let layout = module.binding_layout();
+
+        let graph = self.path.as_deref().unwrap_or(Path::new("untitled.shader"));
+        let title: String = data
+            .title
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let stem = match graph.file_stem() {
+            Some(stem) => format!("{}_{}", stem.to_string_lossy(), title),
+            None => title,
+        };
+
+        let mut paths = Vec::with_capacity(shaders.len());
+        for shader in shaders {
+            let path = graph.with_file_name(shader.file_name(&stem, target));
+            std::fs::write(io.root_path().join(&path), &shader.data)?;
+            tracing::info!("exported {}", path.display());
+            paths.push(path);
+        }
+
+        if !layout.is_empty() {
+            let path = graph.with_file_name(format!("{}.layout.ron", stem));
+            std::fs::write(io.root_path().join(&path), layout.to_ron()?)?;
+            tracing::info!("exported {}", path.display());
+            paths.push(path);
+        }
+
+        Ok(paths)
+    }
+
+    pub fn draw(&mut self, ui: &mut egui::Ui) {
+        let ctx = &ui.ctx().clone();
+        let viewport = ui.max_rect();
+        self.viewport = viewport;
+
+        // Other tabs may have changed the graph earlier in the frame.
+        self.dirty |= self.input.update(&ctx.input());
+        if self.input.mouse.iter().any(|button| button.pressed) {
+            self.gesture += 1;
+        }
+
+        let mouse_is_over_area = viewport.contains(self.input.pointer);
 
         match self.interaction {
             Interaction::None => {
@@ -315,7 +917,9 @@ impl Workspace {
 
                             let slot = if to_min > to_max { min } else { max };
 
-                            self.storage.unlink(link);
+                            let from = self.storage.port_ref(min.port);
+                            let to = self.storage.port_ref(max.port);
+                            self.edit(Change::Unlink { from, to });
 
                             Interaction::LinkCreation { current: slot.port }
                         } else {
@@ -332,17 +936,17 @@ impl Workspace {
                     self.interaction = Interaction::None;
                 }
             }
-            Interaction::BoxSelection { start } => self.box_selection(ctx, start),
+            Interaction::BoxSelection { start } => self.box_selection(ctx, viewport, start),
             Interaction::LinkCreation { current } => {
                 tracing::trace!("link creation {:?}", current)
             }
 
             Interaction::NodeCreation { position, first } => {
-                self.node_creation(ctx, position, first)
+                self.node_creation(ctx, viewport, position, first)
             }
         }
 
-        let painter = ctx.layer_painter(egui::LayerId::background());
+        let painter = ui.painter();
 
         for link in self.storage.links.values_mut() {
             link.shape = Some(painter.add(egui::Shape::Noop));
@@ -352,6 +956,8 @@ impl Workspace {
         let mut drag_delta = None;
         let mut hovered = None;
         let mut create_link = false;
+        let mut unlinked = None;
+        let mut defaults = Vec::new();
 
         for (node_key, node) in &mut self.storage.nodes {
             let selected = self.selection.contains(&node_key);
@@ -361,6 +967,7 @@ impl Workspace {
                 ctx,
                 &mut self.storage.ports,
                 node_key,
+                viewport,
                 self.pan_offset,
                 selected,
                 &mut self.interaction_cache,
@@ -375,13 +982,10 @@ impl Workspace {
                         if let Interaction::None = self.interaction {
                             let port = &self.storage.ports[current];
                             if port.is_input() {
-                                if let Some(link) = port.links.iter().next().copied() {
-                                    let link = Storage::unlink_impl(
-                                        &mut self.storage.ports,
-                                        &mut self.storage.links,
-                                        link,
-                                    );
-                                    current = link.unwrap().min.port;
+                                if let Some(&link) = port.links.iter().next() {
+                                    let from = self.storage.links[link].min.port;
+                                    unlinked = Some((from, current));
+                                    current = from;
                                 }
                             }
 
@@ -389,31 +993,56 @@ impl Workspace {
                         }
                     }
                     NodeInteraction::LinkEnd(_) => create_link = true,
+                    NodeInteraction::Export(target) => self.export = Some((node_key, target)),
+                    NodeInteraction::SetDefault(port, before) => defaults.push((port, before)),
                 }
             }
         }
 
+        // Nodes like sub-graph inputs change their ports from their own settings.
+        let keys: Vec<Node> = self.storage.nodes.keys().collect();
+        for node in keys {
+            self.sync_ports(node);
+        }
+
+        if let Some((from, to)) = unlinked {
+            let from = self.storage.port_ref(from);
+            let to = self.storage.port_ref(to);
+            self.edit(Change::Unlink { from, to });
+        }
+
+        for (port, before) in defaults {
+            let after = self.storage.ports[port].input_default.as_ref().unwrap();
+            self.edit(Change::SetDefault {
+                port: self.storage.port_ref(port),
+                before,
+                after: DefaultValue::new(after),
+            });
+        }
+
         if let Some((key, delta)) = drag_delta {
             if !self.selection.contains(&key) {
                 self.selection.clear();
                 self.selection.insert(key);
             }
 
+            let mut nodes: Vec<_> = self
+                .selection
+                .iter()
+                .filter_map(|&node| self.storage.nodes.get(node))
+                .map(|node| node.id)
+                .collect();
+            nodes.sort_unstable();
+            self.edit(Change::Move { nodes, delta });
+
             for &node in &self.selection {
-                let node = match self.storage.nodes.get_mut(node) {
+                let node = match self.storage.nodes.get(node) {
                     Some(node) => node,
                     None => continue,
                 };
 
-                node.position += delta;
-                node.rect.min += delta;
-                node.rect.max += delta;
-
                 for &port in node.inputs.iter().chain(node.outputs.iter()) {
-                    let port = &mut self.storage.ports[port];
-                    port.position += delta;
-                    port.rect.min += delta;
-                    port.rect.max += delta;
+                    let port = &self.storage.ports[port];
                     if let Some(layer_id) = port.input_default.as_ref().and_then(|d| d.layer_id) {
                         ctx.translate_layer(layer_id, delta)
                     }
@@ -439,7 +1068,7 @@ impl Workspace {
         if let Interaction::LinkCreation { current } = self.interaction {
             let id = egui::Id::new("link creation");
             let layer_id = egui::LayerId::new(egui::Order::Tooltip, id);
-            let painter = ctx.layer_painter(layer_id);
+            let painter = ctx.layer_painter(layer_id).with_clip_rect(viewport);
 
             if hovered.is_none() {
                 let hover_distance = 20.0;
@@ -478,11 +1107,14 @@ impl Workspace {
                 self.interaction = Interaction::None;
 
                 if let Some(snap_port) = snap_port {
-                    if start.is_output() {
-                        self.storage.link(current, snap_port);
+                    let (from, to) = if start.is_output() {
+                        (current, snap_port)
                     } else {
-                        self.storage.link(snap_port, current);
-                    }
+                        (snap_port, current)
+                    };
+                    let from = self.storage.port_ref(from);
+                    let to = self.storage.port_ref(to);
+                    self.edit(Change::Link { from, to });
                 }
             }
 
@@ -492,11 +1124,15 @@ impl Workspace {
         }
 
         if let Some(node) = to_remove {
-            self.storage.despawn(node);
+            let node = self.storage.nodes[node].id;
+            self.edit(Change::Despawn {
+                node,
+                removed: None,
+            });
         }
     }
 
-    fn box_selection(&mut self, ctx: &egui::Context, start: egui::Pos2) {
+    fn box_selection(&mut self, ctx: &egui::Context, viewport: egui::Rect, start: egui::Pos2) {
         let btn = self.selection_button as usize;
         let current = self.input.pointer;
 
@@ -505,7 +1141,7 @@ impl Workspace {
             egui::pos2(start.x.max(current.x), start.y.max(current.y)),
         );
 
-        let rect = ctx.available_rect().shrink(1.0).intersect(rect);
+        let rect = viewport.shrink(1.0).intersect(rect);
 
         self.selection.clear();
         for (key, node) in self.storage.nodes.iter() {
@@ -530,15 +1166,24 @@ impl Workspace {
         };
     }
 
-    fn node_creation(&mut self, ctx: &egui::Context, position: egui::Pos2, first: bool) {
+    fn node_creation(
+        &mut self,
+        ctx: &egui::Context,
+        viewport: egui::Rect,
+        position: egui::Pos2,
+        first: bool,
+    ) {
         let area_position = {
-            let mut rect = ctx.input().screen_rect;
+            let mut rect = viewport;
             rect.max.x = (rect.max.x - 250.0).max(rect.min.x);
             rect.max.y = (rect.max.y - 200.0).max(rect.min.y);
             let egui::Rect { min, max } = rect;
             position.clamp(min, max)
         };
 
+        let mut picked_sub_graph = None;
+        let mut picked_property = None;
+
         let area = egui::Area::new("NodeCreation").order(egui::Order::Foreground);
         let area = area.movable(false).current_pos(area_position);
 
@@ -561,24 +1206,50 @@ impl Workspace {
                     let align = egui::Align::Min;
                     let layout = egui::Layout::top_down(align).with_cross_justify(true);
                     let out = ui.with_layout(layout, |ui| {
+                        let search = &self.search;
+                        let label = |target: &str| -> Option<egui::WidgetText> {
+                            if search.is_empty() {
+                                Some(target.into())
+                            } else {
+                                sublime_fuzzy::best_match(search, target)
+                                    .map(|result| mark_matches(result, target).into())
+                            }
+                        };
+
+                        ui.horizontal_wrapped(|ui| {
+                            for (index, sub_graph) in self.sub_graphs.iter().enumerate() {
+                                if let Some(text) = label(&sub_graph.name) {
+                                    let button = ui.button(text);
+                                    let path = sub_graph.path.display().to_string();
+                                    if button.on_hover_text(path).clicked() {
+                                        picked_sub_graph = Some(index);
+                                    }
+                                }
+                            }
+                        });
+
+                        ui.horizontal_wrapped(|ui| {
+                            for (index, property) in self.storage.properties.iter().enumerate() {
+                                if let Some(text) = label(&property.name) {
+                                    let button = ui.button(text);
+                                    let hover = property.value.type_name();
+                                    if button.on_hover_text(hover).clicked() {
+                                        picked_property = Some(index);
+                                    }
+                                }
+                            }
+                        });
+
                         for group in creator_menu() {
                             if !self.search.is_empty() && group.nothing(&self.search) {
                                 continue;
                             }
 
                             let out = ui.horizontal_wrapped(|ui| {
-                                for &(target, builder) in group.items {
-                                    let label: Option<egui::WidgetText> = if self.search.is_empty()
-                                    {
-                                        Some(target.into())
-                                    } else {
-                                        sublime_fuzzy::best_match(&self.search, target)
-                                            .map(|result| mark_matches(result, target).into())
-                                    };
-
-                                    if let Some(text) = label {
+                                for &(target, _) in group.items {
+                                    if let Some(text) = label(target) {
                                         if ui.button(text).clicked() {
-                                            return Some(builder);
+                                            return Some(target);
                                         }
                                     }
                                 }
@@ -586,8 +1257,8 @@ impl Workspace {
                                 None
                             });
 
-                            if let Some(builder) = out.inner {
-                                return Some(builder);
+                            if let Some(target) = out.inner {
+                                return Some(target);
                             }
                         }
 
@@ -602,9 +1273,26 @@ impl Workspace {
             out.inner
         });
 
-        if let Some(builder) = inner {
-            let node = builder(&mut self.storage);
-            self.storage.nodes[node].position = position - self.pan_offset;
+        let sub_graphs = self.sub_graphs.clone();
+        let sub_graph =
+            picked_sub_graph.and_then(|index| sub_graphs[index].spawn(&mut self.storage));
+        let property = picked_property.map(|index| {
+            let property = self.storage.properties[index].clone();
+            PropertyNode::spawn_property(&mut self.storage, &property)
+        });
+
+        if let Some(node) = inner
+            .and_then(|kind| self.storage.spawn_kind(kind))
+            .or(sub_graph)
+            .or(property)
+        {
+            let node = &mut self.storage.nodes[node];
+            node.position = position - self.pan_offset;
+            let node = node.id;
+            self.edit(Change::Spawn {
+                node,
+                removed: None,
+            });
             self.interaction = Interaction::None;
         } else if response.clicked_elsewhere() {
             self.interaction = Interaction::None;
@@ -615,6 +1303,165 @@ impl Workspace {
     }
 }
 
+impl EditorTab for Workspace {
+    type Param = (
+        SRes<Style>,
+        SRes<AssetServer>,
+        SResMut<EguiClipboard>,
+        SResMut<SubGraphLibrary>,
+    );
+
+    fn ui<'w>(
+        &mut self,
+        ui: &mut egui::Ui,
+        _entity: Entity,
+        (style, assets, clipboard, library): &mut SystemParamItem<'w, '_, Self::Param>,
+    ) {
+        self.sub_graphs = library.entries.clone();
+
+        let rect = ui.available_rect_before_wrap();
+        ui.painter().rect_filled(rect, 0.0, style.panel);
+        ui.set_clip_rect(rect);
+        self.draw(ui);
+        self.load_custom_functions(assets.asset_io());
+
+        let toggle = egui::Rect::from_min_size(
+            rect.right_top() + egui::vec2(-32.0, 8.0),
+            egui::vec2(24.0, 24.0),
+        );
+        let (icon, hover) = match self.time.playing {
+            true => ("⏸", "Pause animated previews"),
+            false => ("▶", "Play animated previews"),
+        };
+        if ui
+            .put(toggle, egui::Button::new(icon))
+            .on_hover_text(hover)
+            .clicked()
+        {
+            self.time.playing = !self.time.playing;
+        }
+
+        let save = {
+            let input = ui.input();
+            input.modifiers.command && input.key_pressed(egui::Key::S)
+        };
+
+        let (undo, redo) = {
+            let input = ui.input();
+            let z = input.modifiers.command && input.key_pressed(egui::Key::Z);
+            (z && !input.modifiers.shift, z && input.modifiers.shift)
+        };
+
+        let (copy, cut, paste, duplicate, collapse) = {
+            let input = ui.input();
+            let command = input.modifiers.command;
+            (
+                command && input.key_pressed(egui::Key::C),
+                command && input.key_pressed(egui::Key::X),
+                command && input.key_pressed(egui::Key::V),
+                command && input.key_pressed(egui::Key::D),
+                command && input.key_pressed(egui::Key::G),
+            )
+        };
+
+        // Text fields have their own undo and clipboard.
+        if ui.rect_contains_pointer(rect) && !ui.ctx().wants_keyboard_input() {
+            if undo {
+                self.undo();
+            } else if redo {
+                self.redo();
+            }
+
+            if copy || cut {
+                if let Some(text) = self.copy_selection() {
+                    clipboard.set_contents(&text);
+                }
+                if cut {
+                    self.delete_selection();
+                }
+            }
+
+            if paste {
+                let pointer = self.input.pointer;
+                if let Some(text) = clipboard.contents() {
+                    if let Err(err) = self.paste(&text, pointer) {
+                        tracing::warn!("failed to paste nodes: {}", err);
+                    }
+                }
+            }
+
+            if duplicate {
+                if let Err(err) = self.duplicate_selection() {
+                    tracing::warn!("failed to duplicate nodes: {}", err);
+                }
+            }
+
+            if collapse {
+                match self.collapse_selection(assets.asset_io()) {
+                    Ok(Some(path)) => library.watch(assets, &path),
+                    Ok(None) => (),
+                    Err(err) => tracing::error!("failed to create sub-graph: {}", err),
+                }
+            }
+        }
+
+        let save = save && ui.rect_contains_pointer(rect);
+        if save && self.path.is_none() {
+            self.save_as.get_or_insert_with(String::new);
+        }
+        let saved_as = self.save_as_ui(ui.ctx(), assets.asset_io());
+        if (save && self.path.is_some()) || saved_as {
+            match self.save(assets.asset_io()) {
+                // Graphs using this one as a sub-graph pick up the changes.
+                Ok(()) => library.watch(assets, self.path.as_deref().unwrap()),
+                Err(err) => tracing::error!("failed to save shader graph: {}", err),
+            }
+        }
+
+        if let Some((node, target)) = self.export.take() {
+            let passes = &self.storage.nodes[node].export_passes;
+            let passes: Vec<_> = Pass::ALL
+                .into_iter()
+                .filter(|p| passes.contains(p))
+                .collect();
+            if let Err(err) = self.export(assets.asset_io(), node, target, &passes) {
+                tracing::error!("failed to export {}: {}", target.label(), err);
+            }
+        }
+    }
+}
+
+pub fn open_shader_graphs(
+    mut events: EventReader<OpenShaderGraph>,
+    assets: Res<AssetServer>,
+    graphs: Res<Assets<ShaderGraph>>,
+    mut workspaces: Query<&mut Workspace>,
+) {
+    for event in events.iter() {
+        if let Some(mut workspace) = workspaces.iter_mut().next() {
+            workspace.loading = Some(assets.load(event.path.as_path()));
+            workspace.path = Some(event.path.clone());
+        }
+    }
+
+    for mut workspace in workspaces.iter_mut() {
+        let handle = match workspace.loading.as_ref() {
+            Some(handle) => handle,
+            None => continue,
+        };
+
+        if let Some(graph) = graphs.get(handle) {
+            match graph.to_storage() {
+                Ok(storage) => workspace.set_storage(storage),
+                Err(err) => tracing::error!("failed to load shader graph: {}", err),
+            }
+            workspace.loading = None;
+        } else if let LoadState::Failed = assets.get_load_state(handle) {
+            workspace.loading = None;
+        }
+    }
+}
+
 fn mark_matches(result: sublime_fuzzy::Match, target: &str) -> egui::text::LayoutJob {
     use egui::epaint::{
         text::{LayoutJob, TextFormat},
@@ -679,7 +1526,10 @@ impl<'a> Group<'a> {
 
 fn creator_menu<'a>() -> &'a [Group<'a>] {
     use self::nodes::math::{Binary, Math, Unary};
-    use self::nodes::{builtin, channel, input, logic, master, math, uv};
+    use self::nodes::{
+        artistic, builtin, channel, compute, custom, input, logic, master, math, matrix,
+        procedural, scene, subgraph, texture, uv,
+    };
     use naga::{BinaryOperator, MathFunction, UnaryOperator};
 
     &[
@@ -690,6 +1540,7 @@ fn creator_menu<'a>() -> &'a [Group<'a>] {
                 ("Color", input::Color::spawn),
                 ("Slider", input::Slider::spawn),
                 ("Constant", input::Constant::spawn),
+                ("Time", input::Time::spawn),
             ],
         },
         Group {
@@ -698,6 +1549,8 @@ fn creator_menu<'a>() -> &'a [Group<'a>] {
                 ("Vector2", input::InputVector2::spawn),
                 ("Vector3", input::InputVector3::spawn),
                 ("Vector4", input::InputVector4::spawn),
+                ("Integer", input::InputInt::spawn),
+                ("Unsigned Integer", input::InputUInt::spawn),
             ],
         },
         // math ops
@@ -805,6 +1658,111 @@ fn creator_menu<'a>() -> &'a [Group<'a>] {
                 ("Comparison", logic::Comparison::spawn),
             ],
         },
+        // integer
+        Group {
+            items: &[
+                ("To Int", math::Cast::spawn_int),
+                ("To UInt", math::Cast::spawn_uint),
+                ("To Float", math::Cast::spawn_float),
+                ("Bitwise And", |s| Binary::spawn(s, BinaryOperator::And)),
+                ("Bitwise Or", |s| {
+                    Binary::spawn(s, BinaryOperator::InclusiveOr)
+                }),
+                ("Bitwise Xor", |s| {
+                    Binary::spawn(s, BinaryOperator::ExclusiveOr)
+                }),
+                ("Bitwise Not", math::BitwiseNot::spawn),
+                ("Shift Left", |s| {
+                    Binary::spawn(s, BinaryOperator::ShiftLeft)
+                }),
+                ("Shift Right", |s| {
+                    Binary::spawn(s, BinaryOperator::ShiftRight)
+                }),
+            ],
+        },
+        Group {
+            items: &[
+                ("CountOneBits", |s| {
+                    Math::spawn(s, MathFunction::CountOneBits)
+                }),
+                ("ReverseBits", |s| Math::spawn(s, MathFunction::ReverseBits)),
+                ("ExtractBits", |s| Math::spawn(s, MathFunction::ExtractBits)),
+                ("InsertBits", |s| Math::spawn(s, MathFunction::InsertBits)),
+            ],
+        },
+        Group {
+            items: &[
+                ("Pack4x8snorm", |s| {
+                    Math::spawn(s, MathFunction::Pack4x8snorm)
+                }),
+                ("Pack4x8unorm", |s| {
+                    Math::spawn(s, MathFunction::Pack4x8unorm)
+                }),
+                ("Pack2x16snorm", |s| {
+                    Math::spawn(s, MathFunction::Pack2x16snorm)
+                }),
+                ("Pack2x16unorm", |s| {
+                    Math::spawn(s, MathFunction::Pack2x16unorm)
+                }),
+                ("Pack2x16float", |s| {
+                    Math::spawn(s, MathFunction::Pack2x16float)
+                }),
+                ("Unpack4x8snorm", |s| {
+                    Math::spawn(s, MathFunction::Unpack4x8snorm)
+                }),
+                ("Unpack4x8unorm", |s| {
+                    Math::spawn(s, MathFunction::Unpack4x8unorm)
+                }),
+                ("Unpack2x16snorm", |s| {
+                    Math::spawn(s, MathFunction::Unpack2x16snorm)
+                }),
+                ("Unpack2x16unorm", |s| {
+                    Math::spawn(s, MathFunction::Unpack2x16unorm)
+                }),
+                ("Unpack2x16float", |s| {
+                    Math::spawn(s, MathFunction::Unpack2x16float)
+                }),
+            ],
+        },
+        // matrix
+        Group {
+            items: &[
+                ("Matrix 2x2", matrix::InputMatrix::spawn_2),
+                ("Matrix 3x3", matrix::InputMatrix::spawn_3),
+                ("Matrix 4x4", matrix::InputMatrix::spawn_4),
+                ("Transformation Matrix", matrix::TransformationMatrix::spawn),
+            ],
+        },
+        Group {
+            items: &[
+                ("Matrix Construction", matrix::MatrixConstruction::spawn),
+                ("Matrix Split", matrix::MatrixSplit::spawn),
+                ("Matrix Multiply", matrix::MatrixMultiply::spawn),
+                ("Transpose", |s| Math::spawn(s, MathFunction::Transpose)),
+                ("Determinant", |s| Math::spawn(s, MathFunction::Determinant)),
+                ("Inverse", matrix::MatrixInverse::spawn),
+            ],
+        },
+        // artistic
+        Group {
+            items: &[
+                ("Channel Mixer", artistic::ChannelMixer::spawn),
+                ("Contrast", artistic::Contrast::spawn),
+                ("Hue", artistic::Hue::spawn),
+                ("Invert Colors", artistic::InvertColors::spawn),
+                ("Replace Color", artistic::ReplaceColor::spawn),
+                ("Saturation", artistic::Saturation::spawn),
+                ("White Balance", artistic::WhiteBalance::spawn),
+            ],
+        },
+        Group {
+            items: &[
+                ("Blend", artistic::Blend::spawn),
+                ("Dither", artistic::Dither::spawn),
+                ("Channel Mask", artistic::ChannelMask::spawn),
+                ("Color Mask", artistic::ColorMask::spawn),
+            ],
+        },
         // channel
         Group {
             items: &[
@@ -819,19 +1777,124 @@ fn creator_menu<'a>() -> &'a [Group<'a>] {
                 ("Blackbody", builtin::Blackbody::spawn),
                 ("GradientNoise", builtin::GradientNoise::spawn),
                 ("SimpleNoise", builtin::SimpleNoise::spawn),
+                ("ValueNoise", builtin::ValueNoise::spawn),
+                ("FractalNoise", builtin::FractalNoise::spawn),
+                ("DomainWarp", builtin::DomainWarp::spawn),
+                ("Voronoi", builtin::Voronoi::spawn),
+            ],
+        },
+        Group {
+            items: &[
+                (
+                    "TileableGradientNoise",
+                    builtin::TileableGradientNoise::spawn,
+                ),
+                ("TileableValueNoise", builtin::TileableValueNoise::spawn),
+                ("TileableVoronoi", builtin::TileableVoronoi::spawn),
+            ],
+        },
+        // procedural
+        Group {
+            items: &[
+                ("Checkerboard", procedural::Checkerboard::spawn),
+                ("Ellipse", procedural::Shape::spawn_ellipse),
+                ("Polygon", procedural::Shape::spawn_polygon),
+                ("Rectangle", procedural::Shape::spawn_rectangle),
+                ("Rounded Polygon", procedural::Shape::spawn_rounded_polygon),
+                (
+                    "Rounded Rectangle",
+                    procedural::Shape::spawn_rounded_rectangle,
+                ),
             ],
         },
         // uv
         Group {
             items: &[("Flipbook", uv::Flipbook::spawn)],
         },
+        // texture
+        Group {
+            items: &[
+                ("Texture 2D Asset", texture::TextureAsset::spawn_2d),
+                (
+                    "Texture 2D Array Asset",
+                    texture::TextureAsset::spawn_2d_array,
+                ),
+                ("Cubemap Asset", texture::TextureAsset::spawn_cube),
+                ("Sampler State", texture::SamplerStateNode::spawn),
+                ("Texel Size", texture::TexelSize::spawn),
+            ],
+        },
+        Group {
+            items: &[
+                ("Sample Texture 2D", texture::SampleTexture::spawn_2d),
+                (
+                    "Sample Texture 2D LOD",
+                    texture::SampleTexture::spawn_2d_lod,
+                ),
+                (
+                    "Sample Texture 2D Array",
+                    texture::SampleTexture::spawn_2d_array,
+                ),
+                ("Sample Cubemap", texture::SampleTexture::spawn_cube),
+            ],
+        },
+        // scene
+        Group {
+            items: &[
+                ("Screen UV", scene::ScreenUv::spawn),
+                ("Scene Color", scene::SceneColor::spawn),
+                ("Scene Depth", scene::SceneDepth::spawn),
+                ("Main Texture", scene::MainTexture::spawn),
+                ("Vertex Color", scene::VertexColor::spawn),
+                ("Vertex ID", scene::DrawIndex::spawn_vertex),
+                ("Instance ID", scene::DrawIndex::spawn_instance),
+                ("Sprite Tint", scene::SpriteTint::spawn),
+            ],
+        },
+        // compute
+        Group {
+            items: &[
+                (
+                    "Global Invocation ID",
+                    compute::InvocationIdNode::spawn_global,
+                ),
+                (
+                    "Local Invocation ID",
+                    compute::InvocationIdNode::spawn_local,
+                ),
+                ("Workgroup ID", compute::InvocationIdNode::spawn_workgroup),
+                ("Storage Buffer", compute::StorageBuffer::spawn),
+            ],
+        },
+        // utility
+        Group {
+            items: &[(custom::CustomFunction::KIND, custom::CustomFunction::spawn)],
+        },
+        // sub-graph
+        Group {
+            items: &[
+                (
+                    subgraph::SubGraphInput::KIND,
+                    subgraph::SubGraphInput::spawn,
+                ),
+                (
+                    subgraph::SubGraphOutput::KIND,
+                    subgraph::SubGraphOutput::spawn,
+                ),
+            ],
+        },
         //master
         Group {
             items: &[
                 ("FragmentInputs", master::FragmentInputs::spawn),
                 ("Master", master::Master::spawn),
+                ("Lit Master", master::Lit::spawn),
                 ("Triangle", master::Triangle::spawn),
                 ("Fullscreen", master::Fullscreen::spawn),
+                ("Post Process Master", master::PostProcess::spawn),
+                ("Sprite Master", master::Sprite::spawn),
+                ("UI Master", master::Ui::spawn),
+                ("Compute Master", master::Compute::spawn),
             ],
         },
     ]
@@ -1,6 +1,6 @@
 use crate::workspace::{
-    Data, Direction, Input, InputDefault, Output, Port, PortData, Preview, PreviewBuilder, Stage,
-    Storage,
+    Data, DefaultValue, Direction, ExportTarget, Input, InputDefault, NodeId, Output, Pass, Port,
+    PortData, Preview, PreviewBuilder, Stage, Storage,
 };
 use egui::style::Margin;
 use egui::*;
@@ -51,13 +51,16 @@ impl<'a> NodeBuilder<'a> {
         let input_default = default
             .or_else(|| match data {
                 Data::Boolean => Some(InputDefault::bool()),
+                Data::Int => Some(InputDefault::int(0)),
+                Data::UInt => Some(InputDefault::uint(0)),
                 Data::Float => Some(InputDefault::float(0.0)),
                 Data::Vector2 => Some(InputDefault::vector2(0.0, 0.0)),
                 Data::Vector3 => Some(InputDefault::vector3(0.0, 0.0, 0.0)),
                 Data::Vector4 => Some(InputDefault::vector4(0.0, 0.0, 0.0, 1.0)),
 
                 Data::VectorAny => Some(InputDefault::vector4(0.0, 0.0, 0.0, 1.0)),
-                Data::FloatOrVector => Some(InputDefault::float(0.0)),
+                Data::FloatOrVector | Data::Number => Some(InputDefault::float(0.0)),
+                Data::IntegerAny => Some(InputDefault::int(0)),
 
                 _ => None,
             })
@@ -75,6 +78,7 @@ impl<'a> NodeBuilder<'a> {
 
             links: Default::default(),
             input_default,
+            inferred: None,
         });
 
         match direction {
@@ -92,16 +96,27 @@ pub enum NodeInteraction {
     PortHovered(Port),
     LinkStart(Port),
     LinkEnd(Port),
+    Export(ExportTarget),
+    /// The default value of an unlinked input was edited, holding its previous value.
+    SetDefault(Port, DefaultValue),
 }
 
 pub struct NodeData {
+    pub id: NodeId,
+    /// Name in the creation menu, used to spawn the node again when loading a graph.
+    pub kind: &'static str,
     pub title: String,
     pub width: f32,
     pub inputs: Vec<Port>,
     pub outputs: Vec<Port>,
 
     pub preview_is_valid: bool,
+    /// Messages of the diagnostics blaming this node.
+    pub errors: Vec<String>,
     pub show_preview: bool,
+    /// Optimizations run on the shader of a master when exporting it, none
+    /// giving the shader as generated.
+    pub export_passes: Vec<Pass>,
     pub position: Pos2,
     pub rect: Rect,
     pub layer: Option<LayerId>,
@@ -117,13 +132,17 @@ impl NodeData {
         builder: impl PreviewBuilder + 'static,
     ) -> Self {
         Self {
+            id: NodeId::default(),
+            kind: "",
             title: title.into(),
             width,
             inputs: Vec::new(),
             outputs: Vec::new(),
 
             preview_is_valid: false,
+            errors: Vec::new(),
             show_preview: false,
+            export_passes: Pass::ALL.to_vec(),
             position: Pos2::new(f32::INFINITY, f32::INFINITY),
             rect: Rect::NOTHING,
             layer: None,
@@ -138,11 +157,12 @@ impl NodeData {
         ctx: &Context,
         ports: &mut SlotMap<Port, PortData>,
         key: Node,
+        viewport: Rect,
         offset: Vec2,
         selected: Option<Color32>,
         interactions: &mut Vec<NodeInteraction>,
     ) {
-        let mut area = Area::new((key, "node"));
+        let mut area = Area::new((key, "node")).drag_bounds(viewport);
 
         if self.position.is_finite() {
             area = area.current_pos(self.position + offset);
@@ -184,11 +204,14 @@ impl NodeData {
                     let width = default.width.unwrap_or(1000.0);
                     let pos = port.rect.left_top() - egui::vec2(width + 10.0, 0.0);
 
-                    let area = Area::new((key, "_InputDefault")).movable(false);
+                    let area = Area::new((key, "_InputDefault"))
+                        .movable(false)
+                        .drag_bounds(viewport);
                     if drag_delta.is_some() {
                         ctx.move_to_top(area.layer())
                     }
                     default.layer_id = Some(area.layer());
+                    let before = DefaultValue::new(default);
                     let response = area.current_pos(pos).show(ctx, |ui| {
                         let frame = Frame {
                             fill: Frame::window(ui.style()).fill.linear_multiply(0.95),
@@ -205,6 +228,10 @@ impl NodeData {
                     });
 
                     default.width = Some(response.inner.inner.rect.width());
+
+                    if DefaultValue::new(default) != before {
+                        interactions.push(NodeInteraction::SetDefault(key, before));
+                    }
                 }
             }
         }
@@ -226,7 +253,35 @@ impl NodeData {
                 if ui.add(Button::new(mark).frame(false)).clicked() {
                     self.show_preview = !self.show_preview;
                 }
+                if !self.errors.is_empty() {
+                    let badge = RichText::new(crate::ui::icon::ERROR).color(Color32::RED);
+                    ui.label(badge).on_hover_text(self.errors.join("\n"));
+                }
                 ui.centered_and_justified(|ui| ui.label(&self.title));
+                if self.builder.is_master() {
+                    ui.menu_button("💾", |ui| {
+                        ui.label("Optimize");
+                        for pass in Pass::ALL {
+                            let mut enabled = self.export_passes.contains(&pass);
+                            if ui.checkbox(&mut enabled, pass.label()).changed() {
+                                self.export_passes.retain(|&p| p != pass);
+                                if enabled {
+                                    self.export_passes.push(pass);
+                                }
+                            }
+                        }
+                        ui.separator();
+                        ui.label("Export as…");
+                        for target in ExportTarget::ALL {
+                            if ui.button(target.label()).clicked() {
+                                interactions.push(NodeInteraction::Export(target));
+                                ui.close_menu();
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Export as…");
+                }
                 if ui.add(Button::new("❌").frame(false)).clicked() {
                     interactions.push(NodeInteraction::Remove);
                 }
@@ -1,9 +1,17 @@
+pub mod artistic;
 pub mod builtin;
 pub mod channel;
+pub mod compute;
+pub mod custom;
 pub mod input;
 pub mod logic;
 pub mod master;
 pub mod math;
+pub mod matrix;
+pub mod procedural;
+pub mod scene;
+pub mod subgraph;
+pub mod texture;
 pub mod util;
 pub mod uv;
 
@@ -61,18 +69,18 @@ fn artistic(ui: &mut egui::Ui) {
         ui,
         "Adjustment",
         &[
-            "Channel Mixer",
-            "Contrast",
-            "Hue",
-            "Invert Colors",
-            "Replace Color",
-            "Saturation",
-            "White Balance",
+            "-Channel Mixer",
+            "-Contrast",
+            "-Hue",
+            "-Invert Colors",
+            "-Replace Color",
+            "-Saturation",
+            "-White Balance",
         ],
     );
-    col(ui, "Blend", &["Blend"]);
-    col(ui, "Filter", &["Dither"]);
-    col(ui, "Mask", &["Channel Mask", "Color Mask"]);
+    col(ui, "Blend", &["-Blend"]);
+    col(ui, "Filter", &["-Dither"]);
+    col(ui, "Mask", &["-Channel Mask", "-Color Mask"]);
     col(
         ui,
         "Normal",
@@ -93,21 +101,31 @@ fn artistic(ui: &mut egui::Ui) {
 
     ui.heading("Procedural");
 
-    ui.label("Checkerboard");
+    ui.label(label("-Checkerboard"));
     col(
         ui,
         "Noise",
-        &["-Gradient Noise", "-Simple Noise", "Voronoi"],
+        &[
+            "-Gradient Noise",
+            "-Simple Noise",
+            "-Voronoi",
+            "-Value Noise",
+            "-Fractal Noise",
+            "-Domain Warp",
+            "-Tileable Gradient Noise",
+            "-Tileable Value Noise",
+            "-Tileable Voronoi",
+        ],
     );
     col(
         ui,
         "Shapes",
         &[
-            "Ellipse",
-            "Polygon",
-            "Rectangle",
-            "Rounded Polygon",
-            "Rounded Rectangle",
+            "-Ellipse",
+            "-Polygon",
+            "-Rectangle",
+            "-Rounded Polygon",
+            "-Rounded Rectangle",
         ],
     );
 }
@@ -121,9 +139,9 @@ fn input(ui: &mut egui::Ui) {
             "-Boolean",
             "-Color",
             "-Constant",
-            "Integer",
+            "-Integer",
             "-Slider",
-            "Time",
+            "-Time",
             "-Float",
             "-Vector 2",
             "-Vector 3",
@@ -135,14 +153,14 @@ fn input(ui: &mut egui::Ui) {
         "Geometry",
         &[
             "Bitangent Vector",
-            "Instance ID",
+            "-Instance ID",
             "Normal Vector",
             "Position",
             "Screen Position",
             "Tangent Vector",
             "UV",
             "Vertex Color",
-            "Vertex ID",
+            "-Vertex ID",
             "View Direction",
             "View Vector",
         ],
@@ -171,10 +189,10 @@ fn input(ui: &mut egui::Ui) {
         ui,
         "Matrix ",
         &[
-            "Matrix 2x2",
-            "Matrix 3x3",
-            "Matrix 4x4",
-            "Transformation Matrix",
+            "-Matrix 2x2",
+            "-Matrix 3x3",
+            "-Matrix 4x4",
+            "-Transformation Matrix",
         ],
     );
     col(
@@ -201,20 +219,20 @@ fn input(ui: &mut egui::Ui) {
         "Texture",
         &[
             "Calculate Level Of Detail Texture 2D Node",
-            "Cubemap Asset",
+            "-Cubemap Asset",
             "Gather Texture 2D Node",
-            "Sample Cubemap",
+            "-Sample Cubemap",
             "Sample Reflected Cubemap",
-            "Sample Texture 2D",
-            "Sample Texture 2D Array",
-            "Sample Texture 2D LOD",
+            "-Sample Texture 2D",
+            "-Sample Texture 2D Array",
+            "-Sample Texture 2D LOD",
             "Sample Texture 3D",
             "Sample Virtual Texture",
-            "Sampler State",
+            "-Sampler State",
             "Split Texture Transform",
-            "Texel Size",
-            "Texture 2D Array Asset",
-            "Texture 2D Asset",
+            "-Texel Size",
+            "-Texture 2D Array Asset",
+            "-Texture 2D Asset",
             "Texture 3D Asset",
         ],
     );
@@ -262,9 +280,9 @@ fn math(ui: &mut egui::Ui) {
         ui,
         "Matrix",
         &[
-            "Matrix Construction",
+            "-Matrix Construction",
             "-Matrix Determinant",
-            "Matrix Split",
+            "-Matrix Split",
             "-Matrix Transpose",
         ],
     );
@@ -346,7 +364,7 @@ fn procedural(ui: &mut egui::Ui) {
     ui.label("Custom Function");
     ui.label("Keyword");
     ui.label("Preview");
-    ui.label("Sub Graph");
+    ui.label(label("-Sub Graph"));
 
     ui.label("HDRP Emission");
 
@@ -0,0 +1,625 @@
+use super::super::asset::{from_state, to_state};
+use super::super::builder::{expr::*, *};
+use super::util::{combo, state_by_name};
+use crate::workspace::{Data, Fragment, InputDefault, Node, Port, PreviewBuilder, Storage};
+use naga::{Expression, MathFunction, ShaderStage};
+use serde::{Deserialize, Serialize};
+
+const CHANNELS: [&str; 4] = ["R", "G", "B", "A"];
+
+/// Checkbox per channel, in a row.
+fn channels_ui(ui: &mut egui::Ui, channels: &mut [bool; 4]) {
+    ui.horizontal(|ui| {
+        for (channel, name) in channels.iter_mut().zip(CHANNELS) {
+            ui.checkbox(channel, name);
+        }
+    });
+}
+
+/// `1.0` for the checked channels, truncated to the width of `input`.
+fn channels_expr(
+    function: &mut FnBuilder,
+    channels: [bool; 4],
+    input: naga::Handle<Expression>,
+) -> EmitResult {
+    let width = VectorKind::parse(function.extract_type(input)?).ok_or(EmitError::FailType)?;
+    let [x, y, z, w] = channels.map(|checked| Float(if checked { 1.0 } else { 0.0 }));
+    let mask = [x, y, z, w].emit(function)?;
+    function.resolve_vector(mask, VectorKind::V4, width)
+}
+
+/// Output channels as weighted sums of the input channels.
+pub struct ChannelMixer {
+    /// Weights of the red, green and blue inputs for each output channel.
+    rows: [[f32; 3]; 3],
+    input: Port,
+    output: Port,
+}
+
+impl ChannelMixer {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Channel Mixer", 160.0, |ctx, node| Self {
+            rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+        })
+    }
+}
+
+impl PreviewBuilder for ChannelMixer {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new(ui.id().with("mixer")).show(ui, |ui| {
+            for (row, name) in self.rows.iter_mut().zip(CHANNELS) {
+                ui.label(name);
+                for value in row {
+                    ui.add(
+                        egui::DragValue::new(value)
+                            .speed(0.01)
+                            .clamp_range(-2.0..=2.0),
+                    );
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.rows)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.rows = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let mut args = vec![function.for_input_vector3(self.input)?];
+        for row in self.rows {
+            let [r, g, b] = row.map(|weight| Float(weight as f64));
+            args.push([r, g, b].emit(function)?);
+        }
+        function.call("builtin_channel_mixer", args)
+    }
+}
+
+/// Contrast around the mid gray.
+pub struct Contrast {
+    input: Port,
+    contrast: Port,
+    output: Port,
+}
+
+impl Contrast {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Contrast", 120.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            contrast: ctx.input(
+                node,
+                "contrast",
+                Fragment,
+                Data::Float,
+                Some(InputDefault::float(1.0)),
+            ),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Contrast {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let input = function.for_input_vector3(self.input)?;
+        let contrast = function.for_input_float(self.contrast)?;
+        function.call("builtin_contrast", [input, contrast])
+    }
+}
+
+/// Unit of the offset of a [`Hue`] node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum HueUnit {
+    Degrees,
+    /// Turns, `1.0` being a full rotation.
+    Normalized,
+}
+
+const HUE_UNITS: [(HueUnit, &str); 2] = [
+    (HueUnit::Degrees, "Degrees"),
+    (HueUnit::Normalized, "Normalized"),
+];
+
+state_by_name!(HueUnit, HUE_UNITS);
+
+/// Rotates the hue of a color.
+pub struct Hue {
+    unit: HueUnit,
+    input: Port,
+    offset: Port,
+    output: Port,
+}
+
+impl Hue {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Hue", 120.0, |ctx, node| Self {
+            unit: HueUnit::Degrees,
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            offset: ctx.input(node, "offset", Fragment, Data::Float, None),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Hue {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            combo(ui, "unit", &mut self.unit, &HUE_UNITS);
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.unit)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.unit = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let input = function.for_input_vector3(self.input)?;
+        let mut offset = function.for_input_float(self.offset)?;
+        if self.unit == HueUnit::Degrees {
+            offset = (Wrap(offset) / Float(360.0)).emit(function)?;
+        }
+        function.call("builtin_hue", [input, offset])
+    }
+}
+
+/// Inverts the checked channels.
+pub struct InvertColors {
+    channels: [bool; 4],
+    input: Port,
+    output: Port,
+}
+
+impl InvertColors {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Invert Colors", 140.0, |ctx, node| Self {
+            channels: [true, true, true, false],
+            input: ctx.input(node, "in", Fragment, Data::FloatOrVector, None),
+            output: ctx.output(node, "out", Fragment, Data::FloatOrVector, None),
+        })
+    }
+}
+
+impl PreviewBuilder for InvertColors {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        channels_ui(ui, &mut self.channels);
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.channels)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.channels = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let input = function.for_input(self.input)?;
+        let mask = channels_expr(function, self.channels, input)?;
+        let difference = (Wrap(mask) - Wrap(input)).emit(function)?;
+        Ok(function.emit(Expression::Math {
+            fun: MathFunction::Abs,
+            arg: difference,
+            arg1: None,
+            arg2: None,
+            arg3: None,
+        }))
+    }
+}
+
+/// Keeps the checked channels, zeroing the others.
+pub struct ChannelMask {
+    channels: [bool; 4],
+    input: Port,
+    output: Port,
+}
+
+impl ChannelMask {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Channel Mask", 140.0, |ctx, node| Self {
+            channels: [true, false, false, false],
+            input: ctx.input(node, "in", Fragment, Data::FloatOrVector, None),
+            output: ctx.output(node, "out", Fragment, Data::FloatOrVector, None),
+        })
+    }
+}
+
+impl PreviewBuilder for ChannelMask {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        channels_ui(ui, &mut self.channels);
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.channels)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.channels = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let input = function.for_input(self.input)?;
+        let mask = channels_expr(function, self.channels, input)?;
+        (Wrap(input) * Wrap(mask)).emit(function)
+    }
+}
+
+/// Replaces the colors within `range` of `from` by `to`, blending over `fuzziness`.
+pub struct ReplaceColor {
+    input: Port,
+    from: Port,
+    to: Port,
+    range: Port,
+    fuzziness: Port,
+    output: Port,
+}
+
+impl ReplaceColor {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Replace Color", 140.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            from: ctx.input(node, "from", Fragment, Data::Vector3, None),
+            to: ctx.input(node, "to", Fragment, Data::Vector3, None),
+            range: ctx.input(node, "range", Fragment, Data::Float, None),
+            fuzziness: ctx.input(node, "fuzziness", Fragment, Data::Float, None),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+        })
+    }
+}
+
+impl PreviewBuilder for ReplaceColor {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let args = [
+            function.for_input_vector3(self.input)?,
+            function.for_input_vector3(self.from)?,
+            function.for_input_vector3(self.to)?,
+            function.for_input_float(self.range)?,
+            function.for_input_float(self.fuzziness)?,
+        ];
+        function.call("builtin_replace_color", args)
+    }
+}
+
+/// Scales the distance of a color to its luminance.
+pub struct Saturation {
+    input: Port,
+    saturation: Port,
+    output: Port,
+}
+
+impl Saturation {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Saturation", 120.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            saturation: ctx.input(
+                node,
+                "saturation",
+                Fragment,
+                Data::Float,
+                Some(InputDefault::float(1.0)),
+            ),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Saturation {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let input = function.for_input_vector3(self.input)?;
+        let saturation = function.for_input_float(self.saturation)?;
+        function.call("builtin_saturation", [input, saturation])
+    }
+}
+
+/// Shifts the white point along the temperature and tint axes.
+pub struct WhiteBalance {
+    input: Port,
+    temperature: Port,
+    tint: Port,
+    output: Port,
+}
+
+impl WhiteBalance {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("White Balance", 140.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            temperature: ctx.input(node, "temperature", Fragment, Data::Float, None),
+            tint: ctx.input(node, "tint", Fragment, Data::Float, None),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+        })
+    }
+}
+
+impl PreviewBuilder for WhiteBalance {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let args = [
+            function.for_input_vector3(self.input)?,
+            function.for_input_float(self.temperature)?,
+            function.for_input_float(self.tint)?,
+        ];
+        function.call("builtin_white_balance", args)
+    }
+}
+
+/// How a [`Blend`] node draws `blend` over `base`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum BlendMode {
+    Burn,
+    Darken,
+    Difference,
+    Dodge,
+    Divide,
+    Exclusion,
+    HardLight,
+    HardMix,
+    Lighten,
+    LinearBurn,
+    LinearDodge,
+    LinearLight,
+    LinearLightAddSub,
+    Multiply,
+    Negation,
+    Overlay,
+    PinLight,
+    Screen,
+    SoftLight,
+    Subtract,
+    VividLight,
+    Overwrite,
+}
+
+const BLEND_MODES: [(BlendMode, &str); 22] = [
+    (BlendMode::Burn, "Burn"),
+    (BlendMode::Darken, "Darken"),
+    (BlendMode::Difference, "Difference"),
+    (BlendMode::Dodge, "Dodge"),
+    (BlendMode::Divide, "Divide"),
+    (BlendMode::Exclusion, "Exclusion"),
+    (BlendMode::HardLight, "Hard Light"),
+    (BlendMode::HardMix, "Hard Mix"),
+    (BlendMode::Lighten, "Lighten"),
+    (BlendMode::LinearBurn, "Linear Burn"),
+    (BlendMode::LinearDodge, "Linear Dodge"),
+    (BlendMode::LinearLight, "Linear Light"),
+    (BlendMode::LinearLightAddSub, "Linear Light Add Sub"),
+    (BlendMode::Multiply, "Multiply"),
+    (BlendMode::Negation, "Negation"),
+    (BlendMode::Overlay, "Overlay"),
+    (BlendMode::PinLight, "Pin Light"),
+    (BlendMode::Screen, "Screen"),
+    (BlendMode::SoftLight, "Soft Light"),
+    (BlendMode::Subtract, "Subtract"),
+    (BlendMode::VividLight, "Vivid Light"),
+    (BlendMode::Overwrite, "Overwrite"),
+];
+
+state_by_name!(BlendMode, BLEND_MODES);
+
+impl BlendMode {
+    /// Function of `builtin.wgsl` applying the mode.
+    fn builtin(self) -> &'static str {
+        match self {
+            Self::Burn => "builtin_blend_burn",
+            Self::Darken => "builtin_blend_darken",
+            Self::Difference => "builtin_blend_difference",
+            Self::Dodge => "builtin_blend_dodge",
+            Self::Divide => "builtin_blend_divide",
+            Self::Exclusion => "builtin_blend_exclusion",
+            Self::HardLight => "builtin_blend_hard_light",
+            Self::HardMix => "builtin_blend_hard_mix",
+            Self::Lighten => "builtin_blend_lighten",
+            Self::LinearBurn => "builtin_blend_linear_burn",
+            Self::LinearDodge => "builtin_blend_linear_dodge",
+            Self::LinearLight => "builtin_blend_linear_light",
+            Self::LinearLightAddSub => "builtin_blend_linear_light_add_sub",
+            Self::Multiply => "builtin_blend_multiply",
+            Self::Negation => "builtin_blend_negation",
+            Self::Overlay => "builtin_blend_overlay",
+            Self::PinLight => "builtin_blend_pin_light",
+            Self::Screen => "builtin_blend_screen",
+            Self::SoftLight => "builtin_blend_soft_light",
+            Self::Subtract => "builtin_blend_subtract",
+            Self::VividLight => "builtin_blend_vivid_light",
+            Self::Overwrite => "builtin_blend_overwrite",
+        }
+    }
+}
+
+/// Draws `blend` over `base` with one of the usual layer modes, faded by `opacity`.
+pub struct Blend {
+    mode: BlendMode,
+    base: Port,
+    blend: Port,
+    opacity: Port,
+    output: Port,
+}
+
+impl Blend {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Blend", 160.0, |ctx, node| Self {
+            mode: BlendMode::Overlay,
+            base: ctx.input(node, "base", Fragment, Data::Vector4, None),
+            blend: ctx.input(node, "blend", Fragment, Data::Vector4, None),
+            opacity: ctx.input(
+                node,
+                "opacity",
+                Fragment,
+                Data::Float,
+                Some(InputDefault::float(1.0)),
+            ),
+            output: ctx.output(node, "out", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Blend {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            combo(ui, "mode", &mut self.mode, &BLEND_MODES);
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.mode)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.mode = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let base = function.for_input_vector4(self.base)?;
+        let blend = function.for_input_vector4(self.blend)?;
+        let opacity = function.for_input_float(self.opacity)?;
+        let blended = function.call(self.mode.builtin(), [base, blend])?;
+        let opacity = VectorKind::V4.splat(function, opacity)?;
+        Ok(function.emit(Expression::Math {
+            fun: MathFunction::Mix,
+            arg: base,
+            arg1: Some(blended),
+            arg2: Some(opacity),
+            arg3: None,
+        }))
+    }
+}
+
+/// Ordered dithering, by the pixel on screen unless `screen` is linked.
+pub struct Dither {
+    input: Port,
+    screen: Port,
+    output: Port,
+}
+
+impl Dither {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Dither", 120.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::FloatOrVector, None),
+            screen: ctx.input(node, "screen", Fragment, Data::Vector2, None),
+            output: ctx.output(node, "out", Fragment, Data::FloatOrVector, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Dither {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let input = function.for_input(self.input)?;
+        let width = VectorKind::parse(function.extract_type(input)?).ok_or(EmitError::FailType)?;
+
+        let pixel = if function.is_linked(self.screen) {
+            function.for_input_vector2(self.screen)?
+        } else {
+            if function.module.stage != Some(ShaderStage::Fragment) {
+                return Err(EmitError::FragmentOnly("dither"));
+            }
+            let input = FunctionArgument(0).emit(function)?;
+            let frag_coord = function.access_index(input, 0);
+            function.resolve_to(frag_coord, VectorKind::V2)?
+        };
+        let threshold = function.call("builtin_dither_threshold", [pixel])?;
+        let threshold = width.splat(function, threshold)?;
+        (Wrap(input) - Wrap(threshold)).emit(function)
+    }
+}
+
+/// `1.0` for the colors within `range` of `mask`, fading out over `fuzziness`.
+pub struct ColorMask {
+    input: Port,
+    mask: Port,
+    range: Port,
+    fuzziness: Port,
+    output: Port,
+}
+
+impl ColorMask {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Color Mask", 140.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::Vector3, None),
+            mask: ctx.input(node, "mask", Fragment, Data::Vector3, None),
+            range: ctx.input(node, "range", Fragment, Data::Float, None),
+            fuzziness: ctx.input(node, "fuzziness", Fragment, Data::Float, None),
+            output: ctx.output(node, "out", Fragment, Data::Float, None),
+        })
+    }
+}
+
+impl PreviewBuilder for ColorMask {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let args = [
+            function.for_input_vector3(self.input)?,
+            function.for_input_vector3(self.mask)?,
+            function.for_input_float(self.range)?,
+            function.for_input_float(self.fuzziness)?,
+        ];
+        function.call("builtin_color_mask", args)
+    }
+}
+
+#[test]
+fn artistic_nodes() {
+    let mut storage = Storage::default();
+    let color = storage.spawn_kind("Color").unwrap();
+    let color = storage.nodes[color].outputs[0];
+    let nodes = [
+        "Channel Mixer",
+        "Contrast",
+        "Hue",
+        "Invert Colors",
+        "Replace Color",
+        "Saturation",
+        "White Balance",
+        "Blend",
+        "Dither",
+        "Channel Mask",
+        "Color Mask",
+    ]
+    .map(|kind| storage.spawn_kind(kind).unwrap());
+    for node in nodes {
+        let input = storage.nodes[node].inputs[0];
+        storage.link(color, input);
+    }
+
+    let validate = |storage: &Storage, node| {
+        let module = storage.nodes[node].builder.module(node, storage).unwrap();
+        module.validate().unwrap();
+        module.build().unwrap()
+    };
+    for node in nodes {
+        validate(&storage, node);
+    }
+
+    // Each mode has its helper, and the settings round trip.
+    let blend = nodes[7];
+    for (mode, _) in BLEND_MODES {
+        let builder = &mut storage.nodes[blend].builder;
+        builder.load(to_state(&mode).unwrap()).unwrap();
+        assert_eq!(builder.save(), to_state(&mode));
+        let source = validate(&storage, blend);
+        assert!(source.contains(&format!("= {}(", mode.builtin())));
+    }
+}
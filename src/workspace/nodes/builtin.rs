@@ -1,25 +1,37 @@
 use super::super::builder::{expr::*, *};
-use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
+use crate::workspace::{Data, Fragment, InputDefault, Node, Port, PreviewBuilder, Storage};
 
+/// Node calling a function of `builtin.wgsl`, an input per argument. Float
+/// arguments may have a default, and a function returning a struct has an
+/// output per member, in order.
 macro_rules! call_node {
     (
         $name:ident => $builtin:ident(
-            $( $arg:ident : $arg_ty:ident ),+
+            $( $arg:ident : $arg_ty:ident $( = $default:literal )? ),+
         ) -> $ret_ty:ident
 
+    ) => {
+        call_node!($name => $builtin($( $arg: $arg_ty $( = $default )? ),+) -> { result: $ret_ty });
+    };
+
+    (
+        $name:ident => $builtin:ident(
+            $( $arg:ident : $arg_ty:ident $( = $default:literal )? ),+
+        ) -> { $( $ret:ident : $ret_ty:ident ),+ }
+
     ) => {
 
         pub struct $name {
             $($arg: Port,)+
-            result: Port,
+            $($ret: Port,)+
         }
 
         impl $name {
             pub fn spawn(storage: &mut Storage) -> Node {
                 storage.spawn(stringify!($name), 150.0, |ctx, node| {
                     Self {
-                        $( $arg: ctx.input(node, stringify!($arg), Fragment, Data::$arg_ty, None), )+
-                        result: ctx.output(node, "out", Fragment, Data::$ret_ty, None),
+                        $( $arg: ctx.input(node, stringify!($arg), Fragment, Data::$arg_ty, call_node!(@default $( $default )?)), )+
+                        $( $ret: ctx.output(node, call_node!(@label $ret), Fragment, Data::$ret_ty, None), )+
                     }
                 })
             }
@@ -27,16 +39,26 @@ macro_rules! call_node {
 
         impl PreviewBuilder for $name {
             fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
-                assert_eq!(self.result, output);
+                let outputs = [$( self.$ret ),+];
+                let index = outputs.iter().position(|&port| port == output);
+                let index = index.ok_or(EmitError::PortNotFound)? as u32;
+
                 let args = [
                     $( call_node!(@ self $arg $arg_ty function)? ),+
                 ];
-                function.call(stringify!($builtin), args)
+                let result = function.call(stringify!($builtin), args)?;
+                Ok(if outputs.len() == 1 { result } else { function.access_index(result, index) })
             }
         }
 
     };
 
+    (@default) => { None };
+    (@default $default:literal) => { Some(InputDefault::float($default)) };
+
+    (@label result) => { "out" };
+    (@label $ret:ident) => { stringify!($ret) };
+
     (@ $self:ident $arg:ident Float   $function:ident) => { $function.for_input_resolve($self.$arg, VectorKind::V1) };
     (@ $self:ident $arg:ident Vector2 $function:ident) => { $function.for_input_resolve($self.$arg, VectorKind::V2) };
     (@ $self:ident $arg:ident Vector3 $function:ident) => { $function.for_input_resolve($self.$arg, VectorKind::V3) };
@@ -47,3 +69,84 @@ macro_rules! call_node {
 call_node!(Blackbody => builtin_blackbody(k: Float) -> Vector3);
 call_node!(SimpleNoise => builtin_simple_noise(uv: Vector2, scale: Float) -> Float);
 call_node!(GradientNoise => builtin_gradient_noise(uv: Vector2, scale: Float) -> Float);
+call_node!(ValueNoise => builtin_value_noise(uv: Vector2, scale: Float = 10.0) -> Float);
+call_node!(
+    FractalNoise => builtin_fractal_noise(
+        uv: Vector2,
+        scale: Float = 10.0,
+        octaves: Float = 4.0,
+        lacunarity: Float = 2.0,
+        gain: Float = 0.5
+    ) -> Float
+);
+call_node!(
+    DomainWarp => builtin_domain_warp(uv: Vector2, scale: Float = 4.0, strength: Float = 0.1)
+        -> Vector2
+);
+call_node!(
+    Voronoi => builtin_voronoi(uv: Vector2, angle_offset: Float = 2.0, cell_density: Float = 5.0)
+        -> { f1: Float, f2: Float, edge: Float, cell: Float }
+);
+call_node!(
+    TileableGradientNoise => builtin_tileable_gradient_noise(
+        uv: Vector2,
+        scale: Float = 8.0,
+        period: Float = 8.0
+    ) -> Float
+);
+call_node!(
+    TileableValueNoise => builtin_tileable_value_noise(
+        uv: Vector2,
+        scale: Float = 8.0,
+        period: Float = 8.0
+    ) -> Float
+);
+call_node!(
+    TileableVoronoi => builtin_tileable_voronoi(
+        uv: Vector2,
+        angle_offset: Float = 2.0,
+        cell_density: Float = 5.0,
+        period: Float = 5.0
+    ) -> { f1: Float, f2: Float, edge: Float, cell: Float }
+);
+
+#[test]
+fn noise_nodes() {
+    let mut storage = Storage::default();
+    let nodes = [
+        "ValueNoise",
+        "FractalNoise",
+        "DomainWarp",
+        "Voronoi",
+        "TileableGradientNoise",
+        "TileableValueNoise",
+        "TileableVoronoi",
+    ]
+    .map(|kind| storage.spawn_kind(kind).unwrap());
+    for node in nodes {
+        let module = storage.nodes[node].builder.module(node, &storage).unwrap();
+        module.validate().unwrap();
+    }
+
+    // Each Voronoi output reads its member of the cell, the warped UV feeding it.
+    let [warp, voronoi] = [nodes[2], nodes[3]];
+    let outputs = storage.nodes[voronoi].outputs.clone();
+    let labels: Vec<_> = outputs
+        .iter()
+        .map(|&port| &storage.ports[port].label)
+        .collect();
+    assert_eq!(labels, ["f1", "f2", "edge", "cell"]);
+    let add = storage.spawn_kind("Add").unwrap();
+    let [uv, a] = [
+        storage.nodes[voronoi].inputs[0],
+        storage.nodes[add].inputs[0],
+    ];
+    storage.link(storage.nodes[warp].outputs[0], uv);
+    storage.link(outputs[2], a);
+    let module = storage.nodes[add].builder.module(add, &storage).unwrap();
+    module.validate().unwrap();
+    let source = module.build().unwrap();
+    assert!(source.contains("= builtin_domain_warp("));
+    assert!(source.contains("= builtin_voronoi("));
+    assert!(source.contains(".edge"));
+}
@@ -1,3 +1,4 @@
+use super::super::asset::{from_state, to_state};
 use super::super::builder::{expr::*, *};
 use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
 
@@ -128,6 +129,34 @@ impl PreviewBuilder for Swizzle {
         });
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        let pattern = [self.x, self.y, self.z, self.w].map(|c| format!("{:?}", c));
+        to_state(&pattern.concat().to_lowercase())
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        use naga::SwizzleComponent::*;
+
+        let pattern: String = from_state(state)?;
+        let mut components = pattern.chars().map(|c| match c {
+            'x' => Ok(X),
+            'y' => Ok(Y),
+            'z' => Ok(Z),
+            'w' => Ok(W),
+            _ => Err(ron::Error::Message(format!(
+                "invalid swizzle `{}`",
+                pattern
+            ))),
+        });
+
+        let mut next = || components.next().unwrap_or(Ok(X));
+        self.x = next()?;
+        self.y = next()?;
+        self.z = next()?;
+        self.w = next()?;
+        Ok(())
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.output, output);
 
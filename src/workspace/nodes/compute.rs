@@ -0,0 +1,95 @@
+use super::super::builder::{expr::*, *};
+use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
+use naga::{Expression, ImageQuery};
+
+/// Size of the texture written by the compute master, as a `vec2<u32>`.
+pub fn expr_output_size(function: &mut FnBuilder) -> EmitResult {
+    let image = function.resource(Resource::StorageTexture);
+    let size = function.emit(Expression::ImageQuery {
+        image,
+        query: ImageQuery::Size { level: None },
+    });
+    Wrap(size).uint().emit(function)
+}
+
+/// Element of the storage buffers matching the texel of the invocation, the
+/// buffers holding one element per texel of the output texture, row by row.
+pub fn expr_invocation_index(function: &mut FnBuilder) -> EmitResult {
+    let id = function.invocation(InvocationId::Global)?;
+    let size = expr_output_size(function)?;
+    let x = function.access_index(id, 0);
+    let y = function.access_index(id, 1);
+    let width = function.access_index(size, 0);
+    (Wrap(x) + Wrap(y) * Wrap(width)).emit(function)
+}
+
+/// Id of the invocation running the compute master.
+pub struct InvocationIdNode {
+    invocation: InvocationId,
+    output: Port,
+}
+
+impl InvocationIdNode {
+    fn spawn_with(storage: &mut Storage, title: &str, invocation: InvocationId) -> Node {
+        storage.spawn(title, 120.0, |ctx, node| Self {
+            invocation,
+            output: ctx.output(node, "id", Fragment, Data::UInt3, None),
+        })
+    }
+
+    pub fn spawn_global(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Global Invocation ID", InvocationId::Global)
+    }
+
+    pub fn spawn_local(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Local Invocation ID", InvocationId::Local)
+    }
+
+    pub fn spawn_workgroup(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Workgroup ID", InvocationId::Workgroup)
+    }
+}
+
+impl PreviewBuilder for InvocationIdNode {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        function.invocation(self.invocation)
+    }
+}
+
+/// Reads the input storage buffer, at the element of the invocation unless
+/// `index` is linked.
+pub struct StorageBuffer {
+    index: Port,
+    output: Port,
+}
+
+impl StorageBuffer {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Storage Buffer", 120.0, |ctx, node| Self {
+            index: ctx.input(node, "index", Fragment, Data::UInt, None),
+            output: ctx.output(node, "value", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for StorageBuffer {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let index = if function.is_linked(self.index) {
+            function.for_input_resolve(self.index, VectorKind::V1)?
+        } else {
+            expr_invocation_index(function)?
+        };
+        let buffer = function.resource(Resource::StorageBuffer { writable: false });
+        let pointer = function.emit(Expression::Access {
+            base: buffer,
+            index,
+        });
+        Ok(function.emit(Expression::Load { pointer }))
+    }
+}
@@ -0,0 +1,517 @@
+use super::super::asset::{from_state, to_state};
+use super::super::builder::{expr::*, *};
+use super::util::fnv1a;
+use crate::workspace::{Data, Node, Port, PortSpec, PreviewBuilder, Storage};
+use naga::{ImageDimension, ScalarKind, TypeInner, VectorSize};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SOURCE: &str = "fn custom(a: vec3<f32>, b: f32) -> vec3<f32> {
+    return a * b;
+}
+";
+
+/// Maps a WGSL type onto the data of a port.
+pub fn port_data(ty: &TypeInner) -> Option<Data> {
+    let size = |size| match size {
+        VectorSize::Bi => 2,
+        VectorSize::Tri => 3,
+        VectorSize::Quad => 4,
+    };
+
+    Some(match *ty {
+        TypeInner::Scalar {
+            kind: ScalarKind::Float,
+            ..
+        } => Data::Float,
+        TypeInner::Scalar {
+            kind: ScalarKind::Bool,
+            ..
+        } => Data::Boolean,
+        TypeInner::Scalar {
+            kind: ScalarKind::Sint,
+            ..
+        } => Data::Int,
+        TypeInner::Scalar {
+            kind: ScalarKind::Uint,
+            ..
+        } => Data::UInt,
+        TypeInner::Vector {
+            kind: ScalarKind::Float,
+            size: vector,
+            ..
+        } => match size(vector) {
+            2 => Data::Vector2,
+            3 => Data::Vector3,
+            _ => Data::Vector4,
+        },
+        TypeInner::Vector {
+            kind: ScalarKind::Sint,
+            size: vector,
+            ..
+        } => match size(vector) {
+            2 => Data::Int2,
+            3 => Data::Int3,
+            _ => Data::Int4,
+        },
+        TypeInner::Vector {
+            kind: ScalarKind::Uint,
+            size: vector,
+            ..
+        } => match size(vector) {
+            2 => Data::UInt2,
+            3 => Data::UInt3,
+            _ => Data::UInt4,
+        },
+        TypeInner::Matrix { columns, rows, .. } if columns == rows => match size(columns) {
+            2 => Data::Matrix2,
+            3 => Data::Matrix3,
+            _ => Data::Matrix4,
+        },
+        TypeInner::Image {
+            dim: ImageDimension::D2,
+            arrayed: false,
+            class,
+        } => Data::Image(class),
+        TypeInner::Image {
+            dim: ImageDimension::D2,
+            arrayed: true,
+            class,
+        } => Data::ImageArray(class),
+        TypeInner::Image {
+            dim: ImageDimension::Cube,
+            arrayed: false,
+            class,
+        } => Data::ImageCube(class),
+        TypeInner::Sampler { .. } => Data::Sampler,
+        _ => return None,
+    })
+}
+
+/// Ports of the function a custom function node calls.
+#[derive(Default)]
+struct Signature {
+    name: String,
+    inputs: Vec<PortSpec>,
+    outputs: Vec<PortSpec>,
+    /// Whether the outputs are the members of a returned struct.
+    is_struct: bool,
+}
+
+impl Signature {
+    /// Reads the last function of `source`, the ones before it being its helpers.
+    fn parse(source: &str) -> Result<Self, String> {
+        let module =
+            naga::front::wgsl::parse_str(source).map_err(|err| match err.location(source) {
+                Some(loc) => format!("{}:{}: {}", loc.line_number, loc.line_position, err),
+                None => err.to_string(),
+            })?;
+
+        // Resources are bound by the graph, at bindings the code can't know.
+        let bound = module
+            .global_variables
+            .iter()
+            .find(|(_, var)| var.binding.is_some());
+        if let Some((_, var)) = bound {
+            let name = var.name.as_deref().unwrap_or_default();
+            return Err(format!("`{}` can't be bound in a custom function", name));
+        }
+
+        let (_, function) = module
+            .functions
+            .iter()
+            .last()
+            .ok_or_else(|| String::from("no function found"))?;
+
+        let unsupported = |name: &str| format!("unsupported type for `{}`", name);
+
+        let mut inputs = Vec::with_capacity(function.arguments.len());
+        for (index, argument) in function.arguments.iter().enumerate() {
+            let label = argument
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("arg{}", index));
+            let data =
+                port_data(&module.types[argument.ty].inner).ok_or_else(|| unsupported(&label))?;
+            inputs.push(PortSpec::new(label, data));
+        }
+
+        let result = function
+            .result
+            .as_ref()
+            .ok_or_else(|| String::from("the function returns nothing"))?;
+
+        let (outputs, is_struct) = match module.types[result.ty].inner {
+            TypeInner::Struct { ref members, .. } => {
+                let mut outputs = Vec::with_capacity(members.len());
+                for (index, member) in members.iter().enumerate() {
+                    let label = member
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("out{}", index));
+                    let data = port_data(&module.types[member.ty].inner)
+                        .ok_or_else(|| unsupported(&label))?;
+                    outputs.push(PortSpec::new(label, data));
+                }
+                (outputs, true)
+            }
+            ref ty => {
+                let data = port_data(ty).ok_or_else(|| unsupported("out"))?;
+                (vec![PortSpec::new("out", data)], false)
+            }
+        };
+
+        Ok(Self {
+            name: function.name.clone().unwrap_or_default(),
+            inputs,
+            outputs,
+            is_struct,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CustomFunctionState {
+    file: Option<PathBuf>,
+    source: String,
+}
+
+/// Calls a function written in WGSL, typed in the node or read from a file
+/// relative to the graph. The code of a file is copied into the node, so graphs
+/// still compile without it, and `Load` reads it again.
+///
+/// The source is merged into the module the way `builtin.wgsl` is, under a
+/// prefix derived from the source so that every node with the same code
+/// shares its functions and globals. Globals bound to resources are rejected.
+pub struct CustomFunction {
+    file: String,
+    source: String,
+    signature: Signature,
+    error: Option<String>,
+    /// Set by `Load`, the file is read by the workspace which knows where the graph is.
+    load: bool,
+}
+
+impl CustomFunction {
+    pub const KIND: &'static str = "Custom Function";
+
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn(Self::KIND, 250.0, |_, _| {
+            let mut node = Self {
+                file: String::new(),
+                source: String::new(),
+                signature: Signature::default(),
+                error: None,
+                load: false,
+            };
+            node.set_source(DEFAULT_SOURCE.to_string());
+            node
+        })
+    }
+
+    /// Parses `source`, keeping the previous ports when it is invalid so that
+    /// links survive while the code is being typed.
+    fn set_source(&mut self, source: String) {
+        self.source = source;
+        match Signature::parse(&self.source) {
+            Ok(signature) => {
+                self.signature = signature;
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    /// Whether `Load` was clicked since the last call.
+    pub fn take_load(&mut self) -> bool {
+        std::mem::take(&mut self.load)
+    }
+
+    pub fn has_file(&self) -> bool {
+        !self.file.trim().is_empty()
+    }
+
+    /// Reads the code of the file again, relative to `dir`, the folder of the
+    /// graph. The copied code is kept when the file can't be read.
+    pub fn read_file(&mut self, dir: &Path) -> std::io::Result<()> {
+        let path = dir.join(self.file.trim());
+        let source = std::fs::read_to_string(&path).map_err(|err| {
+            std::io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        })?;
+        self.set_source(source);
+        Ok(())
+    }
+
+    /// Like [`CustomFunction::read_file`], showing the error on the node.
+    pub fn load_file(&mut self, dir: &Path) {
+        if let Err(err) = self.read_file(dir) {
+            self.error = Some(err.to_string());
+        }
+    }
+
+    fn prefix(&self) -> String {
+        format!("custom_{:016x}_", fnv1a(self.source.bytes()))
+    }
+}
+
+impl PreviewBuilder for CustomFunction {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            ui.horizontal(|ui| {
+                let load = ui
+                    .button("Load")
+                    .on_hover_text("Read relative to the graph file");
+                let hint = egui::TextEdit::singleline(&mut self.file).hint_text("file.wgsl");
+                ui.add_sized(ui.available_size(), hint);
+                if load.clicked() && self.has_file() {
+                    self.load = true;
+                }
+            });
+
+            let mut source = self.source.clone();
+            let editor = egui::TextEdit::multiline(&mut source)
+                .code_editor()
+                .desired_rows(4)
+                .desired_width(f32::INFINITY);
+            if ui.add(editor).changed() {
+                self.set_source(source);
+            }
+
+            if let Some(error) = self.error.as_ref() {
+                ui.label(egui::RichText::new(error).small().color(egui::Color32::RED));
+            }
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        let file = self.file.trim();
+        to_state(&CustomFunctionState {
+            file: (!file.is_empty()).then(|| PathBuf::from(file)),
+            source: self.source.clone(),
+        })
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let CustomFunctionState { file, source } = from_state(state)?;
+        self.file = file
+            .map(|file| file.display().to_string())
+            .unwrap_or_default();
+        self.set_source(source);
+        Ok(())
+    }
+
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        let Signature {
+            inputs, outputs, ..
+        } = &self.signature;
+        Some((inputs.clone(), outputs.clone()))
+    }
+
+    fn output_expr(&self, node: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        if let Some(error) = self.error.as_ref() {
+            return Err(EmitError::CustomFunction(error.clone()));
+        }
+
+        let data = &function.module.storage.nodes[node];
+        let index = data
+            .outputs
+            .iter()
+            .position(|&port| port == output)
+            .ok_or(EmitError::PortNotFound)?;
+        let inputs = data.inputs.clone();
+
+        let prefix = self.prefix();
+        let name = format!("{}{}", prefix, self.signature.name);
+        if function.function_by_name(&name).is_none() {
+            function
+                .module
+                .merge_str(&self.source, &prefix)
+                .map_err(|err| EmitError::CustomFunction(err.to_string()))?;
+        }
+
+        let mut arguments = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let expr = match function.module.storage.ports[input].data {
+                Data::Float | Data::Int | Data::UInt => {
+                    function.for_input_resolve(input, VectorKind::V1)?
+                }
+                Data::Vector2 | Data::Int2 | Data::UInt2 => {
+                    function.for_input_resolve(input, VectorKind::V2)?
+                }
+                Data::Vector3 | Data::Int3 | Data::UInt3 => {
+                    function.for_input_resolve(input, VectorKind::V3)?
+                }
+                Data::Vector4 | Data::Int4 | Data::UInt4 => {
+                    function.for_input_resolve(input, VectorKind::V4)?
+                }
+                _ => function.for_input(input)?,
+            };
+            arguments.push(expr);
+        }
+
+        let result = function.call(&name, arguments)?;
+        if self.signature.is_struct {
+            Ok(function.access_index(result, index as u32))
+        } else {
+            Ok(result)
+        }
+    }
+}
+
+#[test]
+fn merge_custom_function() {
+    let mut storage = Storage::default();
+    let master = storage.spawn_kind("Master").unwrap();
+
+    let source = "
+let SCALE: vec2<f32> = vec2<f32>(0.5, 2.0);
+
+struct Split {
+    low: vec3<f32>,
+    high: vec4<f32>,
+};
+
+fn scale(x: f32, index: i32) -> f32 {
+    return x * SCALE[index];
+}
+
+fn split(color: vec3<f32>) -> Split {
+    let low = color * scale(color.x, 0);
+    return Split(low, vec4<f32>(low * scale(color.y, 1), 1.0));
+}
+";
+
+    let mut nodes = Vec::new();
+    for _ in 0..2 {
+        let node = storage.spawn_kind(CustomFunction::KIND).unwrap();
+        let custom = storage.nodes[node]
+            .builder
+            .downcast_mut::<CustomFunction>()
+            .unwrap();
+        custom.set_source(source.to_string());
+        assert_eq!(custom.error, None);
+        assert!(storage.sync_ports(node));
+        nodes.push(node);
+    }
+
+    let data = &storage.nodes[nodes[0]];
+    assert_eq!(data.inputs.len(), 1);
+    assert_eq!(storage.ports[data.inputs[0]].data, Data::Vector3);
+    let labels: Vec<_> = data
+        .outputs
+        .iter()
+        .map(|&port| storage.ports[port].label.as_str())
+        .collect();
+    assert_eq!(labels, ["low", "high"]);
+
+    let position = storage.nodes[master].inputs[0];
+    storage.link(storage.nodes[nodes[0]].outputs[1], position);
+    let color = storage.nodes[master].inputs[1];
+    storage.link(storage.nodes[nodes[1]].outputs[1], color);
+
+    // Nodes with the same code share its functions.
+    let module = storage.nodes[master]
+        .builder
+        .module(master, &storage)
+        .unwrap();
+    let source = module.build().unwrap();
+    assert_eq!(source.matches("_split(").count(), 3);
+    assert_eq!(source.matches("_scale(").count(), 3);
+
+    // Errors keep the ports.
+    let custom = storage.nodes[nodes[0]]
+        .builder
+        .downcast_mut::<CustomFunction>()
+        .unwrap();
+    custom.set_source(String::from("fn broken("));
+    assert!(custom.error.is_some());
+    assert!(!storage.sync_ports(nodes[0]));
+}
+
+#[test]
+fn read_relative_file() {
+    let dir = std::env::temp_dir().join(format!("shaderlab-custom-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("functions")).unwrap();
+    let source = "fn twice(x: f32) -> f32 {\n    return x * 2.0;\n}\n";
+    std::fs::write(dir.join("functions/twice.wgsl"), source).unwrap();
+
+    let mut storage = Storage::default();
+    let node = storage.spawn_kind(CustomFunction::KIND).unwrap();
+    let custom = storage.nodes[node]
+        .builder
+        .downcast_mut::<CustomFunction>()
+        .unwrap();
+    custom.file = String::from("functions/twice.wgsl");
+    custom.read_file(&dir).unwrap();
+    assert_eq!(custom.source, source);
+
+    // A missing file keeps the copied code.
+    assert!(custom.read_file(&dir.join("functions")).is_err());
+    assert_eq!(custom.source, source);
+    assert_eq!(custom.error, None);
+    custom.load_file(&dir.join("functions"));
+    assert!(custom.error.is_some());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn custom_globals() {
+    let mut storage = Storage::default();
+    let master = storage.spawn_kind("Master").unwrap();
+    let mut spawn = |source: &str| {
+        let node = storage.spawn_kind(CustomFunction::KIND).unwrap();
+        let custom = storage.nodes[node]
+            .builder
+            .downcast_mut::<CustomFunction>()
+            .unwrap();
+        custom.set_source(source.to_string());
+        let error = custom.error.clone();
+        storage.sync_ports(node);
+        (node, error)
+    };
+
+    let (_, error) = spawn(
+        "
+@group(0) @binding(0)
+var<uniform> scale: f32;
+
+fn c() -> f32 {
+    return scale;
+}
+",
+    );
+    assert!(error.unwrap().contains("`scale`"));
+
+    // Both declare `seed`, each under its own prefix.
+    let (a, _) = spawn(
+        "
+var<private> seed: f32;
+
+fn a(x: f32) -> f32 {
+    seed = x;
+    return seed;
+}
+",
+    );
+    let (b, _) = spawn(
+        "
+var<private> seed: f32;
+
+fn b(x: f32) -> vec4<f32> {
+    seed = x;
+    return vec4<f32>(seed);
+}
+",
+    );
+    storage.link(storage.nodes[a].outputs[0], storage.nodes[b].inputs[0]);
+    storage.link(storage.nodes[b].outputs[0], storage.nodes[master].inputs[1]);
+
+    let module = storage.nodes[master].builder.module(master, &storage);
+    let source = module.unwrap().build().unwrap();
+    for node in [a, b] {
+        let custom = storage.nodes[node].builder.downcast_ref::<CustomFunction>();
+        let global = format!("var<private> {}seed: f32;", custom.unwrap().prefix());
+        assert!(source.contains(&global));
+    }
+}
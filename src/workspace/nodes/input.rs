@@ -1,7 +1,10 @@
+use super::super::asset::{from_state, to_state};
 use super::super::builder::{expr::*, *};
-use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
+use super::subgraph;
+use crate::workspace::{blackboard, Data, Fragment, Node, Port, PortSpec, PreviewBuilder, Storage};
 use egui::widgets::color_picker::color_edit_button_hsva;
 use egui::Rgba;
+use serde::{Deserialize, Serialize};
 
 pub struct InputFloat {
     x: Port,
@@ -25,6 +28,48 @@ impl PreviewBuilder for InputFloat {
     }
 }
 
+pub struct InputInt {
+    x: Port,
+    output: Port,
+}
+
+impl InputInt {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Integer", 80.0, |ctx, node| Self {
+            x: ctx.input(node, "x", Fragment, Data::Int, None),
+            output: ctx.output(node, "out", Fragment, Data::Int, None),
+        })
+    }
+}
+
+impl PreviewBuilder for InputInt {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        function.for_input(self.x)
+    }
+}
+
+pub struct InputUInt {
+    x: Port,
+    output: Port,
+}
+
+impl InputUInt {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Unsigned Integer", 80.0, |ctx, node| Self {
+            x: ctx.input(node, "x", Fragment, Data::UInt, None),
+            output: ctx.output(node, "out", Fragment, Data::UInt, None),
+        })
+    }
+}
+
+impl PreviewBuilder for InputUInt {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        function.for_input(self.x)
+    }
+}
+
 pub struct InputVector2 {
     x: Port,
     y: Port,
@@ -134,6 +179,16 @@ impl PreviewBuilder for Color {
         self.rgba = hsva.into();
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.rgba.to_array())
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let [r, g, b, a]: [f32; 4] = from_state(state)?;
+        self.rgba = Rgba::from_rgba_premultiplied(r, g, b, a);
+        Ok(())
+    }
+
     fn show_preview(&self) -> bool {
         false
     }
@@ -159,6 +214,13 @@ pub struct Slider {
     max: f64,
 }
 
+#[derive(Serialize, Deserialize)]
+struct SliderState {
+    value: f64,
+    min: f64,
+    max: f64,
+}
+
 impl Slider {
     pub fn spawn(storage: &mut Storage) -> Node {
         storage.spawn("Slider", 140.0, |ctx, node| Self {
@@ -186,6 +248,22 @@ impl PreviewBuilder for Slider {
         });
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&SliderState {
+            value: self.value,
+            min: self.min,
+            max: self.max,
+        })
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let SliderState { value, min, max } = from_state(state)?;
+        self.value = value;
+        self.min = min;
+        self.max = max;
+        Ok(())
+    }
+
     fn show_preview(&self) -> bool {
         false
     }
@@ -206,6 +284,10 @@ enum ConstantValue {
     SQRT2,
 }
 
+impl ConstantValue {
+    const ALL: [Self; 5] = [Self::PI, Self::TAU, Self::PHI, Self::E, Self::SQRT2];
+}
+
 pub struct Constant {
     selected: ConstantValue,
     port: Port,
@@ -234,6 +316,19 @@ impl PreviewBuilder for Constant {
             });
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&format!("{:?}", self.selected))
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let name: String = from_state(state)?;
+        self.selected = ConstantValue::ALL
+            .into_iter()
+            .find(|value| format!("{:?}", value) == name)
+            .ok_or_else(|| ron::Error::Message(format!("unknown constant `{}`", name)))?;
+        Ok(())
+    }
+
     fn show_preview(&self) -> bool {
         false
     }
@@ -252,3 +347,101 @@ impl PreviewBuilder for Constant {
         Float(value).emit(function)
     }
 }
+
+#[derive(Serialize, Deserialize)]
+struct PropertyState {
+    name: String,
+    ty: String,
+}
+
+/// Reads a property of the blackboard from the uniform buffer of the graph,
+/// so that changing its value doesn't regenerate the shader.
+pub struct PropertyNode {
+    pub name: String,
+    pub data: Data,
+}
+
+impl PropertyNode {
+    pub const KIND: &'static str = "Property";
+
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn(Self::KIND, 100.0, |ctx, node| {
+            ctx.output(node, "out", Fragment, Data::Float, None);
+            Self {
+                name: String::new(),
+                data: Data::Float,
+            }
+        })
+    }
+
+    /// Spawns a node reading `property`, as picked in the creation menu.
+    pub fn spawn_property(storage: &mut Storage, property: &blackboard::Property) -> Node {
+        let node = Self::spawn(storage);
+        storage.nodes[node].kind = Self::KIND;
+        if let Some(builder) = storage.nodes[node].builder.downcast_mut::<Self>() {
+            builder.name = property.name.clone();
+            builder.data = property.value.data();
+        }
+        storage.sync_ports(node);
+        node
+    }
+}
+
+impl PreviewBuilder for PropertyNode {
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&PropertyState {
+            name: self.name.clone(),
+            ty: subgraph::type_name(self.data).to_string(),
+        })
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let PropertyState { name, ty } = from_state(state)?;
+        self.name = name;
+        self.data = subgraph::parse_type(&ty)?;
+        Ok(())
+    }
+
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        let output = PortSpec::new(self.name.clone(), self.data);
+        Some((Vec::new(), vec![output]))
+    }
+
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, _: Port) -> EmitResult {
+        function.property(&self.name)
+    }
+}
+
+/// The clock of the graph, bound as a uniform buffer. Previews using it are
+/// redrawn every frame while the animation plays.
+pub struct Time {
+    outputs: [Port; 4],
+}
+
+impl Time {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Time", 100.0, |ctx, node| Self {
+            outputs: ["time", "sine", "cosine", "delta"]
+                .map(|label| ctx.output(node, label, Fragment, Data::Float, None)),
+        })
+    }
+}
+
+impl PreviewBuilder for Time {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let member = self
+            .outputs
+            .iter()
+            .position(|&port| port == output)
+            .ok_or(EmitError::PortNotFound)?;
+        function.time(member as u32)
+    }
+}
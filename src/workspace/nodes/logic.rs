@@ -1,3 +1,4 @@
+use super::super::asset::{from_state, to_state};
 use super::super::builder::{expr::*, *};
 use super::util::*;
 use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
@@ -22,6 +23,15 @@ impl PreviewBuilder for InputBoolean {
         ui.checkbox(&mut self.value, "value");
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.value)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.value = from_state(state)?;
+        Ok(())
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.output, output);
         Bool(self.value).emit(function)
@@ -39,6 +49,15 @@ pub struct Comparison {
     result: Port,
 }
 
+const COMPARISON_OPS: [(BinaryOperator, &str); 6] = [
+    (BinaryOperator::LessEqual, "<="),
+    (BinaryOperator::Equal, "=="),
+    (BinaryOperator::GreaterEqual, ">="),
+    (BinaryOperator::Less, "<"),
+    (BinaryOperator::NotEqual, "!="),
+    (BinaryOperator::Greater, ">"),
+];
+
 impl Comparison {
     pub fn spawn(storage: &mut Storage) -> Node {
         storage.spawn("Comparison", 100.0, |ctx, node| Self {
@@ -66,6 +85,21 @@ impl PreviewBuilder for Comparison {
         });
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        let (_, name) = COMPARISON_OPS.iter().find(|&&(op, _)| op == self.op)?;
+        to_state(name)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let name: String = from_state(state)?;
+        let (op, _) = COMPARISON_OPS
+            .iter()
+            .find(|&&(_, op)| op == name)
+            .ok_or_else(|| ron::Error::Message(format!("unknown comparison `{}`", name)))?;
+        self.op = *op;
+        Ok(())
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.result, output);
         let (_, left, right) = resolve_pair_min(function, self.left, self.right)?;
@@ -1,5 +1,19 @@
+use super::super::asset::{from_state, to_state};
 use super::super::builder::{expr::*, *};
-use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage, Vertex};
+use super::super::preview::PreviewMode;
+use super::compute::{expr_invocation_index, expr_output_size};
+use super::scene::{
+    expr_main_texture, expr_scene_color, expr_screen_uv, expr_tint, TARGET_SAMPLER,
+};
+use super::util::{combo, state_by_name};
+use crate::workspace::{
+    Data, Fragment, InputDefault, Node, Port, PortSpec, PreviewBuilder, Storage, Vertex,
+};
+use naga::{
+    BinaryOperator, Block, EntryPoint, Expression, RelationalFunction, ShaderStage, Statement,
+    SwizzleComponent, VectorSize,
+};
+use serde::{Deserialize, Serialize};
 
 pub struct FragmentInputs {
     builtin_position: Port,    // vec4<f32>
@@ -40,20 +54,23 @@ impl PreviewBuilder for FragmentInputs {
     }
 
     fn output_expr(&self, _node: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
-        let input = FunctionArgument(0).emit(function)?;
-        match output {
-            port if port == self.builtin_position => AccessIndex(input, 0).emit(function),
-            port if port == self.builtin_font_facing => AccessIndex(input, 1).emit(function),
-
-            port if port == self.position => todo!(),
-            port if port == self.normal => todo!(),
-            port if port == self.tangent => todo!(),
-            port if port == self.bitangent => todo!(),
-            port if port == self.uv0 => todo!(),
-            port if port == self.uv1 => todo!(),
-
-            _ => Err(EmitError::PortNotFound),
-        }
+        let varying = match output {
+            port if port == self.builtin_position || port == self.builtin_font_facing => {
+                let input = FunctionArgument(0).emit(function)?;
+                let index = if port == self.builtin_position { 0 } else { 1 };
+                return AccessIndex(input, index).emit(function);
+            }
+
+            port if port == self.position => Varying::Position,
+            port if port == self.normal => Varying::Normal,
+            port if port == self.tangent => Varying::Tangent,
+            port if port == self.bitangent => Varying::Bitangent,
+            port if port == self.uv0 => Varying::Uv0,
+            port if port == self.uv1 => Varying::Uv1,
+
+            _ => return Err(EmitError::PortNotFound),
+        };
+        function.varying(varying)
     }
 }
 
@@ -72,21 +89,665 @@ impl Master {
 }
 
 impl PreviewBuilder for Master {
+    fn is_master(&self) -> bool {
+        true
+    }
+
     fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
         Err(EmitError::PortNotFound)
     }
 
     fn vertex(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        // Unlinked, the mesh keeps the position of its vertices.
+        if !function.is_linked(self.position) {
+            let position = function.varying(Varying::Position)?;
+            return function.resolve_to(position, VectorKind::V4);
+        }
         function.for_input_vector4(self.position)
     }
 
     fn fragment(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
         function
-            .for_input(self.color)
+            .for_input_vector4(self.color)
             .or_else(|_| expr_fill_white(function))
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum SurfaceType {
+    Opaque,
+    Transparent,
+}
+
+/// How transparent surfaces are combined with what is behind them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum BlendMode {
+    Alpha,
+    Premultiply,
+    Additive,
+    Multiply,
+}
+
+const SURFACE_TYPES: [(SurfaceType, &str); 2] = [
+    (SurfaceType::Opaque, "Opaque"),
+    (SurfaceType::Transparent, "Transparent"),
+];
+
+const BLEND_MODES: [(BlendMode, &str); 4] = [
+    (BlendMode::Alpha, "Alpha"),
+    (BlendMode::Premultiply, "Premultiply"),
+    (BlendMode::Additive, "Additive"),
+    (BlendMode::Multiply, "Multiply"),
+];
+
+state_by_name!(SurfaceType, SURFACE_TYPES);
+state_by_name!(BlendMode, BLEND_MODES);
+
+/// Render state of a master node, applied to the pipelines drawing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Surface {
+    pub ty: SurfaceType,
+    /// Only used by transparent surfaces.
+    pub blend: BlendMode,
+    pub two_sided: bool,
+    pub depth_write: bool,
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Self {
+            ty: SurfaceType::Opaque,
+            blend: BlendMode::Alpha,
+            two_sided: false,
+            depth_write: true,
+        }
+    }
+}
+
+impl Surface {
+    pub fn is_transparent(self) -> bool {
+        self.ty == SurfaceType::Transparent
+    }
+
+    /// Blending of the color target, `None` replacing it.
+    pub fn blend_state(self) -> Option<wgpu::BlendState> {
+        use wgpu::{BlendComponent, BlendFactor, BlendOperation, BlendState};
+
+        if !self.is_transparent() {
+            return None;
+        }
+        let color = |src_factor, dst_factor| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        };
+        Some(match self.blend {
+            BlendMode::Alpha => BlendState::ALPHA_BLENDING,
+            BlendMode::Premultiply => BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: color(BlendFactor::SrcAlpha, BlendFactor::One),
+                alpha: BlendComponent::OVER,
+            },
+            BlendMode::Multiply => BlendState {
+                color: color(BlendFactor::Dst, BlendFactor::Zero),
+                alpha: BlendComponent::OVER,
+            },
+        })
+    }
+
+    pub fn cull_mode(self) -> Option<wgpu::Face> {
+        (!self.two_sided).then_some(wgpu::Face::Back)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        combo(ui, "surface", &mut self.ty, &SURFACE_TYPES);
+        ui.add_enabled_ui(self.is_transparent(), |ui| {
+            combo(ui, "blend", &mut self.blend, &BLEND_MODES);
+        });
+        ui.checkbox(&mut self.two_sided, "two sided");
+        ui.checkbox(&mut self.depth_write, "depth write");
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct LitSettings {
+    surface: Surface,
+    /// Reads smoothness instead of roughness, one minus the other.
+    smoothness: bool,
+}
+
+/// Physically based master, shaded like bevy's `StandardMaterial`.
+///
+/// The fragment shader calls `builtin_lit`, a simple preview light in the
+/// workspace that [`GraphMaterial`] swaps for bevy's PBR lighting.
+///
+/// [`GraphMaterial`]: crate::workspace::GraphMaterial
+pub struct Lit {
+    position: Port,
+    base_color: Port,
+    /// Tangent space, reading the tangents of the mesh once linked.
+    normal: Port,
+    metallic: Port,
+    roughness: Port,
+    emission: Port,
+    occlusion: Port,
+    alpha: Port,
+    /// Fragments with less `alpha` are discarded.
+    alpha_clip: Port,
+
+    settings: LitSettings,
+}
+
+impl Lit {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Lit Master", 150.0, |ctx, node| Self {
+            position: ctx.input(node, "position", Vertex, Data::Vector3, None),
+            base_color: ctx.input(
+                node,
+                "base color",
+                Fragment,
+                Data::Vector3,
+                InputDefault::vector3(0.5, 0.5, 0.5),
+            ),
+            normal: ctx.input(
+                node,
+                "normal",
+                Fragment,
+                Data::Vector3,
+                InputDefault::vector3(0.0, 0.0, 1.0),
+            ),
+            metallic: ctx.input(node, "metallic", Fragment, Data::Float, None),
+            roughness: ctx.input(
+                node,
+                "roughness",
+                Fragment,
+                Data::Float,
+                InputDefault::float(0.5),
+            ),
+            emission: ctx.input(node, "emission", Fragment, Data::Vector3, None),
+            occlusion: ctx.input(
+                node,
+                "occlusion",
+                Fragment,
+                Data::Float,
+                InputDefault::float(1.0),
+            ),
+            alpha: ctx.input(
+                node,
+                "alpha",
+                Fragment,
+                Data::Float,
+                InputDefault::float(1.0),
+            ),
+            alpha_clip: ctx.input(node, "alpha clip", Fragment, Data::Float, None),
+            settings: LitSettings {
+                surface: Surface::default(),
+                smoothness: false,
+            },
+        })
+    }
+}
+
+impl PreviewBuilder for Lit {
+    fn is_master(&self) -> bool {
+        true
+    }
+
+    fn is_lit(&self) -> bool {
+        true
+    }
+
+    fn surface(&self) -> Option<Surface> {
+        Some(self.settings.surface)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            self.settings.surface.ui(ui);
+            ui.checkbox(&mut self.settings.smoothness, "smoothness");
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.settings)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.settings = from_state(state)?;
+        Ok(())
+    }
+
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        let roughness = if self.settings.smoothness {
+            "smoothness"
+        } else {
+            "roughness"
+        };
+        let inputs = vec![
+            PortSpec::new("position", Data::Vector3),
+            PortSpec::new("base color", Data::Vector3),
+            PortSpec::new("normal", Data::Vector3),
+            PortSpec::new("metallic", Data::Float),
+            PortSpec::new(roughness, Data::Float),
+            PortSpec::new("emission", Data::Vector3),
+            PortSpec::new("occlusion", Data::Float),
+            PortSpec::new("alpha", Data::Float),
+            PortSpec::new("alpha clip", Data::Float),
+        ];
+        Some((inputs, Vec::new()))
+    }
+
+    fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
+        Err(EmitError::PortNotFound)
+    }
+
+    fn vertex(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        if !function.is_linked(self.position) {
+            let position = function.varying(Varying::Position)?;
+            return function.resolve_to(position, VectorKind::V4);
+        }
+        function.for_input_vector4(self.position)
+    }
+
+    fn fragment(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        let base_color = function.for_input_vector3(self.base_color)?;
+
+        // Unlinked, the normal of the mesh is kept without reading its tangents.
+        let (normal, tangent, bitangent) = if function.is_linked(self.normal) {
+            (
+                function.for_input_vector3(self.normal)?,
+                function.varying(Varying::Tangent)?,
+                function.varying(Varying::Bitangent)?,
+            )
+        } else {
+            let zero = [Float(0.0); 3].emit(function)?;
+            (
+                [Float(0.0), Float(0.0), Float(1.0)].emit(function)?,
+                zero,
+                zero,
+            )
+        };
+
+        let metallic = function.for_input_float(self.metallic)?;
+        let mut roughness = function.for_input_float(self.roughness)?;
+        if self.settings.smoothness {
+            roughness = (Float(1.0) - roughness).emit(function)?;
+        }
+        let emission = function.for_input_vector3(self.emission)?;
+        let occlusion = function.for_input_float(self.occlusion)?;
+        let alpha = function.for_input_float(self.alpha)?;
+        let alpha_clip = function.for_input_float(self.alpha_clip)?;
+
+        let position = function.varying(Varying::Position)?;
+        let vertex_normal = function.varying(Varying::Normal)?;
+        let input = FunctionArgument(0).emit(function)?;
+        let frag_coord = function.access_index(input, 0);
+        let is_front = function.access_index(input, 1);
+
+        function.call(
+            "builtin_lit",
+            [
+                base_color,
+                normal,
+                metallic,
+                roughness,
+                emission,
+                occlusion,
+                alpha,
+                alpha_clip,
+                position,
+                vertex_normal,
+                tangent,
+                bitangent,
+                frag_coord,
+                is_front,
+            ],
+        )
+    }
+}
+
+/// Full-screen post-processing of the rendered scene.
+///
+/// `vs_main` draws a triangle covering the screen from three vertex indices,
+/// without attributes. The target binds the scene color, the scene depth and
+/// a clamping sampler at bindings 0, 1 and 2, before the properties.
+pub struct PostProcess {
+    /// The scene color at the fragment when unlinked.
+    color: Port,
+}
+
+impl PostProcess {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Post Process Master", 120.0, |ctx, node| Self {
+            color: ctx.input(node, "color", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for PostProcess {
+    fn is_master(&self) -> bool {
+        true
+    }
+
+    fn target(&self) -> Vec<Resource> {
+        vec![
+            Resource::SceneColor,
+            Resource::SceneDepth,
+            Resource::Sampler(TARGET_SAMPLER),
+            Resource::Viewport,
+        ]
+    }
+
+    fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
+        Err(EmitError::PortNotFound)
+    }
+
+    fn vertex(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        expr_fullscreen(function)
+    }
+
+    fn fragment(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        if function.is_linked(self.color) {
+            return function.for_input_vector4(self.color);
+        }
+        let uv = expr_screen_uv(function)?;
+        expr_scene_color(function, uv)
+    }
+}
+
+/// 2D sprite, drawn as a textured quad.
+///
+/// `vs_main` reads the position, `uv0` and color attributes of the quad. The
+/// target binds the sprite texture, a clamping sampler and the `Tint` uniform
+/// at bindings 0, 1 and 2, before the properties.
+pub struct Sprite {
+    position: Port,
+    /// The texture at `uv0` times the vertex color and the tint when unlinked.
+    color: Port,
+
+    surface: Surface,
+}
+
+impl Sprite {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Sprite Master", 120.0, |ctx, node| Self {
+            position: ctx.input(node, "position", Vertex, Data::Vector3, None),
+            color: ctx.input(node, "color", Fragment, Data::Vector4, None),
+            surface: Surface {
+                ty: SurfaceType::Transparent,
+                blend: BlendMode::Alpha,
+                two_sided: true,
+                depth_write: false,
+            },
+        })
+    }
+}
+
+impl PreviewBuilder for Sprite {
+    fn is_master(&self) -> bool {
+        true
+    }
+
+    fn surface(&self) -> Option<Surface> {
+        Some(self.surface)
+    }
+
+    fn target(&self) -> Vec<Resource> {
+        vec![
+            Resource::MainTexture,
+            Resource::Sampler(TARGET_SAMPLER),
+            Resource::Tint,
+        ]
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| self.surface.ui(ui));
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.surface)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.surface = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
+        Err(EmitError::PortNotFound)
+    }
+
+    fn vertex(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        if !function.is_linked(self.position) {
+            let position = function.varying(Varying::Position)?;
+            return function.resolve_to(position, VectorKind::V4);
+        }
+        function.for_input_vector4(self.position)
+    }
+
+    fn fragment(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        if function.is_linked(self.color) {
+            return function.for_input_vector4(self.color);
+        }
+        let uv = function.varying(Varying::Uv0)?;
+        let texture = expr_main_texture(function, uv)?;
+        let color = function.varying(Varying::Color)?;
+        let tint = expr_tint(function)?;
+        (Wrap(texture) * Wrap(color) * Wrap(tint)).emit(function)
+    }
+}
+
+/// Widget of the user interface, blended with premultiplied alpha like egui.
+///
+/// `vs_main` reads the position, `uv0` and color attributes of the widget
+/// mesh. The target binds the font or image texture and a clamping sampler at
+/// bindings 0 and 1, before the properties.
+pub struct Ui {
+    position: Port,
+    /// The texture at `uv0` times the vertex color when unlinked.
+    color: Port,
+}
+
+impl Ui {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("UI Master", 120.0, |ctx, node| Self {
+            position: ctx.input(node, "position", Vertex, Data::Vector3, None),
+            color: ctx.input(node, "color", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Ui {
+    fn is_master(&self) -> bool {
+        true
+    }
+
+    fn surface(&self) -> Option<Surface> {
+        Some(Surface {
+            ty: SurfaceType::Transparent,
+            blend: BlendMode::Premultiply,
+            two_sided: true,
+            depth_write: false,
+        })
+    }
+
+    fn target(&self) -> Vec<Resource> {
+        vec![Resource::MainTexture, Resource::Sampler(TARGET_SAMPLER)]
+    }
+
+    fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
+        Err(EmitError::PortNotFound)
+    }
+
+    fn vertex(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        if !function.is_linked(self.position) {
+            let position = function.varying(Varying::Position)?;
+            return function.resolve_to(position, VectorKind::V4);
+        }
+        function.for_input_vector4(self.position)
+    }
+
+    fn fragment(&self, _: Node, function: &mut FnBuilder) -> EmitResult {
+        if function.is_linked(self.color) {
+            return function.for_input_vector4(self.color);
+        }
+        let uv = function.varying(Varying::Uv0)?;
+        let texture = expr_main_texture(function, uv)?;
+        let color = function.varying(Varying::Color)?;
+        (Wrap(texture) * Wrap(color)).emit(function)
+    }
+}
+
+/// Compute kernel, generating `cs_main` instead of `vs_main` and `fs_main`.
+///
+/// The output storage texture is always at binding 0, before the properties,
+/// and the kernel is dispatched over its texels. Invocations past its edges
+/// return early. Storage buffers hold one element per texel, row by row.
+pub struct Compute {
+    /// Written to the output texture at the texel of the invocation.
+    color: Port,
+    /// Written to the output storage buffer at the element of the invocation.
+    value: Port,
+
+    workgroup_size: [u32; 3],
+}
+
+impl Compute {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Compute Master", 150.0, |ctx, node| Self {
+            color: ctx.input(node, "color", Fragment, Data::Vector4, None),
+            value: ctx.input(node, "value", Fragment, Data::Vector4, None),
+            workgroup_size: [8, 8, 1],
+        })
+    }
+}
+
+impl PreviewBuilder for Compute {
+    fn is_master(&self) -> bool {
+        true
+    }
+
+    fn workgroup_size(&self) -> Option<[u32; 3]> {
+        Some(self.workgroup_size)
+    }
+
+    fn target(&self) -> Vec<Resource> {
+        vec![Resource::StorageTexture]
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("workgroup");
+            // The smallest maximums of `wgpu::Limits::downlevel_defaults`.
+            for (size, max) in self.workgroup_size.iter_mut().zip([256, 256, 64]) {
+                ui.add(egui::DragValue::new(size).clamp_range(1..=max));
+            }
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.workgroup_size)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.workgroup_size = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
+        Err(EmitError::PortNotFound)
+    }
+
+    fn preview_module<'s>(
+        &self,
+        node: Node,
+        storage: &'s Storage,
+        _mode: PreviewMode,
+    ) -> EmitResult<ModuleBuilder<'s>> {
+        let mut module =
+            ModuleBuilder::from_wgsl(storage, include_str!("../builtin.wgsl")).unwrap();
+
+        for resource in self.target() {
+            module.bind(resource);
+        }
+        if !storage.properties.is_empty() {
+            module.bind(Resource::Properties);
+        }
+
+        module.stage = Some(ShaderStage::Compute);
+        module.entry(|module| {
+            let mut function = module.function();
+
+            let id = function.invocation(InvocationId::Global)?;
+            let size = expr_output_size(&mut function)?;
+            let texel = function.emit(Expression::Swizzle {
+                size: VectorSize::Bi,
+                vector: id,
+                pattern: [
+                    SwizzleComponent::X,
+                    SwizzleComponent::Y,
+                    SwizzleComponent::X,
+                    SwizzleComponent::X,
+                ],
+            });
+            let outside = function.emit(Expression::Binary {
+                op: BinaryOperator::GreaterEqual,
+                left: texel,
+                right: size,
+            });
+            let condition = function.emit(Expression::Relational {
+                fun: RelationalFunction::Any,
+                argument: outside,
+            });
+            function.statement(Statement::If {
+                condition,
+                accept: Block::from_vec(vec![Statement::Return { value: None }]),
+                reject: Block::new(),
+            });
+
+            if function.is_linked(self.color) {
+                let value = function
+                    .for_input_vector4(self.color)
+                    .map_err(|err| err.at(node))?;
+                let image = function.resource(Resource::StorageTexture);
+                let coordinate = Wrap(texel).sint().emit(&mut function)?;
+                function.statement(Statement::ImageStore {
+                    image,
+                    coordinate,
+                    array_index: None,
+                    value,
+                });
+            }
+            if function.is_linked(self.value) {
+                let value = function
+                    .for_input_vector4(self.value)
+                    .map_err(|err| err.at(node))?;
+                let index = expr_invocation_index(&mut function)?;
+                let buffer = function.resource(Resource::StorageBuffer { writable: true });
+                let pointer = function.emit(Expression::Access {
+                    base: buffer,
+                    index,
+                });
+                function.statement(Statement::Store { pointer, value });
+            }
+            function.statement(Statement::Return { value: None });
+
+            Ok(EntryPoint {
+                name: String::from("cs_main"),
+                stage: ShaderStage::Compute,
+                early_depth_test: None,
+                workgroup_size: self.workgroup_size,
+                function: function.function,
+            })
+        })?;
+        module.stage = None;
+
+        Ok(module)
+    }
+}
+
 pub struct Triangle {
     position: Port,
 }
@@ -100,6 +761,10 @@ impl Triangle {
 }
 
 impl PreviewBuilder for Triangle {
+    fn is_master(&self) -> bool {
+        true
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.position, output);
         expr_triangle(function)
@@ -127,6 +792,10 @@ impl Fullscreen {
 }
 
 impl PreviewBuilder for Fullscreen {
+    fn is_master(&self) -> bool {
+        true
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.position, output);
         expr_fullscreen(function)
@@ -171,3 +840,145 @@ pub fn expr_triangle(function: &mut FnBuilder) -> EmitResult {
 
     [u, v, Float(0.0).emit(function)?, Float(1.0).emit(function)?].emit(function)
 }
+
+#[test]
+fn interpolate_varyings() {
+    let mut storage = Storage::default();
+    let inputs = storage.spawn_kind("FragmentInputs").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    let uv0 = storage.nodes[inputs].outputs[6];
+    storage.link(uv0, storage.nodes[master].inputs[1]);
+
+    // Only the varyings read by `fs_main` are interpolated.
+    let module = storage.nodes[master]
+        .builder
+        .module(master, &storage)
+        .unwrap();
+    assert_eq!(module.varyings, [Varying::Uv0]);
+    assert_eq!(module.attributes, [Attribute::Position, Attribute::Uv0]);
+    let source = module.build().unwrap();
+    assert!(source.contains("@location(0) uv0_: vec2<f32>"));
+    assert!(source.contains("@location(2) vertex_uv0_: vec2<f32>"));
+
+    // Sub-graphs have no entry point to read them from.
+    let mut module = ModuleBuilder::new(&storage, Default::default());
+    assert!(module.function().varying(Varying::Normal).is_err());
+}
+
+#[test]
+fn lit_surface() {
+    let mut storage = Storage::default();
+    let lit = storage.spawn_kind("Lit Master").unwrap();
+    let roughness = storage.nodes[lit].inputs[4];
+
+    // Reading smoothness relabels the port, keeping its links and default.
+    let builder = storage.nodes[lit].builder.downcast_mut::<Lit>().unwrap();
+    builder.settings.smoothness = true;
+    builder.settings.surface.ty = SurfaceType::Transparent;
+    assert!(storage.sync_ports(lit));
+    assert_eq!(storage.nodes[lit].inputs[4], roughness);
+    assert_eq!(storage.ports[roughness].label, "smoothness");
+
+    let surface = storage.nodes[lit].builder.surface().unwrap();
+    assert_eq!(
+        surface.blend_state(),
+        Some(wgpu::BlendState::ALPHA_BLENDING)
+    );
+    assert_eq!(Surface::default().blend_state(), None);
+
+    // Saved states keep the surface type through `ron::Value`.
+    let builder = &mut storage.nodes[lit].builder;
+    let state = builder.save().unwrap();
+    builder.load(state).unwrap();
+    let builder = builder.downcast_ref::<Lit>().unwrap();
+    assert_eq!(builder.settings.surface.ty, SurfaceType::Transparent);
+
+    // Without a linked normal, meshes need no tangents.
+    let module = storage.nodes[lit].builder.module(lit, &storage).unwrap();
+    assert_eq!(module.attributes, [Attribute::Position, Attribute::Normal]);
+    assert!(module.build().unwrap().contains("builtin_lit("));
+}
+
+#[test]
+fn target_bindings() {
+    let mut storage = Storage::default();
+    let post = storage.spawn_kind("Post Process Master").unwrap();
+
+    // The target comes first, the scene depth being bound even though unread.
+    let module = storage.nodes[post].builder.module(post, &storage).unwrap();
+    let resources: Vec<_> = module.resources.iter().map(|(r, _)| r.clone()).collect();
+    assert_eq!(resources, storage.nodes[post].builder.target());
+    assert!(module.attributes.is_empty());
+    // Screen UVs span the render target, whatever the size of the scene color.
+    let source = module.build().unwrap();
+    assert!(source.contains("viewport.size"));
+    assert!(!source.contains("textureDimensions(scene_color"));
+
+    let depth = storage.spawn_kind("Scene Depth").unwrap();
+    storage.link(
+        storage.nodes[depth].outputs[0],
+        storage.nodes[post].inputs[0],
+    );
+    let module = storage.nodes[post].builder.module(post, &storage).unwrap();
+    module.validate().unwrap();
+    let source = module.build().unwrap();
+    assert!(source.contains("textureLoad(scene_depth"));
+    // Loads stay in the texture.
+    assert!(source.contains("clamp("));
+
+    let sprite = storage.spawn_kind("Sprite Master").unwrap();
+    let module = storage.nodes[sprite]
+        .builder
+        .module(sprite, &storage)
+        .unwrap();
+    assert_eq!(module.varyings, [Varying::Uv0, Varying::Color]);
+    assert!(module.build().unwrap().contains("vertex_color"));
+}
+
+#[test]
+fn compute_kernel() {
+    let mut storage = Storage::default();
+    let compute = storage.spawn_kind("Compute Master").unwrap();
+    let buffer = storage.spawn_kind("Storage Buffer").unwrap();
+    let value = storage.nodes[buffer].outputs[0];
+    storage.link(value, storage.nodes[compute].inputs[0]);
+    storage.link(value, storage.nodes[compute].inputs[1]);
+
+    // Copies the input buffer to both outputs, past the texture it writes.
+    let module = storage.nodes[compute]
+        .builder
+        .module(compute, &storage)
+        .unwrap();
+    let resources: Vec<_> = module.resources.iter().map(|(r, _)| r.clone()).collect();
+    assert_eq!(
+        resources,
+        [
+            Resource::StorageTexture,
+            Resource::StorageBuffer { writable: false },
+            Resource::StorageBuffer { writable: true },
+        ]
+    );
+    let source = module.build().unwrap();
+    assert!(source.contains("@compute @workgroup_size(8, 8, 1)"));
+    assert!(source.contains("textureStore(output_texture"));
+
+    // Elements are picked by an unsigned index.
+    let index = storage.nodes[buffer].inputs[0];
+    assert_eq!(storage.ports[index].data, Data::UInt);
+    let uint = storage.spawn_kind("Unsigned Integer").unwrap();
+    storage.link(storage.nodes[uint].outputs[0], index);
+    let module = storage.nodes[compute].builder.module(compute, &storage);
+    let source = module.unwrap().build().unwrap();
+    assert!(source.contains("input_buffer[0u]"));
+
+    // Invocation ids only exist in `cs_main`.
+    let id = storage.spawn_kind("Global Invocation ID").unwrap();
+    let output = storage.nodes[id].outputs[0];
+    assert_eq!(storage.ports[output].data, Data::UInt3);
+    let err = storage.nodes[id]
+        .builder
+        .module(id, &storage)
+        .err()
+        .unwrap();
+    assert!(matches!(err.inner(), EmitError::ComputeOnly(_)));
+}
@@ -1,7 +1,10 @@
+use super::super::asset::{from_state, to_state};
 use super::super::builder::{expr::*, *};
 use super::util::*;
 use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
-use naga::{BinaryOperator, DerivativeAxis, Expression, MathFunction, UnaryOperator};
+use naga::{
+    BinaryOperator, DerivativeAxis, Expression, MathFunction, ScalarKind, TypeInner, UnaryOperator,
+};
 
 #[allow(clippy::type_complexity)]
 fn math_kind<'a>(fun: MathFunction) -> Option<(&'a [(&'a str, Data)], &'a str, Data)> {
@@ -89,28 +92,31 @@ fn math_kind<'a>(fun: MathFunction) -> Option<(&'a [(&'a str, Data)], &'a str, D
             SmoothStep("edge1": FloatOrVector, "edge2": FloatOrVector, "in": FloatOrVector) -> "out": FloatOrVector,
             Sqrt("in": FloatOrVector) -> "out": FloatOrVector,
             InverseSqrt("in": FloatOrVector) -> "out": FloatOrVector,
-            /*
-            Inverse,
-            Transpose,
-            Determinant,
+
+            // matrix
+            // Inverse isn't in WGSL, see `MatrixInverse`.
+            Transpose("in": MatrixAny) -> "out": MatrixAny,
+            Determinant("in": MatrixAny) -> "out": Float,
+
             // bits
-            CountOneBits,
-            ReverseBits,
-            ExtractBits,
-            InsertBits,
+            CountOneBits("in": IntegerAny) -> "out": IntegerAny,
+            ReverseBits("in": IntegerAny) -> "out": IntegerAny,
+            ExtractBits("in": IntegerAny, "offset": UInt, "count": UInt) -> "out": IntegerAny,
+            InsertBits("in": IntegerAny, "bits": IntegerAny, "offset": UInt, "count": UInt) -> "out": IntegerAny,
+
             // data packing
-            Pack4x8snorm,
-            Pack4x8unorm,
-            Pack2x16snorm,
-            Pack2x16unorm,
-            Pack2x16float,
+            Pack4x8snorm("in": Vector4) -> "out": UInt,
+            Pack4x8unorm("in": Vector4) -> "out": UInt,
+            Pack2x16snorm("in": Vector2) -> "out": UInt,
+            Pack2x16unorm("in": Vector2) -> "out": UInt,
+            Pack2x16float("in": Vector2) -> "out": UInt,
+
             // data unpacking
-            Unpack4x8snorm,
-            Unpack4x8unorm,
-            Unpack2x16snorm,
-            Unpack2x16unorm,
-            Unpack2x16float,
-            */
+            Unpack4x8snorm("in": UInt) -> "out": Vector4,
+            Unpack4x8unorm("in": UInt) -> "out": Vector4,
+            Unpack2x16snorm("in": UInt) -> "out": Vector2,
+            Unpack2x16unorm("in": UInt) -> "out": Vector2,
+            Unpack2x16float("in": UInt) -> "out": Vector2,
         }
     ))
 }
@@ -164,6 +170,10 @@ impl PreviewBuilder for Math {
 
         Ok(expr)
     }
+
+    fn show_preview(&self) -> bool {
+        !matches!(self.fun, MathFunction::Transpose)
+    }
 }
 
 pub struct Unary {
@@ -207,12 +217,23 @@ pub struct Binary {
 }
 
 impl Binary {
+    /// Arithmetic takes floats or integers, bitwise operators integers, and
+    /// shifts an unsigned amount.
     pub fn spawn(storage: &mut Storage, op: BinaryOperator) -> Node {
+        let (data, amount) = match op {
+            BinaryOperator::And | BinaryOperator::InclusiveOr | BinaryOperator::ExclusiveOr => {
+                (Data::IntegerAny, Data::IntegerAny)
+            }
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                (Data::IntegerAny, Data::UInt)
+            }
+            _ => (Data::Number, Data::Number),
+        };
         storage.spawn(format!("{:?}", op), 100.0, |ctx, node| Self {
             op,
-            result: ctx.output(node, "out", Fragment, Data::FloatOrVector, None),
-            left: ctx.input(node, "a", Fragment, Data::FloatOrVector, None),
-            right: ctx.input(node, "b", Fragment, Data::FloatOrVector, None),
+            result: ctx.output(node, "out", Fragment, data, None),
+            left: ctx.input(node, "a", Fragment, data, None),
+            right: ctx.input(node, "b", Fragment, amount, None),
         })
     }
 }
@@ -220,12 +241,101 @@ impl Binary {
 impl PreviewBuilder for Binary {
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.result, output);
-        let (_, left, right) = resolve_pair_min(function, self.left, self.right)?;
         let op = self.op;
+        let (left, right) = match op {
+            // The amount is splatted to the width of the shifted value.
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                let left = function.for_input(self.left)?;
+                let kind = VectorKind::parse(function.extract_type(left)?);
+                let kind = kind.ok_or(EmitError::FailType)?;
+                let right = function.for_input_resolve(self.right, VectorKind::V1)?;
+                (left, kind.splat(function, right)?)
+            }
+            _ => {
+                let (_, left, right) = resolve_pair_min(function, self.left, self.right)?;
+                (left, right)
+            }
+        };
         Ok(function.emit(Expression::Binary { left, op, right }))
     }
 }
 
+/// Flips the bits of an integer.
+pub struct BitwiseNot {
+    input: Port,
+    result: Port,
+}
+
+impl BitwiseNot {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Bitwise Not", 100.0, |ctx, node| Self {
+            result: ctx.output(node, "out", Fragment, Data::IntegerAny, None),
+            input: ctx.input(node, "a", Fragment, Data::IntegerAny, None),
+        })
+    }
+}
+
+impl PreviewBuilder for BitwiseNot {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.result, output);
+        let expr = function.for_input(self.input)?;
+        // As a xor with all the bits set: naga writes `!` for the `Not` of integer vectors.
+        let (kind, width) = match *function.extract_type(expr)? {
+            TypeInner::Scalar { kind, .. } => (kind, VectorKind::V1),
+            ref ty @ TypeInner::Vector { kind, .. } => (kind, VectorKind::parse(ty).unwrap()),
+            _ => return Err(EmitError::FailType),
+        };
+        let ones = match kind {
+            ScalarKind::Sint => width.splat(function, Sint(-1))?,
+            ScalarKind::Uint => width.splat(function, Uint(u32::MAX as u64))?,
+            _ => return Err(EmitError::FailType),
+        };
+        (Wrap(expr) ^ Wrap(ones)).emit(function)
+    }
+}
+
+/// Converts the value of each component to another scalar kind, with the
+/// width of the input.
+pub struct Cast {
+    kind: ScalarKind,
+    input: Port,
+    result: Port,
+}
+
+impl Cast {
+    fn spawn_with(storage: &mut Storage, title: &str, kind: ScalarKind, data: Data) -> Node {
+        storage.spawn(title, 100.0, |ctx, node| Self {
+            kind,
+            result: ctx.output(node, "out", Fragment, data, None),
+            input: ctx.input(node, "in", Fragment, Data::Number, None),
+        })
+    }
+
+    pub fn spawn_int(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "To Int", ScalarKind::Sint, Data::IntAny)
+    }
+
+    pub fn spawn_uint(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "To UInt", ScalarKind::Uint, Data::UIntAny)
+    }
+
+    pub fn spawn_float(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "To Float", ScalarKind::Float, Data::FloatOrVector)
+    }
+}
+
+impl PreviewBuilder for Cast {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.result, output);
+        let expr = function.for_input(self.input)?;
+        Ok(function.emit(Expression::As {
+            expr,
+            kind: self.kind,
+            convert: Some(4),
+        }))
+    }
+}
+
 pub struct Derivative {
     axis: DerivativeAxis,
     input: Port,
@@ -251,6 +361,21 @@ impl PreviewBuilder for Derivative {
         });
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&format!("{:?}", self.axis))
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let name: String = from_state(state)?;
+        self.axis = match name.as_str() {
+            "X" => DerivativeAxis::X,
+            "Y" => DerivativeAxis::Y,
+            "Width" => DerivativeAxis::Width,
+            _ => return Err(ron::Error::Message(format!("unknown axis `{}`", name))),
+        };
+        Ok(())
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.result, output);
 
@@ -338,3 +463,144 @@ impl PreviewBuilder for Remap {
         (out_x + (Wrap(input) - in_x) * (out_y - out_x) / (in_y - in_x)).emit(function)
     }
 }
+
+#[test]
+fn shift_amount() {
+    let mut storage = Storage::default();
+    let vector = storage.spawn_kind("Vector4").unwrap();
+    let to_int = storage.spawn_kind("To Int").unwrap();
+    let int = storage.spawn_kind("Integer").unwrap();
+    let uint = storage.spawn_kind("Unsigned Integer").unwrap();
+    let shift = storage.spawn_kind("Shift Left").unwrap();
+    let to_float = storage.spawn_kind("To Float").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    let [value, amount] = [0, 1].map(|index| storage.nodes[shift].inputs[index]);
+    let shifted = storage.nodes[shift].outputs[0];
+
+    storage.link(
+        storage.nodes[vector].outputs[0],
+        storage.nodes[to_int].inputs[0],
+    );
+    storage.link(storage.nodes[to_int].outputs[0], value);
+    storage.link(shifted, storage.nodes[to_float].inputs[0]);
+    storage.link(
+        storage.nodes[to_float].outputs[0],
+        storage.nodes[master].inputs[1],
+    );
+
+    // The amount is a single unsigned integer, whatever the shifted value.
+    let mut graph = super::super::graph::Graph::default();
+    let int = storage.nodes[int].outputs[0];
+    assert!(!storage.should_link_snap(&mut graph, int, amount));
+    storage.link(storage.nodes[uint].outputs[0], amount);
+    let types = storage.infer_types();
+    assert!(types.conflicts.is_empty());
+    assert_eq!(types.types[amount], Data::UInt);
+    assert_eq!(types.types[shifted], Data::Int4);
+
+    let module = storage.nodes[master].builder.module(master, &storage);
+    let source = module.unwrap().build().unwrap();
+    assert!(source.contains("<< vec4<u32>("));
+}
+
+#[test]
+fn bitwise_not_mask() {
+    let mut storage = Storage::default();
+    let not = storage.spawn_kind("Bitwise Not").unwrap();
+    let to_float = storage.spawn_kind("To Float").unwrap();
+    let vector = storage.spawn_kind("Vector4").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    let input = storage.nodes[not].inputs[0];
+    storage.link(
+        storage.nodes[not].outputs[0],
+        storage.nodes[to_float].inputs[0],
+    );
+    storage.link(
+        storage.nodes[to_float].outputs[0],
+        storage.nodes[vector].inputs[0],
+    );
+    storage.link(
+        storage.nodes[vector].outputs[0],
+        storage.nodes[master].inputs[1],
+    );
+
+    // Every bit is set in the mask, whatever the signedness.
+    let mut source = |kind| {
+        let integer = storage.spawn_kind(kind).unwrap();
+        let link = storage.link(storage.nodes[integer].outputs[0], input);
+        let module = storage.nodes[master].builder.module(master, &storage);
+        let source = module.unwrap().build().unwrap();
+        storage.unlink(link);
+        source
+    };
+    let int = source("Integer");
+    let uint = source("Unsigned Integer");
+    assert!(int.contains("^ -1"));
+    assert!(uint.contains("^ 4294967295u"));
+}
+
+#[test]
+fn cast_results() {
+    let mut storage = Storage::default();
+    let vertex_id = storage.spawn_kind("Vertex ID").unwrap();
+    let int = storage.spawn_kind("Integer").unwrap();
+    let multiply = storage.spawn_kind("Multiply").unwrap();
+    let to_int = storage.spawn_kind("To Int").unwrap();
+    let vector = storage.spawn_kind("Vector3").unwrap();
+    let to_uint = storage.spawn_kind("To UInt").unwrap();
+    let to_float = storage.spawn_kind("To Float").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    let [a, b] = [0, 1].map(|index| storage.nodes[multiply].inputs[index]);
+    let product = storage.nodes[multiply].outputs[0];
+    let vertex_id = storage.nodes[vertex_id].outputs[0];
+    let cast = |node: Node| {
+        (
+            storage.nodes[node].inputs[0],
+            storage.nodes[node].outputs[0],
+        )
+    };
+    let (to_int, to_uint, to_float) = (cast(to_int), cast(to_uint), cast(to_float));
+
+    // Integers don't mix with the other signedness, they are cast.
+    storage.link(storage.nodes[int].outputs[0], a);
+    let link = storage.link(vertex_id, b);
+    assert_eq!(storage.infer_types().conflicts, [b]);
+    storage.unlink(link);
+    storage.link(vertex_id, to_int.0);
+    storage.link(to_int.1, b);
+    let types = storage.infer_types();
+    assert!(types.conflicts.is_empty());
+    assert_eq!(types.types[product], Data::Int);
+
+    // Casts keep the width of their input.
+    storage.link(storage.nodes[vector].outputs[0], to_uint.0);
+    storage.link(to_uint.1, to_float.0);
+    storage.link(to_float.1, storage.nodes[master].inputs[0]);
+    let types = storage.infer_types();
+    assert!(types.conflicts.is_empty());
+    assert_eq!(types.types[to_uint.1], Data::UInt3);
+    assert_eq!(types.types[to_float.1], Data::Vector3);
+
+    let float = storage.spawn_kind("To Float").unwrap();
+    let color = storage.spawn_kind("Vector4").unwrap();
+    storage.link(product, storage.nodes[float].inputs[0]);
+    storage.link(
+        storage.nodes[float].outputs[0],
+        storage.nodes[color].inputs[0],
+    );
+    storage.link(
+        storage.nodes[color].outputs[0],
+        storage.nodes[master].inputs[1],
+    );
+
+    let module = storage.nodes[master]
+        .builder
+        .module(master, &storage)
+        .unwrap();
+    module.validate().unwrap();
+    let source = module.build().unwrap();
+    // Validated as flat, which WGSL integers are without an attribute.
+    assert!(source.contains("@location(0) vertex_id: u32"));
+    assert!(source.contains(": vec3<u32> = vec3<u32>(_e"));
+    assert!(source.contains(": vec3<f32> = vec3<f32>(_e"));
+}
@@ -0,0 +1,489 @@
+use super::super::asset::{from_state, to_state};
+use super::super::builder::{expr::*, *};
+use super::util::{combo, state_by_name};
+use crate::workspace::{Data, Fragment, InputDefault, Node, Port, PreviewBuilder, Storage};
+use naga::{Expression, Handle, MathFunction};
+use serde::{Deserialize, Serialize};
+
+const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Whether the vectors of a matrix node are its rows or its columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum MatrixAxis {
+    #[default]
+    Rows,
+    Columns,
+}
+
+const AXES: [(MatrixAxis, &str); 2] =
+    [(MatrixAxis::Rows, "Rows"), (MatrixAxis::Columns, "Columns")];
+
+state_by_name!(MatrixAxis, AXES);
+
+fn transpose(function: &mut FnBuilder, matrix: Handle<Expression>) -> EmitResult {
+    Ok(function.emit(Expression::Math {
+        fun: MathFunction::Transpose,
+        arg: matrix,
+        arg1: None,
+        arg2: None,
+        arg3: None,
+    }))
+}
+
+/// Kind of a matrix input, the identity when unlinked.
+fn input_kind(
+    function: &mut FnBuilder,
+    input: Port,
+) -> EmitResult<(MatrixKind, Handle<Expression>)> {
+    let matrix = function.for_input(input)?;
+    let kind = MatrixKind::parse(function.extract_type(matrix)?).ok_or(EmitError::FailType)?;
+    Ok((kind, matrix))
+}
+
+/// Constant matrix, edited row by row.
+pub struct InputMatrix {
+    kind: MatrixKind,
+    rows: [[f32; 4]; 4],
+    output: Port,
+}
+
+impl InputMatrix {
+    fn spawn_with(storage: &mut Storage, title: &str, kind: MatrixKind) -> Node {
+        let width = 20.0 + 50.0 * kind.size() as f32;
+        storage.spawn(title, width, |ctx, node| Self {
+            kind,
+            rows: IDENTITY,
+            output: ctx.output(node, "out", Fragment, kind.data(), None),
+        })
+    }
+
+    pub fn spawn_2(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Matrix 2x2", MatrixKind::M2)
+    }
+
+    pub fn spawn_3(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Matrix 3x3", MatrixKind::M3)
+    }
+
+    pub fn spawn_4(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Matrix 4x4", MatrixKind::M4)
+    }
+}
+
+impl PreviewBuilder for InputMatrix {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let size = self.kind.size() as usize;
+        egui::Grid::new(ui.id().with("matrix")).show(ui, |ui| {
+            for row in &mut self.rows[..size] {
+                for value in &mut row[..size] {
+                    ui.add(egui::DragValue::new(value).speed(0.01));
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.rows)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.rows = from_state(state)?;
+        Ok(())
+    }
+
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let size = self.kind.size() as usize;
+        let mut columns = Vec::new();
+        for column in 0..size {
+            let components = self.rows[..size]
+                .iter()
+                .map(|row| Float(row[column] as f64).emit(function))
+                .collect::<EmitResult<_>>()?;
+            let ty = self.kind.column_ty(function);
+            columns.push(function.emit(Expression::Compose { ty, components }));
+        }
+        self.kind.compose(function, columns)
+    }
+}
+
+/// Matrices of the [`Resource::Transform`] uniform, and their products.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Transformation {
+    Model,
+    View,
+    Projection,
+    ModelView,
+    ViewProjection,
+    ModelViewProjection,
+}
+
+const TRANSFORMATIONS: [(Transformation, &str); 6] = [
+    (Transformation::Model, "Model"),
+    (Transformation::View, "View"),
+    (Transformation::Projection, "Projection"),
+    (Transformation::ModelView, "Model View"),
+    (Transformation::ViewProjection, "View Projection"),
+    (Transformation::ModelViewProjection, "Model View Projection"),
+];
+
+state_by_name!(Transformation, TRANSFORMATIONS);
+
+/// Transformation matrix of the drawn mesh, the identity in node previews.
+///
+/// Materials read the model matrix of their entity from bevy's mesh uniform.
+pub struct TransformationMatrix {
+    transformation: Transformation,
+    output: Port,
+}
+
+impl TransformationMatrix {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Transformation Matrix", 160.0, |ctx, node| Self {
+            transformation: Transformation::Model,
+            output: ctx.output(node, "out", Fragment, Data::Matrix4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for TransformationMatrix {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            combo(
+                ui,
+                "transformation",
+                &mut self.transformation,
+                &TRANSFORMATIONS,
+            );
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.transformation)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.transformation = from_state(state)?;
+        Ok(())
+    }
+
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let [model, view, projection] = [0, 1, 2];
+        let members: &[u32] = match self.transformation {
+            Transformation::Model => &[model],
+            Transformation::View => &[view],
+            Transformation::Projection => &[projection],
+            Transformation::ModelView => &[view, model],
+            Transformation::ViewProjection => &[projection, view],
+            Transformation::ModelViewProjection => &[projection, view, model],
+        };
+
+        let mut matrix = function.transform(members[0])?;
+        for &member in &members[1..] {
+            let right = function.transform(member)?;
+            matrix = (Wrap(matrix) * Wrap(right)).emit(function)?;
+        }
+        Ok(matrix)
+    }
+}
+
+/// Matrix made of up to four vectors, its size being their width.
+pub struct MatrixConstruction {
+    axis: MatrixAxis,
+    vectors: [Port; 4],
+    output: Port,
+}
+
+impl MatrixConstruction {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Matrix Construction", 140.0, |ctx, node| {
+            let mut input = |index: usize| {
+                let [x, y, z, w] = IDENTITY[index].map(f64::from);
+                let default = InputDefault::vector4(x, y, z, w);
+                let label = format!("m{}", index);
+                ctx.input(node, label, Fragment, Data::VectorAny, Some(default))
+            };
+            Self {
+                axis: MatrixAxis::Rows,
+                vectors: [input(0), input(1), input(2), input(3)],
+                output: ctx.output(node, "out", Fragment, Data::MatrixAny, None),
+            }
+        })
+    }
+}
+
+impl PreviewBuilder for MatrixConstruction {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            combo(ui, "axis", &mut self.axis, &AXES);
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.axis)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.axis = from_state(state)?;
+        Ok(())
+    }
+
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+
+        // The widest linked vector gives the size, unlinked defaults being truncated.
+        let mut width = None;
+        for &input in &self.vectors {
+            if function.is_linked(input) {
+                let vector = function.for_input(input)?;
+                let kind = VectorKind::parse(function.extract_type(vector)?);
+                if let Some(kind) = kind.filter(|&kind| kind != VectorKind::V1) {
+                    width = Some(width.map_or(kind, |width: VectorKind| width.max(kind)));
+                }
+            }
+        }
+        let kind = width
+            .and_then(MatrixKind::from_vector)
+            .unwrap_or(MatrixKind::M4);
+
+        let vectors = self.vectors[..kind.size() as usize]
+            .iter()
+            .map(|&input| function.for_input_resolve(input, kind.vector()))
+            .collect::<EmitResult<_>>()?;
+        let matrix = kind.compose(function, vectors)?;
+        match self.axis {
+            MatrixAxis::Rows => transpose(function, matrix),
+            MatrixAxis::Columns => Ok(matrix),
+        }
+    }
+}
+
+/// Rows or columns of a matrix, the ones past its size being zero.
+pub struct MatrixSplit {
+    axis: MatrixAxis,
+    input: Port,
+    vectors: [Port; 4],
+}
+
+impl MatrixSplit {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Matrix Split", 140.0, |ctx, node| {
+            let input = ctx.input(node, "in", Fragment, Data::MatrixAny, None);
+            let mut output = |index: usize| {
+                let label = format!("m{}", index);
+                ctx.output(node, label, Fragment, Data::VectorAny, None)
+            };
+            Self {
+                axis: MatrixAxis::Rows,
+                input,
+                vectors: [output(0), output(1), output(2), output(3)],
+            }
+        })
+    }
+}
+
+impl PreviewBuilder for MatrixSplit {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            combo(ui, "axis", &mut self.axis, &AXES);
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.axis)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.axis = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let index = self.vectors.iter().position(|&port| port == output);
+        let index = index.ok_or(EmitError::PortNotFound)? as u32;
+
+        let (kind, matrix) = input_kind(function, self.input)?;
+        if index >= kind.size() {
+            return kind.vector().splat(function, Float(0.0));
+        }
+        let matrix = match self.axis {
+            MatrixAxis::Rows => transpose(function, matrix)?,
+            MatrixAxis::Columns => matrix,
+        };
+        Ok(function.access_index(matrix, index))
+    }
+}
+
+/// Inverse of a matrix, through the `builtin_matrix_inverse_*` functions.
+pub struct MatrixInverse {
+    input: Port,
+    output: Port,
+}
+
+impl MatrixInverse {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Inverse", 100.0, |ctx, node| Self {
+            input: ctx.input(node, "in", Fragment, Data::MatrixAny, None),
+            output: ctx.output(node, "out", Fragment, Data::MatrixAny, None),
+        })
+    }
+}
+
+impl PreviewBuilder for MatrixInverse {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let (kind, matrix) = input_kind(function, self.input)?;
+        let name = format!("builtin_matrix_inverse_{}", kind.size());
+        function.call(&name, [matrix])
+    }
+}
+
+/// Product of a matrix with a vector, or with another matrix.
+///
+/// The linked side gives the size, an unlinked `b` being a vector.
+pub struct MatrixMultiply {
+    a: Port,
+    b: Port,
+    output: Port,
+}
+
+impl MatrixMultiply {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Matrix Multiply", 120.0, |ctx, node| {
+            let default = InputDefault::vector4(0.0, 0.0, 0.0, 1.0);
+            Self {
+                a: ctx.input(node, "a", Fragment, Data::MatrixAny, None),
+                b: ctx.input(node, "b", Fragment, Data::VectorOrMatrix, Some(default)),
+                output: ctx.output(node, "out", Fragment, Data::VectorOrMatrix, None),
+            }
+        })
+    }
+}
+
+impl PreviewBuilder for MatrixMultiply {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+
+        let (a_kind, a) = input_kind(function, self.a)?;
+        let b = function.for_input(self.b)?;
+        let ty = function.extract_type(b)?;
+        let (b_matrix, b_vector) = (MatrixKind::parse(ty), VectorKind::parse(ty));
+
+        let kind = match (b_matrix, b_vector) {
+            _ if !function.is_linked(self.b) => a_kind,
+            (Some(kind), _) => kind,
+            (None, Some(kind)) => MatrixKind::from_vector(kind).ok_or(EmitError::FailType)?,
+            (None, None) => return Err(EmitError::FailType),
+        };
+        let a = function.resolve_matrix(a, a_kind, kind)?;
+        let b = match (b_matrix, b_vector) {
+            (Some(b_kind), _) => function.resolve_matrix(b, b_kind, kind)?,
+            (None, Some(b_kind)) => function.resolve_vector(b, b_kind, kind.vector())?,
+            (None, None) => return Err(EmitError::FailType),
+        };
+        (Wrap(a) * Wrap(b)).emit(function)
+    }
+}
+
+#[test]
+fn matrix_product_types() {
+    let mut storage = Storage::default();
+    let vector = storage.spawn_kind("Vector3").unwrap();
+    let construction = storage.spawn_kind("Matrix Construction").unwrap();
+    let inverse = storage.spawn_kind("Inverse").unwrap();
+    let multiply = storage.spawn_kind("Matrix Multiply").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    let vector = storage.nodes[vector].outputs[0];
+    let inverse = (
+        storage.nodes[inverse].inputs[0],
+        storage.nodes[inverse].outputs[0],
+    );
+    let [a, b] = [0, 1].map(|index| storage.nodes[multiply].inputs[index]);
+    let product = storage.nodes[multiply].outputs[0];
+
+    // Rows of vec3 make a mat3, whose inverse times a vec3 is a vec3.
+    storage.link(vector, storage.nodes[construction].inputs[0]);
+    storage.link(storage.nodes[construction].outputs[0], inverse.0);
+    storage.link(inverse.1, a);
+    let link = storage.link(vector, b);
+    storage.link(product, storage.nodes[master].inputs[1]);
+    let types = storage.infer_types();
+    assert!(types.conflicts.is_empty());
+    assert_eq!(types.types[inverse.1], Data::Matrix3);
+    assert_eq!(types.types[product], Data::Vector3);
+
+    let module = storage.nodes[master].builder.module(master, &storage);
+    let source = module.unwrap().build().unwrap();
+    // Naga suffixes names ending with a digit.
+    assert!(source.contains("= builtin_matrix_inverse_3_("));
+
+    // Times another mat3, the product is a mat3.
+    storage.unlink(link);
+    storage.link(inverse.1, b);
+    assert_eq!(storage.infer_types().types[product], Data::Matrix3);
+}
+
+#[test]
+fn model_matrix() {
+    use crate::workspace::preview::PreviewMode;
+
+    let mut storage = Storage::default();
+    let matrix = storage.spawn_kind("Transformation Matrix").unwrap();
+    let multiply = storage.spawn_kind("Matrix Multiply").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+    storage.link(
+        storage.nodes[matrix].outputs[0],
+        storage.nodes[multiply].inputs[0],
+    );
+    storage.link(
+        storage.nodes[multiply].outputs[0],
+        storage.nodes[master].inputs[1],
+    );
+
+    let source = |mode| {
+        let builder = &storage.nodes[master].builder;
+        let module = builder.preview_module(master, &storage, mode).unwrap();
+        module.build().unwrap()
+    };
+    assert!(source(PreviewMode::Flat).contains("var<uniform> transform: Transform;"));
+
+    // Materials read the model matrix of the drawn entity, declared once for
+    // the vertex position too.
+    let material = source(PreviewMode::Material);
+    assert!(!material.contains("var<uniform> transform: Transform;"));
+    assert_eq!(
+        material
+            .matches("var<uniform> graph_mesh: GraphMesh;")
+            .count(),
+        1
+    );
+}
@@ -0,0 +1,227 @@
+use super::super::builder::{expr::*, *};
+use crate::workspace::{Data, Fragment, InputDefault, Node, Port, PreviewBuilder, Storage};
+use naga::{DerivativeAxis, Expression, Handle, MathFunction, ShaderStage};
+
+/// Texture coordinate of a procedural node, the mesh's first UV set unless `uv` is linked.
+fn uv_expr(function: &mut FnBuilder, uv: Port) -> EmitResult {
+    if function.is_linked(uv) {
+        function.for_input_vector2(uv)
+    } else {
+        function.varying(Varying::Uv0)
+    }
+}
+
+/// Inside of `sdf`, anti-aliased over its screen-space derivative.
+/// Derivatives only exist in fragment shaders, elsewhere the edge is hard.
+fn mask_expr(function: &mut FnBuilder, sdf: Handle<Expression>) -> EmitResult {
+    let width = if function.module.stage == Some(ShaderStage::Fragment) {
+        let axis = DerivativeAxis::Width;
+        function.emit(Expression::Derivative { axis, expr: sdf })
+    } else {
+        Float(0.0).emit(function)?
+    };
+    function.call("builtin_sdf_mask", [sdf, width])
+}
+
+/// Shape centered on the UV square, given as a mask and as a signed distance
+/// to combine with other shapes.
+pub struct Shape {
+    builtin: &'static str,
+    uv: Port,
+    params: Vec<Port>,
+    mask: Port,
+    sdf: Port,
+}
+
+impl Shape {
+    fn spawn_with(
+        storage: &mut Storage,
+        title: &'static str,
+        builtin: &'static str,
+        params: &'static [(&'static str, f64)],
+    ) -> Node {
+        storage.spawn(title, 140.0, |ctx, node| Self {
+            builtin,
+            uv: ctx.input(
+                node,
+                "uv",
+                Fragment,
+                Data::Vector2,
+                Some(InputDefault::marker("mesh uv")),
+            ),
+            params: params
+                .iter()
+                .map(|&(label, default)| {
+                    let default = Some(InputDefault::float(default));
+                    ctx.input(node, label, Fragment, Data::Float, default)
+                })
+                .collect(),
+            mask: ctx.output(node, "mask", Fragment, Data::Float, None),
+            sdf: ctx.output(node, "sdf", Fragment, Data::Float, None),
+        })
+    }
+
+    pub fn spawn_ellipse(storage: &mut Storage) -> Node {
+        let params = &[("width", 0.5), ("height", 0.5)];
+        Self::spawn_with(storage, "Ellipse", "builtin_ellipse_sdf", params)
+    }
+
+    pub fn spawn_rectangle(storage: &mut Storage) -> Node {
+        let params = &[("width", 0.5), ("height", 0.5)];
+        Self::spawn_with(storage, "Rectangle", "builtin_rectangle_sdf", params)
+    }
+
+    pub fn spawn_rounded_rectangle(storage: &mut Storage) -> Node {
+        let params = &[("width", 0.5), ("height", 0.5), ("radius", 0.1)];
+        let builtin = "builtin_rounded_rectangle_sdf";
+        Self::spawn_with(storage, "Rounded Rectangle", builtin, params)
+    }
+
+    pub fn spawn_polygon(storage: &mut Storage) -> Node {
+        let params = &[("width", 0.5), ("height", 0.5), ("sides", 6.0)];
+        Self::spawn_with(storage, "Polygon", "builtin_polygon_sdf", params)
+    }
+
+    pub fn spawn_rounded_polygon(storage: &mut Storage) -> Node {
+        let params = &[
+            ("width", 0.5),
+            ("height", 0.5),
+            ("sides", 5.0),
+            ("roundness", 0.3),
+        ];
+        let builtin = "builtin_rounded_polygon_sdf";
+        Self::spawn_with(storage, "Rounded Polygon", builtin, params)
+    }
+}
+
+impl PreviewBuilder for Shape {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let mut args = vec![uv_expr(function, self.uv)?];
+        for &param in &self.params {
+            args.push(function.for_input_float(param)?);
+        }
+        let sdf = function.call(self.builtin, args)?;
+
+        match output {
+            port if port == self.sdf => Ok(sdf),
+            port if port == self.mask => mask_expr(function, sdf),
+            _ => Err(EmitError::PortNotFound),
+        }
+    }
+}
+
+/// Checkerboard of `frequency` cells per UV unit, `color_b` in the cells
+/// where `mask` is set.
+pub struct Checkerboard {
+    uv: Port,
+    color_a: Port,
+    color_b: Port,
+    frequency: Port,
+    output: Port,
+    mask: Port,
+    sdf: Port,
+}
+
+impl Checkerboard {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Checkerboard", 140.0, |ctx, node| Self {
+            uv: ctx.input(
+                node,
+                "uv",
+                Fragment,
+                Data::Vector2,
+                Some(InputDefault::marker("mesh uv")),
+            ),
+            color_a: ctx.input(
+                node,
+                "color_a",
+                Fragment,
+                Data::Vector3,
+                Some(InputDefault::vector3(0.2, 0.2, 0.2)),
+            ),
+            color_b: ctx.input(
+                node,
+                "color_b",
+                Fragment,
+                Data::Vector3,
+                Some(InputDefault::vector3(0.7, 0.7, 0.7)),
+            ),
+            frequency: ctx.input(
+                node,
+                "frequency",
+                Fragment,
+                Data::Vector2,
+                Some(InputDefault::vector2(4.0, 4.0)),
+            ),
+            output: ctx.output(node, "out", Fragment, Data::Vector3, None),
+            mask: ctx.output(node, "mask", Fragment, Data::Float, None),
+            sdf: ctx.output(node, "sdf", Fragment, Data::Float, None),
+        })
+    }
+}
+
+impl PreviewBuilder for Checkerboard {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let uv = uv_expr(function, self.uv)?;
+        let frequency = function.for_input_vector2(self.frequency)?;
+        let sdf = function.call("builtin_checkerboard_sdf", [uv, frequency])?;
+        if output == self.sdf {
+            return Ok(sdf);
+        }
+
+        let mask = mask_expr(function, sdf)?;
+        if output == self.mask {
+            return Ok(mask);
+        }
+        if output != self.output {
+            return Err(EmitError::PortNotFound);
+        }
+        let color_a = function.for_input_vector3(self.color_a)?;
+        let color_b = function.for_input_vector3(self.color_b)?;
+        let mask = VectorKind::V3.splat(function, mask)?;
+        Ok(function.emit(Expression::Math {
+            fun: MathFunction::Mix,
+            arg: color_a,
+            arg1: Some(color_b),
+            arg2: Some(mask),
+            arg3: None,
+        }))
+    }
+}
+
+#[test]
+fn shape_nodes() {
+    let mut storage = Storage::default();
+    let nodes = [
+        "Checkerboard",
+        "Ellipse",
+        "Polygon",
+        "Rectangle",
+        "Rounded Polygon",
+        "Rounded Rectangle",
+    ]
+    .map(|kind| storage.spawn_kind(kind).unwrap());
+
+    // Previews read the mesh UV and blur the mask over its derivative.
+    for node in nodes {
+        let module = storage.nodes[node].builder.module(node, &storage).unwrap();
+        assert_eq!(module.varyings, [Varying::Uv0]);
+        module.validate().unwrap();
+        let source = module.build().unwrap();
+        assert!(source.contains("fwidth("));
+        assert!(source.contains("builtin_sdf_mask("));
+    }
+
+    // Distances combine, here the union of two shapes.
+    let [ellipse, rectangle] = [nodes[1], nodes[3]].map(|node| storage.nodes[node].outputs[1]);
+    let min = storage.spawn_kind("Min").unwrap();
+    let [a, b] = [0, 1].map(|index| storage.nodes[min].inputs[index]);
+    storage.link(ellipse, a);
+    storage.link(rectangle, b);
+    let module = storage.nodes[min].builder.module(min, &storage).unwrap();
+    module.validate().unwrap();
+    let source = module.build().unwrap();
+    assert!(source.contains("builtin_ellipse_sdf("));
+    assert!(source.contains("builtin_rectangle_sdf("));
+    assert!(!source.contains("fwidth("));
+}
@@ -0,0 +1,281 @@
+use super::super::builder::{expr::*, *};
+use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
+use naga::{Expression, Handle, ImageQuery, MathFunction, SampleLevel, ShaderStage};
+
+/// Sampler bound by the masters reading their render target.
+pub const TARGET_SAMPLER: SamplerState = SamplerState {
+    filter: Filter::Linear,
+    wrap: WrapMode::Clamp,
+};
+
+/// Position of the fragment on the render target, from `(0, 0)` at the top
+/// left to `(1, 1)` at the bottom right.
+pub fn expr_screen_uv(function: &mut FnBuilder) -> EmitResult {
+    if function.module.stage != Some(ShaderStage::Fragment) {
+        return Err(EmitError::FragmentOnly("screen uv"));
+    }
+    let input = FunctionArgument(0).emit(function)?;
+    let frag_coord = function.access_index(input, 0);
+    let frag_coord = function.resolve_to(frag_coord, VectorKind::V2)?;
+
+    let viewport = function.resource(Resource::Viewport);
+    let pointer = function.access_index(viewport, 0);
+    let size = function.emit(Expression::Load { pointer });
+    (Wrap(frag_coord) / Wrap(size)).emit(function)
+}
+
+pub fn expr_scene_color(function: &mut FnBuilder, uv: Handle<Expression>) -> EmitResult {
+    let image = function.resource(Resource::SceneColor);
+    let sampler = function.resource(Resource::Sampler(TARGET_SAMPLER));
+    Ok(function.emit(Expression::ImageSample {
+        image,
+        sampler,
+        gather: None,
+        coordinate: uv,
+        array_index: None,
+        offset: None,
+        level: SampleLevel::Auto,
+        depth_ref: None,
+    }))
+}
+
+/// Linear depth of the scene, loaded without filtering from the texel under
+/// `uv`, clamped to the texture since loads out of bounds are undefined.
+pub fn expr_scene_depth(function: &mut FnBuilder, uv: Handle<Expression>) -> EmitResult {
+    let image = function.resource(Resource::SceneDepth);
+    let size = function.emit(Expression::ImageQuery {
+        image,
+        query: ImageQuery::Size { level: None },
+    });
+    let size = Wrap(size).sint().emit(function)?;
+    let texel = (Wrap(uv) * Wrap(size).float()).sint().emit(function)?;
+    let min = [Sint(0), Sint(0)].emit(function)?;
+    let max = (Wrap(size) - [Sint(1), Sint(1)]).emit(function)?;
+    let coordinate = function.emit(Expression::Math {
+        fun: MathFunction::Clamp,
+        arg: texel,
+        arg1: Some(min),
+        arg2: Some(max),
+        arg3: None,
+    });
+    let level = Sint(0).emit(function)?;
+    let texel = function.emit(Expression::ImageLoad {
+        image,
+        coordinate,
+        array_index: None,
+        sample: None,
+        level: Some(level),
+    });
+    AccessIndex(texel, 0).emit(function)
+}
+
+pub fn expr_main_texture(function: &mut FnBuilder, uv: Handle<Expression>) -> EmitResult {
+    let image = function.resource(Resource::MainTexture);
+    let sampler = function.resource(Resource::Sampler(TARGET_SAMPLER));
+    Ok(function.emit(Expression::ImageSample {
+        image,
+        sampler,
+        gather: None,
+        coordinate: uv,
+        array_index: None,
+        offset: None,
+        level: SampleLevel::Auto,
+        depth_ref: None,
+    }))
+}
+
+pub fn expr_tint(function: &mut FnBuilder) -> EmitResult {
+    let tint = function.resource(Resource::Tint);
+    let pointer = function.access_index(tint, 0);
+    Ok(function.emit(Expression::Load { pointer }))
+}
+
+/// Position of the fragment on screen, for the post-processing master.
+pub struct ScreenUv {
+    output: Port,
+}
+
+impl ScreenUv {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Screen UV", 100.0, |ctx, node| Self {
+            output: ctx.output(node, "uv", Fragment, Data::Vector2, None),
+        })
+    }
+}
+
+impl PreviewBuilder for ScreenUv {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        expr_screen_uv(function)
+    }
+}
+
+/// Color of the rendered scene, read at the fragment unless `uv` is linked.
+pub struct SceneColor {
+    uv: Port,
+    output: Port,
+}
+
+impl SceneColor {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Scene Color", 100.0, |ctx, node| Self {
+            uv: ctx.input(node, "uv", Fragment, Data::Vector2, None),
+            output: ctx.output(node, "color", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for SceneColor {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let uv = if function.is_linked(self.uv) {
+            function.for_input_vector2(self.uv)?
+        } else {
+            expr_screen_uv(function)?
+        };
+        expr_scene_color(function, uv)
+    }
+}
+
+/// Linear depth of the rendered scene, read at the fragment unless `uv` is linked.
+pub struct SceneDepth {
+    uv: Port,
+    output: Port,
+}
+
+impl SceneDepth {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Scene Depth", 100.0, |ctx, node| Self {
+            uv: ctx.input(node, "uv", Fragment, Data::Vector2, None),
+            output: ctx.output(node, "depth", Fragment, Data::Float, None),
+        })
+    }
+}
+
+impl PreviewBuilder for SceneDepth {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        let uv = if function.is_linked(self.uv) {
+            function.for_input_vector2(self.uv)?
+        } else {
+            expr_screen_uv(function)?
+        };
+        expr_scene_depth(function, uv)
+    }
+}
+
+/// Texture of the sprite or widget drawn by its master.
+pub struct MainTexture {
+    output: Port,
+}
+
+impl MainTexture {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Main Texture", 100.0, |ctx, node| Self {
+            output: ctx.output(
+                node,
+                "texture",
+                Fragment,
+                Data::Image(TextureDimension::CLASS),
+                None,
+            ),
+        })
+    }
+}
+
+impl PreviewBuilder for MainTexture {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        Ok(function.resource(Resource::MainTexture))
+    }
+}
+
+/// Color of the vertices, white for meshes without one.
+pub struct VertexColor {
+    output: Port,
+}
+
+impl VertexColor {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Vertex Color", 100.0, |ctx, node| Self {
+            output: ctx.output(node, "color", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for VertexColor {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        function.varying(Varying::Color)
+    }
+}
+
+/// Index of the vertex or of the instance being drawn.
+pub struct DrawIndex {
+    varying: Varying,
+    output: Port,
+}
+
+impl DrawIndex {
+    fn spawn_with(storage: &mut Storage, title: &str, varying: Varying) -> Node {
+        storage.spawn(title, 100.0, |ctx, node| Self {
+            varying,
+            output: ctx.output(node, "id", Fragment, Data::UInt, None),
+        })
+    }
+
+    pub fn spawn_vertex(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Vertex ID", Varying::VertexId)
+    }
+
+    pub fn spawn_instance(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Instance ID", Varying::InstanceId)
+    }
+}
+
+impl PreviewBuilder for DrawIndex {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        function.varying(self.varying)
+    }
+}
+
+/// Color multiplying the sprite being drawn.
+pub struct SpriteTint {
+    output: Port,
+}
+
+impl SpriteTint {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Sprite Tint", 100.0, |ctx, node| Self {
+            output: ctx.output(node, "tint", Fragment, Data::Vector4, None),
+        })
+    }
+}
+
+impl PreviewBuilder for SpriteTint {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        expr_tint(function)
+    }
+}
@@ -0,0 +1,450 @@
+use super::super::asset::{from_state, to_state};
+use super::super::builder::{expr::*, *};
+use super::util::fnv1a;
+use crate::workspace::{
+    Data, Fragment, Node, Port, PortSpec, PreviewBuilder, ShaderGraph, ShaderGraphError, Storage,
+};
+use naga::{Expression, Function, Statement};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Types the inputs and outputs of a sub-graph can have.
+pub const PORT_TYPES: [Data; 4] = [Data::Float, Data::Vector2, Data::Vector3, Data::Vector4];
+
+pub fn type_name(data: Data) -> &'static str {
+    match data {
+        Data::Float => "Float",
+        Data::Vector2 => "Vector2",
+        Data::Vector3 => "Vector3",
+        _ => "Vector4",
+    }
+}
+
+pub fn parse_type(name: &str) -> Result<Data, ron::Error> {
+    PORT_TYPES
+        .into_iter()
+        .find(|&data| type_name(data) == name)
+        .ok_or_else(|| ron::Error::Message(format!("unknown port type `{}`", name)))
+}
+
+fn vector_kind(data: Data) -> VectorKind {
+    match data {
+        Data::Float => VectorKind::V1,
+        Data::Vector2 => VectorKind::V2,
+        Data::Vector3 => VectorKind::V3,
+        _ => VectorKind::V4,
+    }
+}
+
+fn naga_type(module: &mut ModuleBuilder, data: Data) -> naga::Handle<naga::Type> {
+    let ty = BaseTypes::new(module);
+    match data {
+        Data::Float => ty.f32,
+        Data::Vector2 => ty.f32x2,
+        Data::Vector3 => ty.f32x3,
+        _ => ty.f32x4,
+    }
+}
+
+/// Picks the closest type a sub-graph port can have.
+pub fn port_type(data: Data) -> Option<Data> {
+    match data {
+        Data::Float | Data::Vector2 | Data::Vector3 | Data::Vector4 => Some(data),
+        Data::FloatOrVector | Data::Number => Some(Data::Float),
+        Data::VectorAny => Some(Data::Vector4),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PortState {
+    pub name: String,
+    pub data: String,
+}
+
+impl PortState {
+    pub fn new(spec: &PortSpec) -> Self {
+        Self {
+            name: spec.label.clone(),
+            data: type_name(spec.data).to_string(),
+        }
+    }
+}
+
+fn port_ui(ui: &mut egui::Ui, name: &mut String, data: &mut Data) {
+    ui.vertical_centered_justified(|ui| {
+        ui.text_edit_singleline(name);
+        egui::ComboBox::from_id_source(ui.id().with("type"))
+            .selected_text(type_name(*data))
+            .width(ui.available_width())
+            .show_ui(ui, |ui| {
+                for ty in PORT_TYPES {
+                    ui.selectable_value(data, ty, type_name(ty));
+                }
+            });
+    });
+}
+
+/// A named input of a sub-graph.
+///
+/// Reads the matching argument of the sub-graph function, or its `default`
+/// input when the graph is previewed on its own.
+pub struct SubGraphInput {
+    name: String,
+    data: Data,
+}
+
+impl SubGraphInput {
+    pub const KIND: &'static str = "Sub Graph Input";
+
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn(Self::KIND, 120.0, |ctx, node| {
+            let data = Data::Vector4;
+            ctx.input(node, "default", Fragment, data, None);
+            ctx.output(node, "in", Fragment, data, None);
+            Self {
+                name: String::from("in"),
+                data,
+            }
+        })
+    }
+
+    pub fn spec(&self) -> PortSpec {
+        PortSpec::new(&self.name, self.data)
+    }
+}
+
+impl PreviewBuilder for SubGraphInput {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        port_ui(ui, &mut self.name, &mut self.data);
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&PortState::new(&self.spec()))
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let PortState { name, data } = from_state(state)?;
+        self.data = parse_type(&data)?;
+        self.name = name;
+        Ok(())
+    }
+
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        let default = PortSpec::new("default", self.data);
+        Some((vec![default], vec![self.spec()]))
+    }
+
+    fn output_expr(&self, node: Node, function: &mut FnBuilder, _: Port) -> EmitResult {
+        if let Some(&index) = function.parameters.get(&node) {
+            return FunctionArgument(index).emit(function);
+        }
+
+        let &default = function.module.storage.nodes[node]
+            .inputs
+            .first()
+            .ok_or(EmitError::PortNotFound)?;
+        function.for_input_resolve(default, vector_kind(self.data))
+    }
+}
+
+/// A named output of a sub-graph, previewing the value it returns.
+pub struct SubGraphOutput {
+    name: String,
+    data: Data,
+}
+
+impl SubGraphOutput {
+    pub const KIND: &'static str = "Sub Graph Output";
+
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn(Self::KIND, 120.0, |ctx, node| {
+            let data = Data::Vector4;
+            ctx.input(node, "out", Fragment, data, None);
+            Self {
+                name: String::from("out"),
+                data,
+            }
+        })
+    }
+
+    pub fn spec(&self) -> PortSpec {
+        PortSpec::new(&self.name, self.data)
+    }
+}
+
+impl PreviewBuilder for SubGraphOutput {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        port_ui(ui, &mut self.name, &mut self.data);
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&PortState::new(&self.spec()))
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let PortState { name, data } = from_state(state)?;
+        self.data = parse_type(&data)?;
+        self.name = name;
+        Ok(())
+    }
+
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        Some((vec![self.spec()], Vec::new()))
+    }
+
+    fn output_expr(&self, _: Node, _: &mut FnBuilder, _: Port) -> EmitResult {
+        Err(EmitError::PortNotFound)
+    }
+
+    fn fragment(&self, node: Node, function: &mut FnBuilder) -> EmitResult {
+        let &input = function.module.storage.nodes[node]
+            .inputs
+            .first()
+            .ok_or(EmitError::PortNotFound)?;
+        function.for_input_resolve(input, VectorKind::V4)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SubGraphState {
+    path: PathBuf,
+    graph: ShaderGraph,
+}
+
+/// A sub-graph used as a single node.
+///
+/// Keeps a copy of the sub-graph asset, so graphs still compile without the
+/// editor, and compiles it to a function shared by every use in a module.
+pub struct SubGraph {
+    path: PathBuf,
+    graph: ShaderGraph,
+    storage: Storage,
+    inputs: Vec<(Node, PortSpec)>,
+    outputs: Vec<(Node, PortSpec)>,
+}
+
+impl SubGraph {
+    pub const KIND: &'static str = "Sub Graph";
+
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn(Self::KIND, 150.0, |_, _| Self {
+            path: PathBuf::new(),
+            graph: ShaderGraph::default(),
+            storage: Storage::default(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        })
+    }
+
+    /// Whether `graph` has outputs, and can be used as a sub-graph.
+    pub fn is_sub_graph(graph: &ShaderGraph) -> bool {
+        graph
+            .nodes
+            .iter()
+            .any(|node| node.kind == SubGraphOutput::KIND)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn graph(&self) -> &ShaderGraph {
+        &self.graph
+    }
+
+    /// Replaces the sub-graph, the ports follow with [`Storage::sync_ports`].
+    pub fn set(&mut self, path: PathBuf, graph: ShaderGraph) -> Result<(), ShaderGraphError> {
+        let storage = graph.to_storage()?;
+
+        // `to_storage` spawns nodes in file order, which is also the order of the ports.
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for (key, node) in &storage.nodes {
+            if let Some(input) = node.builder.downcast_ref::<SubGraphInput>() {
+                inputs.push((key, input.spec()));
+            } else if let Some(output) = node.builder.downcast_ref::<SubGraphOutput>() {
+                outputs.push((key, output.spec()));
+            }
+        }
+
+        self.path = path;
+        self.graph = graph;
+        self.storage = storage;
+        self.inputs = inputs;
+        self.outputs = outputs;
+        Ok(())
+    }
+
+    /// Readable from the file name, unique from a hash of the whole path since
+    /// sub-graphs in different folders may share a name.
+    fn function_name(&self) -> String {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let stem: String = stem
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        // Components are joined by `/` for the name not to depend on the platform.
+        let components: Vec<_> = self
+            .path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        let hash = fnv1a(components.join("/").into_bytes());
+        format!("sub_graph_{}_{:08x}", stem, hash as u32)
+    }
+
+    fn build_function(&self, module: &mut ModuleBuilder, name: &str) -> EmitResult<Function> {
+        if self.outputs.is_empty() {
+            return Err(EmitError::PortNotFound);
+        }
+
+        let mut function = module.function();
+        function.function.name = Some(name.to_string());
+
+        for (index, (node, spec)) in self.inputs.iter().enumerate() {
+            let ty = naga_type(function.module, spec.data);
+            function.argument(&spec.label, ty, None);
+            function.parameters.insert(*node, index as u32);
+        }
+
+        let mut components = Vec::with_capacity(self.outputs.len());
+        for (node, spec) in &self.outputs {
+            let &input = function.module.storage.nodes[*node]
+                .inputs
+                .first()
+                .ok_or(EmitError::PortNotFound)?;
+            components.push(function.for_input_resolve(input, vector_kind(spec.data))?);
+        }
+
+        let value = if let [value] = components[..] {
+            let ty = naga_type(function.module, self.outputs[0].1.data);
+            function.set_result(ty, None);
+            value
+        } else {
+            let types: Vec<_> = self
+                .outputs
+                .iter()
+                .map(|(_, spec)| naga_type(function.module, spec.data))
+                .collect();
+
+            let module = &mut function.module.module;
+            let mut result = StructBuilder::new(module, format!("{}_Output", name));
+            for (index, ((_, spec), ty)) in self.outputs.iter().zip(types).enumerate() {
+                let member = if result.has(&spec.label) {
+                    format!("{}_{}", spec.label, index)
+                } else {
+                    spec.label.clone()
+                };
+                result = result.append(member, ty, None);
+            }
+            let ty = result.build();
+
+            function.set_result(ty, None);
+            function.emit(Expression::Compose { ty, components })
+        };
+
+        function.statement(Statement::Return { value: Some(value) });
+
+        // Keeps the names apart from the ones of the module the function ends up in.
+        let mut function = function.function;
+        for expr_name in function.named_expressions.values_mut() {
+            *expr_name = format!("{}{}", name, expr_name);
+        }
+        Ok(function)
+    }
+
+    fn append_function(
+        &self,
+        node: Node,
+        module: &mut ModuleBuilder,
+        name: &str,
+    ) -> EmitResult<()> {
+        let mut inner = ModuleBuilder::new(&self.storage, std::mem::take(&mut module.module));
+        inner.resources = std::mem::take(&mut module.resources);
+        // Properties belong to the graph using the sub-graph.
+        inner.properties = std::mem::take(&mut module.properties);
+        let function = self.build_function(&mut inner, name);
+        module.module = inner.module;
+        module.resources = inner.resources;
+        module.properties = inner.properties;
+
+        // Locations point into the storage of the sub-graph, which the editor doesn't show.
+        let function = function.map_err(|err| err.into_inner().at(node))?;
+        module.append_foreign_function(node, function);
+        Ok(())
+    }
+}
+
+impl PreviewBuilder for SubGraph {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            let path = self.path.display().to_string();
+            ui.label(egui::RichText::new(path).small().weak());
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&SubGraphState {
+            path: self.path.clone(),
+            graph: self.graph.clone(),
+        })
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let SubGraphState { path, graph } = from_state(state)?;
+        self.set(path, graph)
+            .map_err(|err| ron::Error::Message(err.to_string()))
+    }
+
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        let specs = |ports: &[(Node, PortSpec)]| ports.iter().map(|(_, s)| s.clone()).collect();
+        Some((specs(&self.inputs), specs(&self.outputs)))
+    }
+
+    fn output_expr(&self, node: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let data = &function.module.storage.nodes[node];
+        let index = data
+            .outputs
+            .iter()
+            .position(|&port| port == output)
+            .ok_or(EmitError::PortNotFound)?;
+        let inputs = data.inputs.clone();
+
+        let name = self.function_name();
+        if function.function_by_name(&name).is_none() {
+            self.append_function(node, function.module, &name)?;
+        }
+
+        let mut arguments = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let data = function.module.storage.ports[input].data;
+            arguments.push(function.for_input_resolve(input, vector_kind(data))?);
+        }
+
+        let result = function.call(&name, arguments)?;
+        if self.outputs.len() == 1 {
+            Ok(result)
+        } else {
+            Ok(function.access_index(result, index as u32))
+        }
+    }
+}
+
+#[test]
+fn unique_function_names() {
+    let name = |path: &str| {
+        let sub_graph = SubGraph {
+            path: PathBuf::from(path),
+            graph: ShaderGraph::default(),
+            storage: Storage::default(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+        sub_graph.function_name()
+    };
+    let [a, b] = ["water/noise.shader", "terrain/noise.shader"].map(name);
+    assert_ne!(a, b);
+    // The same with every toolchain, exported shaders keeping their names.
+    assert_eq!(a, "sub_graph_noise_dfbf8f78");
+}
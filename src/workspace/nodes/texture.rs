@@ -0,0 +1,424 @@
+use super::super::asset::{from_state, to_state};
+use super::super::builder::{expr::*, *};
+use super::util::{combo, state_by_name};
+use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
+use naga::{Expression, ImageQuery, SampleLevel};
+use serde::{Deserialize, Serialize};
+
+fn image_data(dimension: TextureDimension) -> Data {
+    match dimension {
+        TextureDimension::D2 => Data::Image(TextureDimension::CLASS),
+        TextureDimension::D2Array(_) => Data::ImageArray(TextureDimension::CLASS),
+        TextureDimension::Cube => Data::ImageCube(TextureDimension::CLASS),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextureAssetState {
+    path: String,
+    dimension: TextureDimension,
+}
+
+/// An image of the asset folder, bound to the shader as a texture.
+pub struct TextureAsset {
+    output: Port,
+    path: String,
+    dimension: TextureDimension,
+}
+
+impl TextureAsset {
+    fn spawn_with(storage: &mut Storage, title: &str, dimension: TextureDimension) -> Node {
+        storage.spawn(title, 150.0, |ctx, node| Self {
+            output: ctx.output(node, "texture", Fragment, image_data(dimension), None),
+            path: String::new(),
+            dimension,
+        })
+    }
+
+    pub fn spawn_2d(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Texture 2D Asset", TextureDimension::D2)
+    }
+
+    pub fn spawn_2d_array(storage: &mut Storage) -> Node {
+        Self::spawn_with(
+            storage,
+            "Texture 2D Array Asset",
+            TextureDimension::D2Array(1),
+        )
+    }
+
+    pub fn spawn_cube(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Cubemap Asset", TextureDimension::Cube)
+    }
+}
+
+impl PreviewBuilder for TextureAsset {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            let path = egui::TextEdit::singleline(&mut self.path).hint_text("image.png");
+            ui.add(path).on_hover_text("Path in the asset folder");
+            if let TextureDimension::D2Array(layers) = &mut self.dimension {
+                let layers = egui::DragValue::new(layers).clamp_range(1..=256);
+                ui.add(layers.prefix("layers "))
+                    .on_hover_text("Layers stacked vertically in the image");
+            }
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&TextureAssetState {
+            path: self.path.clone(),
+            dimension: self.dimension,
+        })
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let TextureAssetState { path, dimension } = from_state(state)?;
+        if std::mem::discriminant(&dimension) != std::mem::discriminant(&self.dimension) {
+            return Err(ron::Error::Message(String::from("wrong texture dimension")));
+        }
+        self.path = path;
+        self.dimension = dimension;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        Ok(function.resource(Resource::Texture {
+            path: self.path.clone(),
+            dimension: self.dimension,
+        }))
+    }
+}
+
+const FILTERS: [(Filter, &str); 2] = [(Filter::Linear, "Linear"), (Filter::Point, "Point")];
+
+const WRAP_MODES: [(WrapMode, &str); 3] = [
+    (WrapMode::Repeat, "Repeat"),
+    (WrapMode::Clamp, "Clamp"),
+    (WrapMode::Mirror, "Mirror"),
+];
+
+state_by_name!(Filter, FILTERS);
+state_by_name!(WrapMode, WRAP_MODES);
+
+/// Filtering and wrapping of the textures sampled with it.
+pub struct SamplerStateNode {
+    output: Port,
+    state: SamplerState,
+}
+
+impl SamplerStateNode {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Sampler State", 120.0, |ctx, node| Self {
+            output: ctx.output(node, "sampler", Fragment, Data::Sampler, None),
+            state: SamplerState::default(),
+        })
+    }
+}
+
+impl PreviewBuilder for SamplerStateNode {
+    fn show_preview(&self) -> bool {
+        false
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.vertical_centered_justified(|ui| {
+            combo(ui, "filter", &mut self.state.filter, &FILTERS);
+            combo(ui, "wrap", &mut self.state.wrap, &WRAP_MODES);
+        });
+    }
+
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&self.state)
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        self.state = from_state(state)?;
+        Ok(())
+    }
+
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        assert_eq!(self.output, output);
+        Ok(function.resource(Resource::Sampler(self.state)))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Sample {
+    Texture2D,
+    Texture2DLod,
+    Texture2DArray,
+    Cubemap,
+}
+
+/// Samples a texture, with a linear repeating sampler when none is linked.
+pub struct SampleTexture {
+    sample: Sample,
+
+    texture: Port,
+    coordinate: Port,
+    /// Level of detail or array layer.
+    extra: Option<Port>,
+    sampler: Port,
+
+    rgba: Port,
+    r: Port,
+    g: Port,
+    b: Port,
+    a: Port,
+}
+
+impl SampleTexture {
+    fn spawn_with(storage: &mut Storage, title: &str, sample: Sample) -> Node {
+        storage.spawn(title, 150.0, |ctx, node| {
+            let (texture, coordinate) = match sample {
+                Sample::Texture2D | Sample::Texture2DLod => {
+                    (Data::Image(TextureDimension::CLASS), ("uv", Data::Vector2))
+                }
+                Sample::Texture2DArray => (
+                    Data::ImageArray(TextureDimension::CLASS),
+                    ("uv", Data::Vector2),
+                ),
+                Sample::Cubemap => (
+                    Data::ImageCube(TextureDimension::CLASS),
+                    ("direction", Data::Vector3),
+                ),
+            };
+
+            let texture = ctx.input(node, "texture", Fragment, texture, None);
+            let coordinate = ctx.input(node, coordinate.0, Fragment, coordinate.1, None);
+            let extra = match sample {
+                Sample::Texture2DLod => Some(ctx.input(node, "lod", Fragment, Data::Float, None)),
+                Sample::Texture2DArray => {
+                    Some(ctx.input(node, "index", Fragment, Data::Float, None))
+                }
+                _ => None,
+            };
+            let sampler = ctx.input(node, "sampler", Fragment, Data::Sampler, None);
+
+            Self {
+                sample,
+
+                texture,
+                coordinate,
+                extra,
+                sampler,
+
+                rgba: ctx.output(node, "rgba", Fragment, Data::Vector4, None),
+                r: ctx.output(node, "r", Fragment, Data::Float, None),
+                g: ctx.output(node, "g", Fragment, Data::Float, None),
+                b: ctx.output(node, "b", Fragment, Data::Float, None),
+                a: ctx.output(node, "a", Fragment, Data::Float, None),
+            }
+        })
+    }
+
+    pub fn spawn_2d(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Sample Texture 2D", Sample::Texture2D)
+    }
+
+    pub fn spawn_2d_lod(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Sample Texture 2D LOD", Sample::Texture2DLod)
+    }
+
+    pub fn spawn_2d_array(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Sample Texture 2D Array", Sample::Texture2DArray)
+    }
+
+    pub fn spawn_cube(storage: &mut Storage) -> Node {
+        Self::spawn_with(storage, "Sample Cubemap", Sample::Cubemap)
+    }
+
+    fn sample(&self, function: &mut FnBuilder) -> EmitResult {
+        let image = function.for_input(self.texture)?;
+        let sampler = if function.module.storage.ports[self.sampler].links.is_empty() {
+            function.resource(Resource::Sampler(SamplerState::default()))
+        } else {
+            function.for_input(self.sampler)?
+        };
+
+        let coordinate = match self.sample {
+            Sample::Cubemap => function.for_input_vector3(self.coordinate)?,
+            _ => function.for_input_vector2(self.coordinate)?,
+        };
+
+        let mut level = SampleLevel::Auto;
+        let mut array_index = None;
+        if let Some(extra) = self.extra {
+            let extra = function.for_input_float(extra)?;
+            match self.sample {
+                Sample::Texture2DLod => level = SampleLevel::Exact(extra),
+                _ => array_index = Some(extra.sint().emit(function)?),
+            }
+        }
+
+        Ok(function.emit(Expression::ImageSample {
+            image,
+            sampler,
+            gather: None,
+            coordinate,
+            array_index,
+            offset: None,
+            level,
+            depth_ref: None,
+        }))
+    }
+}
+
+impl PreviewBuilder for SampleTexture {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let rgba = self.sample(function)?;
+        match output {
+            port if port == self.rgba => Ok(rgba),
+            port if port == self.r => AccessIndex(rgba, 0).emit(function),
+            port if port == self.g => AccessIndex(rgba, 1).emit(function),
+            port if port == self.b => AccessIndex(rgba, 2).emit(function),
+            port if port == self.a => AccessIndex(rgba, 3).emit(function),
+            _ => Err(EmitError::PortNotFound),
+        }
+    }
+}
+
+/// Size of a 2D texture in pixels.
+pub struct TexelSize {
+    texture: Port,
+    width: Port,
+    height: Port,
+}
+
+impl TexelSize {
+    pub fn spawn(storage: &mut Storage) -> Node {
+        storage.spawn("Texel Size", 120.0, |ctx, node| Self {
+            texture: ctx.input(
+                node,
+                "texture",
+                Fragment,
+                Data::Image(TextureDimension::CLASS),
+                None,
+            ),
+            width: ctx.output(node, "width", Fragment, Data::Float, None),
+            height: ctx.output(node, "height", Fragment, Data::Float, None),
+        })
+    }
+}
+
+impl PreviewBuilder for TexelSize {
+    fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
+        let image = function.for_input(self.texture)?;
+        let size = function.emit(Expression::ImageQuery {
+            image,
+            query: ImageQuery::Size { level: None },
+        });
+        let size = size.float().emit(function)?;
+        match output {
+            port if port == self.width => AccessIndex(size, 0).emit(function),
+            port if port == self.height => AccessIndex(size, 1).emit(function),
+            _ => Err(EmitError::PortNotFound),
+        }
+    }
+}
+
+#[test]
+fn bind_textures_and_samplers() {
+    let mut storage = Storage::default();
+    let master = storage.spawn_kind("Master").unwrap();
+    let texture = storage.spawn_kind("Texture 2D Asset").unwrap();
+    let cube = storage.spawn_kind("Cubemap Asset").unwrap();
+    let sample = storage.spawn_kind("Sample Texture 2D").unwrap();
+    let sample_cube = storage.spawn_kind("Sample Cubemap").unwrap();
+    let size = storage.spawn_kind("Texel Size").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+
+    let out = storage.nodes[texture].outputs[0];
+    storage.link(out, storage.nodes[sample].inputs[0]);
+    storage.link(out, storage.nodes[size].inputs[0]);
+    let out = storage.nodes[cube].outputs[0];
+    storage.link(out, storage.nodes[sample_cube].inputs[0]);
+    // A cubemap doesn't fit a 2D texture input.
+    assert!(!storage.ports[out]
+        .data
+        .can_connect(storage.ports[storage.nodes[sample].inputs[0]].data));
+
+    storage.link(
+        storage.nodes[size].outputs[0],
+        storage.nodes[sample].inputs[1],
+    );
+    storage.link(
+        storage.nodes[sample].outputs[0],
+        storage.nodes[add].inputs[0],
+    );
+    storage.link(
+        storage.nodes[sample_cube].outputs[0],
+        storage.nodes[add].inputs[1],
+    );
+    storage.link(
+        storage.nodes[add].outputs[0],
+        storage.nodes[master].inputs[1],
+    );
+
+    let module = storage.nodes[master]
+        .builder
+        .module(master, &storage)
+        .unwrap();
+    module.build().unwrap();
+
+    // Both unlinked samplers share the default one.
+    let resources: Vec<_> = module.resources.iter().map(|(r, _)| r.clone()).collect();
+    assert_eq!(
+        resources,
+        [
+            Resource::Texture {
+                path: String::new(),
+                dimension: TextureDimension::D2,
+            },
+            Resource::Sampler(SamplerState::default()),
+            Resource::Texture {
+                path: String::new(),
+                dimension: TextureDimension::Cube,
+            },
+        ]
+    );
+}
+
+#[test]
+fn save_texture_settings() {
+    use crate::workspace::ShaderGraph;
+
+    let mut storage = Storage::default();
+    let array = storage.spawn_kind("Texture 2D Array Asset").unwrap();
+    let sampler = storage.spawn_kind("Sampler State").unwrap();
+    let asset = storage.nodes[array]
+        .builder
+        .downcast_mut::<TextureAsset>()
+        .unwrap();
+    asset.path = String::from("terrain.png");
+    asset.dimension = TextureDimension::D2Array(4);
+    let state = SamplerState {
+        filter: Filter::Point,
+        wrap: WrapMode::Mirror,
+    };
+    let node = storage.nodes[sampler].builder.as_mut();
+    node.downcast_mut::<SamplerStateNode>().unwrap().state = state;
+
+    let text = ShaderGraph::from_storage(&storage).to_ron().unwrap();
+    assert!(text.contains("\"D2Array(4)\""));
+    let loaded = ShaderGraph::from_ron(text.as_bytes()).unwrap();
+    let loaded = loaded.to_storage().unwrap();
+
+    let mut nodes = loaded.nodes.values();
+    let asset = nodes.next().unwrap().builder.downcast_ref::<TextureAsset>();
+    let asset = asset.unwrap();
+    assert_eq!(asset.path, "terrain.png");
+    assert_eq!(asset.dimension, TextureDimension::D2Array(4));
+    let node = nodes
+        .next()
+        .unwrap()
+        .builder
+        .downcast_ref::<SamplerStateNode>();
+    assert_eq!(node.unwrap().state, state);
+}
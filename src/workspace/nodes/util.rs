@@ -1,7 +1,10 @@
 use super::super::builder::{expr::*, *};
 use crate::workspace::Port;
-use naga::Expression;
+use naga::{Expression, Handle, ScalarKind, TypeInner};
 
+/// Resolves both inputs to the type inferred for them: a float or an unlinked
+/// input takes the width of the other side, and vectors of different widths,
+/// only linked in graphs saved before inference, are truncated to the narrower.
 pub fn resolve_pair_min(
     function: &mut FnBuilder,
     a: Port,
@@ -11,9 +14,19 @@ pub fn resolve_pair_min(
     naga::Handle<Expression>,
     naga::Handle<Expression>,
 )> {
-    resolve_pair(function, a, b, VectorKind::min)
+    let linked = (function.is_linked(a), function.is_linked(b));
+    resolve_pair(function, a, b, |a, b| match linked {
+        (true, false) => a,
+        (false, true) => b,
+        _ if a == VectorKind::V1 => b,
+        _ if b == VectorKind::V1 => a,
+        _ => a.min(b),
+    })
 }
 
+/// Resolves both inputs to the kind picked by `merge`, scalars being splatted.
+/// An unlinked input also takes the scalar kind of the other side, its
+/// default being typed without regard to links.
 pub fn resolve_pair(
     function: &mut FnBuilder,
     a: Port,
@@ -24,16 +37,103 @@ pub fn resolve_pair(
     naga::Handle<Expression>,
     naga::Handle<Expression>,
 )> {
+    let linked = (function.is_linked(a), function.is_linked(b));
     let a = function.for_input(a)?;
     let b = function.for_input(b)?;
 
+    let (a, b) = match (scalar_kind(function, a)?, scalar_kind(function, b)?) {
+        (a_kind, b_kind) if a_kind == b_kind => (a, b),
+        (_, b_kind) if linked == (false, true) => (cast(function, a, b_kind), b),
+        (a_kind, _) => (a, cast(function, b, a_kind)),
+    };
+
     let a_src = VectorKind::parse(function.extract_type(a)?).unwrap();
     let b_src = VectorKind::parse(function.extract_type(b)?).unwrap();
 
     let dst = merge(a_src, b_src);
 
-    let a = function.resolve_vector(a, a_src, dst).unwrap();
-    let b = function.resolve_vector(b, b_src, dst).unwrap();
+    let mut resolve = |expr, src| match src {
+        VectorKind::V1 => dst.splat(function, expr),
+        _ => Ok(function.resolve_vector(expr, src, dst).unwrap()),
+    };
+    let a = resolve(a, a_src)?;
+    let b = resolve(b, b_src)?;
 
     Ok((dst, a, b))
 }
+
+fn scalar_kind(function: &mut FnBuilder, expr: Handle<Expression>) -> EmitResult<ScalarKind> {
+    match *function.extract_type(expr)? {
+        TypeInner::Scalar { kind, .. } | TypeInner::Vector { kind, .. } => Ok(kind),
+        _ => Err(EmitError::FailType),
+    }
+}
+
+fn cast(
+    function: &mut FnBuilder,
+    expr: Handle<Expression>,
+    kind: ScalarKind,
+) -> Handle<Expression> {
+    function.emit(Expression::As {
+        expr,
+        kind,
+        convert: Some(4),
+    })
+}
+
+/// 64-bit FNV-1a hash, fixed unlike the hashers of `std`, for names in
+/// generated shaders to be the same on every build.
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Serializes a field-less enum as its name in `items`, the `(value, name)`
+/// table of its [`combo`]: node states go through `ron::Value`, which keeps
+/// no variant names. The enum takes `#[serde(into = "String", try_from = "String")]`.
+macro_rules! state_by_name {
+    ($ty:ty, $items:expr) => {
+        impl From<$ty> for String {
+            fn from(value: $ty) -> Self {
+                let mut items = $items.iter();
+                let name = items
+                    .find(|(item, _)| *item == value)
+                    .map(|(_, name)| *name);
+                name.unwrap_or_default().to_string()
+            }
+        }
+
+        impl TryFrom<String> for $ty {
+            type Error = String;
+
+            fn try_from(name: String) -> Result<Self, Self::Error> {
+                let mut items = $items.iter();
+                let item = items.find(|(_, item)| *item == name).map(|(item, _)| *item);
+                item.ok_or_else(|| format!("unknown {} `{}`", stringify!($ty), name))
+            }
+        }
+    };
+}
+pub(crate) use state_by_name;
+
+/// Combo box picking one of `items`, shown by name.
+pub fn combo<T: Copy + PartialEq>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut T,
+    items: &[(T, &str)],
+) {
+    let selected = items
+        .iter()
+        .find(|(item, _)| item == value)
+        .map(|(_, name)| *name);
+    egui::ComboBox::from_id_source(ui.id().with(label))
+        .selected_text(selected.unwrap_or_default())
+        .width(ui.available_width())
+        .show_ui(ui, |ui| {
+            for &(item, name) in items {
+                ui.selectable_value(value, item, name);
+            }
+        });
+}
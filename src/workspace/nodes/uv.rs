@@ -1,6 +1,8 @@
+use super::super::asset::{from_state, to_state};
 use super::super::builder::{expr::*, *};
 use crate::workspace::{Data, Fragment, Node, Port, PreviewBuilder, Storage};
 use naga::{Expression, Handle, MathFunction};
+use serde::{Deserialize, Serialize};
 
 pub struct Flipbook {
     output: Port,
@@ -14,6 +16,12 @@ pub struct Flipbook {
     flip_y: bool,
 }
 
+#[derive(Serialize, Deserialize)]
+struct FlipbookState {
+    flip_x: bool,
+    flip_y: bool,
+}
+
 impl Flipbook {
     pub fn spawn(storage: &mut Storage) -> Node {
         storage.spawn("Flipbook", 100.0, |ctx, node| Self {
@@ -36,6 +44,20 @@ impl PreviewBuilder for Flipbook {
         ui.checkbox(&mut self.flip_y, "invert y");
     }
 
+    fn save(&self) -> Option<ron::Value> {
+        to_state(&FlipbookState {
+            flip_x: self.flip_x,
+            flip_y: self.flip_y,
+        })
+    }
+
+    fn load(&mut self, state: ron::Value) -> Result<(), ron::Error> {
+        let FlipbookState { flip_x, flip_y } = from_state(state)?;
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        Ok(())
+    }
+
     fn output_expr(&self, _: Node, function: &mut FnBuilder, output: Port) -> EmitResult {
         assert_eq!(self.output, output);
 
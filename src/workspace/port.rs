@@ -26,7 +26,26 @@ pub enum Data {
     FloatOrVector,
     FloatOrVectorOrMatrix,
 
+    Int,
+    Int2,
+    Int3,
+    Int4,
+    IntAny,
+
+    UInt,
+    UInt2,
+    UInt3,
+    UInt4,
+    UIntAny,
+
+    /// Signed or unsigned integer, scalar or vector.
+    IntegerAny,
+    /// Float or integer, scalar or vector.
+    Number,
+
     Image(naga::ImageClass),
+    ImageArray(naga::ImageClass),
+    ImageCube(naga::ImageClass),
     VirtualTexture,
     Gradient,
     Sampler,
@@ -37,29 +56,165 @@ impl Data {
         use Data::*;
         match (self, other) {
             (Image(a), Image(b)) => a == b,
+            (ImageArray(a), ImageArray(b)) => a == b,
+            (ImageCube(a), ImageCube(b)) => a == b,
             (VirtualTexture, VirtualTexture) => true,
             (Gradient, Gradient) => true,
             (Sampler, Sampler) => true,
             (Boolean, Boolean)
             | (
-                Float | FloatOrVector | FloatOrVectorOrMatrix,
-                Float | FloatOrVector | FloatOrVectorOrMatrix,
+                Float | FloatOrVector | FloatOrVectorOrMatrix | Number,
+                Float | FloatOrVector | FloatOrVectorOrMatrix | Number,
+            )
+            | (
+                Vector2
+                | Vector3
+                | Vector4
+                | VectorAny
+                | VectorOrMatrix
+                | FloatOrVector
+                | FloatOrVectorOrMatrix
+                | Number,
+                Vector2
+                | Vector3
+                | Vector4
+                | VectorAny
+                | VectorOrMatrix
+                | FloatOrVector
+                | FloatOrVectorOrMatrix
+                | Number,
             )
             | (
-                Vector2 | Vector3 | Vector4 | VectorAny | FloatOrVector | FloatOrVectorOrMatrix,
-                Vector2 | Vector3 | Vector4 | VectorAny | FloatOrVector | FloatOrVectorOrMatrix,
+                Matrix2 | Matrix3 | Matrix4 | MatrixAny | VectorOrMatrix | FloatOrVectorOrMatrix,
+                Matrix2 | Matrix3 | Matrix4 | MatrixAny | VectorOrMatrix | FloatOrVectorOrMatrix,
             )
+            | (Int | IntAny | IntegerAny | Number, Int | IntAny | IntegerAny | Number)
             | (
-                Matrix2 | Matrix3 | Matrix4 | MatrixAny | FloatOrVectorOrMatrix,
-                Matrix2 | Matrix3 | Matrix4 | MatrixAny | FloatOrVectorOrMatrix,
+                Int2 | Int3 | Int4 | IntAny | IntegerAny | Number,
+                Int2 | Int3 | Int4 | IntAny | IntegerAny | Number,
+            )
+            | (UInt | UIntAny | IntegerAny | Number, UInt | UIntAny | IntegerAny | Number)
+            | (
+                UInt2 | UInt3 | UInt4 | UIntAny | IntegerAny | Number,
+                UInt2 | UInt3 | UInt4 | UIntAny | IntegerAny | Number,
             ) => true,
 
             _ => false,
         }
     }
+
+    /// Whether the port takes the type of what is linked to its node, see
+    /// [`Storage::infer_types`](super::Storage::infer_types).
+    pub fn is_dynamic(self) -> bool {
+        use Data::*;
+        matches!(
+            self,
+            VectorAny
+                | MatrixAny
+                | VectorOrMatrix
+                | FloatOrVector
+                | FloatOrVectorOrMatrix
+                | IntAny
+                | UIntAny
+                | IntegerAny
+                | Number
+        )
+    }
+
+    /// Type shared by the dynamic ports of a node linked to both `self` and
+    /// `other`, scalars being splatted to the width of vectors of their kind.
+    ///
+    /// A matrix and a vector of its width unify to the vector, the matrix
+    /// ports of the node taking the matching size, see [`Self::specialize`].
+    pub fn unify(self, other: Self) -> Option<Self> {
+        use Data::*;
+        match (self, other) {
+            _ if self == other => Some(self),
+            (Float, Vector2 | Vector3 | Vector4) => Some(other),
+            (Vector2 | Vector3 | Vector4, Float) => Some(self),
+            (Int, Int2 | Int3 | Int4) | (UInt, UInt2 | UInt3 | UInt4) => Some(other),
+            (Int2 | Int3 | Int4, Int) | (UInt2 | UInt3 | UInt4, UInt) => Some(self),
+            (Matrix2, Vector2) | (Matrix3, Vector3) | (Matrix4, Vector4) => Some(other),
+            (Vector2, Matrix2) | (Vector3, Matrix3) | (Vector4, Matrix4) => Some(self),
+            _ => None,
+        }
+    }
+
+    /// Type taken by a dynamic port of a node whose ports unified to `data`:
+    /// matrices and vectors of the same width stand for each other, as do
+    /// floats and integers of the same width, as in casts.
+    pub fn specialize(self, data: Self) -> Option<Self> {
+        use Data::*;
+        match (self, data) {
+            _ if self.can_connect(data) => Some(data),
+            (FloatOrVector | IntAny | UIntAny, _) => {
+                let width = data.width()?;
+                let family: [Self; 4] = match self {
+                    IntAny => [Int, Int2, Int3, Int4],
+                    UIntAny => [UInt, UInt2, UInt3, UInt4],
+                    _ => [Float, Vector2, Vector3, Vector4],
+                };
+                Some(family[width - 1])
+            }
+            (VectorAny, Matrix2) => Some(Vector2),
+            (VectorAny, Matrix3) => Some(Vector3),
+            (VectorAny, Matrix4) => Some(Vector4),
+            (MatrixAny, Vector2) => Some(Matrix2),
+            (MatrixAny, Vector3) => Some(Matrix3),
+            (MatrixAny, Vector4) => Some(Matrix4),
+            _ => None,
+        }
+    }
+
+    /// Number of components of a scalar or vector.
+    fn width(self) -> Option<usize> {
+        use Data::*;
+        match self {
+            Float | Int | UInt => Some(1),
+            Vector2 | Int2 | UInt2 => Some(2),
+            Vector3 | Int3 | UInt3 => Some(3),
+            Vector4 | Int4 | UInt4 => Some(4),
+            _ => None,
+        }
+    }
+
+    /// Short name shown next to the ports holding values.
+    pub fn label(self) -> Option<&'static str> {
+        use Data::*;
+        Some(match self {
+            Boolean => "bool",
+            Float => "float",
+            Vector2 => "vec2",
+            Vector3 => "vec3",
+            Vector4 => "vec4",
+            VectorAny => "vecN",
+            Matrix2 => "mat2",
+            Matrix3 => "mat3",
+            Matrix4 => "mat4",
+            MatrixAny => "matN",
+            VectorOrMatrix => "vecN|matN",
+            FloatOrVector => "float|vecN",
+            FloatOrVectorOrMatrix => "any",
+            Int => "int",
+            Int2 => "ivec2",
+            Int3 => "ivec3",
+            Int4 => "ivec4",
+            IntAny => "int|ivecN",
+            UInt => "uint",
+            UInt2 => "uvec2",
+            UInt3 => "uvec3",
+            UInt4 => "uvec4",
+            UIntAny => "uint|uvecN",
+            IntegerAny => "integer",
+            Number => "number",
+            Image(_) | ImageArray(_) | ImageCube(_) | VirtualTexture | Gradient | Sampler => {
+                return None
+            }
+        })
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Direction {
     Input,
     Output,
@@ -112,6 +267,22 @@ impl Stage {
     }
 }
 
+/// Label and type of a port that depends on the settings of its node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PortSpec {
+    pub label: String,
+    pub data: Data,
+}
+
+impl PortSpec {
+    pub fn new(label: impl Into<String>, data: Data) -> Self {
+        Self {
+            label: label.into(),
+            data,
+        }
+    }
+}
+
 pub struct PortData {
     pub label: String,
     pub direction: Direction,
@@ -124,6 +295,8 @@ pub struct PortData {
     pub links: AHashSet<Link>,
 
     pub input_default: Option<InputDefault>,
+    /// Concrete type of a dynamic port, from the last [`Storage::update_port_types`](super::Storage::update_port_types).
+    pub inferred: Option<Data>,
 }
 
 impl PortData {
@@ -140,6 +313,7 @@ impl PortData {
             links: AHashSet::default(),
 
             input_default: None,
+            inferred: None,
         }
     }
 
@@ -156,6 +330,7 @@ impl PortData {
             links: AHashSet::default(),
 
             input_default: None,
+            inferred: None,
         }
     }
 
@@ -199,6 +374,16 @@ impl PortData {
             desired_size.y = desired_size.y.at_least(ui.spacing().interact_size.y);
 
             desired_size.x += icon_width + icon_spacing;
+
+            let data = self.inferred.unwrap_or(self.data);
+            let type_label = data.label().map(|label| {
+                let font_id = FontId::monospace(9.0);
+                let color = ui.visuals().weak_text_color();
+                ui.fonts().layout_no_wrap(label.into(), font_id, color)
+            });
+            if let Some(type_label) = &type_label {
+                desired_size.x += type_label.size().x + icon_spacing;
+            }
             desired_size.y = desired_size.y.max(icon_width + total_extra.y);
 
             let (rect, response) = ui.allocate_at_least(desired_size, sense);
@@ -218,14 +403,26 @@ impl PortData {
                 let pad = button_padding.x + icon_width + icon_spacing;
                 let x = if output { max.x - pad } else { min.x + pad };
                 let text_pos = pos2(x, rect.center().y - 0.5 * text.size().y);
+                let width = text.size().x + icon_spacing;
                 text.paint_with_visuals(ui.painter(), text_pos, visuals);
 
+                if let Some(type_label) = type_label {
+                    // Inputs read `label type`, outputs `type label`.
+                    let x = if output {
+                        x - width - type_label.size().x
+                    } else {
+                        x + width
+                    };
+                    let y = rect.center().y - 0.5 * type_label.size().y;
+                    ui.painter().galley(pos2(x, y), type_label);
+                }
+
                 let pad = icon_width * 0.5;
                 let x = if output { max.x - pad } else { min.x + pad };
                 let center = pos2(x, rect.center().y);
 
                 let linked = linked || response.hovered() || response.dragged() || ext_hover;
-                let fill_color = self.data.color();
+                let fill_color = data.color();
                 self.stage.draw(ui.painter(), center, fill_color, linked);
 
                 self.position = center;
@@ -243,6 +440,8 @@ impl PortData {
 pub enum InputDefaultType {
     Marker(String),
     Bool,
+    Int,
+    UInt,
     Float,
     Vector2,
     Vector3,
@@ -283,6 +482,14 @@ impl InputDefault {
         Self::new(InputDefaultType::Bool, 0.0, 0.0, 0.0, 1.0)
     }
 
+    pub const fn int(x: i64) -> Self {
+        Self::new(InputDefaultType::Int, x as f64, 0.0, 0.0, 1.0)
+    }
+
+    pub const fn uint(x: u64) -> Self {
+        Self::new(InputDefaultType::UInt, x as f64, 0.0, 0.0, 1.0)
+    }
+
     pub const fn float(x: f64) -> Self {
         Self::new(InputDefaultType::Float, x, 0.0, 0.0, 1.0)
     }
@@ -305,9 +512,18 @@ impl InputDefault {
             ui.add(widget.speed(0.01).prefix(label))
         }
 
+        fn drag_integer(ui: &mut egui::Ui, value: &mut f64, min: f64, max: f64) -> egui::Response {
+            let widget = egui::DragValue::new(value).clamp_range(min..=max);
+            ui.add(widget.speed(0.1).fixed_decimals(0).prefix("x "))
+        }
+
         match self.kind {
             InputDefaultType::Marker(ref marker) => ui.label(marker),
             InputDefaultType::Bool => ui.checkbox(&mut self.checked, ""),
+            InputDefaultType::Int => {
+                drag_integer(ui, &mut self.x, i32::MIN as f64, i32::MAX as f64)
+            }
+            InputDefaultType::UInt => drag_integer(ui, &mut self.x, 0.0, u32::MAX as f64),
             InputDefaultType::Float => drag_value(ui, "x ", &mut self.x),
             InputDefaultType::Vector2 => {
                 let x = drag_value(ui, "y ", &mut self.y);
@@ -331,12 +547,30 @@ impl InputDefault {
     }
 }
 
+impl InputDefault {
+    /// Type of the value used while the port isn't linked.
+    pub fn data(&self) -> Option<Data> {
+        Some(match self.kind {
+            InputDefaultType::Marker(_) => return None,
+            InputDefaultType::Bool => Data::Boolean,
+            InputDefaultType::Int => Data::Int,
+            InputDefaultType::UInt => Data::UInt,
+            InputDefaultType::Float => Data::Float,
+            InputDefaultType::Vector2 => Data::Vector2,
+            InputDefaultType::Vector3 => Data::Vector3,
+            InputDefaultType::Vector4 => Data::Vector4,
+        })
+    }
+}
+
 impl super::builder::expr::Emit for InputDefault {
     fn emit(&self, function: &mut super::builder::FnBuilder) -> super::builder::EmitResult {
-        use super::builder::expr::{Bool, Float};
+        use super::builder::expr::{Bool, Float, Sint, Uint};
         let [x, y, z, w] = [Float(self.x), Float(self.y), Float(self.z), Float(self.w)];
         match self.kind {
             InputDefaultType::Bool => Bool(self.checked).emit(function),
+            InputDefaultType::Int => Sint(self.x.round() as i64).emit(function),
+            InputDefaultType::UInt => Uint(self.x.round() as u64).emit(function),
             InputDefaultType::Float => x.emit(function),
             InputDefaultType::Vector2 => [x, y].emit(function),
             InputDefaultType::Vector3 => [x, y, z].emit(function),
@@ -1,24 +1,55 @@
+use super::blackboard::uniform_bytes;
 use super::builder::expr::*;
 use super::builder::FnBuilder;
 use super::builder::*;
-use super::nodes::master::expr_fullscreen;
-use super::{Node, Port, Storage};
-use crate::app::RenderPass;
+use super::material::expr_mesh_to_clip;
+use super::nodes::master::{expr_fullscreen, Surface};
+use super::{Diagnostic, Node, Port, PortSpec, Storage, Workspace};
+use crate::ui::shell::EguiContext;
+use ahash::AHashMap;
+use bevy::asset::{HandleId, LoadState};
+use bevy::prelude::*;
+use bevy::render::extract_resource::ExtractResource;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{self, NodeRunError, RenderGraphContext};
+use bevy::render::render_resource::{BindGroupLayout, ComputePipeline, RenderPipeline};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::window::WindowId;
 use naga::{Binding, BuiltIn, EntryPoint, ShaderStage, Statement};
 
+pub const PREVIEW_PASS: &str = "node_previews";
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// How a preview draws its node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreviewMode {
+    /// Fullscreen triangle, in the thumbnail of the node.
+    Flat,
+    /// Mesh seen from the orbit camera, with a depth buffer and lighting.
+    Mesh,
+    /// Scene entity drawn by bevy as a [`GraphMaterial`], the resources of
+    /// the graph being at `@group(1)`.
+    ///
+    /// [`GraphMaterial`]: super::GraphMaterial
+    Material,
+}
+
 pub struct Preview {
-    pub texture_view: wgpu::TextureView,
+    pub image: Handle<Image>,
     pub texture_id: egui::TextureId,
     pub format: wgpu::TextureFormat,
     pub source: String,
     pub size: egui::Vec2,
     pub scale: f32,
+    /// Last redraw, queued again with new bindings when only property values change.
+    pub job: Option<PreviewJob>,
 }
 
 impl Preview {
     pub fn new(
-        device: &wgpu::Device,
-        rpass: &mut wgpu::RenderPass,
+        images: &mut Assets<Image>,
+        context: &mut EguiContext,
         format: wgpu::TextureFormat,
         width: u32,
         height: u32,
@@ -30,54 +61,273 @@ impl Preview {
             depth_or_array_layers: 1,
         };
 
-        let desc = wgpu::TextureDescriptor {
-            label: None,
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        let mut image = Image {
+            texture_descriptor: wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_DST,
+            },
+            ..default()
         };
 
-        let texture = device.create_texture(&desc);
+        // fill image.data with zeroes
+        image.resize(size);
 
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let texture_filter = wgpu::FilterMode::Nearest;
+        let image = images.add(image);
 
         Self {
-            texture_id: rpass.egui_texture_from_wgpu_texture(device, &texture_view, texture_filter),
-            texture_view,
+            texture_id: context.add_image(image.clone_weak()),
+            image,
             format,
             source: String::new(),
             size: egui::vec2(width as f32, height as f32),
             scale,
+            job: None,
         }
     }
 
-    pub fn pass<'a>(
-        &'a self,
-        encoder: &'a mut wgpu::CommandEncoder,
-        clear: impl Into<Option<wgpu::Color>>,
-    ) -> wgpu::RenderPass<'a> {
-        let clear = clear.into();
-        let load = clear.map(wgpu::LoadOp::Clear).unwrap_or(wgpu::LoadOp::Load);
+    /// Unregisters the image from egui and drops the render target.
+    pub fn free(self, images: &mut Assets<Image>, context: &mut EguiContext) {
+        context.remove_image(&self.image);
+        images.remove(&self.image);
+    }
+}
 
-        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &self.texture_view,
-                resolve_target: None,
-                ops: wgpu::Operations { load, store: true },
-            })],
-            depth_stencil_attachment: None,
+#[derive(Clone)]
+pub enum PreviewShader {
+    Render(RenderPipeline),
+    /// Dispatched over the preview image with this workgroup size.
+    Compute(ComputePipeline, [u32; 3]),
+}
+
+/// Shader of a preview, with the layout of the resources it binds.
+pub struct PreviewPipeline {
+    pub source: String,
+    pub pipeline: PreviewShader,
+    pub layout: BindGroupLayout,
+    pub resources: Vec<Resource>,
+    /// Vertex attributes read by `vs_main`, one vertex buffer each.
+    pub attributes: Vec<Attribute>,
+}
+
+/// Resource bound to a preview, in binding order.
+#[derive(Clone)]
+pub enum PreviewBinding {
+    Texture(Handle<Image>, wgpu::TextureViewDimension),
+    Sampler(wgpu::SamplerDescriptor<'static>),
+    /// Contents of a uniform buffer, written again every redraw.
+    Buffer(Resource, Vec<u8>),
+    /// Initial contents of a storage buffer.
+    Storage(Vec<u8>),
+    /// The preview image itself, written by compute masters.
+    StorageTexture,
+}
+
+/// A single preview redraw, handed over to the render world.
+#[derive(Clone)]
+pub struct PreviewJob {
+    pub image: Handle<Image>,
+    pub pipeline: Option<(PreviewShader, BindGroupLayout)>,
+    pub bindings: Vec<PreviewBinding>,
+    /// Contents of the vertex buffers, in the order of the pipeline.
+    pub vertex_buffers: Vec<Vec<u8>>,
+    /// `u32` indices, `vertices` then being a range of indices.
+    pub indices: Option<Vec<u8>>,
+    pub vertices: std::ops::Range<u32>,
+    /// Draws with a depth buffer, for [`PreviewMode::Mesh`].
+    pub depth: bool,
+}
+
+impl PreviewJob {
+    /// Whether the preview reads the clock and has to be redrawn every frame.
+    pub fn is_animated(&self) -> bool {
+        self.bindings
+            .iter()
+            .any(|binding| matches!(binding, PreviewBinding::Buffer(Resource::Time, _)))
+    }
+
+    pub fn write_buffers(&mut self, properties: &[u8], time: &[u8]) {
+        self.write_buffer(&Resource::Properties, properties);
+        self.write_buffer(&Resource::Time, time);
+    }
+
+    pub fn write_buffer(&mut self, resource: &Resource, contents: &[u8]) {
+        for binding in &mut self.bindings {
+            if let PreviewBinding::Buffer(bound, bytes) = binding {
+                if bound == resource {
+                    bytes.clear();
+                    bytes.extend_from_slice(contents);
+                }
+            }
+        }
+    }
+}
+
+/// Clock read by the `Time` node, stopped while the animation is paused.
+pub struct PreviewTime {
+    pub playing: bool,
+    /// Seconds of animation so far.
+    pub elapsed: f32,
+    pub delta: f32,
+}
+
+impl Default for PreviewTime {
+    fn default() -> Self {
+        Self {
+            playing: true,
+            elapsed: 0.0,
+            delta: 0.0,
+        }
+    }
+}
+
+impl PreviewTime {
+    pub fn advance(&mut self, delta: f32) {
+        self.delta = if self.playing { delta } else { 0.0 };
+        self.elapsed += self.delta;
+    }
+
+    /// Contents of the buffer bound by [`Resource::Time`], in [`TIME_MEMBERS`] order.
+    pub fn uniform_bytes(&self) -> Vec<u8> {
+        let (sin, cos) = self.elapsed.sin_cos();
+        [self.elapsed, sin, cos, self.delta]
+            .into_iter()
+            .flat_map(f32::to_le_bytes)
+            .collect()
+    }
+}
+
+/// Stand-ins for what the render target of a master provides, in previews.
+pub struct PreviewScene {
+    /// Sky above a checkered floor.
+    pub color: Handle<Image>,
+    pub depth: Handle<Image>,
+    /// White disc with soft edges.
+    pub main_texture: Handle<Image>,
+}
+
+impl FromWorld for PreviewScene {
+    fn from_world(world: &mut World) -> Self {
+        const SIZE: u32 = 64;
+        let horizon = SIZE / 2;
+
+        let mut color = Vec::new();
+        let mut depth = Vec::new();
+        let mut main_texture = Vec::new();
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (rgb, z) = if y < horizon {
+                    let t = y as f32 / horizon as f32;
+                    let rgb = [0.35 + 0.45 * t, 0.55 + 0.35 * t, 0.9 + 0.1 * t];
+                    (rgb, 1.0)
+                } else {
+                    let checker = ((x / 8) + (y / 8)) % 2 == 0;
+                    let grey = if checker { 0.6 } else { 0.3 };
+                    (
+                        [grey; 3],
+                        1.0 - 0.9 * (y - horizon) as f32 / (SIZE - horizon) as f32,
+                    )
+                };
+                color.extend(rgb.map(|c| (c * 255.0) as u8));
+                color.push(255);
+                depth.extend(f32::to_le_bytes(z));
+
+                let center = SIZE as f32 / 2.0;
+                let distance = (x as f32 + 0.5 - center).hypot(y as f32 + 0.5 - center);
+                let alpha = (center - distance).clamp(0.0, 4.0) / 4.0;
+                main_texture.extend([255, 255, 255, (alpha * 255.0) as u8]);
+            }
+        }
+
+        let size = wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        };
+        let image = |data, format| Image::new(size, wgpu::TextureDimension::D2, data, format);
+
+        let mut images = world.resource_mut::<Assets<Image>>();
+        Self {
+            color: images.add(image(color, wgpu::TextureFormat::Rgba8UnormSrgb)),
+            depth: images.add(image(depth, wgpu::TextureFormat::R32Float)),
+            main_texture: images.add(image(main_texture, wgpu::TextureFormat::Rgba8UnormSrgb)),
+        }
+    }
+}
+
+impl PreviewScene {
+    /// Binds the stand-in of a resource provided by the render target.
+    pub fn binding(&self, resource: &Resource) -> Option<PreviewBinding> {
+        let texture = |handle: &Handle<Image>| {
+            PreviewBinding::Texture(handle.clone_weak(), wgpu::TextureViewDimension::D2)
+        };
+        Some(match resource {
+            Resource::SceneColor => texture(&self.color),
+            Resource::SceneDepth => texture(&self.depth),
+            Resource::MainTexture => texture(&self.main_texture),
+            Resource::Tint => PreviewBinding::Buffer(Resource::Tint, tint_bytes(Vec4::ONE)),
+            // The quad of node previews is already in clip space.
+            Resource::Transform => PreviewBinding::Buffer(
+                Resource::Transform,
+                transform_bytes(Mat4::IDENTITY, Mat4::IDENTITY, Mat4::IDENTITY),
+            ),
+            _ => return None,
         })
     }
 }
 
+/// Contents of the buffer bound by [`Resource::StorageBuffer`], one element
+/// per texel of a `width` by `height` image holding its uv.
+pub fn storage_bytes(width: u32, height: u32) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            let u = (x as f32 + 0.5) / width as f32;
+            let v = (y as f32 + 0.5) / height as f32;
+            [u, v, 0.0, 1.0]
+        })
+        .flat_map(f32::to_le_bytes)
+        .collect()
+}
+
+/// Contents of the buffer bound by [`Resource::Viewport`], for a render target
+/// of `width` by `height` pixels.
+pub fn viewport_bytes(width: u32, height: u32) -> Vec<u8> {
+    [width as f32, height as f32, 0.0, 0.0]
+        .into_iter()
+        .flat_map(f32::to_le_bytes)
+        .collect()
+}
+
+/// Contents of the buffer bound by [`Resource::Tint`].
+pub fn tint_bytes(tint: Vec4) -> Vec<u8> {
+    bytemuck::bytes_of(&tint.to_array()).to_vec()
+}
+
+/// Contents of the buffer bound by [`Resource::Transform`], in [`TRANSFORM_MEMBERS`] order.
+pub fn transform_bytes(model: Mat4, view: Mat4, projection: Mat4) -> Vec<u8> {
+    [model, view, projection]
+        .iter()
+        .flat_map(|matrix| bytemuck::bytes_of(&matrix.to_cols_array()).to_vec())
+        .collect()
+}
+
+/// Previews that have to be redrawn during the current frame.
+#[derive(Clone, Default, ExtractResource)]
+pub struct PreviewQueue {
+    pub jobs: Vec<PreviewJob>,
+}
+
 downcast_rs::impl_downcast!(PreviewBuilder);
 
-pub trait PreviewBuilder: downcast_rs::Downcast {
+pub trait PreviewBuilder: downcast_rs::Downcast + Send + Sync {
     fn format(&self) -> wgpu::TextureFormat {
         wgpu::TextureFormat::Rgba8Unorm
     }
@@ -88,6 +338,22 @@ pub trait PreviewBuilder: downcast_rs::Downcast {
 
     fn ui(&mut self, _: &mut egui::Ui) {}
 
+    /// Settings edited in [`PreviewBuilder::ui`], stored alongside the node in `.shader` files.
+    fn save(&self) -> Option<ron::Value> {
+        None
+    }
+
+    fn load(&mut self, _state: ron::Value) -> Result<(), ron::Error> {
+        Ok(())
+    }
+
+    /// Inputs and outputs derived from the settings of the node.
+    ///
+    /// Nodes returning `Some` have their ports kept in sync by [`Storage::sync_ports`].
+    fn signature(&self) -> Option<(Vec<PortSpec>, Vec<PortSpec>)> {
+        None
+    }
+
     fn output_expr(&self, _node: Node, _: &mut FnBuilder, _: Port) -> EmitResult;
 
     fn vertex(&self, _node: Node, function: &mut FnBuilder) -> EmitResult {
@@ -96,33 +362,82 @@ pub trait PreviewBuilder: downcast_rs::Downcast {
 
     fn fragment(&self, node: Node, function: &mut FnBuilder) -> EmitResult {
         if let Some(&port) = function.module.storage.nodes[node].outputs.first() {
-            let expr = self.output_expr(node, function, port)?;
+            let mut expr = self.output_expr(node, function, port)?;
+            // Integers show as their value, like floats.
+            if let naga::TypeInner::Scalar { kind, .. } | naga::TypeInner::Vector { kind, .. } =
+                *function.extract_type(expr)?
+            {
+                if matches!(kind, naga::ScalarKind::Sint | naga::ScalarKind::Uint) {
+                    expr = Wrap(expr).float().emit(function)?;
+                }
+            }
             function.resolve_to(expr, VectorKind::V4)
         } else {
-            Err(EmitError::PortNotFound)
+            Err(EmitError::PortNotFound.at(node))
         }
     }
 
-    fn pipeline(
+    fn vertices(&self) -> std::ops::Range<u32> {
+        0..3
+    }
+
+    /// Master nodes produce the final shader of a graph.
+    fn is_master(&self) -> bool {
+        false
+    }
+
+    /// Masters lighting their own fragments with `builtin_lit`, left unlit by previews.
+    fn is_lit(&self) -> bool {
+        false
+    }
+
+    /// Render state of a master, the pipeline defaults being kept for `None`.
+    fn surface(&self) -> Option<Surface> {
+        None
+    }
+
+    /// Resources provided by the render target of a master, bound first in this order.
+    fn target(&self) -> Vec<Resource> {
+        Vec::new()
+    }
+
+    /// Compute masters, generating `cs_main` from [`Self::preview_module`]
+    /// instead of `vs_main` and `fs_main`.
+    fn workgroup_size(&self) -> Option<[u32; 3]> {
+        None
+    }
+
+    /// Generates the `vs_main`/`fs_main` module for the node, without touching the GPU.
+    fn module<'s>(&self, node: Node, storage: &'s Storage) -> EmitResult<ModuleBuilder<'s>> {
+        self.preview_module(node, storage, PreviewMode::Flat)
+    }
+
+    fn preview_module<'s>(
         &self,
         node: Node,
-        storage: &Storage,
-        device: &wgpu::Device,
-    ) -> EmitResult<(String, wgpu::RenderPipeline)> {
+        storage: &'s Storage,
+        mode: PreviewMode,
+    ) -> EmitResult<ModuleBuilder<'s>> {
         let mut module = ModuleBuilder::from_wgsl(storage, include_str!("builtin.wgsl")).unwrap();
 
         let ty = BaseTypes::new(&mut module);
 
+        if mode == PreviewMode::Material {
+            module.group = 1;
+            module.mesh_model = true;
+        }
+
+        // Keeps the target and the properties at the first bindings whether or not a node reads them.
+        for resource in self.target() {
+            module.bind(resource);
+        }
+        if !storage.properties.is_empty() {
+            module.bind(Resource::Properties);
+        }
+
         let vs_input = StructBuilder::new(&mut module.module, "VertexInput")
             .builtin("vertex_index", ty.u32, BuiltIn::VertexIndex)
-            .build();
-
-        let vs_output = StructBuilder::new(&mut module.module, "VertexOutput")
-            .builtin(
-                "vertex_position",
-                ty.f32x4,
-                BuiltIn::Position { invariant: false },
-            )
+            .builtin("instance_index", ty.u32, BuiltIn::InstanceIndex)
             .build();
 
         let fs_input = StructBuilder::new(&mut module.module, "FragmentInput")
@@ -134,6 +449,53 @@ pub trait PreviewBuilder: downcast_rs::Downcast {
             .builtin("builtin_font_facing", ty.bool, BuiltIn::FrontFacing)
             .build();
 
+        // The fragment shader goes first, to know which varyings it reads.
+        module.stage = Some(ShaderStage::Fragment);
+        let fragment = {
+            let mut function = module.function();
+            function.argument("input", fs_input, None);
+            function.set_result(
+                ty.f32x4,
+                Binding::Location {
+                    location: 0,
+                    interpolation: Some(naga::Interpolation::Perspective),
+                    sampling: Some(naga::Sampling::Center),
+                },
+            );
+
+            let mut value = self
+                .fragment(node, &mut function)
+                .map_err(|err| err.at(node))?;
+            if mode == PreviewMode::Mesh && !self.is_lit() {
+                let normal = function.varying(Varying::Normal)?;
+                let light = function.camera(1)?;
+                value = function.call("builtin_preview_light", [value, normal, light])?;
+            }
+            let value = Some(value);
+            function.statement(Statement::Return { value });
+
+            EntryPoint {
+                name: String::from("fs_main"),
+                stage: ShaderStage::Fragment,
+                early_depth_test: None,
+                workgroup_size: [0; 3],
+                function: function.function,
+            }
+        };
+
+        let varyings = module.varyings.clone();
+        let varying_types: Vec<_> = varyings.iter().map(|v| v.ty(&mut module)).collect();
+        let mut vs_output = StructBuilder::new(&mut module.module, "VertexOutput").builtin(
+            "vertex_position",
+            ty.f32x4,
+            BuiltIn::Position { invariant: false },
+        );
+        for (location, (varying, ty)) in varyings.iter().zip(varying_types).enumerate() {
+            vs_output = vs_output.interpolator(*varying, ty, location as u32);
+        }
+        let vs_output = vs_output.build();
+
+        module.stage = Some(ShaderStage::Vertex);
         module.entry(|module| {
             let mut function = module.function();
             function.argument("input", vs_input, None);
@@ -142,17 +504,30 @@ pub trait PreviewBuilder: downcast_rs::Downcast {
                 binding: None,
             });
 
-            let position = {
-                let value = self.vertex(node, &mut function);
-                if value.is_err() && !matches!(value, Err(EmitError::MaybeDefault)) {
-                    dbg!(&value);
+            let mut position = self
+                .vertex(node, &mut function)
+                .map_err(|err| err.at(node))?;
+            match mode {
+                PreviewMode::Flat => {}
+                PreviewMode::Mesh => {
+                    let view_proj = function.camera(0)?;
+                    position = function.emit(naga::Expression::Binary {
+                        op: naga::BinaryOperator::Multiply,
+                        left: view_proj,
+                        right: position,
+                    });
                 }
-                value?
-            };
+                PreviewMode::Material => position = expr_mesh_to_clip(&mut function, position),
+            }
+
+            let mut components = vec![position];
+            for varying in varyings {
+                components.push(varying.vertex(&mut function).map_err(|err| err.at(node))?);
+            }
 
             let value = Some(function.emit(naga::Expression::Compose {
                 ty: vs_output,
-                components: vec![position],
+                components,
             }));
             function.statement(Statement::Return { value });
 
@@ -164,36 +539,43 @@ pub trait PreviewBuilder: downcast_rs::Downcast {
                 function: function.function,
             })
         })?;
+        module.push_entry_point(fragment);
+        module.stage = None;
 
-        module.entry(|module| {
-            let mut function = module.function();
-            function.argument("input", fs_input, None);
-            function.set_result(
-                ty.f32x4,
-                Binding::Location {
-                    location: 0,
-                    interpolation: Some(naga::Interpolation::Perspective),
-                    sampling: Some(naga::Sampling::Center),
-                },
-            );
+        Ok(module)
+    }
 
-            let value = self.fragment(node, &mut function);
-            if value.is_err() && !matches!(value, Err(EmitError::MaybeDefault)) {
-                dbg!(&value);
-            }
-            let value = Some(value?);
-            function.statement(Statement::Return { value });
+    /// `format` gives the format of the image loaded from a path, if any yet.
+    fn pipeline(
+        &self,
+        node: Node,
+        storage: &Storage,
+        device: &RenderDevice,
+        mode: PreviewMode,
+        format: &dyn Fn(&str) -> Option<wgpu::TextureFormat>,
+    ) -> EmitResult<PreviewPipeline> {
+        let module = self.preview_module(node, storage, mode)?;
+        let source = module.build()?;
+        let mut resources: Vec<_> = module.resources.into_iter().map(|(r, _)| r).collect();
+        let filterable = filter_resources(&mut resources, format);
+        let attributes = module.attributes;
+        let workgroup_size = self.workgroup_size();
 
-            Ok(EntryPoint {
-                name: String::from("fs_main"),
-                stage: ShaderStage::Fragment,
-                early_depth_test: None,
-                workgroup_size: [0; 3],
-                function: function.function,
+        let entries: Vec<_> = resources
+            .iter()
+            .enumerate()
+            .map(|(binding, resource)| {
+                let mut entry = resource.layout_entry(binding as u32, filterable);
+                if workgroup_size.is_some() {
+                    entry.visibility = wgpu::ShaderStages::COMPUTE;
+                }
+                entry
             })
-        })?;
-
-        let source = module.build()?;
+            .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        });
 
         let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
@@ -202,110 +584,603 @@ pub trait PreviewBuilder: downcast_rs::Downcast {
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[],
+            bind_group_layouts: &[&layout],
             push_constant_ranges: &[],
         });
 
+        if let Some(size) = workgroup_size {
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: "cs_main",
+            });
+            return Ok(PreviewPipeline {
+                source,
+                pipeline: PreviewShader::Compute(pipeline, size),
+                layout,
+                resources,
+                attributes,
+            });
+        }
+
+        let vertex_attributes: Vec<_> = attributes
+            .iter()
+            .map(|attribute| {
+                [wgpu::VertexAttribute {
+                    format: attribute.format(),
+                    offset: 0,
+                    shader_location: attribute.location(),
+                }]
+            })
+            .collect();
+        let buffers: Vec<_> = attributes
+            .iter()
+            .zip(&vertex_attributes)
+            .map(|(attribute, attributes)| wgpu::VertexBufferLayout {
+                array_stride: attribute.format().size(),
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes,
+            })
+            .collect();
+
+        let mut primitive = wgpu::PrimitiveState::default();
+        let mut target = wgpu::ColorTargetState::from(self.format());
+        let mut depth_write_enabled = true;
+        if let Some(surface) = self.surface() {
+            // The fullscreen triangle of flat previews is seen from behind.
+            if mode == PreviewMode::Mesh {
+                primitive.cull_mode = surface.cull_mode();
+            }
+            target.blend = surface.blend_state();
+            depth_write_enabled = surface.depth_write;
+        }
+
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &module,
                 entry_point: "vs_main",
-                buffers: &[],
+                buffers: &buffers,
             },
             fragment: Some(wgpu::FragmentState {
                 module: &module,
                 entry_point: "fs_main",
-                targets: &[Some(self.format().into())],
+                targets: &[Some(target)],
+            }),
+            primitive,
+            depth_stencil: (mode == PreviewMode::Mesh).then(|| wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
             }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
 
-        Ok((source, pipeline))
-    }
-
-    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, pipeline: &'a wgpu::RenderPipeline) {
-        rpass.set_pipeline(pipeline);
-        rpass.draw(0..3, 0..1);
+        Ok(PreviewPipeline {
+            source,
+            pipeline: PreviewShader::Render(pipeline),
+            layout,
+            resources,
+            attributes,
+        })
     }
 }
 
 pub fn update_previews(
-    encoder: &mut wgpu::CommandEncoder,
-    workspace: &mut crate::workspace::Workspace,
-    device: &wgpu::Device,
-    rpass: &mut RenderPass,
-    scale_factor: f32,
+    mut workspaces: Query<&mut Workspace>,
+    mut context: ResMut<EguiContext>,
+    mut images: ResMut<Assets<Image>>,
+    mut layers: ResMut<TextureLayers>,
+    mut queue: ResMut<PreviewQueue>,
+    device: Res<RenderDevice>,
+    assets: Res<AssetServer>,
+    time: Res<Time>,
+    scene: Res<PreviewScene>,
 ) {
-    let storage = unsafe { crate::util::fuck_ref(&workspace.storage) };
+    queue.jobs.clear();
 
-    if !workspace.dirty {
-        return;
-    }
-    workspace.dirty = false;
+    let scale_factor = {
+        let [ctx] = context.ctx_mut([WindowId::primary()]);
+        ctx.pixels_per_point()
+    };
+
+    for mut workspace in workspaces.iter_mut() {
+        for preview in workspace.storage.released_previews.drain(..) {
+            preview.free(&mut images, &mut context);
+        }
 
-    for (node_key, node) in &mut workspace.storage.nodes {
-        let builder = node.builder.as_ref();
+        workspace.time.advance(time.delta_seconds());
+        let properties = uniform_bytes(&workspace.storage.properties);
+        let clock = workspace.time.uniform_bytes();
 
-        if !builder.show_preview() {
+        if !workspace.dirty {
+            // Same shaders, only the buffers are written again.
+            let properties_changed = std::mem::take(&mut workspace.properties_changed);
+            workspace.mesh_preview.redraw |= properties_changed;
+            let playing = workspace.time.playing;
+            for node in workspace.storage.nodes.values_mut() {
+                if let Some(job) = node.preview.as_mut().and_then(|p| p.job.as_mut()) {
+                    if properties_changed || (playing && job.is_animated()) {
+                        job.write_buffers(&properties, &clock);
+                        queue.jobs.push(job.clone());
+                    }
+                }
+            }
             continue;
         }
+        workspace.dirty = false;
+        workspace.properties_changed = false;
+        workspace.mesh_preview.rebuild = true;
 
-        // XXX: 2.0 const from ./node.rs
-        let width_height = (node.rect.width() - 2.0).floor() as u32;
+        let mut diagnostics = workspace.storage.update_port_types();
+        let storage = unsafe { crate::util::fuck_ref(&workspace.storage) };
+        let mut textures = Vec::new();
 
-        if width_height == 0 {
-            continue;
+        for (node_key, node) in &mut workspace.storage.nodes {
+            let builder = node.builder.as_ref();
+
+            if !builder.show_preview() {
+                // Master nodes have no preview, but their shader is what gets exported.
+                if builder.is_master() {
+                    let module = builder.module(node_key, storage);
+                    if let Err(err) = module.and_then(|module| module.validate()) {
+                        diagnostics.push(Diagnostic::new(storage, node_key, &err));
+                    }
+                }
+                continue;
+            }
+
+            // XXX: 2.0 const from ./node.rs
+            let width_height = (node.rect.width() - 2.0).floor() as u32;
+
+            if width_height == 0 {
+                continue;
+            }
+
+            let mut preview = node.preview.take().unwrap_or_else(|| {
+                Preview::new(
+                    &mut images,
+                    &mut context,
+                    builder.format(),
+                    width_height,
+                    width_height,
+                    scale_factor,
+                )
+            });
+
+            if preview.scale != scale_factor
+                || preview.size.x as u32 != width_height
+                || preview.size.y as u32 != width_height
+            {
+                preview.free(&mut images, &mut context);
+                preview = Preview::new(
+                    &mut images,
+                    &mut context,
+                    builder.format(),
+                    width_height,
+                    width_height,
+                    scale_factor,
+                );
+            }
+
+            let format = |path: &str| image_format(&images, path);
+            let pipeline = builder.pipeline(node_key, storage, &device, PreviewMode::Flat, &format);
+
+            node.preview_is_valid = pipeline.is_ok();
+
+            let mut bindings = Vec::new();
+            let mut vertex_buffers = Vec::new();
+            let pipeline = match pipeline {
+                Ok(pipeline) => {
+                    preview.source = pipeline.source;
+                    vertex_buffers = pipeline
+                        .attributes
+                        .iter()
+                        .map(|&attribute| fullscreen_attribute(attribute))
+                        .collect();
+                    for resource in pipeline.resources {
+                        bindings.push(match resource {
+                            Resource::Texture { path, dimension } => {
+                                let handle = assets.load(path.as_str());
+                                let bound = prepare_texture(
+                                    &mut images,
+                                    &mut layers,
+                                    &assets,
+                                    &handle,
+                                    path,
+                                    dimension,
+                                );
+                                let bound = bound.unwrap_or_else(|err| {
+                                    diagnostics.push(Diagnostic::new(storage, node_key, &err));
+                                    handle.clone()
+                                });
+                                textures.push(handle);
+                                PreviewBinding::Texture(bound.clone_weak(), dimension.view())
+                            }
+                            Resource::Sampler(state) => PreviewBinding::Sampler(state.descriptor()),
+                            Resource::Properties => {
+                                PreviewBinding::Buffer(Resource::Properties, properties.clone())
+                            }
+                            Resource::Time => PreviewBinding::Buffer(Resource::Time, clock.clone()),
+                            Resource::Camera => unreachable!("only bound by mesh previews"),
+                            Resource::Viewport => {
+                                let size = preview.size * preview.scale;
+                                let bytes = viewport_bytes(size.x as u32, size.y as u32);
+                                PreviewBinding::Buffer(Resource::Viewport, bytes)
+                            }
+                            Resource::StorageTexture => PreviewBinding::StorageTexture,
+                            Resource::StorageBuffer { writable } => {
+                                let size = preview.size * preview.scale;
+                                let (width, height) = (size.x as u32, size.y as u32);
+                                PreviewBinding::Storage(if writable {
+                                    vec![0; (width * height * 16) as usize]
+                                } else {
+                                    storage_bytes(width, height)
+                                })
+                            }
+                            resource => scene.binding(&resource).unwrap(),
+                        });
+                    }
+                    Some((pipeline.pipeline, pipeline.layout))
+                }
+                // Nodes of compute kernels are only seen through their master.
+                Err(err) if matches!(err.inner(), EmitError::ComputeOnly(_)) => None,
+                Err(err) => {
+                    diagnostics.push(Diagnostic::new(storage, node_key, &err));
+                    None
+                }
+            };
+
+            let job = PreviewJob {
+                image: preview.image.clone_weak(),
+                pipeline,
+                bindings,
+                vertex_buffers,
+                indices: None,
+                vertices: builder.vertices(),
+                depth: false,
+            };
+            queue.jobs.push(job.clone());
+            preview.job = Some(job);
+
+            node.preview = Some(preview);
         }
 
-        let mut preview = node.preview.take().unwrap_or_else(|| {
-            Preview::new(
-                device,
-                rpass,
-                builder.format(),
-                width_height,
-                width_height,
-                scale_factor,
-            )
-        });
+        // A broken node also breaks the previews of everything downstream.
+        let mut unique: Vec<Diagnostic> = Vec::with_capacity(diagnostics.len());
+        for diagnostic in diagnostics {
+            if !unique.contains(&diagnostic) {
+                unique.push(diagnostic);
+            }
+        }
 
-        if preview.scale != scale_factor
-            || preview.size.x as u32 != width_height
-            || preview.size.y as u32 != width_height
-        {
-            rpass
-                .remove_textures(egui::TexturesDelta {
-                    set: ahash::AHashMap::default(),
-                    free: vec![preview.texture_id],
-                })
-                .unwrap();
-            preview = Preview::new(
-                device,
-                rpass,
-                builder.format(),
-                width_height,
-                width_height,
-                scale_factor,
-            );
+        for (node_key, node) in &mut workspace.storage.nodes {
+            node.errors = unique
+                .iter()
+                .filter(|diagnostic| diagnostic.node == node_key)
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect();
         }
+        workspace.diagnostics = unique;
+        workspace.textures = textures;
+    }
+}
 
-        let pipeline = builder.pipeline(node_key, storage, device);
+/// Vertex buffer of the triangle covering the preview, made by `expr_fullscreen`.
+fn fullscreen_attribute(attribute: Attribute) -> Vec<u8> {
+    let uv = [[0.0, 0.0], [2.0, 0.0], [0.0, 2.0]];
+    let vertices: Vec<Vec<f32>> = match attribute {
+        Attribute::Position => vec![
+            vec![-1.0, 1.0, 0.0],
+            vec![3.0, 1.0, 0.0],
+            vec![-1.0, -3.0, 0.0],
+        ],
+        Attribute::Normal => vec![vec![0.0, 0.0, 1.0]; 3],
+        Attribute::Tangent => vec![vec![1.0, 0.0, 0.0, 1.0]; 3],
+        Attribute::Color => vec![vec![1.0; 4]; 3],
+        Attribute::Uv0 | Attribute::Uv1 => uv.iter().map(|uv| uv.to_vec()).collect(),
+    };
+    vertices
+        .iter()
+        .flatten()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
 
-        node.preview_is_valid = pipeline.is_ok();
+/// Format of the image loaded from `path`, if loaded yet.
+pub(super) fn image_format(images: &Assets<Image>, path: &str) -> Option<wgpu::TextureFormat> {
+    let image = images.get(&Handle::weak(HandleId::from(path)))?;
+    Some(image.texture_descriptor.format)
+}
 
-        if let Ok((source, pipeline)) = pipeline {
-            preview.source = source;
+/// Copies of loaded textures split into layers, by image and layer count.
+///
+/// The image assets are kept as loaded, the same file may also be bound as a
+/// plain 2D texture. Copies are dropped by [`watch_textures`] when their image
+/// changes.
+#[derive(Default)]
+pub struct TextureLayers {
+    copies: AHashMap<(HandleId, u32), Handle<Image>>,
+}
 
-            let mut rpass = preview.pass(encoder, wgpu::Color::TRANSPARENT);
-            builder.draw(&mut rpass, &pipeline);
-        } else {
-            let _ = preview.pass(encoder, wgpu::Color::TRANSPARENT);
+/// Splits the layers of a loaded texture, which stay stacked in the image file,
+/// into a copy of it. Returns the image to bind, `handle` itself when there is
+/// nothing to split or the image is still loading.
+pub(super) fn prepare_texture(
+    images: &mut Assets<Image>,
+    copies: &mut TextureLayers,
+    assets: &AssetServer,
+    handle: &Handle<Image>,
+    path: String,
+    dimension: TextureDimension,
+) -> EmitResult<Handle<Image>> {
+    let layers = dimension.layers();
+    if let Some(copy) = copies.copies.get(&(handle.id, layers)) {
+        return Ok(copy.clone());
+    }
+
+    let image = match images.get(handle) {
+        Some(image) => image,
+        None if assets.get_load_state(handle) == LoadState::Failed => {
+            return Err(EmitError::TextureLoad(path));
         }
+        // Redrawn by `watch_textures` once loaded.
+        None => return Ok(handle.clone()),
+    };
+
+    let size = image.texture_descriptor.size;
+    if size.depth_or_array_layers == layers {
+        return Ok(handle.clone());
+    }
+
+    let height = size.height / layers;
+    let is_square = dimension != TextureDimension::Cube || size.width == height;
+    if size.depth_or_array_layers != 1 || size.height % layers != 0 || !is_square {
+        return Err(EmitError::TextureLayers(path, layers));
+    }
 
-        node.preview = Some(preview);
+    let mut copy = image.clone();
+    copy.reinterpret_stacked_2d_as_array(layers);
+    let copy = images.add(copy);
+    copies.copies.insert((handle.id, layers), copy.clone());
+    Ok(copy)
+}
+
+/// Redraws the previews using a texture once it is loaded or changed.
+pub fn watch_textures(
+    mut events: EventReader<AssetEvent<Image>>,
+    mut workspaces: Query<&mut Workspace>,
+    mut layers: ResMut<TextureLayers>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+            layers.copies.retain(|&(id, _), _| id != handle.id);
+        }
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            for mut workspace in workspaces.iter_mut() {
+                if workspace.textures.contains(handle) {
+                    workspace.dirty = true;
+                }
+            }
+        }
     }
 }
+
+/// Draws the queued previews into their images before egui samples them.
+#[derive(Default)]
+pub struct PreviewNode;
+
+impl render_graph::Node for PreviewNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let queue = world.resource::<PreviewQueue>();
+        let gpu_images = world.resource::<RenderAssets<Image>>();
+        let device = &render_context.render_device;
+
+        for job in &queue.jobs {
+            let image = match gpu_images.get(&job.image) {
+                Some(image) => image,
+                None => continue,
+            };
+
+            // Textures that are still loading leave the preview empty.
+            let bind_group = job.pipeline.as_ref().and_then(|(_, layout)| {
+                let mut views = Vec::new();
+                let mut samplers = Vec::new();
+                let mut buffers = Vec::new();
+                for binding in &job.bindings {
+                    match binding {
+                        PreviewBinding::Texture(handle, dimension) => {
+                            let texture = &gpu_images.get(handle)?.texture;
+                            views.push(texture.create_view(&wgpu::TextureViewDescriptor {
+                                dimension: Some(*dimension),
+                                ..default()
+                            }));
+                        }
+                        PreviewBinding::Sampler(descriptor) => {
+                            samplers.push(device.create_sampler(descriptor));
+                        }
+                        PreviewBinding::Buffer(_, contents) => {
+                            let descriptor = wgpu::util::BufferInitDescriptor {
+                                label: Some("node preview properties"),
+                                contents,
+                                usage: wgpu::BufferUsages::UNIFORM,
+                            };
+                            buffers.push(device.create_buffer_with_data(&descriptor));
+                        }
+                        PreviewBinding::Storage(contents) => {
+                            let descriptor = wgpu::util::BufferInitDescriptor {
+                                label: Some("node preview storage"),
+                                contents,
+                                usage: wgpu::BufferUsages::STORAGE,
+                            };
+                            buffers.push(device.create_buffer_with_data(&descriptor));
+                        }
+                        PreviewBinding::StorageTexture => {}
+                    }
+                }
+
+                let (mut views, mut samplers) = (views.iter(), samplers.iter());
+                let mut buffers = buffers.iter();
+                let entries: Vec<_> = job
+                    .bindings
+                    .iter()
+                    .enumerate()
+                    .map(|(binding, resource)| wgpu::BindGroupEntry {
+                        binding: binding as u32,
+                        resource: match resource {
+                            PreviewBinding::Texture(..) => {
+                                wgpu::BindingResource::TextureView(views.next().unwrap())
+                            }
+                            PreviewBinding::Sampler(_) => {
+                                wgpu::BindingResource::Sampler(samplers.next().unwrap())
+                            }
+                            PreviewBinding::Buffer(..) | PreviewBinding::Storage(_) => {
+                                buffers.next().unwrap().as_entire_binding()
+                            }
+                            PreviewBinding::StorageTexture => {
+                                wgpu::BindingResource::TextureView(&image.texture_view)
+                            }
+                        },
+                    })
+                    .collect();
+
+                Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("node preview"),
+                    layout,
+                    entries: &entries,
+                }))
+            });
+
+            let shader = job.pipeline.as_ref().map(|(shader, _)| shader);
+            if let Some(PreviewShader::Compute(pipeline, size)) = shader {
+                if let Some(bind_group) = &bind_group {
+                    let mut cpass = render_context.command_encoder.begin_compute_pass(
+                        &wgpu::ComputePassDescriptor {
+                            label: Some("node preview"),
+                        },
+                    );
+                    cpass.set_pipeline(pipeline);
+                    cpass.set_bind_group(0, bind_group, &[]);
+                    let x = (image.size.x as u32).div_ceil(size[0]);
+                    let y = (image.size.y as u32).div_ceil(size[1]);
+                    cpass.dispatch_workgroups(x, y, 1);
+                }
+                continue;
+            }
+
+            let vertex_buffers: Vec<_> = job
+                .vertex_buffers
+                .iter()
+                .map(|contents| {
+                    device.create_buffer_with_data(&wgpu::util::BufferInitDescriptor {
+                        label: Some("node preview vertices"),
+                        contents,
+                        usage: wgpu::BufferUsages::VERTEX,
+                    })
+                })
+                .collect();
+
+            let indices = job.indices.as_ref().map(|contents| {
+                device.create_buffer_with_data(&wgpu::util::BufferInitDescriptor {
+                    label: Some("node preview indices"),
+                    contents,
+                    usage: wgpu::BufferUsages::INDEX,
+                })
+            });
+
+            let depth = job.depth.then(|| {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("node preview depth"),
+                    size: wgpu::Extent3d {
+                        width: image.size.x as u32,
+                        height: image.size.y as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: DEPTH_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                });
+                texture.create_view(&wgpu::TextureViewDescriptor::default())
+            });
+
+            let mut rpass =
+                render_context
+                    .command_encoder
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("node preview"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &image.texture_view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: depth.as_ref().map(|view| {
+                            wgpu::RenderPassDepthStencilAttachment {
+                                view,
+                                depth_ops: Some(wgpu::Operations {
+                                    load: wgpu::LoadOp::Clear(1.0),
+                                    store: false,
+                                }),
+                                stencil_ops: None,
+                            }
+                        }),
+                    });
+
+            if let (Some(PreviewShader::Render(pipeline)), Some(bind_group)) = (shader, &bind_group)
+            {
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, bind_group, &[]);
+                for (slot, buffer) in vertex_buffers.iter().enumerate() {
+                    rpass.set_vertex_buffer(slot as u32, *buffer.slice(..));
+                }
+                match &indices {
+                    Some(buffer) => {
+                        rpass.set_index_buffer(*buffer.slice(..), wgpu::IndexFormat::Uint32);
+                        rpass.draw_indexed(job.vertices.clone(), 0, 0..1);
+                    }
+                    None => rpass.draw(job.vertices.clone(), 0..1),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn animate_time() {
+    let mut storage = Storage::default();
+    let time = storage.spawn_kind("Time").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+    let sine = storage.nodes[time].outputs[1];
+    storage.link(sine, storage.nodes[add].inputs[0]);
+
+    // Nodes downstream of the clock bind it too.
+    let module = storage.nodes[add].builder.module(add, &storage).unwrap();
+    assert!(module.build().unwrap().contains("var<uniform> time: Time;"));
+    assert_eq!(module.resources.len(), 1);
+    assert_eq!(module.resources[0].0, Resource::Time);
+
+    let mut clock = PreviewTime::default();
+    clock.advance(0.5);
+    clock.playing = false;
+    clock.advance(0.5);
+    let bytes = clock.uniform_bytes();
+    assert_eq!(bytes[0..4], 0.5f32.to_le_bytes());
+    assert_eq!(bytes[4..8], 0.5f32.sin().to_le_bytes());
+    assert_eq!(bytes[12..16], 0.0f32.to_le_bytes());
+}
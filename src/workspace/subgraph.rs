@@ -0,0 +1,485 @@
+//! Sub-graphs: `.shader` files with [`SubGraphOutput`] nodes, used as a single node.
+
+use super::asset::{to_state, SerializedLink, SerializedNode};
+use super::nodes::subgraph::{
+    port_type, PortState, SubGraph, SubGraphInput, SubGraphOutput, PORT_TYPES,
+};
+use super::{Change, Data, Node, Port, PortSpec, ShaderGraph, Storage, Workspace};
+use ahash::AHashMap;
+use bevy::asset::{AssetIo, FileAssetIo};
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct SubGraphAsset {
+    pub path: PathBuf,
+    /// Shown in the creation menu and as the title of the node.
+    pub name: String,
+    pub graph: ShaderGraph,
+}
+
+impl SubGraphAsset {
+    /// Spawns a node using the sub-graph.
+    pub fn spawn(&self, storage: &mut Storage) -> Option<Node> {
+        let node = storage.spawn_kind(SubGraph::KIND)?;
+        let data = &mut storage.nodes[node];
+        data.title = self.name.clone();
+
+        let sub_graph = data.builder.downcast_mut::<SubGraph>()?;
+        if let Err(err) = sub_graph.set(self.path.clone(), self.graph.clone()) {
+            tracing::error!("failed to use {}: {}", self.path.display(), err);
+            storage.despawn(node);
+            return None;
+        }
+
+        storage.sync_ports(node);
+        Some(node)
+    }
+}
+
+/// Every sub-graph in the asset folder.
+#[derive(Default)]
+pub struct SubGraphLibrary {
+    handles: AHashMap<PathBuf, Handle<ShaderGraph>>,
+    /// Sorted by name, shared with the creation menu of every workspace.
+    pub entries: Arc<Vec<SubGraphAsset>>,
+}
+
+impl SubGraphLibrary {
+    /// Loads `path`, or reloads it when it was saved again.
+    pub fn watch(&mut self, assets: &AssetServer, path: &Path) {
+        if self.handles.contains_key(path) {
+            assets.reload_asset(path);
+        } else {
+            self.handles.insert(path.to_owned(), assets.load(path));
+        }
+    }
+
+    fn insert(&mut self, path: &Path, graph: &ShaderGraph) {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.path != path)
+            .cloned()
+            .collect();
+
+        entries.push(SubGraphAsset {
+            path: path.to_owned(),
+            name: name.into_owned(),
+            graph: graph.clone(),
+        });
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        self.entries = Arc::new(entries);
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if self.entries.iter().any(|entry| entry.path == path) {
+            let entries = self.entries.iter().filter(|entry| entry.path != path);
+            self.entries = Arc::new(entries.cloned().collect());
+        }
+    }
+}
+
+/// Loads every `.shader` file of the asset folder to find the sub-graphs.
+pub fn load_sub_graphs(assets: Res<AssetServer>, mut library: ResMut<SubGraphLibrary>) {
+    let io = assets.asset_io();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match io.read_directory(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!("failed to read {}: {}", dir.display(), err);
+                continue;
+            }
+        };
+
+        for path in entries {
+            if io.is_dir(&path) {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "shader") {
+                library.watch(&assets, &path);
+            }
+        }
+    }
+}
+
+/// Keeps the library up to date and refreshes the nodes using a sub-graph that changed.
+pub fn update_sub_graphs(
+    mut events: EventReader<AssetEvent<ShaderGraph>>,
+    assets: Res<AssetServer>,
+    graphs: Res<Assets<ShaderGraph>>,
+    mut library: ResMut<SubGraphLibrary>,
+    mut workspaces: Query<&mut Workspace>,
+) {
+    for event in events.iter() {
+        let handle = match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { .. } => continue,
+        };
+
+        let (path, graph) = match (assets.get_handle_path(handle), graphs.get(handle)) {
+            (Some(path), Some(graph)) => (path.path().to_owned(), graph),
+            _ => continue,
+        };
+
+        if !SubGraph::is_sub_graph(graph) {
+            library.remove(&path);
+            continue;
+        }
+
+        library.insert(&path, graph);
+        for mut workspace in workspaces.iter_mut() {
+            workspace.refresh_sub_graph(&path, graph);
+        }
+    }
+}
+
+impl Workspace {
+    /// Updates the nodes using the sub-graph at `path`.
+    pub fn refresh_sub_graph(&mut self, path: &Path, graph: &ShaderGraph) {
+        let nodes: Vec<Node> = self
+            .storage
+            .nodes
+            .iter()
+            .filter(|(_, node)| {
+                let sub_graph = node.builder.downcast_ref::<SubGraph>();
+                sub_graph.is_some_and(|sub| sub.path() == path && sub.graph() != graph)
+            })
+            .map(|(key, _)| key)
+            .collect();
+
+        for &node in &nodes {
+            let builder = &mut self.storage.nodes[node].builder;
+            let sub_graph = builder.downcast_mut::<SubGraph>().unwrap();
+            if let Err(err) = sub_graph.set(path.to_owned(), graph.clone()) {
+                tracing::error!("failed to update {}: {}", path.display(), err);
+                continue;
+            }
+            self.storage.sync_ports(node);
+        }
+
+        if !nodes.is_empty() {
+            self.dirty = true;
+        }
+    }
+
+    /// Moves the selected nodes into a new sub-graph next to the graph file,
+    /// and replaces them with a node using it.
+    pub fn collapse_selection(&mut self, io: &dyn AssetIo) -> anyhow::Result<Option<PathBuf>> {
+        let io = io
+            .downcast_ref::<FileAssetIo>()
+            .ok_or_else(|| anyhow::anyhow!("sub-graphs can only be saved to files"))?;
+
+        let mut nodes: Vec<Node> = self
+            .selection
+            .iter()
+            .copied()
+            .filter(|&node| self.storage.nodes.contains_key(node))
+            .collect();
+        nodes.sort_by_key(|&node| self.storage.nodes[node].id);
+
+        if nodes.is_empty() {
+            return Ok(None);
+        }
+
+        let collapsed = collapse(&self.storage, &nodes);
+
+        let dir = self
+            .path
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        let path = (1..)
+            .map(|index| dir.join(format!("sub_graph_{}.shader", index)))
+            .find(|path| !io.root_path().join(path).exists())
+            .unwrap();
+
+        std::fs::write(io.root_path().join(&path), collapsed.graph.to_ron()?)?;
+        tracing::info!("saved {}", path.display());
+
+        let asset = SubGraphAsset {
+            name: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            path: path.clone(),
+            graph: collapsed.graph,
+        };
+        let node = asset
+            .spawn(&mut self.storage)
+            .ok_or_else(|| anyhow::anyhow!("failed to use {}", path.display()))?;
+
+        let position = nodes
+            .iter()
+            .map(|&node| self.storage.nodes[node].position)
+            .fold(egui::pos2(f32::INFINITY, f32::INFINITY), egui::Pos2::min);
+        self.storage.nodes[node].position = position;
+
+        self.gesture += 1;
+        let id = self.storage.nodes[node].id;
+        self.edit(Change::Spawn {
+            node: id,
+            removed: None,
+        });
+
+        for &old in &nodes {
+            let old = self.storage.nodes[old].id;
+            self.edit(Change::Despawn {
+                node: old,
+                removed: None,
+            });
+        }
+
+        let inputs = self.storage.nodes[node].inputs.clone();
+        let outputs = self.storage.nodes[node].outputs.clone();
+
+        let mut links = Vec::new();
+        for (&source, &input) in collapsed.sources.iter().zip(&inputs) {
+            links.push((source, input));
+        }
+        for (targets, &output) in collapsed.targets.iter().zip(&outputs) {
+            links.extend(targets.iter().map(|&target| (output, target)));
+        }
+
+        for (from, to) in links {
+            let from = self.storage.port_ref(from);
+            let to = self.storage.port_ref(to);
+            self.edit(Change::Link { from, to });
+        }
+
+        self.selection.clear();
+        self.selection.insert(node);
+
+        Ok(Some(path))
+    }
+}
+
+/// A selection turned into a sub-graph by [`collapse`].
+pub struct Collapsed {
+    pub graph: ShaderGraph,
+    /// Outputs outside the selection feeding each input of the sub-graph.
+    pub sources: Vec<Port>,
+    /// Inputs outside the selection fed by each output of the sub-graph.
+    pub targets: Vec<Vec<Port>>,
+}
+
+/// Copies `nodes` into a sub-graph, with an input for every output linked into
+/// the selection and an output for every port linked out of it.
+pub fn collapse(storage: &Storage, nodes: &[Node]) -> Collapsed {
+    let nodes: Vec<Node> = nodes
+        .iter()
+        .copied()
+        .filter(|&node| !storage.nodes[node].kind.is_empty())
+        .collect();
+
+    let mut graph = ShaderGraph::from_nodes(storage, nodes.iter().copied());
+    let index = |node: Node| nodes.iter().position(|&n| n == node);
+
+    let mut sources: Vec<Port> = Vec::new();
+    let mut inputs: Vec<(PortSpec, Vec<(usize, String)>)> = Vec::new();
+    let mut outputs: Vec<(Port, PortSpec, (usize, String))> = Vec::new();
+    let mut targets: Vec<Vec<Port>> = Vec::new();
+
+    for link in storage.links.values() {
+        let from = &storage.ports[link.min.port];
+        let to = &storage.ports[link.max.port];
+
+        match (index(link.min.node), index(link.max.node)) {
+            (None, Some(to_index)) => {
+                let target = (to_index, to.label.clone());
+                if let Some(position) = sources.iter().position(|&p| p == link.min.port) {
+                    inputs[position].1.push(target);
+                    continue;
+                }
+
+                let data = boundary_type(to.data, from.data);
+                let taken: Vec<_> = inputs.iter().map(|(spec, _)| spec.label.as_str()).collect();
+                let name = unique_label(&taken, &to.label);
+
+                sources.push(link.min.port);
+                inputs.push((PortSpec::new(name, data), vec![target]));
+            }
+            (Some(from_index), None) => {
+                if let Some(position) = outputs.iter().position(|(p, ..)| *p == link.min.port) {
+                    targets[position].push(link.max.port);
+                    continue;
+                }
+
+                let data = boundary_type(from.data, to.data);
+                let taken: Vec<_> = outputs
+                    .iter()
+                    .map(|(_, spec, _)| spec.label.as_str())
+                    .collect();
+                let name = unique_label(&taken, &from.label);
+
+                let source = (from_index, from.label.clone());
+                outputs.push((link.min.port, PortSpec::new(name, data), source));
+                targets.push(vec![link.max.port]);
+            }
+            _ => (),
+        }
+    }
+
+    let (min, max) = graph.nodes.iter().fold(
+        (egui::pos2(f32::INFINITY, f32::INFINITY), egui::Pos2::ZERO),
+        |(min, max), node| {
+            let position = egui::pos2(node.position.0, node.position.1);
+            (min.min(position), max.max(position))
+        },
+    );
+
+    let boundary = |kind: &str, spec: &PortSpec, x: f32, row: usize| SerializedNode {
+        kind: kind.to_string(),
+        title: kind.to_string(),
+        position: (x, min.y + row as f32 * 150.0),
+        defaults: Vec::new(),
+        state: to_state(&PortState::new(spec)),
+    };
+
+    for (row, (spec, targets)) in inputs.iter().enumerate() {
+        let input = graph.nodes.len();
+        graph
+            .nodes
+            .push(boundary(SubGraphInput::KIND, spec, min.x - 200.0, row));
+
+        for target in targets {
+            graph.links.push(SerializedLink {
+                from: (input, spec.label.clone()),
+                to: target.clone(),
+            });
+        }
+    }
+
+    for (row, (_, spec, source)) in outputs.iter().enumerate() {
+        let output = graph.nodes.len();
+        graph
+            .nodes
+            .push(boundary(SubGraphOutput::KIND, spec, max.x + 250.0, row));
+
+        graph.links.push(SerializedLink {
+            from: source.clone(),
+            to: (output, spec.label.clone()),
+        });
+    }
+
+    Collapsed {
+        graph,
+        sources,
+        targets,
+    }
+}
+
+/// Type of a sub-graph port replacing a link between `inner` and `outer`,
+/// preferring the side that has a definite type.
+fn boundary_type(inner: Data, outer: Data) -> Data {
+    [inner, outer]
+        .into_iter()
+        .find(|data| PORT_TYPES.contains(data))
+        .or_else(|| port_type(inner))
+        .unwrap_or(Data::Vector4)
+}
+
+/// Appends a number to `label` while another port already uses it.
+fn unique_label(taken: &[&str], label: &str) -> String {
+    let mut name = label.to_string();
+    let mut count = 1;
+    while taken.contains(&name.as_str()) {
+        count += 1;
+        name = format!("{} {}", label, count);
+    }
+    name
+}
+
+#[test]
+fn collapse_into_function() {
+    let mut storage = Storage::default();
+    let color = storage.spawn_kind("Color").unwrap();
+    let add = storage.spawn_kind("Add").unwrap();
+    let negate = storage.spawn_kind("Negate").unwrap();
+    let master = storage.spawn_kind("Master").unwrap();
+
+    let out = storage.nodes[color].outputs[0];
+    let a = storage.nodes[add].inputs[0];
+    storage.link(out, a);
+    let out = storage.nodes[add].outputs[0];
+    let input = storage.nodes[negate].inputs[0];
+    storage.link(out, input);
+    let out = storage.nodes[negate].outputs[0];
+    let color_input = storage.nodes[master].inputs[1];
+    storage.link(out, color_input);
+
+    let collapsed = collapse(&storage, &[add, negate]);
+    assert_eq!(collapsed.sources, vec![storage.nodes[color].outputs[0]]);
+    assert_eq!(collapsed.targets, vec![vec![color_input]]);
+    assert!(SubGraph::is_sub_graph(&collapsed.graph));
+
+    // The sub-graph survives a round trip through its file.
+    let text = collapsed.graph.to_ron().unwrap();
+    let graph = ShaderGraph::from_ron(text.as_bytes()).unwrap();
+    assert_eq!(graph, collapsed.graph);
+
+    let asset = SubGraphAsset {
+        path: PathBuf::from("shaders/negated sum.shader"),
+        name: String::from("negated sum"),
+        graph,
+    };
+    let sub_graph = asset.spawn(&mut storage).unwrap();
+    assert_eq!(storage.nodes[sub_graph].inputs.len(), 1);
+    assert_eq!(storage.nodes[sub_graph].outputs.len(), 1);
+
+    storage.despawn(add);
+    storage.despawn(negate);
+    let input = storage.nodes[sub_graph].inputs[0];
+    storage.link(storage.nodes[color].outputs[0], input);
+    storage.link(storage.nodes[sub_graph].outputs[0], color_input);
+
+    // Used twice, compiled once.
+    let other = asset.spawn(&mut storage).unwrap();
+    let out = storage.nodes[other].outputs[0];
+    let position = storage.nodes[master].inputs[0];
+    storage.link(out, position);
+
+    let module = storage.nodes[master]
+        .builder
+        .module(master, &storage)
+        .unwrap();
+    let source = module.build().unwrap();
+    let (_, name) = source.split_once("fn sub_graph_negated_sum_").unwrap();
+    let (name, _) = name.split_once('(').unwrap();
+    let call = format!("sub_graph_negated_sum_{}(", name);
+    assert_eq!(source.matches("fn sub_graph_negated_sum_").count(), 1);
+    assert_eq!(source.matches(&call).count(), 3);
+
+    // Loading a graph that uses the sub-graph restores its ports and links.
+    let saved = ShaderGraph::from_storage(&storage).to_ron().unwrap();
+    let loaded = ShaderGraph::from_ron(saved.as_bytes()).unwrap();
+    let loaded = loaded.to_storage().unwrap();
+    assert_eq!(loaded.links.len(), storage.links.len());
+
+    // Editing the sub-graph updates the nodes using it.
+    let mut workspace = Workspace::new();
+    workspace.set_storage(loaded);
+    let mut edited = asset.graph.clone();
+    let output = edited
+        .nodes
+        .iter_mut()
+        .find(|node| node.kind == SubGraphOutput::KIND)
+        .unwrap();
+    output.state = to_state(&PortState {
+        name: String::from("result"),
+        data: String::from("Vector3"),
+    });
+    edited.links.last_mut().unwrap().to.1 = String::from("result");
+
+    workspace.refresh_sub_graph(&asset.path, &edited);
+    for node in workspace.storage.nodes.values() {
+        if node.kind == SubGraph::KIND {
+            let port = &workspace.storage.ports[node.outputs[0]];
+            assert_eq!(port.label, "result");
+            assert_eq!(port.data, Data::Vector3);
+        }
+    }
+}